@@ -0,0 +1,92 @@
+// Cost of the per-tick work of the pitch and trigger chains, and of updating a running chain
+// in place compared to rebuilding it.
+#![allow(dead_code)]
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pitch_calc::*;
+
+#[path = "../src/assets.rs"]
+mod assets;
+#[path = "../src/chain.rs"]
+mod chain;
+#[path = "../src/pitch.rs"]
+mod pitch;
+#[path = "../src/seed.rs"]
+mod seed;
+#[path = "../src/trigger.rs"]
+mod trigger;
+
+use chain::ChainParameter;
+use pitch::*;
+use trigger::*;
+
+const MIN_PITCH: LetterOctave = LetterOctave(Letter::C, 3);
+const MAX_PITCH: LetterOctave = LetterOctave(Letter::C, 5);
+const BEAT_LENGTH: u32 = 15;
+const NOTES_PER_BEAT: &[u32] = &[4, 4, 4, 4];
+
+// Same chain as the sequencer builds
+fn pitch_chain() -> Box<dyn PitchModule> {
+    let producer = Box::new(SinePitchProducer::new(
+        64,
+        MIN_PITCH,
+        MAX_PITCH,
+        Direction::Forward,
+    ));
+    let quantizer = Box::new(PitchQuantizer::new(
+        producer,
+        vec![
+            Letter::C,
+            Letter::D,
+            Letter::E,
+            Letter::F,
+            Letter::G,
+            Letter::A,
+            Letter::B,
+        ],
+    ));
+    let octave_jump = Box::new(OctaveJumpModule::new(quantizer, 0.1, MIN_PITCH, MAX_PITCH));
+    Box::new(RangeClampModule::new(
+        octave_jump,
+        RangeMode::Fold,
+        MIN_PITCH,
+        MAX_PITCH,
+    ))
+}
+
+fn trigger_chain(probability: f64) -> Box<dyn TriggerModule> {
+    let rhythm_divider = Box::new(RhythmDivider::new(
+        Box::new(RandomTriggerProducer::new(probability, Vec::new())),
+        BEAT_LENGTH,
+        NOTES_PER_BEAT.to_vec(),
+    ));
+    Box::new(RestGate::new(rhythm_divider, 0.1))
+}
+
+fn tick_path(c: &mut Criterion) {
+    let mut pitch = pitch_chain();
+    c.bench_function("pitch chain tick", |b| b.iter(|| black_box(pitch.tick())));
+
+    let mut trigger = trigger_chain(0.8);
+    c.bench_function("trigger chain tick", |b| {
+        b.iter(|| black_box(trigger.tick()))
+    });
+}
+
+fn parameter_updates(c: &mut Criterion) {
+    c.bench_function("trigger chain rebuild", |b| {
+        b.iter(|| black_box(trigger_chain(black_box(0.5))))
+    });
+
+    let mut trigger = trigger_chain(0.8);
+    c.bench_function("trigger chain update in place", |b| {
+        b.iter(|| trigger.update(ChainParameter::TriggerProbability(black_box(0.5))))
+    });
+
+    c.bench_function("pitch chain rebuild", |b| {
+        b.iter(|| black_box(pitch_chain()))
+    });
+}
+
+criterion_group!(benches, tick_path, parameter_updates);
+criterion_main!(benches);
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, fs, iter::Peekable, path::Path, str::Chars};
+
+//constants
+// C, D, E, F, G, A, B
+const LETTER_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+// position of each letter on the circle of fifths, from C
+const LETTER_FIFTHS: [i32; 7] = [0, 2, 4, -1, 1, 3, 5];
+// order in which key signatures add sharps, as letter indexes; flats go the other way
+const SHARP_ORDER: [usize; 7] = [3, 0, 4, 1, 5, 2, 6];
+const MIDDLE_C: i32 = 60;
+
+// Pitches of an imported tune, the rhythm is left to the sequencer
+pub struct Melody {
+    pub title: String,
+    pub notes: Vec<u8>,
+}
+
+fn letter_index(letter: char) -> Option<usize> {
+    "CDEFGAB".find(letter.to_ascii_uppercase())
+}
+
+// Accidental of each letter in a key like "G", "F#m", "Bb" or "D dor"
+fn key_signature(key: &str) -> [i32; 7] {
+    let mut chars = key.trim().chars().peekable();
+    let mut signature = [0; 7];
+    let Some(letter) = chars.next().and_then(letter_index) else {
+        // "none", "HP" and the like
+        return signature;
+    };
+    let mut fifths = LETTER_FIFTHS[letter];
+    match chars.peek() {
+        Some('#') => {
+            fifths += 7;
+            chars.next();
+        }
+        Some('b') => {
+            fifths -= 7;
+            chars.next();
+        }
+        _ => (),
+    }
+    let mode: String = chars
+        .collect::<String>()
+        .trim()
+        .to_ascii_lowercase()
+        .chars()
+        .take(3)
+        .collect();
+    fifths += match mode.as_str() {
+        "m" | "min" | "aeo" => -3,
+        "dor" => -2,
+        "phr" => -4,
+        "lyd" => 1,
+        "mix" => -1,
+        "loc" => -5,
+        _ => 0,
+    };
+    for (position, letter) in SHARP_ORDER.iter().enumerate() {
+        if (position as i32) < fifths {
+            signature[*letter] += 1;
+        }
+        if (6 - position as i32) < -fifths {
+            signature[*letter] -= 1;
+        }
+    }
+    signature
+}
+
+fn skip_until(chars: &mut Peekable<Chars>, end: char) {
+    for c in chars.by_ref() {
+        if c == end {
+            break;
+        }
+    }
+}
+
+fn skip_length(chars: &mut Peekable<Chars>) {
+    while chars
+        .peek()
+        .is_some_and(|c| c.is_ascii_digit() || *c == '/')
+    {
+        chars.next();
+    }
+}
+
+// Parses a note from its accidentals on, applying the key and the accidentals of the bar
+fn parse_note(
+    chars: &mut Peekable<Chars>,
+    signature: &[i32; 7],
+    bar_accidentals: &mut HashMap<(usize, i32), i32>,
+) -> Option<u8> {
+    let mut accidental = None;
+    while let Some(c) = chars.peek() {
+        let change = match c {
+            '^' => 1,
+            '_' => -1,
+            '=' => 0,
+            _ => break,
+        };
+        accidental = Some(accidental.unwrap_or(0) + change);
+        chars.next();
+    }
+    let letter_char = chars.next()?;
+    let letter = letter_index(letter_char)?;
+    let mut octave = if letter_char.is_ascii_lowercase() {
+        1
+    } else {
+        0
+    };
+    while let Some(c) = chars.peek() {
+        match c {
+            '\'' => octave += 1,
+            ',' => octave -= 1,
+            _ => break,
+        }
+        chars.next();
+    }
+    skip_length(chars);
+    let alteration = match accidental {
+        Some(alteration) => {
+            bar_accidentals.insert((letter, octave), alteration);
+            alteration
+        }
+        None => *bar_accidentals
+            .get(&(letter, octave))
+            .unwrap_or(&signature[letter]),
+    };
+    let note = MIDDLE_C + octave * 12 + LETTER_SEMITONES[letter] + alteration;
+    (0..=127).contains(&note).then_some(note as u8)
+}
+
+// Reads the pitches of the first tune, chords keep their top note; repeats are not expanded
+pub fn parse_abc(text: &str) -> Result<Melody, String> {
+    let mut title = String::new();
+    let mut signature = [0; 7];
+    let mut in_body = false;
+    let mut notes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('%').next().unwrap_or("");
+        let header = line.len() > 1 && line.as_bytes()[1] == b':';
+        if header && line.as_bytes()[0].is_ascii_alphabetic() {
+            let value = line[2..].trim();
+            match &line[..1] {
+                // the next tune starts
+                "X" if in_body => break,
+                "T" if title.is_empty() => title = value.to_string(),
+                "K" => {
+                    signature = key_signature(value);
+                    in_body = true;
+                }
+                _ => (),
+            }
+            continue;
+        }
+        if !in_body {
+            continue;
+        }
+        let mut bar_accidentals = HashMap::new();
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+                    if let Some(note) = parse_note(&mut chars, &signature, &mut bar_accidentals) {
+                        notes.push(note);
+                    }
+                }
+                '[' => {
+                    chars.next();
+                    let inline_field = chars.clone().nth(1) == Some(':');
+                    if inline_field {
+                        let field: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                        if let Some(key) = field.strip_prefix("K:") {
+                            signature = key_signature(key);
+                        }
+                    } else if chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        // first or second ending
+                        skip_length(&mut chars);
+                    } else {
+                        let mut top = None;
+                        while let Some(&c) = chars.peek() {
+                            if c == ']' {
+                                chars.next();
+                                break;
+                            }
+                            match parse_note(&mut chars, &signature, &mut bar_accidentals) {
+                                Some(note) => top = top.max(Some(note)),
+                                None => continue,
+                            }
+                        }
+                        skip_length(&mut chars);
+                        notes.extend(top);
+                    }
+                }
+                '|' => {
+                    chars.next();
+                    bar_accidentals.clear();
+                }
+                // chord symbols, annotations, decorations and grace notes
+                '"' | '!' | '+' | '{' => {
+                    chars.next();
+                    let end = if c == '{' { '}' } else { c };
+                    skip_until(&mut chars, end);
+                }
+                'z' | 'Z' | 'x' => {
+                    chars.next();
+                    skip_length(&mut chars);
+                }
+                _ => {
+                    chars.next();
+                }
+            }
+        }
+    }
+    if notes.is_empty() {
+        return Err("No notes found, the tune needs a K: line before its body".to_string());
+    }
+    Ok(Melody { title, notes })
+}
+
+// State of the "Melody" window
+pub struct MelodyImport {
+    pub path: String,
+    // pasted ABC, used when no path is given
+    pub text: String,
+    pub error: Option<String>,
+    pub melody: Option<Melody>,
+}
+
+impl MelodyImport {
+    pub fn new() -> MelodyImport {
+        MelodyImport {
+            path: String::new(),
+            text: String::new(),
+            error: None,
+            melody: None,
+        }
+    }
+
+    pub fn import(&mut self) {
+        let path = self.path.trim();
+        let text = if path.is_empty() {
+            Ok(self.text.clone())
+        } else {
+            fs::read_to_string(Path::new(path)).map_err(|err| err.to_string())
+        };
+        match text.and_then(|text| parse_abc(&text)) {
+            Ok(melody) => {
+                self.melody = Some(melody);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::trigger::TriggerEvent;
+
+//constants
+// beats of the bar from 0, the third one only in bars long enough to have it
+const DOWNBEAT: u64 = 0;
+const THIRD_BEAT: u64 = 2;
+
+// Louder notes on the first beat of the bar, and on the third if asked
+#[derive(Clone, Copy, PartialEq)]
+pub struct DownbeatAccent {
+    pub enabled: bool,
+    pub third_beat: bool,
+    // added to the velocity
+    pub amount: u8,
+}
+
+impl DownbeatAccent {
+    // Velocity added to a note, on the beat the rhythm divider placed its trigger on.
+    // A chain without one goes by the tick of the bar the note starts at.
+    pub fn boost(&self, event: &TriggerEvent, tick_in_bar: u64, ticks_per_beat: u64) -> i32 {
+        let ticks_per_beat = ticks_per_beat.max(1);
+        let beat = match (event.beat_index, event.step_index) {
+            (Some(beat), Some(0)) => beat as u64,
+            (Some(_), _) => return 0,
+            (None, _) if tick_in_bar % ticks_per_beat == 0 => tick_in_bar / ticks_per_beat,
+            (None, _) => return 0,
+        };
+        match beat {
+            _ if !self.enabled => 0,
+            DOWNBEAT => self.amount as i32,
+            THIRD_BEAT if self.third_beat => self.amount as i32,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+//constants
+const ACCESSIBILITY_FILE: &str = "accessibility.json";
+pub const UI_SCALES: [f32; 5] = [1.0, 1.25, 1.5, 1.75, 2.0];
+// text size on top of the scale with large text on
+const LARGE_TEXT_SCALE: f32 = 1.3;
+
+// How the UI is sized and driven, saved to the config directory on every change
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Accessibility {
+    pub ui_scale: f32,
+    pub large_text: bool,
+    // the keys go to the focused control rather than the shortcuts
+    pub keyboard_navigation: bool,
+}
+
+impl Default for Accessibility {
+    fn default() -> Accessibility {
+        Accessibility {
+            ui_scale: 1.0,
+            large_text: false,
+            keyboard_navigation: false,
+        }
+    }
+}
+
+impl Accessibility {
+    // The saved settings, the defaults the first time
+    pub fn load() -> Accessibility {
+        let mut accessibility: Accessibility =
+            storage::load_json(ACCESSIBILITY_FILE).unwrap_or_default();
+        // a hand edited file may hold any scale, the nearest one offered is used
+        let ui_scale = accessibility.ui_scale;
+        accessibility.ui_scale = UI_SCALES
+            .into_iter()
+            .min_by(|a, b| (a - ui_scale).abs().total_cmp(&(b - ui_scale).abs()))
+            .unwrap();
+        accessibility
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = storage::save_json(ACCESSIBILITY_FILE, self) {
+            eprintln!("Could not save the accessibility settings: {}", err);
+        }
+    }
+
+    pub fn text_scale(&self) -> f32 {
+        if self.large_text {
+            self.ui_scale * LARGE_TEXT_SCALE
+        } else {
+            self.ui_scale
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use pitch_calc::*;
+use rand::prelude::*;
+
+use crate::pitch::{fit_to_range, ScaleGrid};
+use crate::seed::{seeded_rng, SeedStream};
+
+//constants
+// furthest the pitch center drifts from the pitch chain, in scale degrees
+const AMBIENT_MAX_DRIFT_DEGREES: i32 = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct AmbientSettings {
+    pub enabled: bool,
+    // probability that a trigger actually starts a note
+    pub density: f64,
+    // note durations are multiplied by this so notes overlap
+    pub note_length: f32,
+    // probability of the pitch center moving by one degree at every bar
+    pub drift: f64,
+    pub max_voices: usize,
+}
+
+// Slow, overlapping notes around a pitch center that drifts through the scale
+pub struct AmbientEngine<R: Rng + Send + Sync> {
+    settings: AmbientSettings,
+    rng: R,
+    grid: ScaleGrid,
+    min: i32,
+    max: i32,
+    drift_degrees: i32,
+}
+
+impl AmbientEngine<SmallRng> {
+    pub fn new(
+        settings: AmbientSettings,
+        scale: Vec<Letter>,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> AmbientEngine<SmallRng> {
+        AmbientEngine {
+            settings,
+            rng: SmallRng::from_entropy(),
+            grid: ScaleGrid::new(scale),
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+            drift_degrees: 0,
+        }
+    }
+}
+
+impl<R: Rng + SeedableRng + Send + Sync> AmbientEngine<R> {
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed, SeedStream::Ambient);
+    }
+
+    pub fn note_length(&self) -> f32 {
+        self.settings.note_length
+    }
+
+    pub fn max_voices(&self) -> usize {
+        self.settings.max_voices.max(1)
+    }
+
+    pub fn accepts_trigger(&mut self) -> bool {
+        self.rng.gen_bool(self.settings.density.clamp(0.0, 1.0))
+    }
+
+    // Random walk of the pitch center, once per bar
+    pub fn drift(&mut self) {
+        if self.rng.gen_bool(self.settings.drift.clamp(0.0, 1.0)) {
+            let step = if self.rng.gen_bool(0.5) { 1 } else { -1 };
+            self.drift_degrees = (self.drift_degrees + step)
+                .clamp(-AMBIENT_MAX_DRIFT_DEGREES, AMBIENT_MAX_DRIFT_DEGREES);
+        }
+    }
+
+    pub fn shift(&self, note: LetterOctave) -> LetterOctave {
+        if self.drift_degrees == 0 {
+            return note;
+        }
+        let degree = self.grid.to_degree(note.step().round() as i32) + self.drift_degrees;
+        Step(fit_to_range(self.grid.to_step(degree), self.min, self.max) as f32).to_letter_octave()
+    }
+}
@@ -1,44 +1,112 @@
 use pitch_calc::*;
+use serde::{Deserialize, Serialize};
 
-pub const CHROMATIC_SCALE_NOTES: &[Letter] = &[
-    Letter::C,
-    Letter::Csh,
-    Letter::D,
-    Letter::Dsh,
-    Letter::E,
-    Letter::F,
-    Letter::Fsh,
-    Letter::G,
-    Letter::Gsh,
-    Letter::A,
-    Letter::Ash,
-    Letter::B,
-];
-pub const MAJOR_SCALE_NOTES: &[Letter] = &[
-    Letter::C,
-    Letter::D,
-    Letter::E,
-    Letter::F,
-    Letter::G,
-    Letter::A,
-    Letter::B,
-];
-pub const MINOR_SCALE_NOTES: &[Letter] = &[
-    Letter::C,
-    Letter::D,
-    Letter::Eb,
-    Letter::F,
-    Letter::G,
-    Letter::Ab,
-    Letter::Bb,
+use crate::pitch::letter_semitone;
+
+//constants
+// durations are counted in thirty-second notes, a beat is a quarter note
+const THIRTY_SECONDS_PER_BEAT: u32 = 8;
+
+// A built-in scale, its notes checked at compile time to go up without repeating a pitch
+pub struct ScaleDefinition {
+    pub name: &'static str,
+    pub notes: &'static [Letter],
+}
+
+impl ScaleDefinition {
+    const fn new(name: &'static str, notes: &'static [Letter]) -> ScaleDefinition {
+        assert!(!notes.is_empty(), "a scale has notes");
+        let mut i = 1;
+        while i < notes.len() {
+            assert!(
+                letter_semitone(notes[i - 1]) < letter_semitone(notes[i]),
+                "the notes of a scale go up from C, each pitch once"
+            );
+            i += 1;
+        }
+        ScaleDefinition { name, notes }
+    }
+}
+
+pub const SCALES: &[ScaleDefinition] = &[
+    ScaleDefinition::new(
+        "Chromatic",
+        &[
+            Letter::C,
+            Letter::Csh,
+            Letter::D,
+            Letter::Dsh,
+            Letter::E,
+            Letter::F,
+            Letter::Fsh,
+            Letter::G,
+            Letter::Gsh,
+            Letter::A,
+            Letter::Ash,
+            Letter::B,
+        ],
+    ),
+    ScaleDefinition::new(
+        "Major",
+        &[
+            Letter::C,
+            Letter::D,
+            Letter::E,
+            Letter::F,
+            Letter::G,
+            Letter::A,
+            Letter::B,
+        ],
+    ),
+    ScaleDefinition::new(
+        "Minor",
+        &[
+            Letter::C,
+            Letter::D,
+            Letter::Eb,
+            Letter::F,
+            Letter::G,
+            Letter::Ab,
+            Letter::Bb,
+        ],
+    ),
+    ScaleDefinition::new(
+        "Major Pentatonic",
+        &[Letter::C, Letter::D, Letter::E, Letter::G, Letter::A],
+    ),
+    ScaleDefinition::new(
+        "Minor Pentatonic",
+        &[Letter::C, Letter::Eb, Letter::F, Letter::G, Letter::Bb],
+    ),
 ];
-pub const MAJOR_PENTATONIC_SCALE_NOTES: &[Letter] =
-    &[Letter::C, Letter::D, Letter::E, Letter::G, Letter::A];
-pub const MINOR_PENTATONIC_SCALE_NOTES: &[Letter] =
-    &[Letter::C, Letter::Eb, Letter::F, Letter::G, Letter::Bb];
 
-pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
-    let letter_name = match letter_octave.letter() {
+pub fn letter_from_name(name: &str) -> Option<Letter> {
+    let letter = match name.trim() {
+        "C" => Letter::C,
+        "C#" => Letter::Csh,
+        "Db" => Letter::Db,
+        "D" => Letter::D,
+        "D#" => Letter::Dsh,
+        "Eb" => Letter::Eb,
+        "E" => Letter::E,
+        "F" => Letter::F,
+        "F#" => Letter::Fsh,
+        "Gb" => Letter::Gb,
+        "G" => Letter::G,
+        "G#" => Letter::Gsh,
+        "Ab" => Letter::Ab,
+        "A" => Letter::A,
+        "A#" => Letter::Ash,
+        "Bb" => Letter::Bb,
+        "B" => Letter::B,
+        _ => return None,
+    };
+    Some(letter)
+}
+
+// Inverse of letter_from_name, keeping the spelling of the letter
+pub fn letter_name(letter: Letter) -> &'static str {
+    match letter {
         Letter::C => "C",
         Letter::Csh => "C#",
         Letter::Db => "Db",
@@ -56,11 +124,10 @@ pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
         Letter::Ash => "A#",
         Letter::Bb => "Bb",
         Letter::B => "B",
-    };
-    format!("{}{}", letter_name, letter_octave.octave())
+    }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NoteDurationLetter {
     W,
     H,
@@ -68,53 +135,286 @@ pub enum NoteDurationLetter {
     E,
     S,
     T,
+    // merges the previous and the next duration into one note
+    Tie,
+    DottedH,
+    DottedQ,
+    DottedE,
+    DottedS,
+    // the step is silent
+    Rest,
 }
 
-pub const NOTE_DURATION: [f32; 6] = [4.0, 2.0, 1.0, 0.5, 0.25, 0.125];
+impl NoteDurationLetter {
+    // A tie and a rest take no time of their own
+    pub const fn thirty_seconds(self) -> u32 {
+        match self {
+            NoteDurationLetter::W => 32,
+            NoteDurationLetter::H => 16,
+            NoteDurationLetter::Q => 8,
+            NoteDurationLetter::E => 4,
+            NoteDurationLetter::S => 2,
+            NoteDurationLetter::T => 1,
+            NoteDurationLetter::Tie => 0,
+            NoteDurationLetter::DottedH => 24,
+            NoteDurationLetter::DottedQ => 12,
+            NoteDurationLetter::DottedE => 6,
+            NoteDurationLetter::DottedS => 3,
+            NoteDurationLetter::Rest => 0,
+        }
+    }
 
-pub const STRAIGHT_RHYTHM_PATTERN: &[NoteDurationLetter] = &[
-    NoteDurationLetter::Q,
-    NoteDurationLetter::Q,
-    NoteDurationLetter::Q,
-    NoteDurationLetter::Q,
-];
-pub const SYNCOPATED_RHYTHM_PATTERN: &[NoteDurationLetter] = &[
-    NoteDurationLetter::Q,
-    NoteDurationLetter::S,
-    NoteDurationLetter::Q,
-    NoteDurationLetter::S,
-    NoteDurationLetter::Q,
-    NoteDurationLetter::S,
+    pub fn beats(self) -> f32 {
+        self.thirty_seconds() as f32 / THIRTY_SECONDS_PER_BEAT as f32
+    }
+}
+
+pub const NOTE_DURATION_LETTERS: &[(NoteDurationLetter, &str)] = &[
+    (NoteDurationLetter::W, "W"),
+    (NoteDurationLetter::H, "H"),
+    (NoteDurationLetter::DottedH, "H."),
+    (NoteDurationLetter::Q, "Q"),
+    (NoteDurationLetter::DottedQ, "Q."),
+    (NoteDurationLetter::E, "E"),
+    (NoteDurationLetter::DottedE, "E."),
+    (NoteDurationLetter::S, "S"),
+    (NoteDurationLetter::DottedS, "S."),
+    (NoteDurationLetter::T, "T"),
+    (NoteDurationLetter::Tie, "~"),
+    (NoteDurationLetter::Rest, "R"),
 ];
-pub const FAST_RHYTHM_PATTERN: &[NoteDurationLetter] = &[
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
+
+pub fn note_duration_from_symbol(symbol: &str) -> Option<NoteDurationLetter> {
+    NOTE_DURATION_LETTERS
+        .iter()
+        .find(|(_, s)| *s == symbol.trim())
+        .map(|(letter, _)| *letter)
+}
+
+pub fn note_duration_symbol(letter: NoteDurationLetter) -> &'static str {
+    NOTE_DURATION_LETTERS
+        .iter()
+        .find(|(l, _)| *l == letter)
+        .map(|(_, symbol)| *symbol)
+        .unwrap()
+}
+
+// A built-in rhythm: the durations of the notes and the notes on each beat of the bar, the bar
+// having as many beats as entries. Checked at compile time to have a duration for every note of
+// the bar and to fill whole bars.
+pub struct RhythmPatternDefinition {
+    pub name: &'static str,
+    pub durations: &'static [NoteDurationLetter],
+    pub notes_per_beat: &'static [u32],
+}
+
+impl RhythmPatternDefinition {
+    const fn new(
+        name: &'static str,
+        durations: &'static [NoteDurationLetter],
+        notes_per_beat: &'static [u32],
+    ) -> RhythmPatternDefinition {
+        assert!(!notes_per_beat.is_empty(), "a bar has beats");
+        let mut notes = 0;
+        let mut i = 0;
+        while i < notes_per_beat.len() {
+            notes += notes_per_beat[i];
+            i += 1;
+        }
+        assert!(
+            durations.len() == notes as usize,
+            "a duration for every note of the bar"
+        );
+        let mut length = 0;
+        let mut i = 0;
+        while i < durations.len() {
+            length += durations[i].thirty_seconds();
+            i += 1;
+        }
+        let bar = notes_per_beat.len() as u32 * THIRTY_SECONDS_PER_BEAT;
+        assert!(
+            length > 0 && length % bar == 0,
+            "the durations fill whole bars"
+        );
+        RhythmPatternDefinition {
+            name,
+            durations,
+            notes_per_beat,
+        }
+    }
+}
+
+pub const RHYTHM_PATTERNS: &[RhythmPatternDefinition] = &[
+    RhythmPatternDefinition::new(
+        "Straight",
+        &[
+            NoteDurationLetter::Q,
+            NoteDurationLetter::Q,
+            NoteDurationLetter::Q,
+            NoteDurationLetter::Q,
+        ],
+        &[1, 1, 1, 1],
+    ),
+    RhythmPatternDefinition::new(
+        "Syncopated",
+        &[
+            NoteDurationLetter::DottedE,
+            NoteDurationLetter::S,
+            NoteDurationLetter::DottedE,
+            NoteDurationLetter::S,
+            NoteDurationLetter::DottedE,
+            NoteDurationLetter::S,
+        ],
+        &[2, 2, 2],
+    ),
+    RhythmPatternDefinition::new(
+        "Fast",
+        &[
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+        ],
+        &[2, 2, 2, 2],
+    ),
+    RhythmPatternDefinition::new(
+        "Long and Short",
+        &[
+            NoteDurationLetter::H,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::Q,
+        ],
+        &[1, 0, 2, 1],
+    ),
+    RhythmPatternDefinition::new(
+        "Complex",
+        &[
+            NoteDurationLetter::Q,
+            NoteDurationLetter::E,
+            NoteDurationLetter::E,
+            NoteDurationLetter::Q,
+            NoteDurationLetter::S,
+            NoteDurationLetter::S,
+            NoteDurationLetter::E,
+        ],
+        &[1, 2, 1, 3],
+    ),
+    RhythmPatternDefinition::new(
+        "Held",
+        &[
+            NoteDurationLetter::H,
+            NoteDurationLetter::Tie,
+            NoteDurationLetter::Q,
+            NoteDurationLetter::Q,
+        ],
+        &[1, 1, 1, 1],
+    ),
 ];
-pub const LONG_AND_SHORT_RHYTHM_PATTERN: &[NoteDurationLetter] = &[
-    NoteDurationLetter::H,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::H,
+
+// A rhythm as the sequencer plays it, built in, read from the rhythm file or made in the editor.
+// The durations and the notes per beat travel together so one can't be picked without the other.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RhythmPattern {
+    pub name: String,
+    pub durations: Vec<NoteDurationLetter>,
+    // notes on each beat of the bar
+    pub notes_per_beat: Vec<u32>,
+}
+
+impl RhythmPattern {
+    // The bar has a beat for each entry of the notes per beat
+    pub fn beats_per_bar(&self) -> u32 {
+        self.notes_per_beat.len() as u32
+    }
+}
+
+impl From<&RhythmPatternDefinition> for RhythmPattern {
+    fn from(definition: &RhythmPatternDefinition) -> Self {
+        RhythmPattern {
+            name: definition.name.to_string(),
+            durations: definition.durations.to_vec(),
+            notes_per_beat: definition.notes_per_beat.to_vec(),
+        }
+    }
+}
+
+// Micro-timing and velocity offsets for each sixteenth of a 4/4 bar.
+// Timing is a delay expressed as a fraction of a sixteenth note.
+pub struct GrooveTemplate {
+    pub name: &'static str,
+    pub timing: [f32; 16],
+    pub velocity: [i8; 16],
+}
+
+const fn swing_groove(name: &'static str, amount: f32) -> GrooveTemplate {
+    // every second sixteenth of each eighth pair is pushed late
+    let mut timing = [0.0; 16];
+    let mut velocity = [0; 16];
+    let mut i = 1;
+    while i < 16 {
+        timing[i] = amount;
+        velocity[i] = -10;
+        i += 2;
+    }
+    GrooveTemplate {
+        name,
+        timing,
+        velocity,
+    }
+}
+
+pub const GROOVE_TEMPLATES: &[GrooveTemplate] = &[
+    GrooveTemplate {
+        name: "None",
+        timing: [0.0; 16],
+        velocity: [0; 16],
+    },
+    // MPC swing percentages: the off sixteenth lands at N% of the eighth note
+    swing_groove("MPC 54% swing", 0.08),
+    swing_groove("MPC 58% swing", 0.16),
+    swing_groove("MPC 62% swing", 0.24),
+    swing_groove("MPC 66% swing", 0.32),
+    GrooveTemplate {
+        name: "Laid-back 8ths",
+        timing: [
+            0.0, 0.0, 0.3, 0.0, 0.0, 0.0, 0.3, 0.0, 0.0, 0.0, 0.3, 0.0, 0.0, 0.0, 0.3, 0.0,
+        ],
+        velocity: [8, 0, -12, 0, 0, 0, -12, 0, 4, 0, -12, 0, 0, 0, -12, 0],
+    },
+    GrooveTemplate {
+        name: "Accented downbeats",
+        timing: [0.0; 16],
+        velocity: [
+            20, -15, -5, -15, 10, -15, -5, -15, 15, -15, -5, -15, 10, -15, -5, -15,
+        ],
+    },
 ];
-pub const COMPLEX_RHYTHM_PATTERN: &[NoteDurationLetter] = &[
-    NoteDurationLetter::Q,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
-    NoteDurationLetter::Q,
-    NoteDurationLetter::S,
-    NoteDurationLetter::E,
-    NoteDurationLetter::E,
+
+// General MIDI families, each one covers 8 consecutive programs
+pub const GM_CATEGORIES: &[&str] = &[
+    "Piano",
+    "Chromatic Percussion",
+    "Organ",
+    "Guitar",
+    "Bass",
+    "Strings",
+    "Ensemble",
+    "Brass",
+    "Reed",
+    "Pipe",
+    "Synth Lead",
+    "Synth Pad",
+    "Synth Effects",
+    "Ethnic",
+    "Percussive",
+    "Sound Effects",
 ];
-pub const BEAT_PER_BAR_DIVIDE_FOR_FOUR: [u32; 4] = [1, 1, 1, 1];
-pub const BEAT_PER_BAR_DIVIDE_FOR_SIX: [u32; 4] = [2, 2, 2, 0];
-pub const BEAT_PER_BAR_DIVIDE_FOR_SEVEN: [u32; 4] = [1, 2, 1, 3];
-pub const BEAT_PER_BAR_DIVIDE_FOR_EIGTH: [u32; 4] = [2, 2, 2, 2];
+pub const INSTRUMENTS_PER_GM_CATEGORY: usize = 8;
 
 pub const INSTRUMENT_LIST: &[&str] = &[
     "Acoustic Grand Piano",
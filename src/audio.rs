@@ -0,0 +1,441 @@
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use rustysynth::SoundFont;
+
+use crate::device::{open_output, AudioDeviceSettings};
+use crate::drums::DRUM_CHANNEL;
+use crate::drums::{default_sample, load_sample, DrumVoiceSettings, Sample, SamplePlayer};
+use crate::effects::{EffectSettings, LevelMeter, Mixer, TRACK_CHANNELS};
+use crate::layers::LAYER_HATS_CHANNEL;
+use crate::recorder::{recording_path, Recorder};
+use crate::scope::AudioTap;
+use crate::sink::{NoteSink, SinkEvent};
+use crate::soundfont::{load_sound_font, SoundFontPlayer};
+use crate::synth::{SoundParameter, SoundSource, Synth, SynthSettings};
+
+//constants
+// per UI frame
+const METER_FALLOFF: f32 = 0.9;
+
+enum AudioMessage {
+    Event(SinkEvent),
+    SetTracks(Vec<Box<dyn SoundSource>>),
+    UpdateSynth(SynthSettings),
+    UpdateDrums([DrumVoiceSettings; 3]),
+    UpdateEffects(EffectSettings),
+    SetTempo(f32),
+    SetEnabled(bool),
+    StartRecording(mpsc::SyncSender<Vec<f32>>),
+    StopRecording,
+}
+
+// Forwards the sequencer's events to the audio callback
+#[derive(Clone)]
+pub struct AudioSink {
+    sender: mpsc::Sender<AudioMessage>,
+}
+
+impl AudioSink {
+    fn send(&mut self, event: SinkEvent) {
+        // the engine may be gone, the notes are then simply not heard
+        let _ = self.sender.send(AudioMessage::Event(event));
+    }
+}
+
+impl NoteSink for AudioSink {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.send(SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.send(SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8) {
+        self.send(SinkEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        });
+    }
+
+    fn send_program(&mut self, channel: u8, program: u8) {
+        self.send(SinkEvent::ProgramChange { channel, program });
+    }
+
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8) {
+        self.send(SinkEvent::ChannelPressure { channel, pressure });
+    }
+}
+
+// Lives in the audio callback: applies pending messages at the start of each buffer
+struct AudioRenderer {
+    // shared with the streams started after this one, so the sinks outlive a restart
+    receiver: Arc<Mutex<mpsc::Receiver<AudioMessage>>>,
+    buffer_frames: Arc<AtomicUsize>,
+    // its tracks are set along with enabling the audio
+    mixer: Mixer,
+    enabled: bool,
+    // interleaved copies of the rendered buffers go to the recording writer
+    recording: Option<mpsc::SyncSender<Vec<f32>>>,
+    tap: AudioTap,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl AudioRenderer {
+    fn fill(&mut self, data: &mut [f32], channels: usize) {
+        // only contended while a restart drains the queue
+        let Ok(receiver) = self.receiver.try_lock() else {
+            data.fill(0.0);
+            return;
+        };
+        for message in receiver.try_iter() {
+            match message {
+                AudioMessage::Event(event) => {
+                    if self.enabled {
+                        self.mixer.handle_event(event);
+                    }
+                }
+                AudioMessage::SetTracks(tracks) => self.mixer.set_tracks(tracks),
+                AudioMessage::UpdateSynth(settings) => {
+                    self.mixer.update(SoundParameter::Synth(settings))
+                }
+                AudioMessage::UpdateDrums(voices) => {
+                    self.mixer.update(SoundParameter::Drums(voices))
+                }
+                AudioMessage::UpdateEffects(settings) => self.mixer.update_effects(settings),
+                AudioMessage::SetTempo(bpm) => self.mixer.set_tempo(bpm),
+                AudioMessage::SetEnabled(enabled) => self.enabled = enabled,
+                AudioMessage::StartRecording(sender) => self.recording = Some(sender),
+                AudioMessage::StopRecording => self.recording = None,
+            }
+        }
+        drop(receiver);
+        let frames = data.len() / channels;
+        self.buffer_frames.store(frames, Ordering::Relaxed);
+        self.left.clear();
+        self.left.resize(frames, 0.0);
+        self.right.clear();
+        self.right.resize(frames, 0.0);
+        if self.enabled {
+            self.mixer.render(&mut self.left, &mut self.right);
+        }
+        for (frame, (l, r)) in data
+            .chunks_mut(channels)
+            .zip(self.left.iter().zip(self.right.iter()))
+        {
+            match frame {
+                [mono] => *mono = (l + r) / 2.0,
+                [left, right, rest @ ..] => {
+                    *left = *l;
+                    *right = *r;
+                    rest.fill(0.0);
+                }
+                _ => (),
+            }
+        }
+        self.tap.write(&self.left, &self.right);
+        if let Some(recording) = &self.recording {
+            let buffer = self
+                .left
+                .iter()
+                .zip(self.right.iter())
+                .flat_map(|(l, r)| [*l, *r])
+                .collect();
+            // a full queue loses the buffer rather than stall the device
+            if let Err(mpsc::TrySendError::Disconnected(_)) = recording.try_send(buffer) {
+                self.recording = None;
+            }
+        }
+    }
+}
+
+// Output stream on the selected device, playing the notes through the mixer
+pub struct AudioEngine {
+    stream: Option<cpal::Stream>,
+    sender: mpsc::Sender<AudioMessage>,
+    receiver: Arc<Mutex<mpsc::Receiver<AudioMessage>>>,
+    // frames of the last buffer the device asked for
+    buffer_frames: Arc<AtomicUsize>,
+    // the output, for the scope
+    tap: AudioTap,
+    recorder: Option<Recorder>,
+    meter: Option<LevelMeter>,
+    pub sample_rate: u32,
+}
+
+impl AudioEngine {
+    pub fn start(
+        settings: &AudioDeviceSettings,
+        effects: EffectSettings,
+        bpm: f32,
+    ) -> Result<AudioEngine, String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut engine = AudioEngine {
+            stream: None,
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+            buffer_frames: Arc::new(AtomicUsize::new(0)),
+            tap: AudioTap::new(),
+            recorder: None,
+            meter: None,
+            sample_rate: 0,
+        };
+        engine.restart(settings, effects, bpm)?;
+        Ok(engine)
+    }
+
+    // Replaces the stream, the new one starts silent with a fresh mixer and no tracks.
+    // The sinks handed out before keep working.
+    pub fn restart(
+        &mut self,
+        settings: &AudioDeviceSettings,
+        effects: EffectSettings,
+        bpm: f32,
+    ) -> Result<(), String> {
+        // the recording ends with the stream, it is not resampled
+        if let Err(err) = self.stop_recording() {
+            eprintln!("Could not finish the recording: {}", err);
+        }
+        // stop the old callback first, then drop the events meant for its voices
+        self.stream = None;
+        self.meter = None;
+        self.receiver.lock().unwrap().try_iter().for_each(drop);
+        self.buffer_frames.store(0, Ordering::Relaxed);
+        let output = open_output(settings)?;
+        let sample_rate = output.config.sample_rate.0;
+        let channels = output.config.channels as usize;
+        let mixer = Mixer::new(sample_rate, Vec::new(), effects, bpm);
+        let meter = mixer.meter();
+        let mut renderer = AudioRenderer {
+            receiver: self.receiver.clone(),
+            buffer_frames: self.buffer_frames.clone(),
+            mixer,
+            enabled: false,
+            recording: None,
+            tap: self.tap.clone(),
+            left: Vec::new(),
+            right: Vec::new(),
+        };
+        let stream = output
+            .device
+            .build_output_stream(
+                &output.config,
+                move |data: &mut [f32], _| renderer.fill(data, channels),
+                |err| eprintln!("Audio stream error: {}", err),
+                None,
+            )
+            .map_err(|err| err.to_string())?;
+        stream.play().map_err(|err| err.to_string())?;
+        self.stream = Some(stream);
+        self.meter = Some(meter);
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self) -> Result<PathBuf, String> {
+        if !self.is_running() {
+            return Err("The audio is not running".to_string());
+        }
+        self.stop_recording()?;
+        let (recorder, sender) = Recorder::start(recording_path(), self.sample_rate)?;
+        let path = recorder.path.clone();
+        let _ = self.sender.send(AudioMessage::StartRecording(sender));
+        self.recorder = Some(recorder);
+        Ok(path)
+    }
+
+    // Blocks until the file is written, returns None when nothing was recording
+    pub fn stop_recording(&mut self) -> Result<Option<PathBuf>, String> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(None);
+        };
+        let _ = self.sender.send(AudioMessage::StopRecording);
+        recorder.finish().map(Some)
+    }
+
+    pub fn recording_elapsed(&self) -> Option<std::time::Duration> {
+        self.recorder.as_ref().map(|recorder| recorder.elapsed())
+    }
+
+    // Master peak and gain reduction since the last call
+    pub fn take_meter(&self) -> (f32, f32) {
+        self.meter.as_ref().map_or((0.0, 0.0), |meter| meter.take())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // Frames of one buffer and the latency they add in milliseconds, once the device asked for one
+    pub fn latency(&self) -> Option<(usize, f32)> {
+        let frames = self.buffer_frames.load(Ordering::Relaxed);
+        (self.is_running() && frames > 0)
+            .then(|| (frames, frames as f32 * 1000.0 / self.sample_rate as f32))
+    }
+
+    pub fn tap(&self) -> &AudioTap {
+        &self.tap
+    }
+
+    pub fn sink(&self) -> AudioSink {
+        AudioSink {
+            sender: self.sender.clone(),
+        }
+    }
+
+    pub fn set_tracks(&self, tracks: Vec<Box<dyn SoundSource>>) {
+        let _ = self.sender.send(AudioMessage::SetTracks(tracks));
+    }
+
+    pub fn update_effects(&self, settings: EffectSettings) {
+        let _ = self.sender.send(AudioMessage::UpdateEffects(settings));
+    }
+
+    pub fn set_tempo(&self, bpm: f32) {
+        let _ = self.sender.send(AudioMessage::SetTempo(bpm));
+    }
+
+    // applied in place, notes keep ringing
+    pub fn update_synth(&self, settings: SynthSettings) {
+        let _ = self.sender.send(AudioMessage::UpdateSynth(settings));
+    }
+
+    pub fn update_drums(&self, voices: [DrumVoiceSettings; 3]) {
+        let _ = self.sender.send(AudioMessage::UpdateDrums(voices));
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        let _ = self.sender.send(AudioMessage::SetEnabled(enabled));
+    }
+}
+
+// State of the "Sound" window
+pub struct SoundSettings {
+    pub enabled: bool,
+    // play the SoundFont once loaded rather than the synth
+    pub use_sound_font: bool,
+    pub synth: SynthSettings,
+    pub effects: EffectSettings,
+    pub sound_font_path: String,
+    pub sound_font: Option<Arc<SoundFont>>,
+    // falling meter display of the master bus
+    pub meter_peak: f32,
+    pub meter_reduction: f32,
+    pub drum_sample_paths: [String; 3],
+    // the built-in sound of the voice until a sample is loaded
+    pub drum_samples: [Option<Sample>; 3],
+    pub status: Option<Result<String, String>>,
+}
+
+impl SoundSettings {
+    pub fn new(synth: SynthSettings, effects: EffectSettings) -> SoundSettings {
+        SoundSettings {
+            enabled: false,
+            use_sound_font: true,
+            synth,
+            effects,
+            sound_font_path: String::new(),
+            sound_font: None,
+            meter_peak: 0.0,
+            meter_reduction: 0.0,
+            drum_sample_paths: Default::default(),
+            drum_samples: Default::default(),
+            status: None,
+        }
+    }
+
+    pub fn load_sound_font(&mut self) {
+        let path = self.sound_font_path.trim().to_string();
+        match load_sound_font(path.as_ref()) {
+            Ok(sound_font) => {
+                self.sound_font = Some(sound_font);
+                self.status = Some(Ok(format!("Loaded {}", path)));
+            }
+            Err(err) => self.status = Some(Err(err)),
+        }
+    }
+
+    // Jumps up to new peaks and falls back slowly
+    pub fn update_meter(&mut self, (peak, reduction): (f32, f32)) {
+        self.meter_peak = peak.max(self.meter_peak * METER_FALLOFF);
+        self.meter_reduction = reduction.max(self.meter_reduction * METER_FALLOFF);
+    }
+
+    pub fn load_drum_sample(&mut self, voice: usize) {
+        let path = self.drum_sample_paths[voice].trim().to_string();
+        match load_sample(path.as_ref()) {
+            Ok(sample) => {
+                self.drum_samples[voice] = Some(sample);
+                self.status = Some(Ok(format!("Loaded {}", path)));
+            }
+            Err(err) => self.status = Some(Err(err)),
+        }
+    }
+
+    pub fn build_tracks(
+        &self,
+        sample_rate: u32,
+        drums: [DrumVoiceSettings; 3],
+    ) -> Vec<Box<dyn SoundSource>> {
+        TRACK_CHANNELS
+            .iter()
+            .map(|channel| match *channel {
+                DRUM_CHANNEL | LAYER_HATS_CHANNEL => self.build_drums(sample_rate, drums),
+                _ => self.build_source(sample_rate),
+            })
+            .collect()
+    }
+
+    pub fn build_mixer(&self, sample_rate: u32, bpm: f32, drums: [DrumVoiceSettings; 3]) -> Mixer {
+        Mixer::new(
+            sample_rate,
+            self.build_tracks(sample_rate, drums),
+            self.effects,
+            bpm,
+        )
+    }
+
+    fn build_drums(
+        &self,
+        sample_rate: u32,
+        voices: [DrumVoiceSettings; 3],
+    ) -> Box<dyn SoundSource> {
+        let samples = std::array::from_fn(|voice| match &self.drum_samples[voice] {
+            Some(sample) => sample.clone(),
+            None => default_sample(voice, sample_rate),
+        });
+        Box::new(SamplePlayer::new(samples, voices, sample_rate))
+    }
+
+    // The loaded SoundFont if it is selected, the internal synth otherwise
+    fn build_source(&self, sample_rate: u32) -> Box<dyn SoundSource> {
+        let player = self
+            .sound_font
+            .as_ref()
+            .filter(|_| self.use_sound_font)
+            .map(|sound_font| SoundFontPlayer::new(sound_font, sample_rate));
+        match player {
+            Some(Ok(player)) => Box::new(player),
+            Some(Err(err)) => {
+                eprintln!("Falling back to the internal synth: {}", err);
+                Box::new(Synth::new(sample_rate, self.synth))
+            }
+            None => Box::new(Synth::new(sample_rate, self.synth)),
+        }
+    }
+}
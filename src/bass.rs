@@ -0,0 +1,67 @@
+use std::fmt::Display;
+
+//constants
+pub const BASS_CHANNEL: u8 = 3;
+// share of the time to the next bass note it sounds for
+const BASS_NOTE_LENGTH: f32 = 0.9;
+const FIFTH: i32 = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BassPattern {
+    // the root on the downbeat, held through the bar
+    Root,
+    // the root on the downbeat, the fifth halfway through the bar
+    RootFifth,
+}
+
+pub const BASS_PATTERNS: [BassPattern; 2] = [BassPattern::Root, BassPattern::RootFifth];
+
+impl Display for BassPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            BassPattern::Root => write!(f, "Root"),
+            BassPattern::RootFifth => write!(f, "Root and fifth"),
+        }
+    }
+}
+
+// A bass voice on its own channel playing the root of the harmony below the melody
+#[derive(Clone, Copy, PartialEq)]
+pub struct BassSettings {
+    pub enabled: bool,
+    pub pattern: BassPattern,
+    // below the lowest note of the melody range
+    pub octaves_below: u32,
+    pub instrument: u8,
+}
+
+impl BassSettings {
+    // The note starting on a beat of the bar and the beats it sounds for, None between notes.
+    // The root is a pitch class, 0 being C.
+    pub fn note_at(
+        &self,
+        beat: u64,
+        beats_per_bar: u64,
+        root: usize,
+        lowest_melody_note: i32,
+    ) -> Option<(u8, f32)> {
+        if !self.enabled {
+            return None;
+        }
+        let fifth_beat = match self.pattern {
+            BassPattern::RootFifth if beats_per_bar > 1 => Some(beats_per_bar / 2),
+            _ => None,
+        };
+        let (interval, beats) = if beat == 0 {
+            (0, fifth_beat.unwrap_or(beats_per_bar))
+        } else if Some(beat) == fifth_beat {
+            (FIFTH, beats_per_bar - beat)
+        } else {
+            return None;
+        };
+        // the first root at or above the octaves below the melody
+        let low = lowest_melody_note - 12 * self.octaves_below as i32;
+        let note = low + (root as i32 - low).rem_euclid(12) + interval;
+        Some((note.clamp(0, 127) as u8, beats as f32 * BASS_NOTE_LENGTH))
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+//constants
+const FIFTH: i32 = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CadenceTarget {
+    Root,
+    Fifth,
+    // whichever of the two is closer to the note produced
+    RootOrFifth,
+}
+
+pub const CADENCE_TARGETS: [CadenceTarget; 3] = [
+    CadenceTarget::Root,
+    CadenceTarget::Fifth,
+    CadenceTarget::RootOrFifth,
+];
+
+impl Display for CadenceTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            CadenceTarget::Root => write!(f, "Root"),
+            CadenceTarget::Fifth => write!(f, "Fifth"),
+            CadenceTarget::RootOrFifth => write!(f, "Root or fifth"),
+        }
+    }
+}
+
+// The last note of every phrase of a few bars resolves to the root or the fifth,
+// whatever the producer played for it
+#[derive(Clone, Copy, PartialEq)]
+pub struct CadenceSettings {
+    pub enabled: bool,
+    pub phrase_bars: u32,
+    pub target: CadenceTarget,
+}
+
+impl CadenceSettings {
+    // The resolving note closest to the one produced, inside the range where one fits.
+    // The root is a pitch class, 0 being C.
+    pub fn resolve(&self, note: i32, root: usize, min: i32, max: i32) -> i32 {
+        let pitch_classes: &[i32] = match self.target {
+            CadenceTarget::Root => &[0],
+            CadenceTarget::Fifth => &[FIFTH],
+            CadenceTarget::RootOrFifth => &[0, FIFTH],
+        };
+        let candidates = pitch_classes.iter().flat_map(|interval| {
+            // the resolving note of each octave around the one produced
+            let pitch_class = (root as i32 + interval).rem_euclid(12);
+            let below = note - (note - pitch_class).rem_euclid(12);
+            [below, below + 12]
+        });
+        let in_range = candidates
+            .clone()
+            .filter(|candidate| (min..=max).contains(candidate))
+            .min_by_key(|candidate| (candidate - note).abs());
+        in_range
+            .or_else(|| candidates.min_by_key(|candidate| (candidate - note).abs()))
+            .unwrap_or(note)
+    }
+}
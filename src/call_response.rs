@@ -0,0 +1,123 @@
+use pitch_calc::*;
+use std::fmt::Display;
+
+use crate::pitch::{fit_to_range, ScaleGrid};
+
+//constants
+const RESPONSE_TRANSPOSITION_DEGREES: i32 = 2;
+
+// How the answering voice transforms the call
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResponseTransform {
+    Transpose,
+    Inversion,
+    Retrograde,
+}
+
+pub const RESPONSE_TRANSFORMS: [ResponseTransform; 3] = [
+    ResponseTransform::Transpose,
+    ResponseTransform::Inversion,
+    ResponseTransform::Retrograde,
+];
+
+impl Display for ResponseTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ResponseTransform::Transpose => write!(f, "Transpose"),
+            ResponseTransform::Inversion => write!(f, "Inversion"),
+            ResponseTransform::Retrograde => write!(f, "Retrograde"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct CallResponseSettings {
+    pub enabled: bool,
+    pub transform: ResponseTransform,
+    // program of the answering voice
+    pub instrument: u8,
+}
+
+// Voice A plays a bar, voice B answers the next bar with a transformed copy of it
+pub struct CallResponse {
+    settings: CallResponseSettings,
+    grid: ScaleGrid,
+    min: i32,
+    max: i32,
+    // notes of the current call, in scale degrees
+    call: Vec<i32>,
+    response: Vec<i32>,
+    responding: bool,
+    position: usize,
+}
+
+impl CallResponse {
+    pub fn new(
+        settings: CallResponseSettings,
+        scale: Vec<Letter>,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> CallResponse {
+        CallResponse {
+            settings,
+            grid: ScaleGrid::new(scale),
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+            call: Vec::new(),
+            response: Vec::new(),
+            responding: false,
+            position: 0,
+        }
+    }
+
+    pub fn instrument(&self) -> u8 {
+        self.settings.instrument
+    }
+
+    pub fn is_responding(&self) -> bool {
+        self.responding
+    }
+
+    // Even bars are calls, odd bars answer the previous call
+    pub fn start_bar(&mut self, bar: u64) {
+        self.responding = bar % 2 == 1;
+        self.position = 0;
+        if self.responding {
+            self.response = self.transform(&self.call);
+        } else {
+            self.call.clear();
+        }
+    }
+
+    // Note to play for this trigger, None if the response has nothing left to answer
+    pub fn next_note(&mut self, note: LetterOctave) -> Option<LetterOctave> {
+        if !self.responding {
+            self.call
+                .push(self.grid.to_degree(note.step().round() as i32));
+            return Some(note);
+        }
+
+        let degree = *self.response.get(self.position)?;
+        self.position += 1;
+        Some(
+            Step(fit_to_range(self.grid.to_step(degree), self.min, self.max) as f32)
+                .to_letter_octave(),
+        )
+    }
+
+    fn transform(&self, call: &[i32]) -> Vec<i32> {
+        match self.settings.transform {
+            ResponseTransform::Transpose => call
+                .iter()
+                .map(|d| d + RESPONSE_TRANSPOSITION_DEGREES)
+                .collect(),
+            ResponseTransform::Inversion => {
+                let Some(&axis) = call.first() else {
+                    return Vec::new();
+                };
+                call.iter().map(|d| 2 * axis - d).collect()
+            }
+            ResponseTransform::Retrograde => call.iter().rev().copied().collect(),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+// Values a running pitch or trigger chain takes in place, without being rebuilt.
+// Modules apply the ones they own and pass everything on to their input.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChainParameter {
+    TriggerProbability(f64),
+    RestProbability(f64),
+    OctaveJumpProbability(f64),
+    // rhythm steps the sample and hold keeps a pitch for
+    HoldSteps(u32),
+    // quantizer pitch classes, bit n set for pitch class n (0 = C)
+    Scale(u16),
+    // ticks per beat, follows the tempo
+    BeatLength(u32),
+    // semitones the key moved by from the configured one, sent before the moved scale
+    KeyOffset(i32),
+    // steps the start of a cyclic pitch producer or of the rhythm is shifted by
+    Rotation(u32),
+    // a step of the rhythm went by, played or rested, step sequencers move to their next step
+    AdvanceStep,
+    // the rhythm divider started a beat of the bar, 0 for the downbeat
+    Beat(u32),
+    // a bar started with repeatable randomness, the random modules start over from the seed
+    Seed(u64),
+}
@@ -0,0 +1,112 @@
+// One knob driving several randomness parameters at once
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MacroCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+pub const MACRO_CURVES: &[(MacroCurve, &str)] = &[
+    (MacroCurve::Linear, "Linear"),
+    (MacroCurve::Exponential, "Exponential"),
+    (MacroCurve::Logarithmic, "Logarithmic"),
+];
+
+impl MacroCurve {
+    // maps the macro amount in [0, 1] to [0, 1]
+    pub fn apply(&self, amount: f32) -> f32 {
+        match *self {
+            MacroCurve::Linear => amount,
+            MacroCurve::Exponential => amount * amount,
+            MacroCurve::Logarithmic => amount.sqrt(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        MACRO_CURVES.iter().find(|(c, _)| c == self).unwrap().1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChaosTarget {
+    TriggerProbability,
+    OctaveJumps,
+    VelocityJitter,
+    // the largest step of the evolution
+    MutationRate,
+}
+
+impl ChaosTarget {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ChaosTarget::TriggerProbability => "Trigger probability",
+            ChaosTarget::OctaveJumps => "Octave jumps",
+            ChaosTarget::VelocityJitter => "Velocity jitter",
+            ChaosTarget::MutationRate => "Mutation rate",
+        }
+    }
+
+    // address of the registry parameter driven by this target, None for the mutation rate
+    // which belongs to the evolution rather than the sequencer model
+    pub fn address(&self) -> Option<&'static str> {
+        match *self {
+            ChaosTarget::TriggerProbability => Some("/rhythm/trigger_probability"),
+            ChaosTarget::OctaveJumps => Some("/pitch/octave_jumps"),
+            ChaosTarget::VelocityJitter => Some("/velocity/jitter"),
+            ChaosTarget::MutationRate => None,
+        }
+    }
+}
+
+pub struct ChaosMapping {
+    pub target: ChaosTarget,
+    pub curve: MacroCurve,
+    // how much of the target's range the macro covers at full chaos
+    pub depth: f32,
+}
+
+pub struct ChaosMacro {
+    pub amount: f32,
+    pub mappings: Vec<ChaosMapping>,
+}
+
+impl ChaosMacro {
+    pub fn new() -> ChaosMacro {
+        ChaosMacro {
+            amount: 0.0,
+            mappings: vec![
+                ChaosMapping {
+                    target: ChaosTarget::TriggerProbability,
+                    curve: MacroCurve::Exponential,
+                    depth: 0.5,
+                },
+                ChaosMapping {
+                    target: ChaosTarget::OctaveJumps,
+                    curve: MacroCurve::Linear,
+                    depth: 0.5,
+                },
+                ChaosMapping {
+                    target: ChaosTarget::VelocityJitter,
+                    curve: MacroCurve::Logarithmic,
+                    depth: 1.0,
+                },
+                ChaosMapping {
+                    target: ChaosTarget::MutationRate,
+                    curve: MacroCurve::Exponential,
+                    depth: 0.3,
+                },
+            ],
+        }
+    }
+
+    // Normalized parameter value the macro currently asks for on this mapping.
+    // More chaos means fewer triggers, so trigger probability goes down.
+    pub fn value(&self, mapping: &ChaosMapping) -> f32 {
+        let randomness = (mapping.curve.apply(self.amount) * mapping.depth).clamp(0.0, 1.0);
+        match mapping.target {
+            ChaosTarget::TriggerProbability => 1.0 - randomness,
+            _ => randomness,
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use pitch_calc::*;
+
+use crate::pitch::letter_from_semitone;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Dominant7,
+    Major7,
+    Minor7,
+}
+
+// share of the total weight lost for each chord tone absent from a histogram
+const MISSING_TONE_PENALTY: f32 = 0.1;
+
+// Sevenths first, so that a full seventh chord is not read as its triad
+const CHORD_TEMPLATES: &[(ChordQuality, &[i32])] = &[
+    (ChordQuality::Dominant7, &[0, 4, 7, 10]),
+    (ChordQuality::Major7, &[0, 4, 7, 11]),
+    (ChordQuality::Minor7, &[0, 3, 7, 10]),
+    (ChordQuality::Major, &[0, 4, 7]),
+    (ChordQuality::Minor, &[0, 3, 7]),
+];
+
+impl ChordQuality {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+        }
+    }
+
+    fn intervals(&self) -> &'static [i32] {
+        CHORD_TEMPLATES
+            .iter()
+            .find(|(quality, _)| quality == self)
+            .unwrap()
+            .1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Chord {
+    // pitch class of the root, 0 being C
+    pub root: usize,
+    pub quality: ChordQuality,
+}
+
+impl Chord {
+    pub fn tones(&self) -> Vec<Letter> {
+        self.quality
+            .intervals()
+            .iter()
+            .map(|interval| letter_from_semitone(self.root as i32 + interval))
+            .collect()
+    }
+}
+
+fn pitch_class_mask(pitch_classes: impl Iterator<Item = i32>) -> u16 {
+    pitch_classes.fold(0, |mask, pc| mask | 1 << pc.rem_euclid(12))
+}
+
+// Names the held notes if their pitch classes form exactly one of the known chords.
+// Roots are tried from the lowest note up, so inversions resolve to the bass when ambiguous.
+pub fn recognize_chord(notes: &[u8]) -> Option<Chord> {
+    let mut notes = notes.to_vec();
+    notes.sort();
+    let held = pitch_class_mask(notes.iter().map(|note| *note as i32));
+    for note in notes {
+        let root = (note % 12) as i32;
+        for (quality, intervals) in CHORD_TEMPLATES {
+            if pitch_class_mask(intervals.iter().map(|interval| root + interval)) == held {
+                return Some(Chord {
+                    root: root as usize,
+                    quality: *quality,
+                });
+            }
+        }
+    }
+    None
+}
+
+// Best fitting chord for a weighted pitch-class histogram: weight on chord tones counts for it,
+// weight elsewhere against it, and each missing chord tone costs a little so triads win over
+// sevenths without their seventh
+pub fn best_chord(histogram: &[f32; 12]) -> Option<Chord> {
+    let total: f32 = histogram.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut best = None;
+    let mut best_score = f32::MIN;
+    for root in 0..12 {
+        for (quality, intervals) in CHORD_TEMPLATES {
+            let tones = pitch_class_mask(intervals.iter().map(|interval| root + interval));
+            let mut score = 0.0;
+            for (pc, weight) in histogram.iter().enumerate() {
+                let is_tone = tones & 1 << pc != 0;
+                score += if is_tone { *weight } else { -*weight };
+                if is_tone && *weight <= 0.0 {
+                    score -= total * MISSING_TONE_PENALTY;
+                }
+            }
+            if score > best_score {
+                best_score = score;
+                best = Some(Chord {
+                    root: root as usize,
+                    quality: *quality,
+                });
+            }
+        }
+    }
+    best
+}
@@ -0,0 +1,119 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// The sequencer thread never reads wall-clock time directly, it goes through a Clock.
+// This lets the thread be driven tick by tick with a ManualClock instead of the real timer.
+pub trait Clock: Send {
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Deterministic clock: time only moves when the sequencer sleeps or when advanced explicitly
+pub struct ManualClock {
+    elapsed: Duration,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock {
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+// When each tick of the sequencer is due, at the tempo and resolution it runs at.
+// Tick times are counted from the last tempo change rather than added up one by one,
+// so the rounding of each tick length never accumulates into drift.
+pub struct TickSchedule {
+    // time and tick the current tempo started at
+    anchor: (Duration, u64),
+    ticks: u64,
+    // seconds
+    tick_length: f64,
+}
+
+impl TickSchedule {
+    pub fn new(bpm: f32, resolution: u32) -> TickSchedule {
+        TickSchedule {
+            anchor: (Duration::ZERO, 0),
+            ticks: 0,
+            tick_length: tick_length(bpm, resolution),
+        }
+    }
+
+    // Ticks run since the start
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn tick_length(&self) -> Duration {
+        Duration::from_secs_f64(self.tick_length)
+    }
+
+    pub fn next_tick(&self) -> Duration {
+        self.anchor.0
+            + Duration::from_secs_f64((self.ticks - self.anchor.1) as f64 * self.tick_length)
+    }
+
+    // Counts the next tick as run when it is due by now. After a stall the missed ticks are
+    // skipped rather than played in a burst.
+    pub fn take_due(&mut self, now: Duration, max_lateness: Duration) -> bool {
+        let next_tick = self.next_tick();
+        if now < next_tick {
+            return false;
+        }
+        if now - next_tick > max_lateness {
+            self.anchor = (now, self.ticks);
+        }
+        self.ticks += 1;
+        true
+    }
+
+    // From the next tick on
+    pub fn set_tempo(&mut self, bpm: f32, resolution: u32) {
+        self.anchor = (self.next_tick(), self.ticks);
+        self.tick_length = tick_length(bpm, resolution);
+    }
+}
+
+pub fn tick_length(bpm: f32, resolution: u32) -> f64 {
+    60.0 / (bpm.max(1.0) as f64 * resolution.max(1) as f64)
+}
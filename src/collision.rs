@@ -0,0 +1,68 @@
+use std::fmt::Display;
+
+//constants
+// farthest a note is moved looking for a free pitch of the scale
+const MAX_SHIFT: u8 = 12;
+
+// What happens to a note whose pitch another track already started on the same tick
+#[derive(Clone, Copy, PartialEq)]
+pub enum CollisionAvoidance {
+    Off,
+    // to the next free degree of the scale up, down at the top of the range
+    Shift,
+    Skip,
+}
+
+pub const COLLISION_AVOIDANCES: [CollisionAvoidance; 3] = [
+    CollisionAvoidance::Off,
+    CollisionAvoidance::Shift,
+    CollisionAvoidance::Skip,
+];
+
+impl Display for CollisionAvoidance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            CollisionAvoidance::Off => write!(f, "Off"),
+            CollisionAvoidance::Shift => write!(f, "Shift a scale degree"),
+            CollisionAvoidance::Skip => write!(f, "Skip the note"),
+        }
+    }
+}
+
+// The pitches the tracks started on the current tick, the first track to take a pitch keeps it
+#[derive(Default)]
+pub struct TickNotes {
+    // bit n for MIDI note n
+    started: u128,
+}
+
+impl TickNotes {
+    pub fn clear(&mut self) {
+        self.started = 0;
+    }
+
+    fn is_taken(&self, note: u8) -> bool {
+        self.started & (1 << note.min(127)) != 0
+    }
+
+    // The note to play, moved or None when its pitch was taken. The scale has bit n set
+    // for pitch class n, an empty one lets every pitch through.
+    pub fn claim(&mut self, note: u8, avoidance: CollisionAvoidance, scale: u16) -> Option<u8> {
+        let note = note.min(127);
+        let note = match avoidance {
+            CollisionAvoidance::Off => note,
+            _ if !self.is_taken(note) => note,
+            CollisionAvoidance::Skip => return None,
+            CollisionAvoidance::Shift => self.free_degree(note, scale)?,
+        };
+        self.started |= 1 << note;
+        Some(note)
+    }
+
+    fn free_degree(&self, note: u8, scale: u16) -> Option<u8> {
+        let is_free = |n: &u8| (scale == 0 || scale & (1 << (n % 12)) != 0) && !self.is_taken(*n);
+        (note + 1..=note.saturating_add(MAX_SHIFT).min(127))
+            .find(is_free)
+            .or_else(|| (note.saturating_sub(MAX_SHIFT)..note).rev().find(is_free))
+    }
+}
@@ -0,0 +1,70 @@
+use core::time::Duration;
+use std::collections::VecDeque;
+
+//constants
+// the density is measured over the notes of the last bar of four beats
+const WINDOW_BEATS: f32 = 4.0;
+// notes per beat the velocities are left alone at, eighth notes
+const REFERENCE_NOTES_PER_BEAT: f32 = 2.0;
+// the compensation stops at four times the reference density, and a quarter of it
+const MAX_DOUBLINGS: f32 = 2.0;
+
+// Softer notes as the density rises and louder ones as it falls, so performing the density
+// keeps the loudness steady
+#[derive(Clone, Copy, PartialEq)]
+pub struct DensityCompensation {
+    pub enabled: bool,
+    // velocity taken off at each doubling of the density, given back at each halving
+    pub amount: u8,
+}
+
+impl DensityCompensation {
+    // Velocity added to a note played at the density, none before the density is known
+    pub fn offset(&self, notes_per_beat: Option<f32>) -> i32 {
+        match notes_per_beat {
+            Some(density) if self.enabled && density > 0.0 => {
+                let doublings = (density / REFERENCE_NOTES_PER_BEAT)
+                    .log2()
+                    .clamp(-MAX_DOUBLINGS, MAX_DOUBLINGS);
+                (-doublings * self.amount as f32).round() as i32
+            }
+            _ => 0,
+        }
+    }
+}
+
+// Rolling estimate of the notes per beat of the main voice, from the onsets of the last bar
+pub struct DensityEstimator {
+    onsets: VecDeque<Duration>,
+}
+
+impl DensityEstimator {
+    pub fn new() -> DensityEstimator {
+        DensityEstimator {
+            onsets: VecDeque::new(),
+        }
+    }
+
+    // Forgets the onsets, after a stop the first notes would look sparse
+    pub fn clear(&mut self) {
+        self.onsets.clear();
+    }
+
+    // Adds an onset and returns the density of the notes in the window ending with it, from
+    // the time between the first and the last one. It takes two notes to know.
+    pub fn add(&mut self, now: Duration, beat: Duration) -> Option<f32> {
+        let window = beat.mul_f32(WINDOW_BEATS);
+        while let Some(first) = self.onsets.front() {
+            if now.saturating_sub(*first) <= window {
+                break;
+            }
+            self.onsets.pop_front();
+        }
+        self.onsets.push_back(now);
+        let span = now.saturating_sub(*self.onsets.front()?);
+        if self.onsets.len() < 2 || span.is_zero() {
+            return None;
+        }
+        Some((self.onsets.len() - 1) as f32 * beat.as_secs_f32() / span.as_secs_f32())
+    }
+}
@@ -0,0 +1,135 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+
+//constants
+// rates offered when the device supports them, the device default is always offered
+const COMMON_SAMPLE_RATES: [u32; 4] = [44_100, 48_000, 88_200, 96_000];
+
+// Output selection for the internal audio, None keeps the system default
+#[derive(Clone, PartialEq, Default)]
+pub struct AudioDeviceSettings {
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub buffer_size: Option<u32>,
+}
+
+pub struct OutputDevice {
+    pub device: cpal::Device,
+    pub config: cpal::StreamConfig,
+}
+
+pub fn output_device_names() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(err) => {
+            eprintln!("Could not list the audio output devices: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+// Sample rates the device can play as f32, sorted
+pub fn supported_sample_rates(device_name: Option<&str>) -> Vec<u32> {
+    let Ok(device) = find_device(device_name) else {
+        return Vec::new();
+    };
+    let Ok(configs) = device.supported_output_configs() else {
+        return Vec::new();
+    };
+    let ranges: Vec<_> = configs
+        .filter(|config| config.sample_format() == cpal::SampleFormat::F32)
+        .map(|config| config.min_sample_rate().0..=config.max_sample_rate().0)
+        .collect();
+    let mut rates: Vec<u32> = COMMON_SAMPLE_RATES
+        .into_iter()
+        .filter(|rate| ranges.iter().any(|range| range.contains(rate)))
+        .collect();
+    if let Ok(config) = device.default_output_config() {
+        rates.push(config.sample_rate().0);
+    }
+    rates.sort_unstable();
+    rates.dedup();
+    rates
+}
+
+// Resolves the settings to a device and a stream configuration it supports
+pub fn open_output(settings: &AudioDeviceSettings) -> Result<OutputDevice, String> {
+    let device = find_device(settings.device.as_deref())?;
+    let default_config = device
+        .default_output_config()
+        .map_err(|err| err.to_string())?;
+    let supported = match settings.sample_rate {
+        Some(rate) if rate != default_config.sample_rate().0 => device
+            .supported_output_configs()
+            .map_err(|err| err.to_string())?
+            .filter(|config| config.sample_format() == cpal::SampleFormat::F32)
+            .find(|config| {
+                (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&rate)
+            })
+            .ok_or(format!("{} Hz is not supported by the device", rate))?
+            .with_sample_rate(cpal::SampleRate(rate)),
+        _ => default_config,
+    };
+    let buffer_size = match (settings.buffer_size, supported.buffer_size()) {
+        (Some(frames), cpal::SupportedBufferSize::Range { min, max }) => {
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max))
+        }
+        (Some(frames), cpal::SupportedBufferSize::Unknown) => cpal::BufferSize::Fixed(frames),
+        (None, _) => cpal::BufferSize::Default,
+    };
+    let mut config = supported.config();
+    config.buffer_size = buffer_size;
+    Ok(OutputDevice { device, config })
+}
+
+fn find_device(device_name: Option<&str>) -> Result<cpal::Device, String> {
+    let host = cpal::default_host();
+    match device_name {
+        Some(name) => host
+            .output_devices()
+            .map_err(|err| err.to_string())?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or(format!("Audio device {} not found", name)),
+        None => host
+            .default_output_device()
+            .ok_or("No audio output device available".to_string()),
+    }
+}
+
+// State of the "Audio device" window
+pub struct AudioDevicePanel {
+    pub settings: AudioDeviceSettings,
+    pub device_names: Vec<String>,
+    // of the selected device
+    pub sample_rates: Vec<u32>,
+    pub status: Option<Result<String, String>>,
+}
+
+impl AudioDevicePanel {
+    pub fn new() -> AudioDevicePanel {
+        let mut panel = AudioDevicePanel {
+            settings: AudioDeviceSettings::default(),
+            device_names: Vec::new(),
+            sample_rates: Vec::new(),
+            status: None,
+        };
+        panel.refresh();
+        panel
+    }
+
+    // Lists the devices again, a selection that went away falls back to the default
+    pub fn refresh(&mut self) {
+        self.device_names = output_device_names();
+        if let Some(device) = &self.settings.device {
+            if !self.device_names.contains(device) {
+                self.settings.device = None;
+            }
+        }
+        self.sample_rates = supported_sample_rates(self.settings.device.as_deref());
+        if let Some(rate) = self.settings.sample_rate {
+            if !self.sample_rates.contains(&rate) {
+                self.settings.sample_rate = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{f32::consts::TAU, path::Path, sync::Arc};
+
+use rand::prelude::*;
+
+use crate::chain::ChainParameter;
+use crate::sink::SinkEvent;
+use crate::synth::{SoundParameter, SoundSource};
+use crate::trigger::{StepTrigger, Trigger, TriggerModule};
+
+//constants
+// General MIDI percussion channel (channel 10)
+pub const DRUM_CHANNEL: u8 = 9;
+// kick, snare and closed hi-hat in the General MIDI percussion map
+pub const DRUM_NOTES: [u8; 3] = [36, 38, 42];
+const MAX_DRUM_VOICES: usize = 16;
+const DEFAULT_SAMPLE_SECONDS: f32 = 0.4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct DrumVoiceSettings {
+    // one bit per sixteenth of the bar, bit 0 being the downbeat
+    pub steps: u16,
+    pub gain: f32,
+    // semitones
+    pub pitch: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct DrumSettings {
+    pub enabled: bool,
+    pub voices: [DrumVoiceSettings; 3],
+}
+
+// One step trigger per drum voice, started on a bar line so the steps line up with the bar
+pub struct DrumMachine {
+    triggers: Vec<Box<dyn TriggerModule>>,
+}
+
+impl DrumMachine {
+    pub fn new(settings: &DrumSettings, beat_length: u32) -> DrumMachine {
+        DrumMachine {
+            triggers: settings
+                .voices
+                .iter()
+                .map(|voice| {
+                    Box::new(StepTrigger::new(voice.steps, beat_length)) as Box<dyn TriggerModule>
+                })
+                .collect(),
+        }
+    }
+
+    // Notes to start on this tick
+    pub fn tick(&mut self) -> Vec<u8> {
+        self.triggers
+            .iter_mut()
+            .zip(DRUM_NOTES)
+            .filter_map(|(trigger, note)| (trigger.tick().fired == Trigger::On).then_some(note))
+            .collect()
+    }
+
+    pub fn update(&mut self, parameter: ChainParameter) {
+        for trigger in self.triggers.iter_mut() {
+            trigger.update(parameter);
+        }
+    }
+}
+
+// Mono one-shot
+#[derive(Clone)]
+pub struct Sample {
+    data: Arc<Vec<f32>>,
+    sample_rate: u32,
+}
+
+pub fn load_sample(path: &Path) -> Result<Sample, String> {
+    let mut reader = hound::WavReader::open(path).map_err(|err| err.to_string())?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string())?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(|err| err.to_string())?
+        }
+    };
+    // mixed down to mono
+    let channels = spec.channels.max(1) as usize;
+    let data = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok(Sample {
+        data: Arc::new(data),
+        sample_rate: spec.sample_rate,
+    })
+}
+
+// Synthesized stand-ins used until a WAV file is loaded for the voice
+pub fn default_sample(voice: usize, sample_rate: u32) -> Sample {
+    let mut rng = SmallRng::seed_from_u64(voice as u64);
+    let length = (DEFAULT_SAMPLE_SECONDS * sample_rate as f32) as usize;
+    let mut phase = 0.0;
+    let data = (0..length)
+        .map(|index| {
+            let t = index as f32 / sample_rate as f32;
+            let noise: f32 = rng.gen_range(-1.0..=1.0);
+            match voice {
+                // sine sweeping down from 150 Hz
+                0 => {
+                    phase += (50.0 + 100.0 * (-t * 30.0).exp()) / sample_rate as f32;
+                    (TAU * phase).sin() * (-t * 8.0).exp()
+                }
+                // tone and noise
+                1 => ((TAU * 180.0 * t).sin() * 0.4 + noise * 0.6) * (-t * 18.0).exp(),
+                // short noise burst
+                _ => noise * 0.5 * (-t * 60.0).exp(),
+            }
+        })
+        .collect();
+    Sample {
+        data: Arc::new(data),
+        sample_rate,
+    }
+}
+
+struct PlayingSample {
+    voice: usize,
+    position: f32,
+    rate: f32,
+    gain: f32,
+}
+
+// Plays one sample per drum voice, with its gain and pitch; the oldest hit is cut past the voice limit
+pub struct SamplePlayer {
+    samples: [Sample; 3],
+    voices: [DrumVoiceSettings; 3],
+    playing: Vec<PlayingSample>,
+    sample_rate: u32,
+}
+
+impl SamplePlayer {
+    pub fn new(
+        samples: [Sample; 3],
+        voices: [DrumVoiceSettings; 3],
+        sample_rate: u32,
+    ) -> SamplePlayer {
+        SamplePlayer {
+            samples,
+            voices,
+            playing: Vec::new(),
+            sample_rate,
+        }
+    }
+}
+
+impl SoundSource for SamplePlayer {
+    fn handle_event(&mut self, event: SinkEvent) {
+        let SinkEvent::NoteOn { note, velocity, .. } = event else {
+            return;
+        };
+        let Some(voice) = DRUM_NOTES.iter().position(|n| *n == note) else {
+            return;
+        };
+        if self.playing.len() >= MAX_DRUM_VOICES {
+            self.playing.remove(0);
+        }
+        let settings = self.voices[voice];
+        self.playing.push(PlayingSample {
+            voice,
+            position: 0.0,
+            rate: self.samples[voice].sample_rate as f32 / self.sample_rate as f32
+                * 2f32.powf(settings.pitch / 12.0),
+            gain: settings.gain * velocity as f32 / 127.0,
+        });
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for playing in self.playing.iter_mut() {
+            let data = &self.samples[playing.voice].data;
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let index = playing.position as usize;
+                if index + 1 >= data.len() {
+                    playing.position = data.len() as f32;
+                    break;
+                }
+                let fraction = playing.position - index as f32;
+                let sample = data[index] + (data[index + 1] - data[index]) * fraction;
+                *l += sample * playing.gain;
+                *r += sample * playing.gain;
+                playing.position += playing.rate;
+            }
+        }
+        let samples = &self.samples;
+        self.playing
+            .retain(|playing| (playing.position as usize + 1) < samples[playing.voice].data.len());
+    }
+
+    fn update(&mut self, parameter: SoundParameter) {
+        if let SoundParameter::Drums(voices) = parameter {
+            self.voices = voices;
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use crate::bass::BASS_CHANNEL;
+use crate::drums::DRUM_CHANNEL;
+use crate::layers::{LAYER_BASS_CHANNEL, LAYER_HATS_CHANNEL};
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use crate::sink::SinkEvent;
+use crate::split::SPLIT_CHANNEL;
+use crate::synth::{SoundParameter, SoundSource};
+
+//constants
+// one track per MIDI channel the sequencer plays on: the main voice, the response,
+// the split half of the main voice, the bass, the drums and the two derived layers
+pub const TRACK_COUNT: usize = 7;
+pub const TRACK_CHANNELS: [u8; TRACK_COUNT] = [
+    0,
+    1,
+    SPLIT_CHANNEL,
+    BASS_CHANNEL,
+    DRUM_CHANNEL,
+    LAYER_BASS_CHANNEL,
+    LAYER_HATS_CHANNEL,
+];
+const MAX_DELAY_SECONDS: f32 = 4.0;
+// Freeverb tunings, in samples at 44.1 kHz
+const COMB_LENGTHS: [usize; 4] = [1116, 1188, 1277, 1356];
+const ALLPASS_LENGTHS: [usize; 2] = [556, 441];
+const STEREO_SPREAD: usize = 23;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const REVERB_GAIN: f32 = 0.015;
+const LIMITER_LOOKAHEAD_SECONDS: f32 = 0.005;
+const LIMITER_RELEASE_SECONDS: f32 = 0.15;
+const LIMITER_THRESHOLD: f32 = 0.9;
+// the soft clipper is linear below the knee and never reaches full scale
+const CLIPPER_KNEE: f32 = 0.9;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct TrackSends {
+    pub delay: f32,
+    pub reverb: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct EffectSettings {
+    // delay time in beats, follows the tempo
+    pub delay_beats: f32,
+    pub delay_feedback: f32,
+    pub reverb_size: f32,
+    pub reverb_damping: f32,
+    pub sends: [TrackSends; TRACK_COUNT],
+    pub master_volume: f32,
+    pub limiter: bool,
+}
+
+// A processing stage of the audio graph, working in place on a stereo block
+pub trait AudioNode: Send {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]);
+}
+
+pub struct Delay {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    position: usize,
+    length: usize,
+    feedback: f32,
+}
+
+impl Delay {
+    pub fn new(sample_rate: u32) -> Delay {
+        let size = (MAX_DELAY_SECONDS * sample_rate as f32) as usize;
+        Delay {
+            left: vec![0.0; size],
+            right: vec![0.0; size],
+            position: 0,
+            length: 1,
+            feedback: 0.0,
+        }
+    }
+
+    pub fn set_time(&mut self, seconds: f32, sample_rate: u32) {
+        self.length = ((seconds * sample_rate as f32) as usize).clamp(1, self.left.len());
+        self.position %= self.length;
+    }
+}
+
+impl AudioNode for Delay {
+    // replaces the input with the echoes only
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let echo_l = self.left[self.position];
+            let echo_r = self.right[self.position];
+            self.left[self.position] = *l + echo_l * self.feedback;
+            self.right[self.position] = *r + echo_r * self.feedback;
+            *l = echo_l;
+            *r = echo_r;
+            self.position = (self.position + 1) % self.length;
+        }
+    }
+}
+
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    filtered: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.filtered = output * (1.0 - damping) + self.filtered * damping;
+        self.buffer[self.position] = input + self.filtered * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * ALLPASS_FEEDBACK;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+// Freeverb-style: parallel damped combs into series allpasses, per channel
+pub struct Reverb {
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    size: f32,
+    damping: f32,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: u32) -> Reverb {
+        let scale = |length: usize, spread: usize| {
+            ((length + spread) as f32 * sample_rate as f32 / 44_100.0) as usize
+        };
+        let combs = |spread| {
+            COMB_LENGTHS
+                .iter()
+                .map(|length| Comb {
+                    buffer: vec![0.0; scale(*length, spread)],
+                    position: 0,
+                    filtered: 0.0,
+                })
+                .collect()
+        };
+        let allpasses = |spread| {
+            ALLPASS_LENGTHS
+                .iter()
+                .map(|length| Allpass {
+                    buffer: vec![0.0; scale(*length, spread)],
+                    position: 0,
+                })
+                .collect()
+        };
+        Reverb {
+            combs: [combs(0), combs(STEREO_SPREAD)],
+            allpasses: [allpasses(0), allpasses(STEREO_SPREAD)],
+            size: 0.0,
+            damping: 0.0,
+        }
+    }
+}
+
+impl AudioNode for Reverb {
+    // replaces the input with the reverb tail only
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let feedback = 0.7 + self.size * 0.28;
+        for (channel, samples) in [left, right].into_iter().enumerate() {
+            for sample in samples.iter_mut() {
+                let input = *sample * REVERB_GAIN;
+                let mut output = 0.0;
+                for comb in self.combs[channel].iter_mut() {
+                    output += comb.process(input, feedback, self.damping);
+                }
+                for allpass in self.allpasses[channel].iter_mut() {
+                    output = allpass.process(output);
+                }
+                *sample = output;
+            }
+        }
+    }
+}
+
+// Peak level and gain reduction of the master bus, read by the UI
+#[derive(Clone)]
+pub struct LevelMeter {
+    // bits of non-negative f32s, whose order matches the integer order
+    peak: Arc<AtomicU32>,
+    reduction: Arc<AtomicU32>,
+}
+
+impl LevelMeter {
+    fn new() -> LevelMeter {
+        LevelMeter {
+            peak: Arc::new(AtomicU32::new(0)),
+            reduction: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    fn store(&self, peak: f32, reduction: f32) {
+        self.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        self.reduction
+            .fetch_max(reduction.to_bits(), Ordering::Relaxed);
+    }
+
+    // Highest peak and gain reduction (0 to 1) since the last read
+    pub fn take(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.peak.swap(0, Ordering::Relaxed)),
+            f32::from_bits(self.reduction.swap(0, Ordering::Relaxed)),
+        )
+    }
+}
+
+// Master bus: volume, then a lookahead limiter ahead of a soft clipper
+pub struct Limiter {
+    buffers: [Vec<f32>; 2],
+    position: usize,
+    gain: f32,
+    attack: f32,
+    release: f32,
+    volume: f32,
+    enabled: bool,
+    meter: LevelMeter,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: u32) -> Limiter {
+        let lookahead = ((LIMITER_LOOKAHEAD_SECONDS * sample_rate as f32) as usize).max(1);
+        Limiter {
+            buffers: [vec![0.0; lookahead], vec![0.0; lookahead]],
+            position: 0,
+            gain: 1.0,
+            // the gain settles within the lookahead, before the peak comes out of the delay
+            attack: (-4.0 / lookahead as f32).exp(),
+            release: (-1.0 / (LIMITER_RELEASE_SECONDS * sample_rate as f32)).exp(),
+            volume: 1.0,
+            enabled: true,
+            meter: LevelMeter::new(),
+        }
+    }
+}
+
+fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= CLIPPER_KNEE {
+        sample
+    } else {
+        let over = (magnitude - CLIPPER_KNEE) / (1.0 - CLIPPER_KNEE);
+        sample.signum() * (CLIPPER_KNEE + (1.0 - CLIPPER_KNEE) * over.tanh())
+    }
+}
+
+impl AudioNode for Limiter {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let mut peak: f32 = 0.0;
+        let mut lowest_gain: f32 = 1.0;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let (mut out_l, mut out_r) = (*l * self.volume, *r * self.volume);
+            if self.enabled {
+                let level = out_l.abs().max(out_r.abs());
+                let target = if level > LIMITER_THRESHOLD {
+                    LIMITER_THRESHOLD / level
+                } else {
+                    1.0
+                };
+                let coefficient = if target < self.gain {
+                    self.attack
+                } else {
+                    self.release
+                };
+                self.gain = target + (self.gain - target) * coefficient;
+                lowest_gain = lowest_gain.min(self.gain);
+                let [buffer_l, buffer_r] = &mut self.buffers;
+                let delayed_l = std::mem::replace(&mut buffer_l[self.position], out_l);
+                let delayed_r = std::mem::replace(&mut buffer_r[self.position], out_r);
+                self.position = (self.position + 1) % buffer_l.len();
+                out_l = soft_clip(delayed_l * self.gain);
+                out_r = soft_clip(delayed_r * self.gain);
+            }
+            peak = peak.max(out_l.abs()).max(out_r.abs());
+            *l = out_l;
+            *r = out_r;
+        }
+        self.meter.store(peak, 1.0 - lowest_gain);
+    }
+}
+
+// The audio graph: one source per track, summed dry and through the delay and reverb sends
+pub struct Mixer {
+    sample_rate: u32,
+    tracks: Vec<Box<dyn SoundSource>>,
+    settings: EffectSettings,
+    bpm: f32,
+    delay: Delay,
+    reverb: Reverb,
+    limiter: Limiter,
+    // track, delay send, reverb send and master buses
+    buses: [Vec<f32>; 8],
+}
+
+impl Mixer {
+    pub fn new(
+        sample_rate: u32,
+        tracks: Vec<Box<dyn SoundSource>>,
+        settings: EffectSettings,
+        bpm: f32,
+    ) -> Mixer {
+        let mut mixer = Mixer {
+            sample_rate,
+            tracks,
+            settings,
+            bpm,
+            delay: Delay::new(sample_rate),
+            reverb: Reverb::new(sample_rate),
+            limiter: Limiter::new(sample_rate),
+            buses: Default::default(),
+        };
+        mixer.update_effects(settings);
+        mixer
+    }
+
+    pub fn set_tracks(&mut self, tracks: Vec<Box<dyn SoundSource>>) {
+        self.tracks = tracks;
+    }
+
+    pub fn update_effects(&mut self, settings: EffectSettings) {
+        self.settings = settings;
+        self.delay.feedback = settings.delay_feedback;
+        self.reverb.size = settings.reverb_size;
+        self.reverb.damping = settings.reverb_damping;
+        self.limiter.volume = settings.master_volume;
+        self.limiter.enabled = settings.limiter;
+        self.set_tempo(self.bpm);
+    }
+
+    pub fn meter(&self) -> LevelMeter {
+        self.limiter.meter.clone()
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm;
+        self.delay
+            .set_time(self.settings.delay_beats * 60.0 / bpm, self.sample_rate);
+    }
+}
+
+impl SoundSource for Mixer {
+    fn handle_event(&mut self, event: SinkEvent) {
+        let channel = match event {
+            SinkEvent::NoteOn { channel, .. }
+            | SinkEvent::NoteOff { channel, .. }
+            | SinkEvent::ControlChange { channel, .. }
+            | SinkEvent::ProgramChange { channel, .. }
+            | SinkEvent::ChannelPressure { channel, .. } => channel,
+        };
+        let track = TRACK_CHANNELS.iter().position(|c| *c == channel);
+        if let Some(track) = track.and_then(|index| self.tracks.get_mut(index)) {
+            track.handle_event(event);
+        }
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for bus in self.buses.iter_mut() {
+            bus.clear();
+            bus.resize(left.len(), 0.0);
+        }
+        let [track_l, track_r, delay_l, delay_r, reverb_l, reverb_r, master_l, master_r] =
+            &mut self.buses;
+        for (track, sends) in self.tracks.iter_mut().zip(self.settings.sends) {
+            track_l.fill(0.0);
+            track_r.fill(0.0);
+            track.render(track_l, track_r);
+            for i in 0..left.len() {
+                master_l[i] += track_l[i];
+                master_r[i] += track_r[i];
+                delay_l[i] += track_l[i] * sends.delay;
+                delay_r[i] += track_r[i] * sends.delay;
+                reverb_l[i] += track_l[i] * sends.reverb;
+                reverb_r[i] += track_r[i] * sends.reverb;
+            }
+        }
+        self.delay.process(delay_l, delay_r);
+        self.reverb.process(reverb_l, reverb_r);
+        for i in 0..left.len() {
+            master_l[i] += delay_l[i] + reverb_l[i];
+            master_r[i] += delay_r[i] + reverb_r[i];
+        }
+        self.limiter.process(master_l, master_r);
+        for i in 0..left.len() {
+            left[i] += master_l[i];
+            right[i] += master_r[i];
+        }
+    }
+
+    fn update(&mut self, parameter: SoundParameter) {
+        for track in self.tracks.iter_mut() {
+            track.update(parameter);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use core::time::Duration;
+
+// Attack/decay shape followed by channel pressure over the length of each note
+#[derive(Clone, Copy, PartialEq)]
+pub struct PressureEnvelope {
+    pub enabled: bool,
+    pub attack_ms: f32,
+    pub decay_ms: f32,
+    // peak pressure, 0..=127
+    pub depth: f32,
+}
+
+impl PressureEnvelope {
+    pub fn value_at(&self, elapsed: Duration) -> u8 {
+        let t = elapsed.as_secs_f32() * 1000.0;
+        let level = if t < self.attack_ms {
+            t / self.attack_ms
+        } else if t < self.attack_ms + self.decay_ms {
+            1.0 - (t - self.attack_ms) / self.decay_ms
+        } else {
+            0.0
+        };
+        (level * self.depth).clamp(0.0, 127.0) as u8
+    }
+}
@@ -0,0 +1,107 @@
+use rand::prelude::*;
+
+use crate::params::PARAMETERS;
+use crate::seed::{seeded_rng, SeedStream};
+use crate::SequencerModel;
+use crate::{EVOLVE_BARS_DEFAULT_VALUE, EVOLVE_STEP_DEFAULT_VALUE};
+
+//constants
+// left alone unless unlocked, a drifting tempo throws the followers off
+const LOCKED_BY_DEFAULT: &[&str] = &["/tempo"];
+
+// A parameter the evolution may move, and how far
+pub struct EvolveRule {
+    pub name: &'static str,
+    pub address: &'static str,
+    pub unit: &'static str,
+    pub locked: bool,
+    // bounds the parameter is kept in, in its own unit
+    pub min: f32,
+    pub max: f32,
+}
+
+// Slow drift of the settings: every few bars one unlocked parameter takes a small random step
+pub struct Evolve {
+    pub enabled: bool,
+    pub every_bars: u32,
+    // largest step, as a share of the bounds
+    pub step: f32,
+    pub rules: Vec<EvolveRule>,
+    // bars played since the last change
+    bars: u32,
+    last_bar: Option<u64>,
+    rng: SmallRng,
+}
+
+impl Evolve {
+    // Every continuous parameter, within its whole range
+    pub fn new(model: &SequencerModel) -> Evolve {
+        let rules = PARAMETERS
+            .iter()
+            .filter(|parameter| !parameter.stepped)
+            .map(|parameter| {
+                let range = (parameter.range)(model);
+                EvolveRule {
+                    name: parameter.name,
+                    address: parameter.address,
+                    unit: parameter.unit,
+                    locked: LOCKED_BY_DEFAULT.contains(&parameter.address),
+                    min: *range.start(),
+                    max: *range.end(),
+                }
+            })
+            .collect();
+        Evolve {
+            enabled: false,
+            every_bars: EVOLVE_BARS_DEFAULT_VALUE,
+            step: EVOLVE_STEP_DEFAULT_VALUE,
+            rules,
+            bars: 0,
+            last_bar: None,
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    // Called every frame with the bar playing, None while stopped. Returns a line describing
+    // the change when one was made. With a seed per bar the same bar makes the same change.
+    pub fn update(&mut self, bar: Option<u64>, model: &mut SequencerModel) -> Option<String> {
+        let previous = self.last_bar;
+        self.last_bar = bar;
+        let bar = bar.filter(|bar| Some(*bar) != previous && previous.is_some())?;
+        if !self.enabled {
+            self.bars = 0;
+            return None;
+        }
+        self.bars += 1;
+        if self.bars < self.every_bars.max(1) {
+            return None;
+        }
+        self.bars = 0;
+        let mut rng = match model.seed.bar_seed(bar) {
+            Some(seed) => seeded_rng(seed, SeedStream::Evolve),
+            None => SmallRng::from_rng(&mut self.rng).unwrap(),
+        };
+        let candidates: Vec<&EvolveRule> = self
+            .rules
+            .iter()
+            .filter(|rule| !rule.locked && rule.max > rule.min)
+            .collect();
+        let rule = candidates.choose(&mut rng)?;
+        let parameter = PARAMETERS
+            .iter()
+            .find(|parameter| parameter.address == rule.address)?;
+        let before = (parameter.get)(model);
+        let step = (rule.max - rule.min) * self.step * rng.gen_range(-1.0..=1.0);
+        parameter.set_value(model, (before + step).clamp(rule.min, rule.max));
+        let after = (parameter.get)(model);
+        let line = format!(
+            "Bar {}: {} {:.2} -> {:.2} {}",
+            bar + 1,
+            rule.name,
+            before,
+            after,
+            rule.unit
+        );
+        Some(line.trim_end().to_string())
+    }
+}
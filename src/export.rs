@@ -0,0 +1,238 @@
+use std::{path::Path, time::Duration};
+
+use midly::{
+    num::{u15, u24, u28, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+
+use crate::audio::SoundSettings;
+use crate::notation::{abc_text, tracker_text, NotationFormat};
+use crate::sequencer::{Sequencer, SequencerConfiguration};
+use crate::sink::SinkEvent;
+use crate::synth::{render_click, SoundSource};
+
+//constants
+const TICKS_PER_BEAT: u16 = 480;
+const SAMPLE_RATE: u32 = 44_100;
+// rendered after the last event so released notes can ring out
+const TAIL_SECONDS: f32 = 1.0;
+pub const MIN_EXPORT_BARS: u32 = 1;
+pub const MAX_EXPORT_BARS: u32 = 64;
+const EXPORT_BARS_DEFAULT_VALUE: u32 = 8;
+
+pub enum ExportAction {
+    Files,
+    CopyNotation,
+    SaveNotation,
+}
+
+// State of the "Export" window
+pub struct ExportSettings {
+    pub bars: u32,
+    // the .mid file, stems are written next to it
+    pub path: String,
+    pub audio_stem: bool,
+    pub click_track: bool,
+    pub notation: NotationFormat,
+    pub status: Option<Result<String, String>>,
+}
+
+impl ExportSettings {
+    pub fn new() -> ExportSettings {
+        ExportSettings {
+            bars: EXPORT_BARS_DEFAULT_VALUE,
+            path: String::new(),
+            audio_stem: false,
+            click_track: false,
+            notation: NotationFormat::Tracker,
+            status: None,
+        }
+    }
+
+    // The audio stem plays through the same sound as the internal audio
+    pub fn export(&mut self, config: SequencerConfiguration, sound: &SoundSettings) {
+        let mixer = sound.build_mixer(SAMPLE_RATE, config.bpm, config.drums.voices);
+        self.status = Some(export(self, config, Box::new(mixer)));
+    }
+
+    // The bars as text, in the selected notation
+    pub fn notation_text(&self, config: SequencerConfiguration) -> String {
+        let bpm = config.bpm;
+        let events = Sequencer::render(config, self.bars);
+        match self.notation {
+            NotationFormat::Tracker => tracker_text(&events, bpm, self.bars),
+            NotationFormat::Abc => abc_text(&events, bpm, self.bars),
+        }
+    }
+
+    // Next to the .mid file, with the extension of the notation
+    pub fn save_notation(&mut self, config: SequencerConfiguration) {
+        let path = Path::new(self.path.trim()).with_extension(self.notation.extension());
+        let text = self.notation_text(config);
+        self.status = Some(
+            std::fs::write(&path, text)
+                .map(|_| format!("Wrote {}", path.display()))
+                .map_err(|err| err.to_string()),
+        );
+    }
+}
+
+// Renders the bars once and writes every requested file from the same events
+fn export(
+    settings: &ExportSettings,
+    config: SequencerConfiguration,
+    mut source: Box<dyn SoundSource>,
+) -> Result<String, String> {
+    let path = Path::new(settings.path.trim()).with_extension("mid");
+    let bpm = config.bpm;
+    let events = Sequencer::render(config, settings.bars);
+    write_midi_file(&path, &events, bpm)?;
+    let mut written = vec![path.display().to_string()];
+
+    let length = events
+        .last()
+        .map_or(Duration::ZERO, |(time, _)| *time)
+        .as_secs_f32()
+        + TAIL_SECONDS;
+    let sample_count = (length * SAMPLE_RATE as f32) as usize;
+    if settings.audio_stem {
+        let stem_path = path.with_extension("wav");
+        let (left, right) = render_audio(&events, source.as_mut(), SAMPLE_RATE, sample_count);
+        write_wav(&stem_path, &left, &right)?;
+        written.push(stem_path.display().to_string());
+    }
+    if settings.click_track {
+        let click_path = path.with_file_name(format!(
+            "{}_click.wav",
+            path.file_stem().unwrap_or_default().to_string_lossy()
+        ));
+        let mut click = vec![0.0; sample_count];
+        render_click(&mut click, SAMPLE_RATE, bpm);
+        write_wav(&click_path, &click, &click)?;
+        written.push(click_path.display().to_string());
+    }
+    Ok(format!("Wrote {}", written.join(", ")))
+}
+
+fn midi_message(event: SinkEvent) -> (u8, MidiMessage) {
+    match event {
+        SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        } => (
+            channel,
+            MidiMessage::NoteOn {
+                key: u7::new(note),
+                vel: u7::new(velocity),
+            },
+        ),
+        SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        } => (
+            channel,
+            MidiMessage::NoteOff {
+                key: u7::new(note),
+                vel: u7::new(velocity),
+            },
+        ),
+        SinkEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        } => (
+            channel,
+            MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            },
+        ),
+        SinkEvent::ProgramChange { channel, program } => (
+            channel,
+            MidiMessage::ProgramChange {
+                program: u7::new(program),
+            },
+        ),
+        SinkEvent::ChannelPressure { channel, pressure } => (
+            channel,
+            MidiMessage::ChannelAftertouch {
+                vel: u7::new(pressure),
+            },
+        ),
+    }
+}
+
+fn write_midi_file(path: &Path, events: &[(Duration, SinkEvent)], bpm: f32) -> Result<(), String> {
+    let ticks_per_second = TICKS_PER_BEAT as f32 * bpm / 60.0;
+    let mut track = vec![TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new((60_000_000.0 / bpm) as u32))),
+    }];
+    let mut last_tick = 0;
+    for (time, event) in events {
+        let tick = (time.as_secs_f32() * ticks_per_second) as u32;
+        let (channel, message) = midi_message(*event);
+        track.push(TrackEvent {
+            delta: u28::new(tick - last_tick),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(channel),
+                message,
+            },
+        });
+        last_tick = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    let smf = Smf {
+        header: Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(TICKS_PER_BEAT)),
+        ),
+        tracks: vec![track],
+    };
+    smf.save(path).map_err(|err| err.to_string())
+}
+
+// Plays the events through a sound source, splitting the blocks at every event time
+pub fn render_audio(
+    events: &[(Duration, SinkEvent)],
+    source: &mut dyn SoundSource,
+    sample_rate: u32,
+    sample_count: usize,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut left = vec![0.0; sample_count];
+    let mut right = vec![0.0; sample_count];
+    let mut position = 0;
+    for (time, event) in events {
+        let sample = ((time.as_secs_f64() * sample_rate as f64) as usize).min(sample_count);
+        if sample > position {
+            source.render(&mut left[position..sample], &mut right[position..sample]);
+            position = sample;
+        }
+        source.handle_event(*event);
+    }
+    source.render(&mut left[position..], &mut right[position..]);
+    (left, right)
+}
+
+fn write_wav(path: &Path, left: &[f32], right: &[f32]) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(|err| err.to_string())?;
+    for (l, r) in left.iter().zip(right) {
+        for sample in [l, r] {
+            writer
+                .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    writer.finalize().map_err(|err| err.to_string())
+}
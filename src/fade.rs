@@ -0,0 +1,109 @@
+// Play ramps the velocity up over a few bars, Stop ramps it down before silencing
+#[derive(Clone, Copy, PartialEq)]
+pub struct FadeSettings {
+    pub enabled: bool,
+    pub bars: u32,
+}
+
+// Where the transport is between silent and playing
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum TransportPhase {
+    #[default]
+    Stopped,
+    FadingIn,
+    Playing,
+    // still playing, stops once the velocity reached nothing
+    FadingOut,
+}
+
+pub struct Fade {
+    phase: TransportPhase,
+    // share of the velocity the notes keep
+    gain: f32,
+    // gain gained or lost each tick of the ramp
+    step: f32,
+}
+
+impl Fade {
+    pub fn new(is_playing: bool) -> Fade {
+        Fade {
+            phase: if is_playing {
+                TransportPhase::Playing
+            } else {
+                TransportPhase::Stopped
+            },
+            gain: if is_playing { 1.0 } else { 0.0 },
+            step: 1.0,
+        }
+    }
+
+    pub fn phase(&self) -> TransportPhase {
+        self.phase
+    }
+
+    fn set_ramp(&mut self, settings: &FadeSettings, ticks_per_bar: u64) {
+        let ticks = settings.bars as u64 * ticks_per_bar;
+        self.step = 1.0 / ticks.max(1) as f32;
+    }
+
+    // A fade out going on turns back from the velocity it reached
+    pub fn start(&mut self, settings: &FadeSettings, ticks_per_bar: u64) {
+        match self.phase {
+            TransportPhase::Playing | TransportPhase::FadingIn => (),
+            _ if !settings.enabled || settings.bars == 0 => {
+                self.phase = TransportPhase::Playing;
+                self.gain = 1.0;
+            }
+            _ => {
+                self.phase = TransportPhase::FadingIn;
+                self.set_ramp(settings, ticks_per_bar);
+            }
+        }
+    }
+
+    // True when the transport stops right away rather than fading out
+    pub fn stop(&mut self, settings: &FadeSettings, ticks_per_bar: u64) -> bool {
+        match self.phase {
+            TransportPhase::Stopped => true,
+            TransportPhase::FadingOut => false,
+            _ if !settings.enabled || settings.bars == 0 || self.gain <= 0.0 => {
+                self.stopped();
+                true
+            }
+            _ => {
+                self.phase = TransportPhase::FadingOut;
+                self.set_ramp(settings, ticks_per_bar);
+                false
+            }
+        }
+    }
+
+    pub fn stopped(&mut self) {
+        self.phase = TransportPhase::Stopped;
+        self.gain = 0.0;
+    }
+
+    // Moves the ramp on by a tick, true when a fade out reached silence
+    pub fn tick(&mut self) -> bool {
+        match self.phase {
+            TransportPhase::FadingIn => {
+                self.gain += self.step;
+                if self.gain >= 1.0 {
+                    self.gain = 1.0;
+                    self.phase = TransportPhase::Playing;
+                }
+                false
+            }
+            TransportPhase::FadingOut => {
+                self.gain -= self.step;
+                self.gain <= 0.0
+            }
+            _ => false,
+        }
+    }
+
+    // A note keeps sounding, however far the fade is
+    pub fn apply(&self, velocity: u8) -> u8 {
+        (velocity as f32 * self.gain).round().clamp(1.0, 127.0) as u8
+    }
+}
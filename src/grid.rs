@@ -0,0 +1,247 @@
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+//constants
+const GRID_CLIENT_NAME: &str = "Generative Sequencer Grid";
+const NOTE_ON_MSG: u8 = 0x90;
+const CONTROL_CHANGE_MSG: u8 = 0xB0;
+pub const GRID_SIZE: usize = 8;
+// two rows of eight sixteenths each, top down: the kick, the snare, the hats and the logic
+// pattern of the main voice
+pub const GRID_TRACKS: usize = 4;
+const STEPS_PER_ROW: usize = 8;
+
+// How the pads are numbered, monome-style grids speaking MIDI mostly take one of them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GridLayout {
+    // Launchpad, S and Mini: note 16 * row + column, the side buttons in column 8
+    LaunchpadXY,
+    // Launchpad MK2, Pro, X and Mini MK3 in programmer mode: note 11 at the bottom left
+    LaunchpadProgrammer,
+}
+
+pub const GRID_LAYOUTS: [GridLayout; 2] =
+    [GridLayout::LaunchpadXY, GridLayout::LaunchpadProgrammer];
+
+impl Display for GridLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            GridLayout::LaunchpadXY => write!(f, "Launchpad / Mini (X-Y)"),
+            GridLayout::LaunchpadProgrammer => write!(f, "Launchpad programmer mode"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GridColor {
+    Off,
+    Dim,
+    Green,
+    Red,
+    Amber,
+}
+
+impl GridLayout {
+    // The press a message stands for, None for releases and other messages
+    fn decode(&self, message: &[u8]) -> Option<GridEvent> {
+        let [status, number, value] = *message else {
+            return None;
+        };
+        if value == 0 {
+            return None;
+        }
+        let (row, column) = match (self, status & 0xF0) {
+            (GridLayout::LaunchpadXY, NOTE_ON_MSG) => (number as usize / 16, number as usize % 16),
+            (GridLayout::LaunchpadProgrammer, NOTE_ON_MSG | CONTROL_CHANGE_MSG) => {
+                let (row, column) = (number as usize / 10, number as usize % 10);
+                if !(1..=GRID_SIZE).contains(&row) || column == 0 {
+                    return None;
+                }
+                (GRID_SIZE - row, column - 1)
+            }
+            _ => return None,
+        };
+        match column {
+            _ if row >= GRID_SIZE => None,
+            GRID_SIZE => Some(GridEvent::Side(row)),
+            column if column < GRID_SIZE => Some(GridEvent::Step {
+                track: row / 2,
+                step: row % 2 * STEPS_PER_ROW + column,
+            }),
+            _ => None,
+        }
+    }
+
+    // The message lighting a pad, or a side button in column 8
+    fn led_message(&self, row: usize, column: usize, color: GridColor) -> [u8; 3] {
+        match self {
+            // velocity 16 * green + red, plus the copy and clear flags
+            GridLayout::LaunchpadXY => {
+                let velocity = match color {
+                    GridColor::Off => 12,
+                    GridColor::Dim => 28,
+                    GridColor::Green => 60,
+                    GridColor::Red => 15,
+                    GridColor::Amber => 63,
+                };
+                [NOTE_ON_MSG, (16 * row + column) as u8, velocity]
+            }
+            // colors of the palette, the side buttons being controllers
+            GridLayout::LaunchpadProgrammer => {
+                let velocity = match color {
+                    GridColor::Off => 0,
+                    GridColor::Dim => 1,
+                    GridColor::Green => 21,
+                    GridColor::Red => 5,
+                    GridColor::Amber => 9,
+                };
+                let number = (10 * (GRID_SIZE - row) + column + 1) as u8;
+                let status = if column == GRID_SIZE {
+                    CONTROL_CHANGE_MSG
+                } else {
+                    NOTE_ON_MSG
+                };
+                [status, number, velocity]
+            }
+        }
+    }
+}
+
+// What the grid window asks for
+pub enum GridAction {
+    Connect,
+    Disconnect,
+}
+
+// A press on the grid
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GridEvent {
+    // sixteenth of a track, from 0
+    Step { track: usize, step: usize },
+    // side button, from the top
+    Side(usize),
+}
+
+// What the LEDs show, the side buttons in the last column
+#[derive(Clone, Copy, PartialEq)]
+pub struct GridFrame {
+    leds: [[GridColor; GRID_SIZE + 1]; GRID_SIZE],
+}
+
+impl GridFrame {
+    // The steps of the tracks lit, the playhead column red where a step plays and dim
+    // elsewhere, and the side button of the selected pattern
+    pub fn new(
+        tracks: [u16; GRID_TRACKS],
+        playhead: Option<usize>,
+        selected: Option<usize>,
+    ) -> GridFrame {
+        let mut leds = [[GridColor::Off; GRID_SIZE + 1]; GRID_SIZE];
+        for (row, row_leds) in leds.iter_mut().enumerate() {
+            let (track, first_step) = (row / 2, row % 2 * STEPS_PER_ROW);
+            for (column, led) in row_leds.iter_mut().take(GRID_SIZE).enumerate() {
+                let step = first_step + column;
+                let on = tracks[track] & 1 << step != 0;
+                *led = match (on, playhead == Some(step)) {
+                    (true, true) => GridColor::Red,
+                    (false, true) => GridColor::Dim,
+                    // the logic pattern apart from the drums
+                    (true, false) if track == GRID_TRACKS - 1 => GridColor::Amber,
+                    (true, false) => GridColor::Green,
+                    (false, false) => GridColor::Off,
+                };
+            }
+            if selected == Some(row) {
+                row_leds[GRID_SIZE] = GridColor::Green;
+            }
+        }
+        GridFrame { leds }
+    }
+}
+
+// A grid controller on a MIDI port of the same name both ways. The presses are collected from
+// midir's callback thread, the LEDs are sent from the UI as they change.
+pub struct GridController {
+    layout: GridLayout,
+    _input: MidiInputConnection<()>,
+    output: MidiOutputConnection,
+    events: Arc<Mutex<Vec<GridEvent>>>,
+    // None until the first frame is sent
+    shown: Option<GridFrame>,
+}
+
+impl GridController {
+    pub fn connect(port: &str, layout: GridLayout) -> Result<GridController, String> {
+        let midi_in = MidiInput::new(GRID_CLIENT_NAME).map_err(|err| err.to_string())?;
+        let in_port = midi_in
+            .ports()
+            .into_iter()
+            .find(|p| midi_in.port_name(p).ok().as_deref() == Some(port))
+            .ok_or(format!("No MIDI input port named {}", port))?;
+        let midi_out = MidiOutput::new(GRID_CLIENT_NAME).map_err(|err| err.to_string())?;
+        let out_port = midi_out
+            .ports()
+            .into_iter()
+            .find(|p| midi_out.port_name(p).ok().as_deref() == Some(port))
+            .ok_or(format!("No MIDI output port named {}", port))?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let callback_events = events.clone();
+        let input = midi_in
+            .connect(
+                &in_port,
+                GRID_CLIENT_NAME,
+                move |_timestamp, message, _| {
+                    if let Some(event) = layout.decode(message) {
+                        callback_events.lock().unwrap().push(event);
+                    }
+                },
+                (),
+            )
+            .map_err(|err| err.to_string())?;
+        let output = midi_out
+            .connect(&out_port, GRID_CLIENT_NAME)
+            .map_err(|err| err.to_string())?;
+        Ok(GridController {
+            layout,
+            _input: input,
+            output,
+            events,
+            shown: None,
+        })
+    }
+
+    // Presses since the last call, in order
+    pub fn take_events(&self) -> Vec<GridEvent> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+
+    // Sends the LEDs that changed since the last frame, all of them the first time
+    pub fn show(&mut self, frame: &GridFrame) {
+        for row in 0..GRID_SIZE {
+            for column in 0..=GRID_SIZE {
+                let color = frame.leds[row][column];
+                if self.shown.map(|shown| shown.leds[row][column]) == Some(color) {
+                    continue;
+                }
+                // a grid unplugged on the way stays dark, the presses stop as well
+                let _ = self
+                    .output
+                    .send(&self.layout.led_message(row, column, color));
+            }
+        }
+        self.shown = Some(*frame);
+    }
+}
+
+impl Drop for GridController {
+    // Leaves the grid dark
+    fn drop(&mut self) {
+        self.show(&GridFrame {
+            leds: [[GridColor::Off; GRID_SIZE + 1]; GRID_SIZE],
+        });
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fs, path::Path};
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::chord::{best_chord, Chord};
+use crate::key_detection::{detect_key, DetectedKey};
+
+//constants
+const DEFAULT_BPM: f32 = 120.0;
+const DEFAULT_BEATS_PER_BAR: f32 = 4.0;
+const DRUM_CHANNEL: u8 = 9;
+
+// Key, tempo and chord of every bar of an imported MIDI file, for the generator to play along
+pub struct GuideTrack {
+    pub name: String,
+    pub bpm: f32,
+    pub key: Option<DetectedKey>,
+    // None for bars without any pitched note
+    pub bars: Vec<Option<Chord>>,
+}
+
+struct GuideNote {
+    key: u8,
+    start: u64,
+    end: u64,
+}
+
+// Reads the first tempo and time signature of the file, so tempo or meter changes are not followed
+pub fn load_guide(path: &Path) -> Result<GuideTrack, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let smf = Smf::parse(&bytes).map_err(|err| err.to_string())?;
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks) => ticks.as_int() as f32,
+        Timing::Timecode(..) => return Err("SMPTE timed files are not supported".to_string()),
+    };
+
+    let mut bpm = None;
+    let mut beats_per_bar = None;
+    let mut notes = Vec::new();
+    for track in smf.tracks.iter() {
+        let mut time = 0;
+        // start time of the sounding notes, by channel and key
+        let mut started: Vec<(u8, u8, u64)> = Vec::new();
+        for event in track {
+            time += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(micros_per_beat)) => {
+                    bpm.get_or_insert(60_000_000.0 / micros_per_beat.as_int() as f32);
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, ..)) => {
+                    // in quarter notes, 6/8 is 3 beats
+                    beats_per_bar
+                        .get_or_insert(numerator as f32 * 4.0 / 2f32.powi(denominator as i32));
+                }
+                TrackEventKind::Midi { channel, message } if channel.as_int() != DRUM_CHANNEL => {
+                    let channel = channel.as_int();
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            started.push((channel, key.as_int(), time));
+                        }
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            let key = key.as_int();
+                            if let Some(index) = started
+                                .iter()
+                                .position(|(c, k, _)| *c == channel && *k == key)
+                            {
+                                let (_, _, start) = started.remove(index);
+                                notes.push(GuideNote {
+                                    key,
+                                    start,
+                                    end: time,
+                                });
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    if notes.is_empty() {
+        return Err("No notes found".to_string());
+    }
+
+    let ticks_per_bar = (ticks_per_beat * beats_per_bar.unwrap_or(DEFAULT_BEATS_PER_BAR)) as u64;
+    let last_tick = notes.iter().map(|note| note.end).max().unwrap();
+    let bar_count = last_tick.div_ceil(ticks_per_bar.max(1)) as usize;
+
+    // pitch classes weighted by how long they sound, per bar and over the whole file
+    let mut histograms = vec![[0.0; 12]; bar_count];
+    let mut total = [0.0; 12];
+    for note in &notes {
+        let pc = (note.key % 12) as usize;
+        total[pc] += (note.end - note.start) as f32;
+        for (bar, histogram) in histograms.iter_mut().enumerate() {
+            let bar_start = bar as u64 * ticks_per_bar;
+            let overlap_start = note.start.max(bar_start);
+            let overlap_end = note.end.min(bar_start + ticks_per_bar);
+            if overlap_end > overlap_start {
+                histogram[pc] += (overlap_end - overlap_start) as f32;
+            }
+        }
+    }
+
+    Ok(GuideTrack {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        bpm: bpm.unwrap_or(DEFAULT_BPM),
+        key: detect_key(&total),
+        bars: histograms.iter().map(best_chord).collect(),
+    })
+}
+
+// State of the "MIDI guide" window
+pub struct GuideImport {
+    pub path: String,
+    pub error: Option<String>,
+    pub track: Option<GuideTrack>,
+}
+
+impl GuideImport {
+    pub fn new() -> GuideImport {
+        GuideImport {
+            path: String::new(),
+            error: None,
+            track: None,
+        }
+    }
+
+    pub fn import(&mut self) {
+        match load_guide(Path::new(self.path.trim())) {
+            Ok(track) => {
+                self.track = Some(track);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
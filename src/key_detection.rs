@@ -0,0 +1,165 @@
+//constants
+// Krumhansl-Kessler key profiles, from C
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyMode {
+    Major,
+    Minor,
+}
+
+impl KeyMode {
+    // also the name of the matching library scale
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyMode::Major => "Major",
+            KeyMode::Minor => "Minor",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DetectedKey {
+    // pitch class of the tonic, 0 being C
+    pub tonic: usize,
+    pub mode: KeyMode,
+    pub correlation: f32,
+}
+
+// Best matching key for a pitch-class histogram, None if no note was counted
+pub fn detect_key(histogram: &[f32; 12]) -> Option<DetectedKey> {
+    if histogram.iter().all(|&count| count == 0.0) {
+        return None;
+    }
+    let mut best: Option<DetectedKey> = None;
+    for tonic in 0..12 {
+        for (mode, profile) in [
+            (KeyMode::Major, &MAJOR_PROFILE),
+            (KeyMode::Minor, &MINOR_PROFILE),
+        ] {
+            let rotated: Vec<f32> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+            let correlation = correlation(histogram, &rotated);
+            if best.map_or(true, |key| correlation > key.correlation) {
+                best = Some(DetectedKey {
+                    tonic,
+                    mode,
+                    correlation,
+                });
+            }
+        }
+    }
+    best
+}
+
+// Pearson correlation
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / a.len() as f32;
+    let mean_b = b.iter().sum::<f32>() / b.len() as f32;
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        0.0
+    } else {
+        covariance / (variance_a * variance_b).sqrt()
+    }
+}
+
+// Listens to the MIDI input for a while, then holds the detected key until applied or discarded
+pub struct KeyDetection {
+    histogram: [f32; 12],
+    // seconds since the app started
+    listening_until: Option<f64>,
+    pub result: Option<DetectedKey>,
+    // set when listening ended without any note
+    pub no_notes: bool,
+}
+
+impl KeyDetection {
+    pub fn new() -> KeyDetection {
+        KeyDetection {
+            histogram: [0.0; 12],
+            listening_until: None,
+            result: None,
+            no_notes: false,
+        }
+    }
+
+    pub fn start(&mut self, now: f64, duration: f64) {
+        self.histogram = [0.0; 12];
+        self.listening_until = Some(now + duration);
+        self.result = None;
+        self.no_notes = false;
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listening_until.is_some()
+    }
+
+    pub fn remaining(&self, now: f64) -> f64 {
+        self.listening_until
+            .map_or(0.0, |until| (until - now).max(0.0))
+    }
+
+    pub fn add_notes(&mut self, notes: &[u8]) {
+        if self.is_listening() {
+            for note in notes {
+                self.histogram[(*note % 12) as usize] += 1.0;
+            }
+        }
+    }
+
+    pub fn update(&mut self, now: f64) {
+        if let Some(until) = self.listening_until {
+            if now >= until {
+                self.listening_until = None;
+                self.result = detect_key(&self.histogram);
+                self.no_notes = self.result.is_none();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The notes counted by pitch class, as the detection does while listening
+    fn histogram(notes: &[u8]) -> [f32; 12] {
+        let mut histogram = [0.0; 12];
+        for note in notes {
+            histogram[(*note % 12) as usize] += 1.0;
+        }
+        histogram
+    }
+
+    // A scale played up to the octave, its tonic counted twice
+    #[test]
+    fn c_major_scale_is_c_major() {
+        let key = detect_key(&histogram(&[60, 62, 64, 65, 67, 69, 71, 72])).unwrap();
+        assert_eq!(key.tonic, 0);
+        assert!(key.mode == KeyMode::Major);
+    }
+
+    #[test]
+    fn a_natural_minor_scale_is_a_minor() {
+        let key = detect_key(&histogram(&[57, 59, 60, 62, 64, 65, 67, 69])).unwrap();
+        assert_eq!(key.tonic, 9);
+        assert!(key.mode == KeyMode::Minor);
+    }
+
+    #[test]
+    fn no_notes_is_no_key() {
+        assert!(detect_key(&[0.0; 12]).is_none());
+    }
+}
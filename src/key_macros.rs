@@ -0,0 +1,109 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+use crate::variations::{VARIATION_COUNT, VARIATION_NAMES};
+
+//constants
+const KEY_MACROS_FILE: &str = "key_macros.json";
+
+// What a key does when pressed, the held actions are undone on the release
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MacroAction {
+    PlayStop,
+    // a 1/16 roll on the main voice, until pressed again
+    Fill,
+    // every track to a variation, 0 for A, at the next bar line
+    Variation(usize),
+    DoubleTempo,
+    MuteDrums,
+}
+
+pub const MACRO_ACTIONS: [MacroAction; 4 + VARIATION_COUNT] = [
+    MacroAction::PlayStop,
+    MacroAction::Fill,
+    MacroAction::Variation(0),
+    MacroAction::Variation(1),
+    MacroAction::Variation(2),
+    MacroAction::Variation(3),
+    MacroAction::DoubleTempo,
+    MacroAction::MuteDrums,
+];
+
+impl Display for MacroAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MacroAction::PlayStop => write!(f, "Play / stop"),
+            MacroAction::Fill => write!(f, "Toggle fill"),
+            MacroAction::Variation(index) => {
+                write!(f, "Jump to pattern {}", VARIATION_NAMES[index])
+            }
+            MacroAction::DoubleTempo => write!(f, "Double tempo while held"),
+            MacroAction::MuteDrums => write!(f, "Mute drums"),
+        }
+    }
+}
+
+impl MacroAction {
+    pub fn is_held(&self) -> bool {
+        *self == MacroAction::DoubleTempo
+    }
+}
+
+// A key, by the name the UI gives it, and its action
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: String,
+    pub action: MacroAction,
+}
+
+// The bindings, saved to the config directory on every change
+pub struct KeyMacros {
+    pub enabled: bool,
+    pub bindings: Vec<KeyBinding>,
+    // binding waiting for the next key pressed
+    pub learning: Option<usize>,
+}
+
+impl KeyMacros {
+    // The saved bindings, a few defaults the first time
+    pub fn load() -> KeyMacros {
+        let mut bindings = storage::load_json(KEY_MACROS_FILE).unwrap_or_else(|| {
+            [
+                ("F", MacroAction::Fill),
+                ("B", MacroAction::Variation(1)),
+                ("T", MacroAction::DoubleTempo),
+                ("M", MacroAction::MuteDrums),
+            ]
+            .map(|(key, action)| KeyBinding {
+                key: key.to_string(),
+                action,
+            })
+            .to_vec()
+        });
+        // a hand edited file may name a variation that does not exist
+        bindings.retain(|binding| {
+            !matches!(binding.action, MacroAction::Variation(index) if index >= VARIATION_COUNT)
+        });
+        KeyMacros {
+            enabled: false,
+            bindings,
+            learning: None,
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = storage::save_json(KEY_MACROS_FILE, &self.bindings) {
+            eprintln!("Could not save the key macros: {}", err);
+        }
+    }
+
+    // Actions bound to a key
+    pub fn actions<'a>(&'a self, key: &'a str) -> impl Iterator<Item = MacroAction> + 'a {
+        self.bindings
+            .iter()
+            .filter(move |binding| binding.key == key)
+            .map(|binding| binding.action)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::drums::DRUM_NOTES;
+
+//constants
+pub const LAYER_BASS_CHANNEL: u8 = 4;
+pub const LAYER_HATS_CHANNEL: u8 = 5;
+// closed hi-hat, the drum sounds of the internal audio play it as well
+const HAT_NOTE: u8 = DRUM_NOTES[2];
+// the divisions count in sixteenths
+const STEPS_PER_BEAT: u64 = 4;
+const BASS_VELOCITY: u8 = 100;
+const HAT_VELOCITY: u8 = 60;
+// on the hats following a note of the main line
+const HAT_ACCENT_VELOCITY: u8 = 100;
+// share of its division a layer note sounds for
+const LAYER_NOTE_LENGTH: f32 = 0.9;
+
+// Two layers following the main line on the same clock: a bass playing its last note below it
+// every few sixteenths, and hats on a finer division. Divisions that don't go into the bar make
+// the layers drift across it.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LayerSettings {
+    pub enabled: bool,
+    // sixteenths between two bass notes
+    pub bass_steps: u32,
+    pub bass_octaves: u32,
+    // sixteenths between two hats
+    pub hat_steps: u32,
+}
+
+pub struct LayerNote {
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+    pub beats: f32,
+}
+
+// The derivation rules, fed with the notes of the main line
+pub struct DerivedLayers {
+    pub settings: LayerSettings,
+    last_note: Option<u8>,
+    // the main line started a note since the last hat
+    main_played: bool,
+}
+
+impl DerivedLayers {
+    pub fn new(settings: LayerSettings) -> DerivedLayers {
+        DerivedLayers {
+            settings,
+            last_note: None,
+            main_played: false,
+        }
+    }
+
+    pub fn follow(&mut self, note: u8) {
+        self.last_note = Some(note);
+        self.main_played = true;
+    }
+
+    // Notes of the layers starting on the tick, the bass waits for the first note of the main line
+    pub fn tick(&mut self, tick: u64, ticks_per_beat: u64) -> Vec<LayerNote> {
+        let step_ticks = (ticks_per_beat / STEPS_PER_BEAT).max(1);
+        if !self.settings.enabled || tick % step_ticks != 0 {
+            return Vec::new();
+        }
+        let step = tick / step_ticks;
+        let beats_per_step = step_ticks as f32 / ticks_per_beat as f32;
+        let mut notes = Vec::new();
+        let bass_steps = self.settings.bass_steps.max(1);
+        if let Some(note) = self.last_note.filter(|_| step % bass_steps as u64 == 0) {
+            let below = 12 * self.settings.bass_octaves as i32;
+            notes.push(LayerNote {
+                channel: LAYER_BASS_CHANNEL,
+                note: (note as i32 - below).max(0) as u8,
+                velocity: BASS_VELOCITY,
+                beats: bass_steps as f32 * beats_per_step * LAYER_NOTE_LENGTH,
+            });
+        }
+        let hat_steps = self.settings.hat_steps.max(1);
+        if step % hat_steps as u64 == 0 {
+            notes.push(LayerNote {
+                channel: LAYER_HATS_CHANNEL,
+                note: HAT_NOTE,
+                velocity: if self.main_played {
+                    HAT_ACCENT_VELOCITY
+                } else {
+                    HAT_VELOCITY
+                },
+                beats: hat_steps as f32 * beats_per_step * LAYER_NOTE_LENGTH,
+            });
+            self.main_played = false;
+        }
+        notes
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    sync::{RwLock, RwLockReadGuard},
+};
+
+use pitch_calc::*;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{
+    self, letter_from_name, letter_name, note_duration_from_symbol, NoteDurationLetter,
+    RhythmPattern,
+};
+use crate::storage::config_dir;
+
+//constants
+const SCALES_FILE: &str = "scales.toml";
+const RHYTHMS_FILE: &str = "rhythms.toml";
+const INSTRUMENTS_FILE: &str = "instruments.toml";
+
+pub struct Scale {
+    pub name: String,
+    pub notes: Vec<Letter>,
+}
+
+pub struct Instrument {
+    pub program: u8,
+    pub name: String,
+    pub category: &'static str,
+}
+
+impl Instrument {
+    fn new(program: usize, name: String) -> Instrument {
+        Instrument {
+            program: program as u8,
+            name,
+            category: assets::GM_CATEGORIES[program / assets::INSTRUMENTS_PER_GM_CATEGORY],
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{:03} {}", self.program, self.name)
+    }
+
+    // case-insensitive match on the name or the category
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.trim().to_lowercase();
+        search.is_empty()
+            || self.name.to_lowercase().contains(&search)
+            || self.category.to_lowercase().contains(&search)
+    }
+}
+
+// Scales, rhythm patterns and instrument names available to the UI.
+// Built-ins are always present, user TOML files add scales and rhythms and can rename instruments.
+pub struct AssetLibrary {
+    pub scales: Vec<Scale>,
+    pub rhythm_patterns: Vec<RhythmPattern>,
+    pub instruments: Vec<Instrument>,
+}
+
+static LIBRARY: RwLock<AssetLibrary> = RwLock::new(AssetLibrary {
+    scales: Vec::new(),
+    rhythm_patterns: Vec::new(),
+    instruments: Vec::new(),
+});
+
+pub fn library() -> RwLockReadGuard<'static, AssetLibrary> {
+    LIBRARY.read().unwrap()
+}
+
+// (Re)load the library from the config directory, called at startup and by "Reload assets"
+pub fn reload_library() {
+    let mut library = AssetLibrary::built_in();
+    library.scales.extend(load_user_scales());
+    library.rhythm_patterns.extend(load_user_rhythm_patterns());
+    if let Some(names) = load_user_instruments() {
+        library.instruments = instruments_from_names(names);
+    }
+    *LIBRARY.write().unwrap() = library;
+}
+
+impl AssetLibrary {
+    fn built_in() -> AssetLibrary {
+        AssetLibrary {
+            scales: assets::SCALES
+                .iter()
+                .map(|scale| Scale {
+                    name: scale.name.to_string(),
+                    notes: scale.notes.to_vec(),
+                })
+                .collect(),
+            rhythm_patterns: assets::RHYTHM_PATTERNS
+                .iter()
+                .map(RhythmPattern::from)
+                .collect(),
+            instruments: instruments_from_names(
+                assets::INSTRUMENT_LIST
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn instruments_from_names(names: Vec<String>) -> Vec<Instrument> {
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(program, name)| Instrument::new(program, name))
+        .collect()
+}
+
+// file formats
+
+#[derive(Serialize, Deserialize)]
+struct ScalesFile {
+    scale: Vec<ScaleEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScaleEntry {
+    name: String,
+    notes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RhythmsFile {
+    rhythm: Vec<RhythmEntry>,
+}
+
+#[derive(Deserialize)]
+struct RhythmEntry {
+    name: String,
+    durations: Vec<String>,
+    notes_per_beat: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+struct InstrumentsFile {
+    instruments: Vec<String>,
+}
+
+fn read_toml<T: for<'de> Deserialize<'de>>(file_name: &str) -> Option<T> {
+    let path = config_dir().join(file_name);
+    let contents = fs::read_to_string(&path).ok()?;
+    match toml::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            eprintln!("Ignoring {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+fn load_user_scales() -> Vec<Scale> {
+    let Some(file) = read_toml::<ScalesFile>(SCALES_FILE) else {
+        return Vec::new();
+    };
+    file.scale
+        .into_iter()
+        .filter_map(|entry| {
+            let notes: Option<Vec<Letter>> =
+                entry.notes.iter().map(|n| letter_from_name(n)).collect();
+            match notes {
+                Some(notes) if !notes.is_empty() => Some(Scale {
+                    name: entry.name,
+                    notes,
+                }),
+                _ => {
+                    eprintln!("Ignoring scale {}: invalid note names", entry.name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Appends a scale to the user scales file, leaving the rest of the file as written.
+// The library has to be reloaded to see it.
+pub fn add_user_scale(name: &str, notes: &[Letter]) -> io::Result<()> {
+    let entry = ScalesFile {
+        scale: vec![ScaleEntry {
+            name: name.to_string(),
+            notes: notes.iter().map(|n| letter_name(*n).to_string()).collect(),
+        }],
+    };
+    let contents = toml::to_string(&entry).map_err(io::Error::other)?;
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(SCALES_FILE))?;
+    write!(file, "\n{}", contents)
+}
+
+fn load_user_rhythm_patterns() -> Vec<RhythmPattern> {
+    let Some(file) = read_toml::<RhythmsFile>(RHYTHMS_FILE) else {
+        return Vec::new();
+    };
+    file.rhythm
+        .into_iter()
+        .filter_map(|entry| {
+            let durations: Option<Vec<NoteDurationLetter>> = entry
+                .durations
+                .iter()
+                .map(|d| note_duration_from_symbol(d))
+                .collect();
+            if entry.notes_per_beat.is_empty() {
+                eprintln!("Ignoring rhythm {}: no beats", entry.name);
+                return None;
+            }
+            match durations {
+                Some(durations) if !durations.is_empty() => Some(RhythmPattern {
+                    name: entry.name,
+                    durations,
+                    notes_per_beat: entry.notes_per_beat,
+                }),
+                _ => {
+                    eprintln!("Ignoring rhythm {}: invalid durations", entry.name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// The instrument list replaces the GM names only if it still covers all 128 programs
+fn load_user_instruments() -> Option<Vec<String>> {
+    let file = read_toml::<InstrumentsFile>(INSTRUMENTS_FILE)?;
+    if file.instruments.len() != assets::INSTRUMENT_LIST.len() {
+        eprintln!(
+            "Ignoring {}: expected {} instrument names",
+            INSTRUMENTS_FILE,
+            assets::INSTRUMENT_LIST.len()
+        );
+        return None;
+    }
+    Some(file.instruments)
+}
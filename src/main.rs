@@ -1,91 +1,691 @@
+mod abc;
+mod accent;
+mod accessibility;
+mod ambient;
 mod assets;
+mod audio;
+mod bass;
+mod cadence;
+mod call_response;
+mod chain;
+mod chaos;
+mod chord;
+mod clock;
+mod collision;
+mod density;
+mod device;
+mod drums;
+mod effects;
+mod envelope;
+mod evolve;
+mod export;
+mod fade;
+mod grid;
+mod guide;
+mod key_detection;
+mod key_macros;
+mod layers;
+mod library;
+mod manual;
+mod midi_input;
+mod migration;
+mod network;
+mod notation;
+mod note_event;
+mod notes;
+mod params;
+mod patch;
+mod phrase;
 mod pitch;
+mod plugins;
+mod preset;
+mod preset_file;
+#[cfg(feature = "profile")]
+mod profile;
+mod recorder;
+mod registry;
+mod rhythm;
+mod roll;
+mod routing;
+mod scheduler;
+mod scope;
+mod scripts;
+mod seed;
 mod sequencer;
+mod session;
+mod sink;
+mod slew;
+mod slots;
+mod soundfont;
+mod split;
+mod statistics;
+mod storage;
+mod sustain;
+mod synth;
+mod tempo;
+mod tension;
+mod timbre;
+mod transport;
 mod trigger;
+mod ump;
+mod variations;
+mod velocity;
+mod visuals;
+mod voices;
 
-use std::str::FromStr;
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
-use assets::{format_letter_octave, NoteDurationLetter, INSTRUMENT_LIST};
+use abc::MelodyImport;
+use accent::DownbeatAccent;
+use accessibility::{Accessibility, UI_SCALES};
+use ambient::AmbientSettings;
+use assets::{note_duration_symbol, RhythmPattern, GROOVE_TEMPLATES, NOTE_DURATION_LETTERS};
+use audio::{AudioEngine, SoundSettings};
+use bass::{BassPattern, BassSettings, BASS_PATTERNS};
+use cadence::{CadenceSettings, CadenceTarget, CADENCE_TARGETS};
+use call_response::{CallResponseSettings, ResponseTransform, RESPONSE_TRANSFORMS};
+use chain::ChainParameter;
+use chaos::*;
+use chord::{recognize_chord, Chord};
+use collision::{CollisionAvoidance, COLLISION_AVOIDANCES};
+use density::DensityCompensation;
+use device::AudioDevicePanel;
+use drums::{DrumSettings, DrumVoiceSettings};
+use effects::{EffectSettings, TrackSends, TRACK_COUNT};
+use envelope::PressureEnvelope;
+use evolve::Evolve;
+use export::{ExportAction, ExportSettings, MAX_EXPORT_BARS, MIN_EXPORT_BARS};
+use fade::{FadeSettings, TransportPhase};
+use grid::{GridAction, GridController, GridEvent, GridFrame, GridLayout, GRID_LAYOUTS};
+use guide::GuideImport;
+use key_detection::{DetectedKey, KeyDetection};
+use key_macros::{KeyBinding, KeyMacros, MacroAction, MACRO_ACTIONS};
+use layers::LayerSettings;
+use library::*;
+use midi_input::{MidiInputListener, RemoteTransport};
 use nannou::prelude::*;
 use nannou_egui::{
     egui::{self, RichText},
     Egui,
 };
-use pitch::PitchProducerType;
+use network::{NetworkSyncPanel, SyncRole, SYNC_ROLES};
+use notation::NOTATION_FORMATS;
+use note_event::NoteProcessorSettings;
+use notes::{
+    format_letter_octave, note_name_style, pitch_class_name, set_note_name_style, NOTE_NAMINGS,
+    PITCH_CLASS_COUNT,
+};
+use params::*;
+use patch::{PatchAction, PatchPanel};
+use phrase::PhraseSettings;
+use pitch::{
+    scale_mask, transpose_scale, Direction, KeyModulation, PitchChainStage, PitchStage, RangeMode,
+};
 use pitch_calc::*;
+use plugins::PluginLibrary;
+use preset_file::{
+    decode_thumbnail, BrowserAction, PresetBrowser, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH,
+};
+#[cfg(feature = "profile")]
+use profile::PROFILE_TICKS_DEFAULT_VALUE;
+use registry::{producer_registry, ProducerSetting, ReloadedProducers};
+use rhythm::*;
+use roll::{RollRate, ROLL_RATES};
+use routing::{RoutingAction, RoutingTable};
+use scope::Scope;
+use scripts::ScriptLibrary;
+use seed::SeedSettings;
 use sequencer::*;
+use session::{Autosave, RecoveryAction};
+use sink::MidiSink;
+use slew::SlewedParameter;
+use slots::{PatternSlots, SlotAction, SLOT_COUNT};
+use split::KeyboardSplit;
+use statistics::{StatisticsSummary, MAX_STATISTICS_BARS};
+use sustain::{SustainAutomation, SustainMode};
+use synth::{SynthSettings, Waveform, WAVEFORMS};
+use tempo::TempoFollow;
+use tension::{TensionSettings, TensionShape};
+use timbre::TimbreDrift;
+use trigger::{LogicOperation, StepLights, TriggerStage, TRIGGER_STAGES};
+use ump::{MidiProtocol, MIDI_PROTOCOLS};
+use variations::{
+    cycle_text, parse_cycle, vary_bass, vary_drums, vary_melody, ActiveVariations, BassVariation,
+    PatternVariations, TrackVariations, MAX_VARIATION_NOTES_PER_BEAT, VARIATION_COUNT,
+    VARIATION_NAMES,
+};
+use velocity::{VelocityCurve, VelocityCurves, CURVE_NAMES};
+use visuals::{VisualStyle, Visuals, VISUAL_STYLES};
+use voices::{StealPolicy, VoiceSettings, STEAL_POLICIES};
 
 //constants
 const WINDOW_NAME: &str = "Sound generator";
 
 const INSTRUMENT_DEFAULT_VALUE: u8 = 10;
+// acoustic bass below middle C
+const SPLIT_DEFAULT_VALUE: KeyboardSplit = KeyboardSplit {
+    enabled: false,
+    point: 60,
+    instrument: 32,
+};
+// all off, a fifth above with a soft dotted-eighth echo once turned on
+const NOTE_PROCESSORS_DEFAULT_VALUE: NoteProcessorSettings = NoteProcessorSettings {
+    harmony: false,
+    harmony_degrees: 4,
+    strum: false,
+    strum_ms: 20.0,
+    echo: false,
+    echo_repeats: 2,
+    echo_delay: 0.75,
+    echo_decay: 0.5,
+    humanize: false,
+    humanize_ms: 10.0,
+    humanize_velocity: 8,
+};
+const MAX_STRUM_MS: f32 = 100.0;
+const MAX_ECHO_REPEATS: u32 = 8;
+const MIN_ECHO_DELAY: f32 = 0.125;
+const MAX_ECHO_DELAY: f32 = 2.0;
+const MAX_HUMANIZE_MS: f32 = 50.0;
+const MAX_HUMANIZE_VELOCITY: u8 = 30;
+// electric bass, two octaves below the melody
+const BASS_DEFAULT_VALUE: BassSettings = BassSettings {
+    enabled: false,
+    pattern: BassPattern::Root,
+    octaves_below: 2,
+    instrument: 33,
+};
+const MAX_BASS_OCTAVES_BELOW: u32 = 3;
+// a bass every three sixteenths and eighth hats, drifting against the bar
+const LAYERS_DEFAULT_VALUE: LayerSettings = LayerSettings {
+    enabled: false,
+    bass_steps: 3,
+    bass_octaves: 1,
+    hat_steps: 2,
+};
+const MAX_LAYER_STEPS: u32 = 16;
+const MAX_LAYER_OCTAVES: u32 = 3;
+const TIMBRE_DRIFT_DEFAULT_VALUE: TimbreDrift = TimbreDrift {
+    enabled: false,
+    every_bars: 8,
+    programs: Vec::new(),
+};
+const MAX_TIMBRE_DRIFT_BARS: u32 = 64;
+// bars each variation of a cycle plays for
+const MAX_VARIATION_BARS: u32 = 16;
+const CADENCE_DEFAULT_VALUE: CadenceSettings = CadenceSettings {
+    enabled: false,
+    phrase_bars: 4,
+    target: CadenceTarget::Root,
+};
+const MIN_CADENCE_PHRASE_BARS: u32 = 1;
+const MAX_CADENCE_PHRASE_BARS: u32 = 16;
+const SCALE_LOCK_DEFAULT_VALUE: bool = false;
+const COLLISION_AVOIDANCE_DEFAULT_VALUE: CollisionAvoidance = CollisionAvoidance::Off;
+const MIDI_PROTOCOL_DEFAULT_VALUE: MidiProtocol = MidiProtocol::Midi1;
+const VOICES_DEFAULT_VALUE: VoiceSettings = VoiceSettings {
+    max_voices: 16,
+    steal: StealPolicy::Oldest,
+};
+const MAX_VOICES: usize = 64;
+const VELOCITY_CURVE_EDITOR_SIZE: f32 = 160.0;
+const VELOCITY_CURVE_SEGMENTS: usize = 64;
+const VELOCITY_CURVE_POINT_RADIUS: f32 = 4.0;
+// how far an arrow key moves the selected point, across and up
+const VELOCITY_CURVE_NUDGE: f32 = 0.02;
 const BPM_DEFAULT_VALUE: f32 = 160.0;
 const MIN_BPM_VALUE: f32 = 60.0;
 const MAX_BPM_VALUE: f32 = 240.0;
 const QUANTIZER_SCALE_INDEX_DEFAULT_VALUE: usize = 1;
-const QUANTIZER_SCALES: &[(&[Letter], &str)] = &[
-    (assets::CHROMATIC_SCALE_NOTES, "Chromatic"),
-    (assets::MAJOR_SCALE_NOTES, "Major"),
-    (assets::MINOR_SCALE_NOTES, "Minor"),
-    (assets::MAJOR_PENTATONIC_SCALE_NOTES, "Major Pentatonic"),
-    (assets::MINOR_PENTATONIC_SCALE_NOTES, "Minor Pentatonic"),
-];
+const SCALE_ROOT_DEFAULT_VALUE: usize = 0;
+const DETECTION_BARS_DEFAULT_VALUE: u32 = 4;
+const MIN_DETECTION_BARS: u32 = 1;
+const MAX_DETECTION_BARS: u32 = 16;
+const EVOLVE_BARS_DEFAULT_VALUE: u32 = 4;
+const MIN_EVOLVE_BARS: u32 = 1;
+const MAX_EVOLVE_BARS: u32 = 32;
+const EVOLVE_STEP_DEFAULT_VALUE: f32 = 0.05;
+const MAX_EVOLVE_STEP: f32 = 0.5;
 
 const DEFAULT_CYCLE_LENGTH: u32 = 64;
 const MIN_CYCLE_LENGTH: u32 = 16;
 const MAX_CYCLE_LENGTH: u32 = 128;
+const MAX_RHYTHM_ROTATION: u32 = 16;
 const PITCH_MIN_VALUE: LetterOctave = LetterOctave(Letter::C, 0);
 const PITCH_MAX_VALUE: LetterOctave = LetterOctave(Letter::C, 7);
 const MIN_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 3);
 const MAX_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 5);
-const PITCH_PRODUCER_TYPE_DEFAULT_VALUE: usize = 0;
-const PITCH_PRODUCER_TYPE_NAMES: &[&str] = &["Ramp", "Square", "Sine", "Random"];
-
-const RHYTHM_PATTERNS: &[(&[NoteDurationLetter], &str)] = &[
-    (assets::STRAIGHT_RHYTHM_PATTERN, "Straight"),
-    (assets::SYNCOPATED_RHYTHM_PATTERN, "Syncopated"),
-    (assets::FAST_RHYTHM_PATTERN, "Fast"),
-    (assets::LONG_AND_SHORT_RHYTHM_PATTERN, "Long and Short"),
-    (assets::COMPLEX_RHYTHM_PATTERN, "Complex"),
+const PITCH_PRODUCER_DEFAULT_VALUE: usize = 0;
+const TRIGGER_PRODUCER_DEFAULT_VALUE: usize = 0;
+const RANGE_MODE_DEFAULT_VALUE: usize = 0;
+const DIRECTION_DEFAULT_VALUE: usize = 0;
+const RANGE_MODE_NAMES: &[&str] = &["Clamp", "Fold", "Wrap"];
+const DIRECTION_NAMES: &[&str] = &["Forward", "Reverse", "Ping-pong", "Random"];
+const OCTAVE_JUMP_PROBABILITY_DEFAULT_VALUE: f64 = 0.0;
+const SAMPLE_HOLD_STEPS_DEFAULT_VALUE: u32 = 1;
+const MAX_SAMPLE_HOLD_STEPS: u32 = 16;
+const PITCH_CHAIN_DEFAULT_VALUE: &[PitchChainStage] = &[
+    PitchChainStage {
+        stage: PitchStage::SampleHold,
+        enabled: true,
+    },
+    PitchChainStage {
+        stage: PitchStage::Quantizer,
+        enabled: true,
+    },
+    PitchChainStage {
+        stage: PitchStage::OctaveJumps,
+        enabled: true,
+    },
+    PitchChainStage {
+        stage: PitchStage::Transpose,
+        enabled: false,
+    },
+    PitchChainStage {
+        stage: PitchStage::RangeClamp,
+        enabled: true,
+    },
+    PitchChainStage {
+        stage: PitchStage::Harmony,
+        enabled: false,
+    },
+    PitchChainStage {
+        stage: PitchStage::VoiceLeading,
+        enabled: false,
+    },
 ];
-const RHYTHM_PATTERN_DEFAULT_VALUE: usize = 0;
-const NOTES_PER_BEAT: &[[u32; 4]] = &[
-    assets::BEAT_PER_BAR_DIVIDE_FOR_FOUR,
-    assets::BEAT_PER_BAR_DIVIDE_FOR_SIX,
-    assets::BEAT_PER_BAR_DIVIDE_FOR_EIGTH,
-    assets::BEAT_PER_BAR_DIVIDE_FOR_FOUR,
-    assets::BEAT_PER_BAR_DIVIDE_FOR_SEVEN,
+const TRANSPOSE_DEFAULT_VALUE: i32 = 0;
+const MAX_TRANSPOSE: i32 = 24;
+// a third above
+const HARMONY_STEPS_DEFAULT_VALUE: i32 = 2;
+const MAX_HARMONY_STEPS: i32 = 7;
+// a fifth
+const MAX_INTERVAL_DEFAULT_VALUE: i32 = 7;
+const MAX_INTERVAL_LIMIT: i32 = 24;
+const REPEAT_AVOIDANCE_DEFAULT_VALUE: f64 = 0.5;
+const REST_PROBABILITY_DEFAULT_VALUE: f64 = 0.0;
+const BEAT_WEIGHT_DEFAULT_VALUE: f32 = 1.0;
+const MAX_BEAT_WEIGHT: f32 = 2.0;
+const TRIGGER_CHAIN_DEFAULT_VALUE: &[TriggerStage] =
+    &[TriggerStage::BeatDivision, TriggerStage::RestGate];
+const CLOCK_DIVISION_DEFAULT_VALUE: u32 = 1;
+const MAX_CLOCK_DIVISION: u32 = 8;
+// half and double time and beyond, for the main voice against the tempo
+const TRACK_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const TRACK_SPEED_NAMES: [&str; 5] = ["1/4x", "1/2x", "1x", "2x", "4x"];
+const TRACK_SPEED_DEFAULT_VALUE: usize = 2;
+const SWING_DEFAULT_VALUE: f32 = 0.0;
+const MAX_SWING: f32 = 0.5;
+const RATCHET_COUNT_DEFAULT_VALUE: u32 = 2;
+const MAX_RATCHET_COUNT: u32 = 4;
+const LOGIC_OPERATION_NAMES: &[&str] = &["And", "Or", "Xor"];
+const LOGIC_OPERATION_DEFAULT_VALUE: usize = 0;
+// the quarter notes
+const LOGIC_STEPS_DEFAULT_VALUE: u16 = 0x1111;
+const GROOVE_DEFAULT_VALUE: usize = 0;
+const TRIGGER_PROBABILITY_DEFAULT_VALUE: f64 = 1.0;
+const VELOCITY_JITTER_DEFAULT_VALUE: f32 = 0.0;
+const ACCENT_DEFAULT_VALUE: DownbeatAccent = DownbeatAccent {
+    enabled: false,
+    third_beat: true,
+    amount: 20,
+};
+// velocity added at most
+const MAX_ACCENT: u8 = 40;
+const DENSITY_COMPENSATION_DEFAULT_VALUE: DensityCompensation = DensityCompensation {
+    enabled: false,
+    amount: 8,
+};
+// velocity taken off at each doubling of the density, at most
+const MAX_DENSITY_COMPENSATION: u8 = 24;
+const FADE_DEFAULT_VALUE: FadeSettings = FadeSettings {
+    enabled: false,
+    bars: 2,
+};
+const MAX_FADE_BARS: u32 = 16;
+const NOTE_LENGTH_DEFAULT_VALUE: f32 = 1.0;
+const DEGREE_LANE_DEFAULT_VALUE: &[u8] = &[1, 3, 5, 3, 4, 2, 5, 1];
+const MAX_DEGREE: u8 = 7;
+const MAX_DEGREE_LANE_STEPS: usize = 16;
+const INTERVALS_DEFAULT_VALUE: &[i32] = &[2, 2, -1, 3, -2, -1];
+const MAX_INTERVAL: i32 = 7;
+const MAX_INTERVALS: usize = 16;
+const MIN_NOTE_LENGTH: f32 = 0.25;
+const MAX_NOTE_LENGTH: f32 = 4.0;
+const PRESSURE_ENVELOPE_DEFAULT_VALUE: PressureEnvelope = PressureEnvelope {
+    enabled: false,
+    attack_ms: 80.0,
+    decay_ms: 400.0,
+    depth: 100.0,
+};
+const MAX_PRESSURE_ENVELOPE_TIME_MS: f32 = 2000.0;
+// up a whole step every 16 bars
+const KEY_MODULATION_DEFAULT_VALUE: KeyModulation = KeyModulation {
+    enabled: false,
+    interval: 2,
+    bars: 16,
+};
+const MAX_MODULATION_BARS: u32 = 64;
+const PHRASE_DEFAULT_VALUE: PhraseSettings = PhraseSettings {
+    enabled: false,
+    motif_length: 4,
+    statements: 4,
+    seed_from_melody: false,
+};
+const SEED_DEFAULT_VALUE: SeedSettings = SeedSettings {
+    per_bar: false,
+    master: 0,
+};
+// stays exact as a parameter value
+const MAX_SEED: u32 = 65535;
+const MIN_MOTIF_LENGTH: usize = 4;
+const MAX_MOTIF_LENGTH: usize = 8;
+const MIN_PHRASE_STATEMENTS: usize = 2;
+const MAX_PHRASE_STATEMENTS: usize = 8;
+const TENSION_SHAPE_DEFAULT_VALUE: usize = 0;
+const TENSION_SHAPE_NAMES: &[&str] = &["Off", "Linear", "Arch", "Exponential"];
+const TENSION_PHRASE_BARS_DEFAULT_VALUE: u32 = 8;
+const MIN_TENSION_PHRASE_BARS: u32 = 2;
+const MAX_TENSION_PHRASE_BARS: u32 = 32;
+const TENSION_DEPTH_DEFAULT_VALUE: f32 = 0.5;
+const CALL_RESPONSE_DEFAULT_VALUE: CallResponseSettings = CallResponseSettings {
+    enabled: false,
+    transform: ResponseTransform::Transpose,
+    instrument: 0,
+};
+const AMBIENT_DEFAULT_VALUE: AmbientSettings = AmbientSettings {
+    enabled: false,
+    density: 0.15,
+    note_length: 8.0,
+    drift: 0.25,
+    max_voices: 6,
+};
+const MIN_AMBIENT_NOTE_LENGTH: f32 = 1.0;
+const MAX_AMBIENT_NOTE_LENGTH: f32 = 16.0;
+const MAX_AMBIENT_VOICES: usize = 16;
+const SLEW_BEATS_DEFAULT_VALUE: f32 = 0.0;
+const MAX_SLEW_BEATS: f32 = 16.0;
+const MAX_AUTO_RESTARTS: u32 = 3;
+const RESOLUTION_DEFAULT_VALUE: u32 = 96;
+const RESOLUTION_OPTIONS: &[u32] = &[24, 40, 48, 96, 192, 384, 480, 960];
+const SYNTH_DEFAULT_VALUE: SynthSettings = SynthSettings {
+    waveform: Waveform::Saw,
+    fm_ratio: 2.0,
+    fm_index: 0.0,
+    cutoff: 2000.0,
+    resonance: 0.2,
+    attack: 0.005,
+    decay: 0.2,
+    sustain: 0.7,
+    release: 0.3,
+};
+const MIN_FM_RATIO: f32 = 0.5;
+const MAX_FM_RATIO: f32 = 8.0;
+const MAX_FM_INDEX: f32 = 10.0;
+const MIN_CUTOFF: f32 = 20.0;
+const MAX_CUTOFF: f32 = 20_000.0;
+const MAX_RESONANCE: f32 = 0.95;
+const EFFECTS_DEFAULT_VALUE: EffectSettings = EffectSettings {
+    delay_beats: 0.75,
+    delay_feedback: 0.35,
+    reverb_size: 0.5,
+    reverb_damping: 0.5,
+    sends: [TrackSends {
+        delay: 0.0,
+        reverb: 0.2,
+    }; TRACK_COUNT],
+    master_volume: 0.8,
+    limiter: true,
+};
+const MAX_MASTER_VOLUME: f32 = 2.0;
+const TRACK_NAMES: [&str; TRACK_COUNT] = [
+    "Lead",
+    "Response",
+    "Split",
+    "Bass",
+    "Drums",
+    "Derived bass",
+    "Derived hats",
 ];
+const DRUMS_DEFAULT_VALUE: DrumSettings = DrumSettings {
+    enabled: false,
+    voices: [
+        // four on the floor, backbeat and eighth hi-hats
+        DrumVoiceSettings {
+            steps: 0x1111,
+            gain: 1.0,
+            pitch: 0.0,
+        },
+        DrumVoiceSettings {
+            steps: 0x1010,
+            gain: 0.8,
+            pitch: 0.0,
+        },
+        DrumVoiceSettings {
+            steps: 0x5555,
+            gain: 0.5,
+            pitch: 0.0,
+        },
+    ],
+};
+const DRUM_VOICE_NAMES: [&str; 3] = ["Kick", "Snare", "Hi-hat"];
+const MAX_DRUM_GAIN: f32 = 2.0;
+const MIN_DRUM_PITCH: f32 = -12.0;
+const MAX_DRUM_PITCH: f32 = 12.0;
+const STATISTICS_BARS_DEFAULT_VALUE: usize = 8;
+const AUDIO_BUFFER_SIZES: [u32; 6] = [64, 128, 256, 512, 1024, 2048];
+const SYNC_PORT_DEFAULT_VALUE: u16 = 47_100;
+const MIN_DELAY_BEATS: f32 = 0.25;
+const MAX_DELAY_BEATS: f32 = 2.0;
+const MAX_DELAY_FEEDBACK: f32 = 0.9;
+const MIN_ENVELOPE_SECONDS: f32 = 0.001;
+const MAX_ENVELOPE_SECONDS: f32 = 4.0;
+const SUSTAIN_MODE_DEFAULT_VALUE: usize = 0;
+const SUSTAIN_MODE_NAMES: &[&str] = &["Off", "Bar", "Phrase", "Random"];
+const SUSTAIN_PHRASE_BARS_DEFAULT_VALUE: u32 = 4;
+const MIN_SUSTAIN_PHRASE_BARS: u32 = 1;
+const MAX_SUSTAIN_PHRASE_BARS: u32 = 16;
+const SUSTAIN_PROBABILITY_DEFAULT_VALUE: f64 = 0.5;
+
+const RHYTHM_PATTERN_DEFAULT_VALUE: usize = 0;
+const MAX_BEATS_PER_BAR: usize = 8;
+const LOOP_BARS_OPTIONS: &[u32] = &[1, 2, 4, 8, 16];
+const STEP_LIGHT_SIZE: f32 = 10.0;
 
 fn main() {
-    nannou::app(model).update(update).run();
+    // `sound-generator profile [ticks]` times the producers instead of starting the app,
+    // in builds with the profile feature, which counts every allocation of the program
+    #[cfg(feature = "profile")]
+    if let Some("profile") = std::env::args().nth(1).as_deref() {
+        let ticks = std::env::args()
+            .nth(2)
+            .and_then(|ticks| ticks.parse().ok())
+            .unwrap_or(PROFILE_TICKS_DEFAULT_VALUE);
+        reload_library();
+        // the scripts and plugins are producers too
+        ScriptLibrary::new().reload_all();
+        PluginLibrary::new().reload();
+        profile::run(default_sequencer_model().into(), ticks, MAX_BPM_VALUE);
+        return;
+    }
+    nannou::app(model).update(update).exit(exit).run();
 }
 #[derive(Clone)]
 struct SequencerModel {
     min_pitch: f32,
     max_pitch: f32,
-    pitch_producer_type_index: Option<usize>,
+    // indexes in the producer registry
+    pitch_producer_index: Option<usize>,
+    trigger_producer_index: Option<usize>,
     cycle_length: f32,
+    pitch_rotation: f32,
     rhythm_pattern: Option<usize>,
-    notes_per_beat: [u32; 4],
+    rhythm_rotation: f32,
+    custom_rhythm_patterns: Vec<RhythmPattern>,
     instrument: u8,
+    timbre_drift: TimbreDrift,
     quantizer_scale_index: Option<usize>,
+    scale_root_index: Option<usize>,
+    // chord played on the MIDI input, replaces the scale while following chords
+    chord: Option<Chord>,
+    range_mode_index: Option<usize>,
+    direction_index: Option<usize>,
+    octave_jump_probability: f64,
+    sample_hold_steps: f32,
+    pitch_chain: Vec<PitchChainStage>,
+    transpose: f32,
+    harmony_steps: f32,
+    max_interval: f32,
+    repeat_avoidance: f64,
+    rest_probability: f64,
+    trigger_chain: Vec<TriggerStage>,
+    clock_division: f32,
+    swing: f32,
+    ratchet_count: f32,
+    logic_operation_index: Option<usize>,
+    logic_steps: u16,
+    groove_index: Option<usize>,
+    trigger_probability: f64,
+    beat_weights: Vec<f32>,
+    velocity_jitter: f32,
+    accent: DownbeatAccent,
+    density_compensation: DensityCompensation,
+    fade: FadeSettings,
+    note_length: f32,
+    speed_index: usize,
+    pressure_envelope: PressureEnvelope,
+    key_modulation: KeyModulation,
+    phrase: PhraseSettings,
+    seed: SeedSettings,
+    routing: RoutingTable,
+    midi_protocol: MidiProtocol,
+    voices: VoiceSettings,
+    velocity_curves: VelocityCurves,
+    tension_shape_index: Option<usize>,
+    call_response: CallResponseSettings,
+    ambient: AmbientSettings,
+    tension_phrase_bars: f32,
+    tension_depth: f32,
+    sustain_mode_index: Option<usize>,
+    sustain_phrase_bars: f32,
+    sustain_probability: f64,
+    split: KeyboardSplit,
+    note_processors: NoteProcessorSettings,
+    scale_lock: bool,
+    collision_avoidance: CollisionAvoidance,
+    cadence: CadenceSettings,
+    bass: BassSettings,
+    layers: LayerSettings,
+    drums: DrumSettings,
+    variations: PatternVariations,
+    // MIDI notes of the imported melody
+    melody: Vec<u8>,
+    degree_lane: Vec<u8>,
+    intervals: Vec<i32>,
     bpm: f32,
+    slew_beats: f32,
+    // ticks per quarter note
+    resolution: u32,
 }
+impl SequencerModel {
+    fn selected_rhythm_pattern(&self) -> RhythmPattern {
+        rhythm_pattern(
+            &library(),
+            &self.custom_rhythm_patterns,
+            self.rhythm_pattern.unwrap(),
+        )
+        .clone()
+    }
+}
+
 impl From<SequencerModel> for SequencerConfiguration {
     fn from(model: SequencerModel) -> Self {
+        let library = library();
         SequencerConfiguration {
             min_pitch: Step(model.min_pitch).to_letter_octave(),
             max_pitch: Step(model.max_pitch).to_letter_octave(),
-            pitch_producer_type: pitch_producer_type_from_index(model.pitch_producer_type_index),
+            pitch_producer: producer_registry().pitch_producers
+                [model.pitch_producer_index.unwrap()]
+            .name
+            .clone(),
+            trigger_producer: producer_registry().trigger_producers
+                [model.trigger_producer_index.unwrap()]
+            .name
+            .clone(),
             cycle_length: model.cycle_length as u32,
-            rhythm_pattern: RHYTHM_PATTERNS[model.rhythm_pattern.unwrap()].0.to_vec(),
-            notes_per_beat: NOTES_PER_BEAT[model.rhythm_pattern.unwrap()],
+            rhythm_pattern: rhythm_pattern(
+                &library,
+                &model.custom_rhythm_patterns,
+                model.rhythm_pattern.unwrap(),
+            )
+            .clone(),
+            rhythm_rotation: model.rhythm_rotation as u32,
+            pitch_rotation: model.pitch_rotation as u32,
             instrument: model.instrument,
-            quantizer_scale: QUANTIZER_SCALES[model.quantizer_scale_index.unwrap()]
-                .0
-                .to_vec(),
+            timbre_drift: model.timbre_drift.clone(),
+            quantizer_scale: match model.chord {
+                Some(chord) => chord.tones(),
+                None => key_scale(&library, &model),
+            },
+            root: model
+                .chord
+                .map_or(model.scale_root_index.unwrap(), |chord| chord.root),
+            range_mode: range_mode_from_index(model.range_mode_index),
+            direction: direction_from_index(model.direction_index),
+            octave_jump_probability: model.octave_jump_probability,
+            sample_hold_steps: model.sample_hold_steps as u32,
+            pitch_chain: model.pitch_chain.clone(),
+            transpose: model.transpose as i32,
+            harmony_steps: model.harmony_steps as i32,
+            max_interval: model.max_interval as i32,
+            repeat_avoidance: model.repeat_avoidance,
+            rest_probability: model.rest_probability,
+            trigger_chain: model.trigger_chain.clone(),
+            clock_division: model.clock_division as u32,
+            swing: model.swing,
+            ratchet_count: model.ratchet_count as u32,
+            logic_operation: logic_operation_from_index(model.logic_operation_index),
+            logic_steps: model.logic_steps,
+            groove: &GROOVE_TEMPLATES[model.groove_index.unwrap()],
+            trigger_probability: model.trigger_probability,
+            velocity_jitter: model.velocity_jitter,
+            accent: model.accent,
+            density_compensation: model.density_compensation,
+            fade: model.fade,
+            note_length: model.note_length,
+            speed: TRACK_SPEEDS[model.speed_index],
+            pressure_envelope: model.pressure_envelope,
+            key_modulation: model.key_modulation,
+            sustain: sustain_automation_from_model(&model),
+            split: model.split,
+            note_processors: model.note_processors,
+            scale_lock: model.scale_lock,
+            collision_avoidance: model.collision_avoidance,
+            cadence: model.cadence,
+            bass: model.bass,
+            layers: model.layers,
+            drums: model.drums,
+            variations: model.variations,
+            melody: model.melody,
+            degree_lane: model.degree_lane,
+            intervals: model.intervals,
+            beat_weights: model.beat_weights,
+            phrase: model.phrase,
+            seed: model.seed,
+            routing: model.routing,
+            midi_protocol: model.midi_protocol,
+            voices: model.voices,
+            velocity_curves: model.velocity_curves,
+            call_response: model.call_response,
+            ambient: model.ambient,
+            tension: TensionSettings {
+                shape: tension_shape_from_index(model.tension_shape_index),
+                phrase_bars: model.tension_phrase_bars as u32,
+                depth: model.tension_depth,
+            },
             bpm: model.bpm,
+            slew_beats: model.slew_beats,
+            resolution: model.resolution,
         }
     }
 }
@@ -94,7 +694,155 @@ struct Model {
     egui: Egui,
     sequencer_model: SequencerModel,
     sequencer: Sequencer,
-    is_playing: bool,
+    rhythm_editor: RhythmEditor,
+    chaos: ChaosMacro,
+    evolve: Evolve,
+    instrument_search: String,
+    auto_restart: bool,
+    // automatic restarts since the last manual one
+    restart_count: u32,
+    // kept after a restart so the error stays visible
+    last_failure: Option<String>,
+    midi_input: Option<MidiInputListener>,
+    key_detection: KeyDetection,
+    detection_bars: u32,
+    follow_chords: bool,
+    // Start, Stop, Continue and MMC from the MIDI input drive the transport
+    midi_transport: bool,
+    // program n of the MIDI input recalls slot n + 1
+    program_change_slots: bool,
+    // the roll the sequencer plays, held with the mouse or a MIDI key
+    roll: Option<RollRate>,
+    roll_keys: bool,
+    // the player triggers the notes of the main voice
+    manual_trigger: bool,
+    // the note ons of the MIDI input press the manual trigger
+    manual_keys: bool,
+    // dragging the pitch range plays its boundaries
+    audition_pitch: bool,
+    key_macros: KeyMacros,
+    accessibility: Accessibility,
+    // the fill of the key macros, a 1/16 roll
+    fill: bool,
+    // tempo to go back to once the tempo doubling key is released
+    tempo_before_double: Option<f32>,
+    grid: Option<GridController>,
+    grid_port: Option<String>,
+    grid_layout: GridLayout,
+    // why the grid didn't connect
+    grid_error: Option<String>,
+    tempo_follow: TempoFollow,
+    guide: GuideImport,
+    melody: MelodyImport,
+    export: ExportSettings,
+    patch: PatchPanel,
+    preset_browser: PresetBrowser,
+    // names of the MIDI output ports the tracks can be routed to
+    output_ports: Vec<String>,
+    // GM program whose velocity curve is edited
+    velocity_curve_instrument: u8,
+    // filters the programs of the timbre drift
+    timbre_search: String,
+    // of the browser thumbnails, by preset file
+    preset_thumbnails: HashMap<PathBuf, egui::TextureHandle>,
+    autosave: Autosave,
+    visuals: Visuals,
+    scope: Scope,
+    audio: Option<AudioEngine>,
+    audio_devices: AudioDevicePanel,
+    network_sync: NetworkSyncPanel,
+    scripts: ScriptLibrary,
+    plugins: PluginLibrary,
+    sound: SoundSettings,
+    statistics_bars: usize,
+    // None plays on without looping
+    loop_bars: Option<u32>,
+    // scale, rhythm and instrument changes wait for the next bar line
+    quantize_changes: bool,
+    slots: PatternSlots,
+}
+
+// The settings a first start plays with
+fn default_sequencer_model() -> SequencerModel {
+    let notes_per_beat = library().rhythm_patterns[RHYTHM_PATTERN_DEFAULT_VALUE]
+        .notes_per_beat
+        .clone();
+    SequencerModel {
+        min_pitch: MIN_PITCH_DEFAULT_VALUE.step(),
+        max_pitch: MAX_PITCH_DEFAULT_VALUE.step(),
+        pitch_producer_index: Some(PITCH_PRODUCER_DEFAULT_VALUE),
+        trigger_producer_index: Some(TRIGGER_PRODUCER_DEFAULT_VALUE),
+        cycle_length: DEFAULT_CYCLE_LENGTH as f32,
+        pitch_rotation: 0.0,
+        rhythm_pattern: Some(RHYTHM_PATTERN_DEFAULT_VALUE),
+        rhythm_rotation: 0.0,
+        variations: PatternVariations::new(
+            &notes_per_beat,
+            &DRUMS_DEFAULT_VALUE,
+            &BASS_DEFAULT_VALUE,
+        ),
+        beat_weights: vec![BEAT_WEIGHT_DEFAULT_VALUE; notes_per_beat.len()],
+        custom_rhythm_patterns: load_custom_rhythm_patterns(),
+        instrument: INSTRUMENT_DEFAULT_VALUE,
+        timbre_drift: TIMBRE_DRIFT_DEFAULT_VALUE,
+        quantizer_scale_index: Some(QUANTIZER_SCALE_INDEX_DEFAULT_VALUE),
+        scale_root_index: Some(SCALE_ROOT_DEFAULT_VALUE),
+        chord: None,
+        range_mode_index: Some(RANGE_MODE_DEFAULT_VALUE),
+        direction_index: Some(DIRECTION_DEFAULT_VALUE),
+        octave_jump_probability: OCTAVE_JUMP_PROBABILITY_DEFAULT_VALUE,
+        sample_hold_steps: SAMPLE_HOLD_STEPS_DEFAULT_VALUE as f32,
+        pitch_chain: PITCH_CHAIN_DEFAULT_VALUE.to_vec(),
+        transpose: TRANSPOSE_DEFAULT_VALUE as f32,
+        harmony_steps: HARMONY_STEPS_DEFAULT_VALUE as f32,
+        max_interval: MAX_INTERVAL_DEFAULT_VALUE as f32,
+        repeat_avoidance: REPEAT_AVOIDANCE_DEFAULT_VALUE,
+        rest_probability: REST_PROBABILITY_DEFAULT_VALUE,
+        trigger_chain: TRIGGER_CHAIN_DEFAULT_VALUE.to_vec(),
+        clock_division: CLOCK_DIVISION_DEFAULT_VALUE as f32,
+        swing: SWING_DEFAULT_VALUE,
+        ratchet_count: RATCHET_COUNT_DEFAULT_VALUE as f32,
+        logic_operation_index: Some(LOGIC_OPERATION_DEFAULT_VALUE),
+        logic_steps: LOGIC_STEPS_DEFAULT_VALUE,
+        groove_index: Some(GROOVE_DEFAULT_VALUE),
+        trigger_probability: TRIGGER_PROBABILITY_DEFAULT_VALUE,
+        velocity_jitter: VELOCITY_JITTER_DEFAULT_VALUE,
+        accent: ACCENT_DEFAULT_VALUE,
+        density_compensation: DENSITY_COMPENSATION_DEFAULT_VALUE,
+        fade: FADE_DEFAULT_VALUE,
+        note_length: NOTE_LENGTH_DEFAULT_VALUE,
+        speed_index: TRACK_SPEED_DEFAULT_VALUE,
+        pressure_envelope: PRESSURE_ENVELOPE_DEFAULT_VALUE,
+        key_modulation: KEY_MODULATION_DEFAULT_VALUE,
+        phrase: PHRASE_DEFAULT_VALUE,
+        seed: SEED_DEFAULT_VALUE,
+        routing: RoutingTable::new(),
+        midi_protocol: MIDI_PROTOCOL_DEFAULT_VALUE,
+        voices: VOICES_DEFAULT_VALUE,
+        velocity_curves: VelocityCurves::default(),
+        tension_shape_index: Some(TENSION_SHAPE_DEFAULT_VALUE),
+        call_response: CALL_RESPONSE_DEFAULT_VALUE,
+        ambient: AMBIENT_DEFAULT_VALUE,
+        tension_phrase_bars: TENSION_PHRASE_BARS_DEFAULT_VALUE as f32,
+        tension_depth: TENSION_DEPTH_DEFAULT_VALUE,
+        sustain_mode_index: Some(SUSTAIN_MODE_DEFAULT_VALUE),
+        sustain_phrase_bars: SUSTAIN_PHRASE_BARS_DEFAULT_VALUE as f32,
+        sustain_probability: SUSTAIN_PROBABILITY_DEFAULT_VALUE,
+        split: SPLIT_DEFAULT_VALUE,
+        note_processors: NOTE_PROCESSORS_DEFAULT_VALUE,
+        scale_lock: SCALE_LOCK_DEFAULT_VALUE,
+        collision_avoidance: COLLISION_AVOIDANCE_DEFAULT_VALUE,
+        cadence: CADENCE_DEFAULT_VALUE,
+        bass: BASS_DEFAULT_VALUE,
+        layers: LAYERS_DEFAULT_VALUE,
+        drums: DRUMS_DEFAULT_VALUE,
+        melody: Vec::new(),
+        degree_lane: DEGREE_LANE_DEFAULT_VALUE.to_vec(),
+        intervals: INTERVALS_DEFAULT_VALUE.to_vec(),
+        bpm: BPM_DEFAULT_VALUE,
+        slew_beats: SLEW_BEATS_DEFAULT_VALUE,
+        resolution: RESOLUTION_DEFAULT_VALUE,
+    }
 }
 
 fn model(app: &App) -> Model {
@@ -102,7 +850,7 @@ fn model(app: &App) -> Model {
     let window_id = app
         .new_window()
         .title(WINDOW_NAME)
-        .size(300, 300)
+        .size(600, 500)
         .view(view)
         .raw_event(raw_window_event)
         .build()
@@ -110,29 +858,99 @@ fn model(app: &App) -> Model {
     let window = app.window(window_id).unwrap();
 
     let egui = Egui::from_window(&window);
+    let accessibility = Accessibility::load();
+    apply_accessibility(egui.ctx(), &accessibility);
 
-    let sequencer_model = SequencerModel {
-        min_pitch: MIN_PITCH_DEFAULT_VALUE.step(),
-        max_pitch: MAX_PITCH_DEFAULT_VALUE.step(),
-        pitch_producer_type_index: Some(PITCH_PRODUCER_TYPE_DEFAULT_VALUE),
-        cycle_length: DEFAULT_CYCLE_LENGTH as f32,
-        rhythm_pattern: Some(RHYTHM_PATTERN_DEFAULT_VALUE),
-        notes_per_beat: NOTES_PER_BEAT[RHYTHM_PATTERN_DEFAULT_VALUE],
-        instrument: INSTRUMENT_DEFAULT_VALUE,
-        quantizer_scale_index: Some(QUANTIZER_SCALE_INDEX_DEFAULT_VALUE),
-        bpm: BPM_DEFAULT_VALUE,
-    };
+    reload_library();
+
+    let sequencer_model = default_sequencer_model();
+
+    let mut plugins = PluginLibrary::new();
+    plugins.reload();
 
     let is_playing = true;
-    let sequencer = Sequencer::new(sequencer_model.clone().into(), is_playing);
+    let mut sound = SoundSettings::new(SYNTH_DEFAULT_VALUE, EFFECTS_DEFAULT_VALUE);
+    let audio_devices = AudioDevicePanel::new();
+    let audio =
+        match AudioEngine::start(&audio_devices.settings, sound.effects, sequencer_model.bpm) {
+            Ok(audio) => Some(audio),
+            Err(err) => {
+                sound.status = Some(Err(err));
+                None
+            }
+        };
+    let sequencer = Sequencer::new(
+        sequencer_model.clone().into(),
+        is_playing,
+        audio.as_ref().map(|audio| audio.sink()),
+    );
+    let evolve = Evolve::new(&sequencer_model);
 
     Model {
         egui,
         sequencer_model,
         sequencer,
-        is_playing,
+        rhythm_editor: RhythmEditor::new(),
+        chaos: ChaosMacro::new(),
+        evolve,
+        instrument_search: String::new(),
+        auto_restart: true,
+        restart_count: 0,
+        last_failure: None,
+        midi_input: MidiInputListener::connect_first_port(),
+        key_detection: KeyDetection::new(),
+        detection_bars: DETECTION_BARS_DEFAULT_VALUE,
+        follow_chords: false,
+        midi_transport: false,
+        program_change_slots: false,
+        roll: None,
+        roll_keys: false,
+        manual_trigger: false,
+        manual_keys: true,
+        audition_pitch: false,
+        key_macros: KeyMacros::load(),
+        accessibility,
+        fill: false,
+        tempo_before_double: None,
+        grid: None,
+        grid_port: None,
+        grid_layout: GridLayout::LaunchpadXY,
+        grid_error: None,
+        tempo_follow: TempoFollow::new(MIN_BPM_VALUE, MAX_BPM_VALUE),
+        guide: GuideImport::new(),
+        melody: MelodyImport::new(),
+        export: ExportSettings::new(),
+        patch: PatchPanel::new(),
+        preset_browser: PresetBrowser::new(),
+        output_ports: MidiSink::port_names(),
+        velocity_curve_instrument: INSTRUMENT_DEFAULT_VALUE,
+        timbre_search: String::new(),
+        preset_thumbnails: HashMap::new(),
+        autosave: Autosave::start(),
+        visuals: Visuals::new(),
+        scope: Scope::new(),
+        audio,
+        audio_devices,
+        network_sync: NetworkSyncPanel::new(SYNC_PORT_DEFAULT_VALUE),
+        scripts: ScriptLibrary::new(),
+        plugins,
+        sound,
+        statistics_bars: STATISTICS_BARS_DEFAULT_VALUE,
+        loop_bars: None,
+        quantize_changes: false,
+        slots: PatternSlots::load(),
     }
 }
+// The sequencer stops first so its note offs still reach the MIDI output and the internal audio,
+// then the connections close and the last session is written
+fn exit(_app: &App, mut model: Model) {
+    drop(model.sequencer);
+    drop(model.midi_input.take());
+    drop(model.network_sync.sync.take());
+    drop(model.audio.take());
+    model.autosave.finish();
+}
+
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
     model.egui.handle_raw_event(event);
 }
@@ -142,14 +960,75 @@ fn update(app: &App, model: &mut Model, update: Update) {
 
     egui.set_elapsed_time(update.since_start);
     let ctx = egui.begin_frame();
-    let scale = &mut model.sequencer_model.quantizer_scale_index;
-    let mut pitch_producer_type = model.sequencer_model.pitch_producer_type_index.clone();
-    let mut tempo = model.sequencer_model.bpm.clone();
-    let mut min_pitch = model.sequencer_model.min_pitch.clone();
-    let mut max_pitch = model.sequencer_model.max_pitch.clone();
-    let mut cycle_length = model.sequencer_model.cycle_length.clone();
-    let mut rhythm_pattern = model.sequencer_model.rhythm_pattern.clone();
-    let instrument = &mut model.sequencer_model.instrument;
+    let previous_values = parameter_values(&model.sequencer_model);
+    // not a parameter, stored with the presets all the same
+    let previous_velocity_curves = model.sequencer_model.velocity_curves.clone();
+    let sequencer_model = &mut model.sequencer_model;
+    let min_pitch_text = format_letter_octave(Step(sequencer_model.min_pitch).to_letter_octave());
+    let max_pitch_text = format_letter_octave(Step(sequencer_model.max_pitch).to_letter_octave());
+    let library = library();
+    let registry = producer_registry();
+    let mut reload_assets_clicked = false;
+    let mut restart_clicked = false;
+    let sequencer_failure = model.sequencer.failure();
+    if sequencer_failure.is_some() {
+        model.last_failure = sequencer_failure.clone();
+    }
+    let instrument_search = &mut model.instrument_search;
+    let follow_chords = &mut model.follow_chords;
+    let was_following_chords = *follow_chords;
+    let previous_midi_protocol = sequencer_model.midi_protocol;
+    let previous_voices = sequencer_model.voices;
+    let midi_transport = &mut model.midi_transport;
+    let program_change_slots = &mut model.program_change_slots;
+    let has_midi_input = model.midi_input.is_some();
+    let audition_pitch = &mut model.audition_pitch;
+    // the range boundary being dragged, to play
+    let mut auditioned = None;
+    let previous_pitch_range = (
+        sequencer_model.min_pitch.round(),
+        sequencer_model.max_pitch.round(),
+    );
+
+    let previous_loop_bars = model.loop_bars;
+    let was_quantizing = model.quantize_changes;
+    let sequencer_state = model.sequencer.state();
+    if show_transport_bar(
+        &ctx,
+        &sequencer_state,
+        &model.sequencer.step_lights(),
+        &mut model.loop_bars,
+        &mut model.quantize_changes,
+    ) {
+        model.sequencer.rewind();
+    }
+    if model.loop_bars != previous_loop_bars {
+        model.sequencer.set_loop_bars(model.loop_bars);
+    }
+    if model.quantize_changes != was_quantizing {
+        model.sequencer.set_quantize_changes(model.quantize_changes);
+    }
+    // a recalled slot lands on a single bar line while quantizing changes
+    let mut slot_action = show_slot_bar(&ctx, &model.slots);
+    if let Some(midi_input) = &model.midi_input {
+        // the last program received wins, those past the slots are not for us
+        let program = midi_input.take_program_changes().pop();
+        if *program_change_slots && slot_action.is_none() {
+            if let Some(program) = program.filter(|p| (*p as usize) < SLOT_COUNT) {
+                slot_action = Some(SlotAction::Recall(program as usize));
+            }
+        }
+    }
+    match slot_action {
+        Some(SlotAction::Store(index)) => model.slots.store(index, sequencer_model),
+        Some(SlotAction::Recall(index)) => {
+            model.sequencer.hold_changes(true);
+            for note in model.slots.recall(index, sequencer_model) {
+                eprintln!("Recalling slot {}: {}", index + 1, note);
+            }
+        }
+        None => (),
+    }
 
     egui::Window::new("Settings")
         .default_width(250.0)
@@ -159,152 +1038,4306 @@ fn update(app: &App, model: &mut Model, update: Update) {
                 .spacing([20.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
+                    let scale = &mut sequencer_model.quantizer_scale_index;
                     ui.label("Scale:");
                     egui::ComboBox::from_id_source("scale")
-                        .selected_text(format!("{}", QUANTIZER_SCALES[scale.unwrap()].1))
+                        .selected_text(&library.scales[scale.unwrap()].name)
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, scale_asset) in library.scales.iter().enumerate() {
+                                ui.selectable_value(scale, Some(index), &scale_asset.name);
+                            }
+                        });
+                    ui.end_row();
+                    let root = &mut sequencer_model.scale_root_index;
+                    ui.label("Root:");
+                    egui::ComboBox::from_id_source("root")
+                        .selected_text(pitch_class_name(root.unwrap()))
                         .width(160.0)
                         .show_ui(ui, |ui| {
-                            for (index, (_, name)) in QUANTIZER_SCALES.iter().enumerate() {
-                                ui.selectable_value(scale, Some(index), *name);
+                            for index in 0..PITCH_CLASS_COUNT {
+                                ui.selectable_value(root, Some(index), pitch_class_name(index));
                             }
                         });
                     ui.end_row();
+                    let key_modulation = &mut sequencer_model.key_modulation;
+                    ui.label("Modulate:");
+                    ui.checkbox(&mut key_modulation.enabled, "");
+                    ui.end_row();
+                    if key_modulation.enabled {
+                        ui.label("By:");
+                        ui.add(
+                            egui::Slider::new(&mut key_modulation.interval, -11..=11).suffix(" st"),
+                        );
+                        ui.end_row();
+                        ui.label("Every:");
+                        ui.add(
+                            egui::Slider::new(&mut key_modulation.bars, 1..=MAX_MODULATION_BARS)
+                                .suffix(" bars"),
+                        );
+                        ui.end_row();
+                    }
+                    let mut style = note_name_style();
+                    ui.label("Note names:");
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("note_naming")
+                            .selected_text(
+                                NOTE_NAMINGS
+                                    .iter()
+                                    .find(|(naming, _)| *naming == style.naming)
+                                    .map_or("", |(_, name)| *name),
+                            )
+                            .width(110.0)
+                            .show_ui(ui, |ui| {
+                                for (naming, name) in NOTE_NAMINGS {
+                                    ui.selectable_value(&mut style.naming, *naming, *name);
+                                }
+                            });
+                        ui.checkbox(&mut style.flats, "Flats");
+                    });
+                    if style != note_name_style() {
+                        set_note_name_style(style);
+                    }
+                    ui.end_row();
+                    ui.label("Follow chords:");
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(has_midi_input, egui::Checkbox::new(follow_chords, ""));
+                        if let Some(chord) = sequencer_model.chord {
+                            ui.label(format!(
+                                "{}{}",
+                                pitch_class_name(chord.root),
+                                chord.quality.suffix()
+                            ));
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("MIDI transport:");
+                    ui.add_enabled(has_midi_input, egui::Checkbox::new(midi_transport, ""))
+                        .on_hover_text("Start, stop and continue with the play button of a DAW");
+                    ui.end_row();
+                    ui.label("Program change:");
+                    ui.add_enabled(
+                        has_midi_input,
+                        egui::Checkbox::new(program_change_slots, ""),
+                    )
+                    .on_hover_text("Program 1 recalls slot 1, program 2 slot 2 and so on");
+                    ui.end_row();
+                    // only offered where the backend sends more than MIDI 1.0
+                    if MIDI_PROTOCOLS.iter().filter(|p| p.is_available()).count() > 1 {
+                        ui.label("MIDI output:");
+                        let midi_protocol = &mut sequencer_model.midi_protocol;
+                        egui::ComboBox::from_id_source("midi_protocol")
+                            .selected_text(midi_protocol.to_string())
+                            .show_ui(ui, |ui| {
+                                for protocol in MIDI_PROTOCOLS {
+                                    ui.add_enabled_ui(protocol.is_available(), |ui| {
+                                        ui.selectable_value(
+                                            midi_protocol,
+                                            protocol,
+                                            protocol.to_string(),
+                                        );
+                                    });
+                                }
+                            });
+                        ui.end_row();
+                    }
+                    let voices = &mut sequencer_model.voices;
+                    ui.label("Voices:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut voices.max_voices, 1..=MAX_VOICES))
+                            .on_hover_text("Pitched notes sounding at once, the drums aside");
+                        ui.label("Steal:");
+                        egui::ComboBox::from_id_source("steal_policy")
+                            .selected_text(voices.steal.to_string())
+                            .show_ui(ui, |ui| {
+                                for policy in STEAL_POLICIES {
+                                    ui.selectable_value(
+                                        &mut voices.steal,
+                                        policy,
+                                        policy.to_string(),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text("The note making room for a new one, none drops it");
+                    });
+                    ui.end_row();
+                    let rhythm_pattern = &mut sequencer_model.rhythm_pattern;
+                    let custom_rhythm_patterns = &sequencer_model.custom_rhythm_patterns;
                     ui.label("Rhythm:");
                     egui::ComboBox::from_id_source("rhythm")
-                        .selected_text(format!("{}", RHYTHM_PATTERNS[rhythm_pattern.unwrap()].1))
+                        .selected_text(
+                            &rhythm::rhythm_pattern(
+                                &library,
+                                custom_rhythm_patterns,
+                                rhythm_pattern.unwrap(),
+                            )
+                            .name,
+                        )
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, pattern) in library.rhythm_patterns.iter().enumerate() {
+                                ui.selectable_value(rhythm_pattern, Some(index), &pattern.name);
+                            }
+                            for (index, pattern) in custom_rhythm_patterns.iter().enumerate() {
+                                ui.selectable_value(
+                                    rhythm_pattern,
+                                    Some(library.rhythm_patterns.len() + index),
+                                    &pattern.name,
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    let trigger_producer = &mut sequencer_model.trigger_producer_index;
+                    ui.label("Trigger:");
+                    egui::ComboBox::from_id_source("trigger")
+                        .selected_text(
+                            registry.trigger_producers[trigger_producer.unwrap()]
+                                .name
+                                .as_str(),
+                        )
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, entry) in registry.trigger_producers.iter().enumerate() {
+                                ui.selectable_value(trigger_producer, Some(index), &entry.name);
+                            }
+                        });
+                    ui.end_row();
+                    let trigger_entry = &registry.trigger_producers[trigger_producer.unwrap()];
+                    if trigger_entry.uses(ProducerSetting::TriggerProbability) {
+                        ui.label("Trigger probability:");
+                        ui.add(egui::Slider::new(
+                            &mut sequencer_model.trigger_probability,
+                            0.0..=1.0,
+                        ));
+                        ui.end_row();
+                    }
+                    ui.label("Rests:");
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.rest_probability,
+                        0.0..=1.0,
+                    ));
+                    ui.end_row();
+                    ui.label("Velocity jitter:");
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.velocity_jitter,
+                        0.0..=1.0,
+                    ));
+                    ui.end_row();
+                    let accent = &mut sequencer_model.accent;
+                    ui.label("Downbeat accent:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut accent.enabled, "")
+                            .on_hover_text("Louder notes on beat 1");
+                        if accent.enabled {
+                            ui.checkbox(&mut accent.third_beat, "Beat 3");
+                            ui.add(egui::Slider::new(&mut accent.amount, 0..=MAX_ACCENT));
+                        }
+                    });
+                    ui.end_row();
+                    let density_compensation = &mut sequencer_model.density_compensation;
+                    ui.label("Density compensation:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut density_compensation.enabled, "")
+                            .on_hover_text("Softer notes as the density rises, louder as it falls");
+                        if density_compensation.enabled {
+                            ui.add(egui::Slider::new(
+                                &mut density_compensation.amount,
+                                0..=MAX_DENSITY_COMPENSATION,
+                            ))
+                            .on_hover_text("Velocity taken off each time the density doubles");
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Note length:");
+                    ui.add(
+                        egui::Slider::new(
+                            &mut sequencer_model.note_length,
+                            MIN_NOTE_LENGTH..=MAX_NOTE_LENGTH,
+                        )
+                        .logarithmic(true)
+                        .suffix("x"),
+                    );
+                    ui.end_row();
+                    ui.label("Speed:");
+                    ui.horizontal(|ui| {
+                        for (index, name) in TRACK_SPEED_NAMES.iter().enumerate() {
+                            ui.selectable_value(&mut sequencer_model.speed_index, index, *name);
+                        }
+                    })
+                    .response
+                    .on_hover_text("Against the tempo, changes at the next bar");
+                    ui.end_row();
+                    ui.label("Rhythm rotation:");
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.rhythm_rotation,
+                        0.0..=MAX_RHYTHM_ROTATION as f32,
+                    ));
+                    ui.end_row();
+                    let pressure_envelope = &mut sequencer_model.pressure_envelope;
+                    ui.label("Aftertouch:");
+                    ui.checkbox(&mut pressure_envelope.enabled, "");
+                    ui.end_row();
+                    if pressure_envelope.enabled {
+                        ui.label("Attack (ms):");
+                        ui.add(egui::Slider::new(
+                            &mut pressure_envelope.attack_ms,
+                            0.0..=MAX_PRESSURE_ENVELOPE_TIME_MS,
+                        ));
+                        ui.end_row();
+                        ui.label("Decay (ms):");
+                        ui.add(egui::Slider::new(
+                            &mut pressure_envelope.decay_ms,
+                            0.0..=MAX_PRESSURE_ENVELOPE_TIME_MS,
+                        ));
+                        ui.end_row();
+                        ui.label("Depth:");
+                        ui.add(egui::Slider::new(&mut pressure_envelope.depth, 0.0..=127.0));
+                        ui.end_row();
+                    }
+                    let sustain_mode = &mut sequencer_model.sustain_mode_index;
+                    ui.label("Sustain:");
+                    egui::ComboBox::from_id_source("sustain")
+                        .selected_text(SUSTAIN_MODE_NAMES[sustain_mode.unwrap()])
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, name) in SUSTAIN_MODE_NAMES.iter().enumerate() {
+                                ui.selectable_value(sustain_mode, Some(index), *name);
+                            }
+                        });
+                    ui.end_row();
+                    match sustain_mode_from_index(sequencer_model.sustain_mode_index) {
+                        SustainMode::Phrase => {
+                            ui.label("Phrase bars:");
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut sequencer_model.sustain_phrase_bars,
+                                    MIN_SUSTAIN_PHRASE_BARS as f32..=MAX_SUSTAIN_PHRASE_BARS as f32,
+                                )
+                                .step_by(1.0),
+                            );
+                            ui.end_row();
+                        }
+                        SustainMode::Random => {
+                            ui.label("Sustain probability:");
+                            ui.add(egui::Slider::new(
+                                &mut sequencer_model.sustain_probability,
+                                0.0..=1.0,
+                            ));
+                            ui.end_row();
+                        }
+                        _ => (),
+                    }
+                    let groove = &mut sequencer_model.groove_index;
+                    ui.label("Groove:");
+                    egui::ComboBox::from_id_source("groove")
+                        .selected_text(GROOVE_TEMPLATES[groove.unwrap()].name)
                         .width(160.0)
                         .show_ui(ui, |ui| {
-                            for (index, (_, name)) in RHYTHM_PATTERNS.iter().enumerate() {
-                                ui.selectable_value(&mut rhythm_pattern, Some(index), *name);
+                            for (index, template) in GROOVE_TEMPLATES.iter().enumerate() {
+                                ui.selectable_value(groove, Some(index), template.name);
                             }
                         });
                     ui.end_row();
+                    let pitch_producer = &mut sequencer_model.pitch_producer_index;
                     ui.label("Pitch:");
                     egui::ComboBox::from_id_source("pitch")
-                        .selected_text(format!(
-                            "{}",
-                            PITCH_PRODUCER_TYPE_NAMES[pitch_producer_type.unwrap()]
-                        ))
+                        .selected_text(
+                            registry.pitch_producers[pitch_producer.unwrap()]
+                                .name
+                                .as_str(),
+                        )
                         .width(160.0)
                         .show_ui(ui, |ui| {
-                            for (index, name) in PITCH_PRODUCER_TYPE_NAMES.iter().enumerate() {
-                                ui.selectable_value(&mut pitch_producer_type, Some(index), *name);
+                            for (index, entry) in registry.pitch_producers.iter().enumerate() {
+                                ui.selectable_value(pitch_producer, Some(index), &entry.name);
                             }
                         });
                     ui.end_row();
-                    ui.label("Cycle length:");
+                    let pitch_entry = &registry.pitch_producers[pitch_producer.unwrap()];
+                    if pitch_entry.uses(ProducerSetting::Direction) {
+                        let direction = &mut sequencer_model.direction_index;
+                        ui.label("Direction:");
+                        egui::ComboBox::from_id_source("direction")
+                            .selected_text(DIRECTION_NAMES[direction.unwrap()])
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for (index, name) in DIRECTION_NAMES.iter().enumerate() {
+                                    ui.selectable_value(direction, Some(index), *name);
+                                }
+                            });
+                        ui.end_row();
+                    }
+                    if pitch_entry.uses(ProducerSetting::CycleLength) {
+                        ui.label("Cycle length:");
+                        ui.add(egui::Slider::new(
+                            &mut sequencer_model.cycle_length,
+                            MIN_CYCLE_LENGTH as f32..=MAX_CYCLE_LENGTH as f32,
+                        ));
+                        ui.end_row();
+                    }
+                    ui.label("Pitch rotation:");
                     ui.add(egui::Slider::new(
-                        &mut cycle_length,
-                        MIN_CYCLE_LENGTH as f32..=MAX_CYCLE_LENGTH as f32,
+                        &mut sequencer_model.pitch_rotation,
+                        0.0..=MAX_CYCLE_LENGTH as f32,
                     ));
                     ui.end_row();
+                    if pitch_entry.uses(ProducerSetting::Melody)
+                        && sequencer_model.melody.is_empty()
+                    {
+                        ui.label("");
+                        ui.label("Random until a melody is imported");
+                        ui.end_row();
+                    }
+                    if pitch_entry.uses(ProducerSetting::DegreeLane) {
+                        ui.label("");
+                        ui.label("Steps set in the Degree lane window");
+                        ui.end_row();
+                    }
+                    if pitch_entry.uses(ProducerSetting::Intervals) {
+                        ui.label("");
+                        ui.label("Intervals set in the Intervals window");
+                        ui.end_row();
+                    }
                     ui.label("Min:");
-                    ui.add(
-                        egui::Slider::new(&mut min_pitch, PITCH_MIN_VALUE.step()..=max_pitch).text(
-                            format_letter_octave(
-                                Step(model.sequencer_model.min_pitch).to_letter_octave(),
-                            ),
-                        ),
+                    let min_slider = ui.add(
+                        egui::Slider::new(
+                            &mut sequencer_model.min_pitch,
+                            PITCH_MIN_VALUE.step()..=sequencer_model.max_pitch,
+                        )
+                        .text(min_pitch_text),
                     );
                     ui.end_row();
                     ui.label("Max:");
-                    ui.add(
-                        egui::Slider::new(&mut max_pitch, min_pitch..=PITCH_MAX_VALUE.step()).text(
-                            format_letter_octave(
-                                Step(model.sequencer_model.max_pitch).to_letter_octave(),
+                    let max_slider = ui.add(
+                        egui::Slider::new(
+                            &mut sequencer_model.max_pitch,
+                            sequencer_model.min_pitch..=PITCH_MAX_VALUE.step(),
+                        )
+                        .text(max_pitch_text),
+                    );
+                    ui.end_row();
+                    ui.label("Audition:");
+                    ui.checkbox(audition_pitch, "")
+                        .on_hover_text("Dragging Min or Max plays the note at the slider");
+                    ui.end_row();
+                    // once when the drag starts, then on each new note
+                    if *audition_pitch {
+                        for (slider, pitch, previous) in [
+                            (
+                                &min_slider,
+                                sequencer_model.min_pitch.round(),
+                                previous_pitch_range.0,
+                            ),
+                            (
+                                &max_slider,
+                                sequencer_model.max_pitch.round(),
+                                previous_pitch_range.1,
                             ),
-                        ),
+                        ] {
+                            if slider.drag_started() || (slider.dragged() && pitch != previous) {
+                                auditioned = Some(pitch as u8);
+                            }
+                        }
+                    }
+                    let range_mode = &mut sequencer_model.range_mode_index;
+                    ui.label("Range:");
+                    egui::ComboBox::from_id_source("range")
+                        .selected_text(format!("{}", RANGE_MODE_NAMES[range_mode.unwrap()]))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, name) in RANGE_MODE_NAMES.iter().enumerate() {
+                                ui.selectable_value(range_mode, Some(index), *name);
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Octave jumps:");
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.octave_jump_probability,
+                        0.0..=1.0,
+                    ));
+                    ui.end_row();
+                    ui.label("Sample & hold:");
+                    ui.add(
+                        egui::Slider::new(
+                            &mut sequencer_model.sample_hold_steps,
+                            1.0..=MAX_SAMPLE_HOLD_STEPS as f32,
+                        )
+                        .step_by(1.0)
+                        .suffix(" steps"),
                     );
                     ui.end_row();
+                    let seed = &mut sequencer_model.seed;
+                    ui.label("Seed per bar:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut seed.per_bar, "")
+                            .on_hover_text("A looped bar plays the same notes on every pass");
+                        if seed.per_bar {
+                            ui.add(
+                                egui::DragValue::new(&mut seed.master).clamp_range(0..=MAX_SEED),
+                            );
+                            if ui
+                                .button("Regenerate")
+                                .on_hover_text("New material from the next bar")
+                                .clicked()
+                            {
+                                seed.master = (seed.master + 1) % (MAX_SEED + 1);
+                            }
+                        }
+                    });
+                    ui.end_row();
+                    let phrase = &mut sequencer_model.phrase;
+                    ui.label("Phrase:");
+                    ui.checkbox(&mut phrase.enabled, "");
+                    ui.end_row();
+                    if phrase.enabled {
+                        ui.label("Motif length:");
+                        ui.add(egui::Slider::new(
+                            &mut phrase.motif_length,
+                            MIN_MOTIF_LENGTH..=MAX_MOTIF_LENGTH,
+                        ));
+                        ui.end_row();
+                        ui.label("Motif statements:");
+                        ui.add(egui::Slider::new(
+                            &mut phrase.statements,
+                            MIN_PHRASE_STATEMENTS..=MAX_PHRASE_STATEMENTS,
+                        ));
+                        ui.end_row();
+                        ui.label("Motifs from melody:");
+                        ui.checkbox(&mut phrase.seed_from_melody, "");
+                        ui.end_row();
+                    }
+                    let tension_shape = &mut sequencer_model.tension_shape_index;
+                    ui.label("Tension:");
+                    egui::ComboBox::from_id_source("tension")
+                        .selected_text(TENSION_SHAPE_NAMES[tension_shape.unwrap()])
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (index, name) in TENSION_SHAPE_NAMES.iter().enumerate() {
+                                ui.selectable_value(tension_shape, Some(index), *name);
+                            }
+                        });
+                    ui.end_row();
+                    if tension_shape_from_index(sequencer_model.tension_shape_index)
+                        != TensionShape::Off
+                    {
+                        ui.label("Tension bars:");
+                        ui.add(
+                            egui::Slider::new(
+                                &mut sequencer_model.tension_phrase_bars,
+                                MIN_TENSION_PHRASE_BARS as f32..=MAX_TENSION_PHRASE_BARS as f32,
+                            )
+                            .step_by(1.0),
+                        );
+                        ui.end_row();
+                        ui.label("Tension depth:");
+                        ui.add(egui::Slider::new(
+                            &mut sequencer_model.tension_depth,
+                            0.0..=1.0,
+                        ));
+                        ui.end_row();
+                    }
+                    let cadence = &mut sequencer_model.cadence;
+                    ui.label("Cadence:");
+                    ui.checkbox(&mut cadence.enabled, "")
+                        .on_hover_text("The last note of each phrase resolves");
+                    ui.end_row();
+                    if cadence.enabled {
+                        ui.label("Cadence bars:");
+                        ui.add(egui::Slider::new(
+                            &mut cadence.phrase_bars,
+                            MIN_CADENCE_PHRASE_BARS..=MAX_CADENCE_PHRASE_BARS,
+                        ));
+                        ui.end_row();
+                        ui.label("Resolves to:");
+                        egui::ComboBox::from_id_source("cadence_target")
+                            .selected_text(cadence.target.to_string())
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for target in CADENCE_TARGETS {
+                                    ui.selectable_value(
+                                        &mut cadence.target,
+                                        target,
+                                        target.to_string(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
 
                     ui.label("Tempo:");
-                    ui.add(egui::Slider::new(&mut tempo, MIN_BPM_VALUE..=MAX_BPM_VALUE));
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.bpm,
+                        MIN_BPM_VALUE..=MAX_BPM_VALUE,
+                    ));
+                    ui.end_row();
+                    ui.label("Slew (beats):");
+                    ui.add(egui::Slider::new(
+                        &mut sequencer_model.slew_beats,
+                        0.0..=MAX_SLEW_BEATS,
+                    ));
                     ui.end_row();
+                    let instrument = &mut sequencer_model.instrument;
                     ui.label("Instrument:");
                     egui::ComboBox::from_id_source("instrument")
-                        .selected_text(format!("{}", INSTRUMENT_LIST[*instrument as usize]))
+                        .selected_text(library.instruments[*instrument as usize].label())
                         .width(160.0)
                         .show_ui(ui, |ui| {
-                            for (index, (name)) in INSTRUMENT_LIST.iter().enumerate() {
-                                ui.selectable_value(instrument, index as u8, *name);
+                            // grouped by GM category, filtered by the search field
+                            let mut category = "";
+                            for entry in library.instruments.iter() {
+                                if !entry.matches(instrument_search) {
+                                    continue;
+                                }
+                                if entry.category != category {
+                                    category = entry.category;
+                                    ui.label(RichText::new(category).strong());
+                                }
+                                ui.selectable_value(instrument, entry.program, entry.label());
                             }
                         });
                     ui.end_row();
+                    ui.label("Search:");
+                    ui.add(
+                        egui::TextEdit::singleline(instrument_search)
+                            .hint_text("Instrument or category")
+                            .desired_width(160.0),
+                    );
+                    ui.end_row();
+                    let split = &mut sequencer_model.split;
+                    ui.label("Split:");
+                    ui.checkbox(&mut split.enabled, "")
+                        .on_hover_text("The notes below the split point play another instrument");
+                    ui.end_row();
+                    if split.enabled {
+                        ui.label("Split point:");
+                        let mut point = split.point as f32;
+                        let point_text = format_letter_octave(Step(point).to_letter_octave());
+                        ui.add(
+                            egui::Slider::new(
+                                &mut point,
+                                PITCH_MIN_VALUE.step()..=PITCH_MAX_VALUE.step(),
+                            )
+                            .step_by(1.0)
+                            .text(point_text),
+                        );
+                        split.point = point as u8;
+                        ui.end_row();
+                        let split_instrument = &mut split.instrument;
+                        ui.label("Below:");
+                        egui::ComboBox::from_id_source("split_instrument")
+                            .selected_text(library.instruments[*split_instrument as usize].label())
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                let mut category = "";
+                                for entry in library.instruments.iter() {
+                                    if !entry.matches(instrument_search) {
+                                        continue;
+                                    }
+                                    if entry.category != category {
+                                        category = entry.category;
+                                        ui.label(RichText::new(category).strong());
+                                    }
+                                    ui.selectable_value(
+                                        split_instrument,
+                                        entry.program,
+                                        entry.label(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+                    ui.label("Scale lock:");
+                    ui.checkbox(&mut sequencer_model.scale_lock, "")
+                        .on_hover_text("Every note is moved onto the scale right before it plays");
+                    ui.end_row();
+                    ui.label("Collisions:");
+                    let avoidance = &mut sequencer_model.collision_avoidance;
+                    egui::ComboBox::from_id_source("collision_avoidance")
+                        .selected_text(avoidance.to_string())
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for option in COLLISION_AVOIDANCES {
+                                ui.selectable_value(avoidance, option, option.to_string());
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "When the melody starts the pitch the bass starts on the same tick",
+                        );
+                    ui.end_row();
+                    let ambient = &mut sequencer_model.ambient;
+                    ui.label("Ambient:");
+                    ui.checkbox(&mut ambient.enabled, "");
+                    ui.end_row();
+                    if ambient.enabled {
+                        ui.label("Density:");
+                        ui.add(egui::Slider::new(&mut ambient.density, 0.0..=1.0));
+                        ui.end_row();
+                        ui.label("Note length:");
+                        ui.add(
+                            egui::Slider::new(
+                                &mut ambient.note_length,
+                                MIN_AMBIENT_NOTE_LENGTH..=MAX_AMBIENT_NOTE_LENGTH,
+                            )
+                            .suffix("x"),
+                        );
+                        ui.end_row();
+                        ui.label("Drift:");
+                        ui.add(egui::Slider::new(&mut ambient.drift, 0.0..=1.0));
+                        ui.end_row();
+                        ui.label("Voices:");
+                        ui.add(egui::Slider::new(
+                            &mut ambient.max_voices,
+                            1..=MAX_AMBIENT_VOICES,
+                        ));
+                        ui.end_row();
+                    }
+                    let call_response = &mut sequencer_model.call_response;
+                    ui.label("Call and response:");
+                    ui.checkbox(&mut call_response.enabled, "");
+                    ui.end_row();
+                    if call_response.enabled {
+                        ui.label("Response:");
+                        egui::ComboBox::from_id_source("response")
+                            .selected_text(call_response.transform.to_string())
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for transform in RESPONSE_TRANSFORMS {
+                                    ui.selectable_value(
+                                        &mut call_response.transform,
+                                        transform,
+                                        transform.to_string(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                        let response_instrument = &mut call_response.instrument;
+                        ui.label("Response instrument:");
+                        egui::ComboBox::from_id_source("response_instrument")
+                            .selected_text(
+                                library.instruments[*response_instrument as usize].label(),
+                            )
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                let mut category = "";
+                                for entry in library.instruments.iter() {
+                                    if !entry.matches(instrument_search) {
+                                        continue;
+                                    }
+                                    if entry.category != category {
+                                        category = entry.category;
+                                        ui.label(RichText::new(category).strong());
+                                    }
+                                    ui.selectable_value(
+                                        response_instrument,
+                                        entry.program,
+                                        entry.label(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
                 });
             ui.separator();
 
-            let play_text = if model.is_playing { "Pause" } else { "Play" };
+            // as the engine reports it, a start or stop shows once the engine took it;
+            // a fade out can be turned back with Play
+            let is_playing =
+                sequencer_state.is_playing && sequencer_state.phase != TransportPhase::FadingOut;
+            let play_text = if is_playing { "Pause" } else { "Play" };
 
-            if ui
-                .add(egui::Button::new(RichText::new(play_text).heading()))
-                .clicked()
-            {
-                if model.is_playing {
-                    model.sequencer.stop();
-                    model.is_playing = false;
-                } else {
-                    model.sequencer.start();
-                    model.is_playing = true;
+            ui.horizontal(|ui| {
+                if ui
+                    .add(egui::Button::new(RichText::new(play_text).heading()))
+                    .clicked()
+                {
+                    if is_playing {
+                        model.sequencer.stop();
+                    } else {
+                        model.sequencer.start();
+                    }
+                };
+                let fade = &mut sequencer_model.fade;
+                ui.checkbox(&mut fade.enabled, "Fade")
+                    .on_hover_text("Ramp the velocity up on Play and down on Pause");
+                if fade.enabled {
+                    ui.add(
+                        egui::DragValue::new(&mut fade.bars)
+                            .clamp_range(1..=MAX_FADE_BARS)
+                            .suffix(" bars"),
+                    );
                 }
-            };
+                match sequencer_state.phase {
+                    TransportPhase::FadingIn => {
+                        ui.label("fading in");
+                    }
+                    TransportPhase::FadingOut => {
+                        ui.label("fading out");
+                    }
+                    _ => (),
+                }
+            });
+            if ui.button("Reload assets").clicked() {
+                reload_assets_clicked = true;
+            }
+            if let Some(failure) = &model.last_failure {
+                ui.separator();
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!(
+                        "Sequencer failed: {} (restarted {} times)",
+                        failure, model.restart_count
+                    ),
+                );
+                if sequencer_failure.is_some() && ui.button("Restart sequencer").clicked() {
+                    restart_clicked = true;
+                }
+            }
+            ui.checkbox(&mut model.auto_restart, "Restart automatically");
         });
-
-    // Update changes
-    model
-        .sequencer
-        .update_instrument(model.sequencer_model.instrument);
-    if model.sequencer_model.rhythm_pattern != rhythm_pattern {
-        model.sequencer_model.rhythm_pattern = rhythm_pattern;
-        model.sequencer_model.notes_per_beat = NOTES_PER_BEAT[rhythm_pattern.unwrap()];
-
-        model.sequencer.update_rhythm_pattern(
-            RHYTHM_PATTERNS[model.sequencer_model.rhythm_pattern.unwrap()]
-                .0
-                .to_vec(),
-        );
+    drop(library);
+    drop(registry);
+    if model.sequencer_model.voices != previous_voices {
+        model.sequencer.update_voices(model.sequencer_model.voices);
+    }
+    if model.sequencer_model.midi_protocol != previous_midi_protocol {
         model
             .sequencer
-            .update_trigger_producer(model.sequencer_model.clone().into());
+            .update_midi_protocol(model.sequencer_model.midi_protocol);
+    }
+    if was_following_chords && !model.follow_chords {
+        stop_following_chords(&mut model.sequencer_model, &model.sequencer);
+    }
+    if let Some(note) = auditioned {
+        model.sequencer.preview_note(note);
+    }
+    if reload_assets_clicked {
+        reload_assets(&mut model.sequencer_model, &model.sequencer);
+    }
+
+    // Replace a dead sequencer thread with a new one using the last configuration
+    if sequencer_failure.is_some() {
+        if restart_clicked {
+            model.restart_count = 0;
+        }
+        if restart_clicked || (model.auto_restart && model.restart_count < MAX_AUTO_RESTARTS) {
+            if !restart_clicked {
+                model.restart_count += 1;
+            }
+            model.sequencer = Sequencer::new(
+                model.sequencer_model.clone().into(),
+                model.sequencer.state().is_playing,
+                model.audio.as_ref().map(|audio| audio.sink()),
+            );
+            model.sequencer.set_loop_bars(model.loop_bars);
+            model.sequencer.set_quantize_changes(model.quantize_changes);
+            model.sequencer.set_manual_trigger(model.manual_trigger);
+            if let Some(midi_input) = &model.midi_input {
+                midi_input.set_manual_trigger(
+                    (model.manual_trigger && model.manual_keys)
+                        .then(|| model.sequencer.manual_trigger()),
+                );
+            }
+        }
+    }
+
+    if show_chaos_window(&ctx, &mut model.chaos) {
+        // the macro overrides the parameters it is mapped to
+        for mapping in &model.chaos.mappings {
+            let value = model.chaos.value(mapping);
+            match mapping.target.address() {
+                Some(address) => find_parameter(address)
+                    .unwrap()
+                    .set_normalized(&mut model.sequencer_model, value),
+                None => model.evolve.step = value * MAX_EVOLVE_STEP,
+            }
+        }
+    }
+
+    show_evolve_window(&ctx, &mut model.evolve, &model.sequencer_model);
+    let state = model.sequencer.state();
+    // 0 for the first bar, as the sequencer counts them for the seeds
+    let bar = state
+        .is_playing
+        .then(|| state.transport.bar_beat_tick().0 - 1);
+    if let Some(change) = model.evolve.update(bar, &mut model.sequencer_model) {
+        eprintln!("Evolve: {}", change);
+    }
+
+    if let Some(midi_input) = &model.midi_input {
+        model.key_detection.add_notes(&midi_input.take_note_ons());
+        model.tempo_follow.add_onsets(&midi_input.take_onsets());
+        for transport in midi_input.take_transport() {
+            if !model.midi_transport {
+                continue;
+            }
+            match transport {
+                RemoteTransport::Start => {
+                    model.sequencer.rewind();
+                    model.sequencer.start();
+                }
+                RemoteTransport::Continue => model.sequencer.start(),
+                RemoteTransport::Stop => model.sequencer.stop(),
+            }
+        }
+        // the last recognized chord holds until another one is played
+        if model.follow_chords {
+            let chord = recognize_chord(&midi_input.held_notes());
+            if chord.is_some() && chord != model.sequencer_model.chord {
+                model.sequencer_model.chord = chord;
+                model
+                    .sequencer
+                    .update_chain(ChainParameter::Scale(scale_mask(&chord.unwrap().tones())));
+                model.sequencer.update_root(chord.unwrap().root);
+            }
+            ctx.request_repaint();
+        }
+    }
+    let now = update.since_start.as_secs_f64();
+    model.key_detection.update(now);
+    let bar_seconds = 60.0 / model.sequencer_model.bpm as f64
+        * model
+            .sequencer_model
+            .selected_rhythm_pattern()
+            .beats_per_bar() as f64;
+    if let Some(key) = show_scale_detection_window(
+        &ctx,
+        &mut model.key_detection,
+        &mut model.detection_bars,
+        model.midi_input.is_some(),
+        now,
+        bar_seconds,
+    ) {
+        apply_detected_key(&mut model.sequencer_model, key);
+    }
+
+    show_tempo_follow_window(&ctx, &mut model.tempo_follow, model.midi_input.is_some());
+    if show_key_macros_window(&ctx, &mut model.key_macros) {
+        model.key_macros.save();
+    }
+    if show_accessibility_window(&ctx, &mut model.accessibility) {
+        apply_accessibility(&ctx, &model.accessibility);
+        model.accessibility.save();
+    }
+    // Escape hands the keys back to the shortcuts
+    if model.accessibility.keyboard_navigation
+        && ctx.input(|input| input.key_pressed(egui::Key::Escape))
+    {
+        ctx.memory_mut(|memory| {
+            if let Some(id) = memory.focus() {
+                memory.surrender_focus(id);
+            }
+        });
+    }
+    let shortcuts = shortcut_keys(&ctx, &model.accessibility);
+    // Keys going down and up, unless typing in a text field. Key repeats are left out.
+    let key_events: Vec<(egui::Key, bool)> = if ctx.wants_keyboard_input() {
+        Vec::new()
+    } else {
+        ctx.input(|input| {
+            input
+                .events
+                .iter()
+                .filter_map(|event| match *event {
+                    egui::Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        ..
+                    } => Some((key, pressed)),
+                    _ => None,
+                })
+                .collect()
+        })
+    };
+    for (key, pressed) in key_events {
+        if let Some(index) = model.key_macros.learning.filter(|_| pressed) {
+            model.key_macros.bindings[index].key = key.name().to_string();
+            model.key_macros.learning = None;
+            model.key_macros.save();
+            continue;
+        }
+        if !model.key_macros.enabled || !shortcuts {
+            continue;
+        }
+        let actions: Vec<MacroAction> = model.key_macros.actions(key.name()).collect();
+        for action in actions {
+            if pressed || action.is_held() {
+                run_key_macro(model, action, pressed);
+            }
+        }
+    }
+    match show_grid_window(
+        &ctx,
+        &model.output_ports,
+        &mut model.grid_port,
+        &mut model.grid_layout,
+        model.grid.is_some(),
+        model.grid_error.as_deref(),
+    ) {
+        Some(GridAction::Connect) => {
+            // the old connection lets go of the port first
+            model.grid = None;
+            if let Some(port) = model.grid_port.as_deref() {
+                match GridController::connect(port, model.grid_layout) {
+                    Ok(grid) => {
+                        model.grid = Some(grid);
+                        model.grid_error = None;
+                    }
+                    Err(err) => model.grid_error = Some(err),
+                }
+            }
+        }
+        Some(GridAction::Disconnect) => model.grid = None,
+        None => (),
+    }
+    if let Some(grid) = model.grid.as_mut() {
+        update_grid(grid, &mut model.sequencer_model, &model.sequencer);
+        ctx.request_repaint();
+    }
+    let mut roll = show_roll_window(&ctx, &mut model.roll_keys, model.midi_input.is_some());
+    if let Some(midi_input) = model.midi_input.as_ref().filter(|_| model.roll_keys) {
+        roll = roll.or(RollRate::from_held_keys(&midi_input.held_notes()));
+        ctx.request_repaint();
+    }
+    if model.fill {
+        roll = roll.or(Some(RollRate::Sixteenth));
+    }
+    if roll != model.roll {
+        match roll {
+            Some(rate) => model.sequencer.press_roll(rate),
+            None => model.sequencer.release_roll(),
+        }
+        model.roll = roll;
+    }
+    let previous_manual = (model.manual_trigger, model.manual_keys);
+    let pad_pressed = show_manual_trigger_window(
+        &ctx,
+        &mut model.manual_trigger,
+        &mut model.manual_keys,
+        model.midi_input.is_some(),
+    );
+    // Space presses too, unless typing in a text field. Key repeats don't play.
+    let key_pressed = shortcuts
+        && !ctx.wants_keyboard_input()
+        && ctx.input(|input| {
+            input.events.iter().any(|event| {
+                matches!(
+                    event,
+                    egui::Event::Key {
+                        key: egui::Key::Space,
+                        pressed: true,
+                        repeat: false,
+                        ..
+                    }
+                )
+            })
+        });
+    if model.manual_trigger && (pad_pressed || key_pressed) {
+        model.sequencer.manual_trigger().press();
+    }
+    if (model.manual_trigger, model.manual_keys) != previous_manual {
+        model.sequencer.set_manual_trigger(model.manual_trigger);
+        if let Some(midi_input) = &model.midi_input {
+            midi_input.set_manual_trigger(
+                (model.manual_trigger && model.manual_keys)
+                    .then(|| model.sequencer.manual_trigger()),
+            );
+        }
+    }
+    show_bass_window(&ctx, &mut model.sequencer_model.bass);
+    show_layers_window(&ctx, &mut model.sequencer_model.layers);
+    let bpm = model.sequencer_model.bpm;
+    if let Some(bpm) = model
+        .tempo_follow
+        .update(bpm, update.since_last.as_secs_f32())
+    {
+        model.sequencer_model.bpm = bpm.clamp(MIN_BPM_VALUE, MAX_BPM_VALUE);
+    }
+
+    if show_guide_window(&ctx, &mut model.guide, model.sequencer.guide_bar()) {
+        send_guide(&model.guide, &mut model.sequencer_model, &model.sequencer);
     }
 
-    if (model.sequencer_model.pitch_producer_type_index != pitch_producer_type) {
-        model.sequencer_model.pitch_producer_type_index = pitch_producer_type;
+    if show_melody_window(&ctx, &mut model.melody) {
+        model.sequencer_model.melody = model
+            .melody
+            .melody
+            .as_ref()
+            .map_or(Vec::new(), |melody| melody.notes.clone());
         model
             .sequencer
             .update_pitch_producer(model.sequencer_model.clone().into());
     }
-    if (model.sequencer_model.min_pitch != min_pitch) {
-        model.sequencer_model.min_pitch = min_pitch;
+
+    let latency = model.audio.as_ref().and_then(|audio| audio.latency());
+    let sample_rate = model.audio.as_ref().map(|audio| audio.sample_rate);
+    if show_audio_device_window(&ctx, &mut model.audio_devices, latency, sample_rate) {
+        restart_audio(model);
+    }
+
+    let mut reloaded = model.scripts.poll();
+    if show_scripts_window(&ctx, &model.scripts) {
+        reloaded = model.scripts.reload_all();
+    }
+    rebuild_reloaded_chains(&model.sequencer_model, &model.sequencer, reloaded);
+    if show_plugins_window(&ctx, &model.plugins) {
+        let reloaded = model.plugins.reload();
+        rebuild_reloaded_chains(&model.sequencer_model, &model.sequencer, reloaded);
+    }
+
+    if show_network_sync_window(&ctx, &mut model.network_sync) {
+        model.network_sync.connect();
+    }
+    sync_transport(model);
+
+    let has_audio = model.audio.as_ref().is_some_and(|audio| audio.is_running());
+    let recording = model
+        .audio
+        .as_ref()
+        .and_then(|audio| audio.recording_elapsed());
+    let mut record_clicked = false;
+    if recording.is_some() {
+        ctx.request_repaint();
+    }
+    if show_sound_window(
+        &ctx,
+        &mut model.sound,
+        has_audio,
+        recording,
+        &mut record_clicked,
+    ) {
+        if let Some(audio) = &model.audio {
+            audio.set_tracks(
+                model
+                    .sound
+                    .build_tracks(audio.sample_rate, model.sequencer_model.drums.voices),
+            );
+            audio.set_enabled(model.sound.enabled);
+        }
+    }
+
+    if let (true, Some(audio)) = (record_clicked, model.audio.as_mut()) {
+        model.sound.status = Some(if recording.is_some() {
+            audio
+                .stop_recording()
+                .map(|path| format!("Recorded {}", path.unwrap_or_default().display()))
+        } else {
+            audio
+                .start_recording()
+                .map(|path| format!("Recording to {}", path.display()))
+        });
+    }
+
+    show_drums_window(&ctx, &mut model.sequencer_model.drums);
+    show_note_processors_window(&ctx, &mut model.sequencer_model.note_processors);
+    if show_variations_window(
+        &ctx,
+        &mut model.sequencer_model,
+        model.sequencer.active_variations(),
+    ) {
         model
             .sequencer
-            .update_pitch_producer(model.sequencer_model.clone().into());
+            .update_variations(model.sequencer_model.variations.clone());
     }
-    if (model.sequencer_model.max_pitch != max_pitch) {
-        model.sequencer_model.max_pitch = max_pitch;
-        model
+
+    if show_synth_window(&ctx, &mut model.sound.synth) {
+        if let Some(audio) = &model.audio {
+            audio.update_synth(model.sound.synth);
+        }
+    }
+
+    if let Some(audio) = &model.audio {
+        model.sound.update_meter(audio.take_meter());
+    }
+    let meter = (model.sound.meter_peak, model.sound.meter_reduction);
+    if show_effects_window(&ctx, &mut model.sound.effects, meter) {
+        if let Some(audio) = &model.audio {
+            audio.update_effects(model.sound.effects);
+        }
+    }
+
+    match show_routing_window(
+        &ctx,
+        &mut model.sequencer_model.routing,
+        &model.output_ports,
+    ) {
+        Some(RoutingAction::Changed) => model
             .sequencer
-            .update_pitch_producer(model.sequencer_model.clone().into());
+            .update_routing(model.sequencer_model.routing.clone()),
+        Some(RoutingAction::RescanPorts) => model.output_ports = MidiSink::port_names(),
+        None => (),
     }
-    if (model.sequencer_model.cycle_length != cycle_length) {
-        model.sequencer_model.cycle_length = cycle_length;
+
+    show_velocity_curves_window(
+        &ctx,
+        &mut model.sequencer_model.velocity_curves,
+        &mut model.velocity_curve_instrument,
+    );
+    if show_timbre_drift_window(
+        &ctx,
+        &mut model.sequencer_model.timbre_drift,
+        &mut model.timbre_search,
+        model.sequencer.state().instrument,
+    ) {
         model
             .sequencer
-            .update_pitch_producer(model.sequencer_model.clone().into());
+            .update_timbre_drift(model.sequencer_model.timbre_drift.clone());
+    }
+
+    let statistics = model.sequencer.statistics(model.statistics_bars);
+    show_statistics_window(&ctx, &statistics, &mut model.statistics_bars);
+
+    match show_export_window(&ctx, &mut model.export) {
+        Some(ExportAction::Files) => model
+            .export
+            .export(model.sequencer_model.clone().into(), &model.sound),
+        Some(ExportAction::CopyNotation) => {
+            let text = model
+                .export
+                .notation_text(model.sequencer_model.clone().into());
+            ctx.output_mut(|output| output.copied_text = text);
+            model.export.status = Some(Ok("Copied to the clipboard".to_string()));
+        }
+        Some(ExportAction::SaveNotation) => model
+            .export
+            .save_notation(model.sequencer_model.clone().into()),
+        None => (),
+    }
+
+    match show_patch_window(&ctx, &mut model.patch) {
+        Some(PatchAction::Export) => model.patch.export(&model.sequencer_model, &model.scripts),
+        Some(PatchAction::Import) => {
+            let reloaded = model
+                .patch
+                .import(&mut model.sequencer_model, &mut model.scripts);
+            rebuild_reloaded_chains(&model.sequencer_model, &model.sequencer, reloaded);
+        }
+        None => (),
+    }
+
+    let browser_action = show_preset_browser_window(
+        &ctx,
+        &mut model.preset_browser,
+        &mut model.preset_thumbnails,
+    );
+    match browser_action {
+        Some(BrowserAction::Save) => {
+            model.preset_browser.save(&model.sequencer_model);
+            model.preset_thumbnails.clear();
+        }
+        Some(BrowserAction::Load(index)) => {
+            // like a slot recall, the preset comes in on the next bar
+            model.sequencer.hold_changes(true);
+            model.preset_browser.load(index, &mut model.sequencer_model)
+        }
+        Some(BrowserAction::Rescan) => {
+            model.preset_browser.rescan();
+            model.preset_thumbnails.clear();
+        }
+        None => (),
     }
-    if (model.sequencer_model.bpm != tempo) {
-        model.sequencer_model.bpm = tempo;
+
+    model.visuals.update(
+        model.sequencer.take_note_ons(),
+        update.since_start.as_secs_f32(),
+        &model.sequencer.transport(),
+    );
+    show_visuals_window(&ctx, &mut model.visuals.style);
+    if show_timing_window(&ctx, &mut model.sequencer_model.resolution) {
         model
             .sequencer
-            .update_trigger_producer(model.sequencer_model.clone().into());
+            .set_resolution(model.sequencer_model.resolution);
     }
-}
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    draw.background().color(BLACK);
+    let tap = model
+        .audio
+        .as_ref()
+        .filter(|audio| audio.is_running() && model.sound.enabled)
+        .map(|audio| audio.tap());
+    model.scope.update(tap);
+    show_scope_window(&ctx, &mut model.scope, tap.is_some());
+
+    match show_recovery_window(&ctx, &model.autosave) {
+        Some(RecoveryAction::Restore) => {
+            if let Some(session) = model.autosave.take_recovered() {
+                for note in session.preset.apply(&mut model.sequencer_model) {
+                    eprintln!("Recovering the session: {}", note);
+                }
+                model.melody.melody = session.melody();
+                model.sequencer_model.melody = session.melody;
+                model
+                    .sequencer
+                    .update_pitch_producer(model.sequencer_model.clone().into());
+            }
+        }
+        Some(RecoveryAction::Discard) => {
+            model.autosave.take_recovered();
+        }
+        None => (),
+    }
+    model
+        .autosave
+        .update(&model.sequencer_model, model.melody.melody.as_ref());
+
+    let degree_lane_changed = show_degree_lane_window(&ctx, &mut model.sequencer_model.degree_lane);
+    let intervals_changed = show_intervals_window(&ctx, &mut model.sequencer_model.intervals);
+    let pitch_chain_changed = show_pitch_chain_window(&ctx, &mut model.sequencer_model);
+    if degree_lane_changed || intervals_changed || pitch_chain_changed {
+        model
+            .sequencer
+            .update_pitch_producer(model.sequencer_model.clone().into());
+    }
+    let beat_density_changed = show_beat_density_window(&ctx, &mut model.sequencer_model);
+    if show_trigger_chain_window(&ctx, &mut model.sequencer_model) || beat_density_changed {
+        model
+            .sequencer
+            .queue_trigger_producer(model.sequencer_model.clone().into());
+    }
+
+    let rhythm_patterns_changed = show_rhythm_editor(
+        &ctx,
+        &mut model.rhythm_editor,
+        &mut model.sequencer_model.custom_rhythm_patterns,
+        &mut model.sequencer_model.rhythm_pattern,
+    );
+    if rhythm_patterns_changed {
+        save_custom_rhythm_patterns(&model.sequencer_model.custom_rhythm_patterns);
+    }
+
+    // Update changes
+    if model.sequencer_model.velocity_curves != previous_velocity_curves {
+        model
+            .sequencer
+            .update_velocity_curves(model.sequencer_model.velocity_curves.clone());
+    }
+    apply_parameter_changes(
+        &previous_values,
+        &mut model.sequencer_model,
+        &model.sequencer,
+        model.audio.as_ref(),
+    );
+    if matches!(slot_action, Some(SlotAction::Recall(_)))
+        || matches!(browser_action, Some(BrowserAction::Load(_)))
+    {
+        model.sequencer.hold_changes(false);
+    }
+}
+
+// Sends the sequencer only what the changed parameters require, once per frame
+fn apply_parameter_changes(
+    previous: &[f32],
+    sequencer_model: &mut SequencerModel,
+    sequencer: &Sequencer,
+    audio: Option<&AudioEngine>,
+) {
+    let targets = changed_targets(previous, sequencer_model);
+    if targets.contains(&ParameterTarget::RhythmPattern) {
+        send_rhythm_pattern(sequencer_model, sequencer);
+    }
+    if targets.contains(&ParameterTarget::RhythmPattern) {
+        sequencer.queue_trigger_producer(sequencer_model.clone().into());
+    }
+    if targets.contains(&ParameterTarget::PitchChain) {
+        // a new key waits for the bar line like the rhythm and the instrument
+        if parameter_changed(previous, sequencer_model, "/pitch/scale")
+            || parameter_changed(previous, sequencer_model, "/pitch/root")
+        {
+            sequencer.queue_pitch_producer(sequencer_model.clone().into());
+        } else {
+            sequencer.update_pitch_producer(sequencer_model.clone().into());
+        }
+    }
+    if targets.contains(&ParameterTarget::Tempo) {
+        sequencer.update_slewed(
+            SlewedParameter::Bpm,
+            sequencer_model.bpm,
+            sequencer_model.slew_beats,
+        );
+        // the delay follows the target tempo right away
+        if let Some(audio) = audio {
+            audio.set_tempo(sequencer_model.bpm);
+        }
+    }
+    if targets.contains(&ParameterTarget::TriggerProbability) {
+        sequencer.update_slewed(
+            SlewedParameter::TriggerProbability,
+            sequencer_model.trigger_probability as f32,
+            sequencer_model.slew_beats,
+        );
+    }
+    if targets.contains(&ParameterTarget::RestProbability) {
+        sequencer.update_chain(ChainParameter::RestProbability(
+            sequencer_model.rest_probability,
+        ));
+    }
+    if targets.contains(&ParameterTarget::OctaveJumps) {
+        sequencer.update_chain(ChainParameter::OctaveJumpProbability(
+            sequencer_model.octave_jump_probability,
+        ));
+    }
+    if targets.contains(&ParameterTarget::SampleHold) {
+        sequencer.update_chain(ChainParameter::HoldSteps(
+            sequencer_model.sample_hold_steps as u32,
+        ));
+    }
+    if targets.contains(&ParameterTarget::Groove) {
+        sequencer.update_groove(&GROOVE_TEMPLATES[sequencer_model.groove_index.unwrap()]);
+    }
+    if targets.contains(&ParameterTarget::VelocityJitter) {
+        sequencer.update_velocity_jitter(sequencer_model.velocity_jitter);
+    }
+    if targets.contains(&ParameterTarget::Fade) {
+        sequencer.update_fade(sequencer_model.fade);
+    }
+    if targets.contains(&ParameterTarget::Accent) {
+        sequencer.update_accent(sequencer_model.accent);
+    }
+    if targets.contains(&ParameterTarget::DensityCompensation) {
+        sequencer.update_density_compensation(sequencer_model.density_compensation);
+    }
+    if targets.contains(&ParameterTarget::NoteLength) {
+        sequencer.update_note_length(sequencer_model.note_length);
+    }
+    if targets.contains(&ParameterTarget::Speed) {
+        sequencer.update_speed(TRACK_SPEEDS[sequencer_model.speed_index]);
+    }
+    if targets.contains(&ParameterTarget::Rotation) {
+        sequencer.update_rotation(
+            sequencer_model.rhythm_rotation as u32,
+            sequencer_model.pitch_rotation as u32,
+        );
+    }
+    if targets.contains(&ParameterTarget::PressureEnvelope) {
+        sequencer.update_pressure_envelope(sequencer_model.pressure_envelope);
+    }
+    if targets.contains(&ParameterTarget::KeyModulation) {
+        sequencer.update_key_modulation(sequencer_model.key_modulation);
+    }
+    if targets.contains(&ParameterTarget::Sustain) {
+        sequencer.update_sustain(sustain_automation_from_model(sequencer_model));
+    }
+    if targets.contains(&ParameterTarget::Instrument) {
+        sequencer.update_instrument(sequencer_model.instrument);
+    }
+    if targets.contains(&ParameterTarget::Split) {
+        sequencer.update_split(sequencer_model.split);
+    }
+    if targets.contains(&ParameterTarget::NoteProcessors) {
+        sequencer.update_note_processors(sequencer_model.note_processors);
+    }
+    if targets.contains(&ParameterTarget::Cadence) {
+        sequencer.update_cadence(sequencer_model.cadence);
+    }
+    if targets.contains(&ParameterTarget::Seed) {
+        sequencer.update_seed(sequencer_model.seed);
+    }
+    if targets.contains(&ParameterTarget::ScaleLock) {
+        sequencer.update_scale_lock(sequencer_model.scale_lock);
+    }
+    if targets.contains(&ParameterTarget::CollisionAvoidance) {
+        sequencer.update_collision_avoidance(sequencer_model.collision_avoidance);
+    }
+    if targets.contains(&ParameterTarget::Bass) {
+        sequencer.update_bass(sequencer_model.bass);
+    }
+    if targets.contains(&ParameterTarget::Layers) {
+        sequencer.update_layers(sequencer_model.layers);
+    }
+    if targets.contains(&ParameterTarget::Drums) {
+        sequencer.update_drums(sequencer_model.drums);
+        if let Some(audio) = audio {
+            audio.update_drums(sequencer_model.drums.voices);
+        }
+    }
+}
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    draw.background().color(BLACK);
+    model.visuals.draw(&draw, app.window_rect());
+    model.scope.draw(&draw, app.window_rect());
     draw.to_frame(app, &frame).unwrap();
     model.egui.draw_to_frame(&frame).unwrap();
 }
 
-fn pitch_producer_type_from_index(idx: Option<usize>) -> PitchProducerType {
-    PitchProducerType::from_str(PITCH_PRODUCER_TYPE_NAMES[idx.unwrap()]).unwrap()
+fn range_mode_from_index(idx: Option<usize>) -> RangeMode {
+    RangeMode::from_str(RANGE_MODE_NAMES[idx.unwrap()]).unwrap()
+}
+
+fn logic_operation_from_index(idx: Option<usize>) -> LogicOperation {
+    LogicOperation::from_str(LOGIC_OPERATION_NAMES[idx.unwrap()]).unwrap()
+}
+
+fn direction_from_index(idx: Option<usize>) -> Direction {
+    Direction::from_str(DIRECTION_NAMES[idx.unwrap()]).unwrap()
+}
+
+fn tension_shape_from_index(idx: Option<usize>) -> TensionShape {
+    TensionShape::from_str(TENSION_SHAPE_NAMES[idx.unwrap()]).unwrap()
+}
+
+fn sustain_mode_from_index(idx: Option<usize>) -> SustainMode {
+    SustainMode::from_str(SUSTAIN_MODE_NAMES[idx.unwrap()]).unwrap()
+}
+
+fn sustain_automation_from_model(model: &SequencerModel) -> SustainAutomation {
+    SustainAutomation {
+        mode: sustain_mode_from_index(model.sustain_mode_index),
+        phrase_bars: model.sustain_phrase_bars as u32,
+        probability: model.sustain_probability,
+    }
+}
+
+// The selected scale moved to the selected root
+fn key_scale(library: &AssetLibrary, model: &SequencerModel) -> Vec<Letter> {
+    transpose_scale(
+        &library.scales[model.quantizer_scale_index.unwrap()].notes,
+        model.scale_root_index.unwrap() as i32,
+    )
+}
+
+// Puts the key scale back on the quantizer
+fn stop_following_chords(sequencer_model: &mut SequencerModel, sequencer: &Sequencer) {
+    sequencer_model.chord = None;
+    let scale = key_scale(&library(), sequencer_model);
+    sequencer.update_chain(ChainParameter::Scale(scale_mask(&scale)));
+    sequencer.update_root(sequencer_model.scale_root_index.unwrap());
+}
+
+// Points the quantizer at the detected key, if the library still has a scale for its mode
+fn apply_detected_key(sequencer_model: &mut SequencerModel, key: DetectedKey) {
+    let scale_index = library()
+        .scales
+        .iter()
+        .position(|scale| scale.name == key.mode.name());
+    if let Some(scale_index) = scale_index {
+        sequencer_model.quantizer_scale_index = Some(scale_index);
+        sequencer_model.scale_root_index = Some(key.tonic);
+    }
+}
+
+// Restarts the internal audio on the selected output, or starts it if it could not start before
+fn restart_audio(model: &mut Model) {
+    let settings = &model.audio_devices.settings;
+    let effects = model.sound.effects;
+    let bpm = model.sequencer_model.bpm;
+    let result = match &mut model.audio {
+        Some(audio) => audio.restart(settings, effects, bpm),
+        None => AudioEngine::start(settings, effects, bpm).map(|audio| {
+            model.sequencer.set_audio_sink(audio.sink());
+            model.audio = Some(audio);
+        }),
+    };
+    let Some(audio) = model.audio.as_ref().filter(|_| result.is_ok()) else {
+        model.audio_devices.status = result.err().map(Err);
+        return;
+    };
+    audio.set_tracks(
+        model
+            .sound
+            .build_tracks(audio.sample_rate, model.sequencer_model.drums.voices),
+    );
+    audio.set_enabled(model.sound.enabled);
+    model.audio_devices.status = Some(Ok(format!("Playing at {} Hz", audio.sample_rate)));
+}
+
+// Rebuilds the chains whose producer was reloaded
+fn rebuild_reloaded_chains(
+    sequencer_model: &SequencerModel,
+    sequencer: &Sequencer,
+    reloaded: ReloadedProducers,
+) {
+    if reloaded.is_empty() {
+        return;
+    }
+    let config: SequencerConfiguration = sequencer_model.clone().into();
+    if reloaded.pitch_producers.contains(&config.pitch_producer) {
+        sequencer.update_pitch_producer(config.clone());
+    }
+    if reloaded
+        .trigger_producers
+        .contains(&config.trigger_producer)
+    {
+        sequencer.update_trigger_producer(config);
+    }
+}
+
+// Sends the transport to the followers, or takes the one of the leader
+fn sync_transport(model: &mut Model) {
+    let Some(sync) = model.network_sync.sync.as_mut() else {
+        return;
+    };
+    let engine = model.sequencer.state();
+    if sync.role() == SyncRole::Leader {
+        sync.lead(engine.is_playing, engine.tempo, engine.transport.beats());
+        return;
+    }
+    let Some(state) = sync.follow() else {
+        return;
+    };
+    // the tempo goes through the parameter changes like a slider move
+    model.sequencer_model.bpm = state.bpm.clamp(MIN_BPM_VALUE, MAX_BPM_VALUE);
+    if state.is_playing != engine.is_playing {
+        if state.is_playing {
+            model.sequencer.start();
+        } else {
+            model.sequencer.stop();
+        }
+    }
+    if state.is_playing {
+        model.sequencer.sync_to(state.beat);
+    }
+}
+
+// Takes the tempo and key of a newly imported guide and sends the sequencer its chords,
+// bars without a chord keep the key scale
+fn send_guide(guide: &GuideImport, sequencer_model: &mut SequencerModel, sequencer: &Sequencer) {
+    let Some(track) = guide.track.as_ref() else {
+        sequencer.update_guide(None);
+        sequencer.update_pitch_producer(sequencer_model.clone().into());
+        return;
+    };
+    sequencer_model.bpm = track.bpm.clamp(MIN_BPM_VALUE, MAX_BPM_VALUE);
+    if let Some(key) = track.key {
+        apply_detected_key(sequencer_model, key);
+    }
+    let key = GuideBar {
+        mask: scale_mask(&key_scale(&library(), sequencer_model)),
+        root: sequencer_model.scale_root_index.unwrap(),
+    };
+    sequencer.update_guide(Some(
+        track
+            .bars
+            .iter()
+            .map(|chord| {
+                chord.map_or(key, |chord| GuideBar {
+                    mask: scale_mask(&chord.tones()),
+                    root: chord.root,
+                })
+            })
+            .collect(),
+    ));
+}
+
+fn send_rhythm_pattern(sequencer_model: &SequencerModel, sequencer: &Sequencer) {
+    sequencer.update_rhythm_pattern(rhythm_pattern(
+        &library(),
+        &sequencer_model.custom_rhythm_patterns,
+        sequencer_model.rhythm_pattern.unwrap(),
+    ));
+}
+
+// Reloads the asset files and resends everything, since indices may now point at new content
+fn reload_assets(sequencer_model: &mut SequencerModel, sequencer: &Sequencer) {
+    reload_library();
+    let (scale_count, rhythm_count) = {
+        let library = library();
+        (library.scales.len(), library.rhythm_patterns.len())
+    };
+    if sequencer_model.quantizer_scale_index.unwrap() >= scale_count {
+        sequencer_model.quantizer_scale_index = Some(QUANTIZER_SCALE_INDEX_DEFAULT_VALUE);
+    }
+    if sequencer_model.rhythm_pattern.unwrap()
+        >= rhythm_count + sequencer_model.custom_rhythm_patterns.len()
+    {
+        sequencer_model.rhythm_pattern = Some(RHYTHM_PATTERN_DEFAULT_VALUE);
+    }
+    send_rhythm_pattern(sequencer_model, sequencer);
+    sequencer.update_trigger_producer(sequencer_model.clone().into());
+    sequencer.update_pitch_producer(sequencer_model.clone().into());
+}
+
+// Returns true when the list of custom patterns was modified
+fn show_rhythm_editor(
+    ctx: &egui::Context,
+    editor: &mut RhythmEditor,
+    custom_rhythm_patterns: &mut Vec<RhythmPattern>,
+    rhythm_pattern: &mut Option<usize>,
+) -> bool {
+    let mut changed = false;
+    egui::Window::new("Rhythm editor")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (letter, symbol) in NOTE_DURATION_LETTERS {
+                    if ui.button(*symbol).clicked() {
+                        editor.durations.push(*letter);
+                    }
+                }
+            });
+            ui.label("Pattern (click a step to remove it):");
+            ui.horizontal_wrapped(|ui| {
+                let mut removed = None;
+                for (index, letter) in editor.durations.iter().enumerate() {
+                    if ui.small_button(note_duration_symbol(*letter)).clicked() {
+                        removed = Some(index);
+                    }
+                }
+                if let Some(index) = removed {
+                    editor.durations.remove(index);
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Notes per beat:");
+                for notes in editor.notes_per_beat.iter_mut() {
+                    ui.add(egui::DragValue::new(notes).clamp_range(0..=8));
+                }
+                if ui
+                    .add_enabled(
+                        editor.notes_per_beat.len() < MAX_BEATS_PER_BAR,
+                        egui::Button::new("+").small(),
+                    )
+                    .on_hover_text("Add a beat to the bar")
+                    .clicked()
+                {
+                    editor.notes_per_beat.push(1);
+                }
+                if ui
+                    .add_enabled(
+                        editor.notes_per_beat.len() > 1,
+                        egui::Button::new("-").small(),
+                    )
+                    .on_hover_text("Remove the last beat of the bar")
+                    .clicked()
+                {
+                    editor.notes_per_beat.pop();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut editor.name);
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(editor.is_valid(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    custom_rhythm_patterns.push(editor.to_pattern());
+                    *editor = RhythmEditor::new();
+                    changed = true;
+                }
+                if ui.button("Clear").clicked() {
+                    editor.durations.clear();
+                }
+            });
+
+            ui.separator();
+            let mut deleted = None;
+            for (index, pattern) in custom_rhythm_patterns.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(&pattern.name);
+                    if ui.small_button("Delete").clicked() {
+                        deleted = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = deleted {
+                custom_rhythm_patterns.remove(index);
+                // keep the selection pointing at the same pattern, or fall back to the default
+                let deleted_index = library().rhythm_patterns.len() + index;
+                match rhythm_pattern.unwrap() {
+                    selected if selected == deleted_index => {
+                        *rhythm_pattern = Some(RHYTHM_PATTERN_DEFAULT_VALUE)
+                    }
+                    selected if selected > deleted_index => *rhythm_pattern = Some(selected - 1),
+                    _ => (),
+                }
+                changed = true;
+            }
+        });
+    changed
+}
+
+// Returns true when the macro needs to be re-applied to its targets
+fn show_chaos_window(ctx: &egui::Context, chaos: &mut ChaosMacro) -> bool {
+    let mut changed = false;
+    egui::Window::new("Chaos")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            changed |= ui
+                .add(egui::Slider::new(&mut chaos.amount, 0.0..=1.0).text("Amount"))
+                .changed();
+            ui.separator();
+            egui::Grid::new("chaos_grid")
+                .num_columns(3)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    for (index, mapping) in chaos.mappings.iter_mut().enumerate() {
+                        ui.label(mapping.target.name());
+                        egui::ComboBox::from_id_source(("chaos_curve", index))
+                            .selected_text(mapping.curve.name())
+                            .width(100.0)
+                            .show_ui(ui, |ui| {
+                                for (curve, name) in MACRO_CURVES {
+                                    changed |= ui
+                                        .selectable_value(&mut mapping.curve, *curve, *name)
+                                        .changed();
+                                }
+                            });
+                        changed |= ui
+                            .add(egui::Slider::new(&mut mapping.depth, 0.0..=1.0).text("Depth"))
+                            .changed();
+                        ui.end_row();
+                    }
+                });
+        });
+    changed
+}
+
+fn show_evolve_window(ctx: &egui::Context, evolve: &mut Evolve, sequencer_model: &SequencerModel) {
+    egui::Window::new("Evolve")
+        .default_open(false)
+        .default_width(320.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut evolve.enabled, "Evolve")
+                .on_hover_text("Every few bars, a small random change to one unlocked parameter");
+            ui.add(
+                egui::Slider::new(&mut evolve.every_bars, MIN_EVOLVE_BARS..=MAX_EVOLVE_BARS)
+                    .text("Every bars"),
+            );
+            ui.add(egui::Slider::new(&mut evolve.step, 0.0..=MAX_EVOLVE_STEP).text("Step"))
+                .on_hover_text("Largest change, as a share of the bounds");
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("evolve_grid")
+                        .num_columns(4)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Lock");
+                            ui.strong("Parameter");
+                            ui.strong("From");
+                            ui.strong("To");
+                            ui.end_row();
+                            for rule in evolve.rules.iter_mut() {
+                                let range = find_parameter(rule.address)
+                                    .map(|parameter| (parameter.range)(sequencer_model))
+                                    .unwrap_or(rule.min..=rule.max);
+                                ui.checkbox(&mut rule.locked, "");
+                                ui.label(rule.name);
+                                ui.add_enabled(
+                                    !rule.locked,
+                                    egui::DragValue::new(&mut rule.min)
+                                        .clamp_range(*range.start()..=rule.max)
+                                        .speed((range.end() - range.start()) / 200.0),
+                                );
+                                ui.add_enabled(
+                                    !rule.locked,
+                                    egui::DragValue::new(&mut rule.max)
+                                        .clamp_range(rule.min..=*range.end())
+                                        .speed((range.end() - range.start()) / 200.0)
+                                        .suffix(format!(" {}", rule.unit)),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+}
+
+// A row per track: the MIDI port and channel it is sent on, whether the internal synth plays
+// it, and a mute for each destination
+fn show_routing_window(
+    ctx: &egui::Context,
+    routing: &mut RoutingTable,
+    output_ports: &[String],
+) -> Option<RoutingAction> {
+    let mut action = None;
+    egui::Window::new("Routing")
+        .default_open(false)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .checkbox(&mut routing.enabled, "Route tracks")
+                    .on_hover_text("Off, every track goes to the first port and the synth")
+                    .changed();
+                if ui.button("Rescan ports").clicked() {
+                    action = Some(RoutingAction::RescanPorts);
+                }
+            });
+            ui.add_enabled_ui(routing.enabled, |ui| {
+                egui::Grid::new("routing_matrix")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Track");
+                        ui.strong("MIDI port");
+                        ui.strong("Channel");
+                        ui.strong("Mute MIDI");
+                        ui.strong("Synth");
+                        ui.strong("Mute synth");
+                        ui.end_row();
+                        for (track, (name, route)) in TRACK_NAMES
+                            .iter()
+                            .zip(routing.routes.iter_mut())
+                            .enumerate()
+                        {
+                            ui.label(*name);
+                            egui::ComboBox::from_id_source(("routing_port", track))
+                                .selected_text(route.port.as_deref().unwrap_or("First port"))
+                                .width(140.0)
+                                .show_ui(ui, |ui| {
+                                    changed |= ui
+                                        .selectable_value(&mut route.port, None, "First port")
+                                        .changed();
+                                    for port in output_ports {
+                                        changed |= ui
+                                            .selectable_value(
+                                                &mut route.port,
+                                                Some(port.clone()),
+                                                port,
+                                            )
+                                            .changed();
+                                    }
+                                });
+                            // shown from 1 as on the instruments
+                            let mut channel = route.channel + 1;
+                            if ui
+                                .add(egui::DragValue::new(&mut channel).clamp_range(1..=16))
+                                .changed()
+                            {
+                                route.channel = channel - 1;
+                                changed = true;
+                            }
+                            changed |= ui.checkbox(&mut route.midi_muted, "").changed();
+                            changed |= ui.checkbox(&mut route.synth, "").changed();
+                            changed |= ui
+                                .add_enabled(
+                                    route.synth,
+                                    egui::Checkbox::new(&mut route.synth_muted, ""),
+                                )
+                                .changed();
+                            ui.end_row();
+                        }
+                    });
+            });
+            if changed {
+                action = Some(RoutingAction::Changed);
+            }
+        });
+    action
+}
+
+// The curve of an instrument, picked from the GM programs, with the ones set marked
+fn show_velocity_curves_window(
+    ctx: &egui::Context,
+    curves: &mut VelocityCurves,
+    instrument: &mut u8,
+) {
+    egui::Window::new("Velocity curves")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            let library = library();
+            let label = |program: u8| {
+                let label = library.instruments[program as usize].label();
+                if curves.0.contains_key(&program) {
+                    format!("{} *", label)
+                } else {
+                    label
+                }
+            };
+            egui::ComboBox::from_id_source("velocity_curve_instrument")
+                .selected_text(label(*instrument))
+                .width(200.0)
+                .show_ui(ui, |ui| {
+                    for entry in library.instruments.iter() {
+                        ui.selectable_value(instrument, entry.program, label(entry.program));
+                    }
+                });
+            let mut curve = curves
+                .0
+                .get(instrument)
+                .cloned()
+                .unwrap_or(VelocityCurve::Linear);
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                for name in CURVE_NAMES {
+                    if ui.selectable_label(curve.name() == name, name).clicked()
+                        && curve.name() != name
+                    {
+                        curve = VelocityCurve::from_name(name);
+                        changed = true;
+                    }
+                }
+            });
+            changed |= show_velocity_curve_editor(ui, &mut curve);
+            if let VelocityCurve::Custom(_) = curve {
+                ui.label("Drag the points, double click to add one, right click to remove it");
+                ui.label(
+                    "Focused, Space picks a point, the arrows move it, Insert adds one after it \
+                     and Delete removes it",
+                );
+            }
+            if changed {
+                // linear is what an instrument without a curve plays
+                match curve {
+                    VelocityCurve::Linear => curves.0.remove(instrument),
+                    curve => curves.0.insert(*instrument, curve),
+                };
+            }
+        });
+}
+
+// Velocity across, response up. Returns true when a point of a custom curve was moved,
+// added or removed.
+fn show_velocity_curve_editor(ui: &mut egui::Ui, curve: &mut VelocityCurve) -> bool {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(VELOCITY_CURVE_EDITOR_SIZE, VELOCITY_CURVE_EDITOR_SIZE),
+        egui::Sense::click_and_drag(),
+    );
+    let painter = ui.painter_at(rect);
+    let to_screen = |[x, y]: [f32; 2]| {
+        egui::pos2(
+            rect.left() + x * rect.width(),
+            rect.bottom() - y * rect.height(),
+        )
+    };
+    let from_screen = |pos: egui::Pos2| {
+        [
+            ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+            ((rect.bottom() - pos.y) / rect.height()).clamp(0.0, 1.0),
+        ]
+    };
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 0.0, visuals.extreme_bg_color);
+    painter.line_segment(
+        [to_screen([0.0, 0.0]), to_screen([1.0, 1.0])],
+        visuals.widgets.noninteractive.bg_stroke,
+    );
+    let line: Vec<egui::Pos2> = (0..=VELOCITY_CURVE_SEGMENTS)
+        .map(|i| {
+            let x = i as f32 / VELOCITY_CURVE_SEGMENTS as f32;
+            to_screen([x, curve.response(x)])
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        line,
+        egui::Stroke::new(2.0, visuals.selection.bg_fill),
+    ));
+    let VelocityCurve::Custom(points) = curve else {
+        return false;
+    };
+    for point in points.iter() {
+        painter.circle_filled(
+            to_screen(*point),
+            VELOCITY_CURVE_POINT_RADIUS,
+            visuals.strong_text_color(),
+        );
+    }
+    let Some(last) = points.len().checked_sub(1) else {
+        return false;
+    };
+    // From the keyboard: Space or Enter selects the next point and the arrows move it.
+    // Insert adds a point after it, Delete removes it.
+    if response.has_focus() {
+        let selected_id = response.id.with("selected_point");
+        let mut selected = ui
+            .data(|data| data.get_temp::<usize>(selected_id))
+            .unwrap_or(0)
+            .min(last);
+        if response.clicked() {
+            selected = (selected + 1) % points.len();
+        }
+        let (insert, delete, x, y) = ui.input(|input| {
+            let nudge = |negative, positive| {
+                (input.key_pressed(positive) as i32 - input.key_pressed(negative) as i32) as f32
+                    * VELOCITY_CURVE_NUDGE
+            };
+            (
+                input.key_pressed(egui::Key::Insert),
+                input.key_pressed(egui::Key::Delete),
+                nudge(egui::Key::ArrowLeft, egui::Key::ArrowRight),
+                nudge(egui::Key::ArrowDown, egui::Key::ArrowUp),
+            )
+        });
+        let mut changed = false;
+        if x != 0.0 || y != 0.0 {
+            let [point_x, point_y] = points[selected];
+            move_velocity_curve_point(
+                points,
+                selected,
+                [point_x + x, (point_y + y).clamp(0.0, 1.0)],
+            );
+            changed = true;
+        } else if insert && selected != last {
+            let [[x0, y0], [x1, y1]] = [points[selected], points[selected + 1]];
+            selected += 1;
+            points.insert(selected, [(x0 + x1) / 2.0, (y0 + y1) / 2.0]);
+            changed = true;
+        } else if delete && selected != 0 && selected != last {
+            points.remove(selected);
+            selected -= 1;
+            changed = true;
+        }
+        ui.data_mut(|data| data.insert_temp(selected_id, selected));
+        painter.circle_stroke(
+            to_screen(points[selected]),
+            VELOCITY_CURVE_POINT_RADIUS * 2.0,
+            visuals.selection.stroke,
+        );
+        if changed {
+            return true;
+        }
+    }
+    let Some(pointer) = response.interact_pointer_pos() else {
+        return false;
+    };
+    let nearest = points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| (index, to_screen(*point).distance(pointer)))
+        .filter(|(_, distance)| *distance <= VELOCITY_CURVE_POINT_RADIUS * 2.0)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index);
+    // the point held stays the same while dragging, even past another one
+    let dragged_id = response.id.with("dragged_point");
+    if response.drag_started() {
+        ui.data_mut(|data| data.insert_temp(dragged_id, nearest));
+    }
+    let [x, y] = from_screen(pointer);
+    if response.dragged() {
+        let Some(index) = ui
+            .data(|data| data.get_temp::<Option<usize>>(dragged_id))
+            .flatten()
+        else {
+            return false;
+        };
+        move_velocity_curve_point(points, index, [x, y]);
+        return true;
+    }
+    if response.double_clicked() && nearest.is_none() {
+        let index = points.partition_point(|point| point[0] < x);
+        points.insert(index, [x, y]);
+        return true;
+    }
+    match nearest {
+        Some(index) if response.secondary_clicked() && index != 0 && index != last => {
+            points.remove(index);
+            true
+        }
+        _ => false,
+    }
+}
+
+// The ends stay at the lowest and the highest velocity, the others between their neighbours
+fn move_velocity_curve_point(points: &mut [[f32; 2]], index: usize, [x, y]: [f32; 2]) {
+    let last = points.len() - 1;
+    points[index] = match index {
+        0 => [0.0, y],
+        _ if index == last => [1.0, y],
+        _ => [x.clamp(points[index - 1][0], points[index + 1][0]), y],
+    };
+}
+
+// Returns the detected key once the user accepts it
+fn show_scale_detection_window(
+    ctx: &egui::Context,
+    detection: &mut KeyDetection,
+    detection_bars: &mut u32,
+    has_midi_input: bool,
+    now: f64,
+    bar_seconds: f64,
+) -> Option<DetectedKey> {
+    let mut accepted = None;
+    egui::Window::new("Detect scale")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            if !has_midi_input {
+                ui.label("No MIDI input port available");
+                return;
+            }
+            ui.add(
+                egui::Slider::new(detection_bars, MIN_DETECTION_BARS..=MAX_DETECTION_BARS)
+                    .text("Bars"),
+            );
+            if detection.is_listening() {
+                ui.label(format!(
+                    "Listening... {:.1}s left",
+                    detection.remaining(now)
+                ));
+                ctx.request_repaint();
+            } else if ui.button("Detect scale").clicked() {
+                detection.start(now, *detection_bars as f64 * bar_seconds);
+            }
+            if detection.no_notes {
+                ui.label("No notes were played");
+            }
+            if let Some(key) = detection.result {
+                ui.separator();
+                ui.label(format!(
+                    "Detected {} {} (match {:.2})",
+                    pitch_class_name(key.tonic),
+                    key.mode.name(),
+                    key.correlation
+                ));
+                // user scale files may leave out the scale of the mode
+                let has_scale = library()
+                    .scales
+                    .iter()
+                    .any(|scale| scale.name == key.mode.name());
+                if !has_scale {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("No \"{}\" scale in the library to apply", key.mode.name()),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(has_scale, egui::Button::new("Apply"))
+                        .clicked()
+                    {
+                        accepted = Some(key);
+                        detection.result = None;
+                    }
+                    if ui.button("Discard").clicked() {
+                        detection.result = None;
+                    }
+                });
+            }
+        });
+    accepted
+}
+
+fn show_bass_window(ctx: &egui::Context, bass: &mut BassSettings) {
+    let library = library();
+    egui::Window::new("Bass")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut bass.enabled, "Play a bass line")
+                .on_hover_text("The root of the scale or chord on the downbeats");
+            ui.horizontal(|ui| {
+                ui.label("Pattern:");
+                egui::ComboBox::from_id_source("bass_pattern")
+                    .selected_text(bass.pattern.to_string())
+                    .show_ui(ui, |ui| {
+                        for pattern in BASS_PATTERNS {
+                            ui.selectable_value(&mut bass.pattern, pattern, pattern.to_string());
+                        }
+                    });
+            });
+            ui.add(
+                egui::Slider::new(&mut bass.octaves_below, 1..=MAX_BASS_OCTAVES_BELOW)
+                    .text("Octaves below the melody"),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Instrument:");
+                egui::ComboBox::from_id_source("bass_instrument")
+                    .selected_text(library.instruments[bass.instrument as usize].label())
+                    .width(160.0)
+                    .show_ui(ui, |ui| {
+                        let mut category = "";
+                        for entry in library.instruments.iter() {
+                            if entry.category != category {
+                                category = entry.category;
+                                ui.label(RichText::new(category).strong());
+                            }
+                            ui.selectable_value(&mut bass.instrument, entry.program, entry.label());
+                        }
+                    });
+            });
+        });
+}
+
+fn show_layers_window(ctx: &egui::Context, layers: &mut LayerSettings) {
+    egui::Window::new("Derived layers")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut layers.enabled, "Derive layers")
+                .on_hover_text("A bass and hats following the main voice, each on its own channel");
+            ui.add(
+                egui::Slider::new(&mut layers.bass_steps, 1..=MAX_LAYER_STEPS)
+                    .text("Sixteenths between bass notes"),
+            );
+            ui.add(
+                egui::Slider::new(&mut layers.bass_octaves, 0..=MAX_LAYER_OCTAVES)
+                    .text("Bass octaves below"),
+            );
+            ui.add(
+                egui::Slider::new(&mut layers.hat_steps, 1..=MAX_LAYER_STEPS)
+                    .text("Sixteenths between hats"),
+            );
+            ui.label("Divisions that don't go into the bar drift across it");
+        });
+}
+
+// Returns true when the drift changed
+fn show_timbre_drift_window(
+    ctx: &egui::Context,
+    drift: &mut TimbreDrift,
+    search: &mut String,
+    playing: u8,
+) -> bool {
+    let mut changed = false;
+    egui::Window::new("Timbre drift")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            let library = library();
+            changed |= ui
+                .checkbox(&mut drift.enabled, "Drift the instrument")
+                .on_hover_text("The main voice moves to a neighbouring program of the set")
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut drift.every_bars, 1..=MAX_TIMBRE_DRIFT_BARS)
+                        .text("Bars between changes"),
+                )
+                .changed();
+            ui.label(format!(
+                "Playing {}",
+                library.instruments[playing as usize].label()
+            ));
+            if drift.programs.len() < 2 {
+                ui.label("Pick two programs or more to walk through");
+            }
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(search)
+                        .hint_text("Instrument or category")
+                        .desired_width(160.0),
+                );
+                if ui.button("Clear set").clicked() {
+                    drift.programs.clear();
+                    changed = true;
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .show(ui, |ui| {
+                    let mut category = "";
+                    for entry in library.instruments.iter() {
+                        if !entry.matches(search) {
+                            continue;
+                        }
+                        if entry.category != category {
+                            category = entry.category;
+                            ui.label(RichText::new(category).strong());
+                        }
+                        let mut selected = drift.programs.contains(&entry.program);
+                        if ui.checkbox(&mut selected, entry.label()).changed() {
+                            drift.toggle(entry.program);
+                            changed = true;
+                        }
+                    }
+                });
+        });
+    changed
+}
+
+fn show_tempo_follow_window(ctx: &egui::Context, follow: &mut TempoFollow, has_midi_input: bool) {
+    egui::Window::new("Tempo follow")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut follow.enabled, "Follow the player")
+                .on_hover_text("The tempo glides toward the one the notes are played at");
+            if !has_midi_input {
+                ui.label("No MIDI input port available, tap the beat instead");
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Tap").clicked() {
+                    follow.tap();
+                }
+                match follow.estimate {
+                    Some(bpm) => ui.label(format!("Estimated {:.1} BPM", bpm)),
+                    None => ui.label(format!("Listening... {} onsets", follow.onset_count())),
+                };
+            });
+            if follow.enabled {
+                ctx.request_repaint();
+            }
+        });
+}
+
+// Returns the roll held down with the mouse, the buttons only play while pressed
+fn show_roll_window(
+    ctx: &egui::Context,
+    roll_keys: &mut bool,
+    has_midi_input: bool,
+) -> Option<RollRate> {
+    let mut held = None;
+    egui::Window::new("Roll")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for rate in ROLL_RATES {
+                    let button =
+                        ui.add(egui::Button::new(RichText::new(rate.to_string()).heading()));
+                    if button.is_pointer_button_down_on() {
+                        held = Some(rate);
+                    }
+                }
+            });
+            ui.add_enabled(has_midi_input, egui::Checkbox::new(roll_keys, "MIDI keys"))
+                .on_hover_text("C1 to D#1 hold the rolls, from 1/4 up to 1/32");
+            ui.label("A held roll repeats a note instead of the rhythm");
+        });
+    held
+}
+
+// Applies the presses of the grid and shows the steps on its LEDs. The drum steps go through
+// the parameter changes, the logic pattern rebuilds the trigger chain.
+fn update_grid(
+    grid: &mut GridController,
+    sequencer_model: &mut SequencerModel,
+    sequencer: &Sequencer,
+) {
+    let mut logic_changed = false;
+    for event in grid.take_events() {
+        match event {
+            GridEvent::Step { track, step } if track < sequencer_model.drums.voices.len() => {
+                sequencer_model.drums.voices[track].steps ^= 1 << step;
+            }
+            GridEvent::Step { step, .. } => {
+                sequencer_model.logic_steps ^= 1 << step;
+                logic_changed = true;
+            }
+            GridEvent::Side(index) if index < VARIATION_COUNT => {
+                sequencer_model.variations.select(index);
+                sequencer.update_variations(sequencer_model.variations.clone());
+            }
+            GridEvent::Side(_) => (),
+        }
+    }
+    if logic_changed {
+        sequencer.queue_trigger_producer(sequencer_model.clone().into());
+    }
+    let state = sequencer.state();
+    let transport = state.transport;
+    let ticks_per_beat = transport.ticks_per_beat.max(1);
+    let tick_in_bar = transport.tick % (ticks_per_beat * transport.beats_per_bar.max(1));
+    let playhead = Some((tick_in_bar * 4 / ticks_per_beat) as usize)
+        .filter(|sixteenth| state.is_playing && *sixteenth < 16);
+    let drums = sequencer_model.drums.voices;
+    grid.show(&GridFrame::new(
+        [
+            drums[0].steps,
+            drums[1].steps,
+            drums[2].steps,
+            sequencer_model.logic_steps,
+        ],
+        playhead,
+        sequencer.active_variations().melody,
+    ));
+}
+
+fn show_grid_window(
+    ctx: &egui::Context,
+    ports: &[String],
+    port: &mut Option<String>,
+    layout: &mut GridLayout,
+    connected: bool,
+    error: Option<&str>,
+) -> Option<GridAction> {
+    let mut action = None;
+    egui::Window::new("Grid controller")
+        .default_open(false)
+        .default_width(300.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("grid_controller")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Port:");
+                    egui::ComboBox::from_id_source("grid_port")
+                        .selected_text(port.as_deref().unwrap_or("None"))
+                        .width(180.0)
+                        .show_ui(ui, |ui| {
+                            for name in ports {
+                                ui.selectable_value(port, Some(name.clone()), name);
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Layout:");
+                    egui::ComboBox::from_id_source("grid_layout")
+                        .selected_text(layout.to_string())
+                        .width(180.0)
+                        .show_ui(ui, |ui| {
+                            for grid_layout in GRID_LAYOUTS {
+                                ui.selectable_value(layout, grid_layout, grid_layout.to_string());
+                            }
+                        });
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(port.is_some(), egui::Button::new("Connect"))
+                    .clicked()
+                {
+                    action = Some(GridAction::Connect);
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("Disconnect"))
+                    .clicked()
+                {
+                    action = Some(GridAction::Disconnect);
+                }
+                if connected {
+                    ui.label("Connected");
+                }
+            });
+            if let Some(error) = error {
+                ui.label(error);
+            }
+            ui.label(format!(
+                "Two rows of sixteenths for the kick, the snare, the hats and the logic pattern, \
+                 top down. The side buttons jump to the patterns {} to {}.",
+                VARIATION_NAMES[0],
+                VARIATION_NAMES[VARIATION_COUNT - 1]
+            ));
+        });
+    action
+}
+
+// Whether the keys go to the shortcuts, Space and the key macros. With keyboard navigation
+// they go to the focused control first.
+fn shortcut_keys(ctx: &egui::Context, accessibility: &Accessibility) -> bool {
+    !accessibility.keyboard_navigation || ctx.memory(|memory| memory.focus().is_none())
+}
+
+// Sizes egui's default style to the settings
+fn apply_accessibility(ctx: &egui::Context, accessibility: &Accessibility) {
+    let mut style = egui::Style::default();
+    let scale = accessibility.ui_scale;
+    for font in style.text_styles.values_mut() {
+        font.size *= accessibility.text_scale();
+    }
+    let spacing = &mut style.spacing;
+    spacing.item_spacing *= scale;
+    spacing.button_padding *= scale;
+    spacing.interact_size *= scale;
+    spacing.indent *= scale;
+    spacing.slider_width *= scale;
+    spacing.combo_width *= scale;
+    spacing.icon_width *= scale;
+    spacing.icon_width_inner *= scale;
+    spacing.icon_spacing *= scale;
+    if accessibility.keyboard_navigation {
+        // egui draws the focused control as the active one, a thick outline shows it
+        let focused = &mut style.visuals.widgets.active;
+        focused.bg_stroke = egui::Stroke::new(2.0 * scale, style.visuals.selection.stroke.color);
+    }
+    ctx.set_style(style);
+}
+
+// Returns true when a setting changed
+fn show_accessibility_window(ctx: &egui::Context, accessibility: &mut Accessibility) -> bool {
+    let previous = *accessibility;
+    egui::Window::new("Accessibility")
+        .default_open(false)
+        .default_width(300.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("UI scale:");
+                for scale in UI_SCALES {
+                    ui.selectable_value(&mut accessibility.ui_scale, scale, format!("{}x", scale));
+                }
+            });
+            ui.checkbox(&mut accessibility.large_text, "Large text");
+            ui.checkbox(&mut accessibility.keyboard_navigation, "Keyboard navigation")
+                .on_hover_text("The keys go to the focused control, the shortcuts wait until it's left");
+            ui.label(
+                "Tab and Shift+Tab move between the controls, Space or Enter presses the focused one \
+                 and the arrow keys step the sliders and values. Escape leaves the control.",
+            );
+        });
+    *accessibility != previous
+}
+
+// Carries out an action of the key macros, on the press, and on the release for the held ones
+fn run_key_macro(model: &mut Model, action: MacroAction, pressed: bool) {
+    match action {
+        MacroAction::PlayStop => {
+            let state = model.sequencer.state();
+            if state.is_playing && state.phase != TransportPhase::FadingOut {
+                model.sequencer.stop();
+            } else {
+                model.sequencer.start();
+            }
+        }
+        MacroAction::Fill => model.fill = !model.fill,
+        MacroAction::Variation(index) => {
+            model.sequencer_model.variations.select(index);
+            model
+                .sequencer
+                .update_variations(model.sequencer_model.variations.clone());
+        }
+        // the tempo goes through the parameter changes like a slider move
+        MacroAction::DoubleTempo => {
+            let bpm = &mut model.sequencer_model.bpm;
+            if pressed {
+                if model.tempo_before_double.is_none() {
+                    model.tempo_before_double = Some(*bpm);
+                    *bpm = (*bpm * 2.0).min(MAX_BPM_VALUE);
+                }
+            } else if let Some(before) = model.tempo_before_double.take() {
+                *bpm = before;
+            }
+        }
+        MacroAction::MuteDrums => {
+            model.sequencer_model.drums.enabled = !model.sequencer_model.drums.enabled;
+        }
+    }
+}
+
+// Returns true when a binding changed, the key buttons wait for the next key pressed
+fn show_key_macros_window(ctx: &egui::Context, key_macros: &mut KeyMacros) -> bool {
+    let mut changed = false;
+    egui::Window::new("Key macros")
+        .default_open(false)
+        .default_width(300.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut key_macros.enabled, "Play the macros from the keyboard")
+                .on_hover_text("Not while typing in a text field");
+            let mut removed = None;
+            egui::Grid::new("key_macros")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (index, binding) in key_macros.bindings.iter_mut().enumerate() {
+                        let learning = key_macros.learning == Some(index);
+                        let text = if learning {
+                            "Press a key...".to_string()
+                        } else {
+                            binding.key.clone()
+                        };
+                        if ui.selectable_label(learning, text).clicked() {
+                            key_macros.learning = (!learning).then_some(index);
+                        }
+                        egui::ComboBox::from_id_source(("key_macro", index))
+                            .selected_text(binding.action.to_string())
+                            .width(180.0)
+                            .show_ui(ui, |ui| {
+                                for action in MACRO_ACTIONS {
+                                    changed |= ui
+                                        .selectable_value(
+                                            &mut binding.action,
+                                            action,
+                                            action.to_string(),
+                                        )
+                                        .changed();
+                                }
+                            });
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(index) = removed {
+                key_macros.bindings.remove(index);
+                key_macros.learning = None;
+                changed = true;
+            }
+            if ui.button("Add").clicked() {
+                key_macros.bindings.push(KeyBinding {
+                    key: "?".to_string(),
+                    action: MacroAction::PlayStop,
+                });
+                key_macros.learning = Some(key_macros.bindings.len() - 1);
+                changed = true;
+            }
+        });
+    changed
+}
+
+// Returns true when the pad was pressed down, a note plays on the press rather than the release
+fn show_manual_trigger_window(
+    ctx: &egui::Context,
+    manual_trigger: &mut bool,
+    manual_keys: &mut bool,
+    has_midi_input: bool,
+) -> bool {
+    let mut pressed = false;
+    egui::Window::new("Manual trigger")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(manual_trigger, "Play the notes by hand")
+                .on_hover_text(
+                    "Each press plays the next generated note, the rhythm sets its length",
+                );
+            let pad = ui.add_enabled(
+                *manual_trigger,
+                egui::Button::new(RichText::new("Play").heading())
+                    .min_size(egui::vec2(120.0, 60.0)),
+            );
+            if pad.is_pointer_button_down_on() && ui.input(|input| input.pointer.any_pressed()) {
+                pressed = true;
+            }
+            // from the keyboard, on the key going down as well
+            if pad.has_focus()
+                && ui.input(|input| {
+                    input.key_pressed(egui::Key::Space) || input.key_pressed(egui::Key::Enter)
+                })
+            {
+                pressed = true;
+            }
+            ui.add_enabled(
+                has_midi_input,
+                egui::Checkbox::new(manual_keys, "MIDI keys"),
+            )
+            .on_hover_text("Any note played on the MIDI input plays the next note");
+            ui.label("Space plays the next note as well, while playing");
+        });
+    pressed
+}
+
+// Returns true when a step of the lane changed
+fn show_degree_lane_window(ctx: &egui::Context, degree_lane: &mut Vec<u8>) -> bool {
+    let mut changed = false;
+    egui::Window::new("Degree lane")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label("Scale degree of each step, 1 for the root:");
+            ui.horizontal_wrapped(|ui| {
+                for degree in degree_lane.iter_mut() {
+                    changed |= ui
+                        .add(egui::DragValue::new(degree).clamp_range(1..=MAX_DEGREE))
+                        .changed();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        degree_lane.len() < MAX_DEGREE_LANE_STEPS,
+                        egui::Button::new("Add step"),
+                    )
+                    .clicked()
+                {
+                    degree_lane.push(1);
+                    changed = true;
+                }
+                if ui
+                    .add_enabled(degree_lane.len() > 1, egui::Button::new("Remove step"))
+                    .clicked()
+                {
+                    degree_lane.pop();
+                    changed = true;
+                }
+            });
+            ui.label("Select the Degree lane producer to play it in the current key");
+        });
+    changed
+}
+
+// Returns true when the interval list changed
+fn show_intervals_window(ctx: &egui::Context, intervals: &mut Vec<i32>) -> bool {
+    let mut changed = false;
+    egui::Window::new("Intervals")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label("Scale steps to move by, in turn:");
+            ui.horizontal_wrapped(|ui| {
+                for interval in intervals.iter_mut() {
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(interval)
+                                .clamp_range(-MAX_INTERVAL..=MAX_INTERVAL)
+                                .custom_formatter(|value, _| format!("{:+}", value)),
+                        )
+                        .changed();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        intervals.len() < MAX_INTERVALS,
+                        egui::Button::new("Add interval"),
+                    )
+                    .clicked()
+                {
+                    intervals.push(1);
+                    changed = true;
+                }
+                if ui
+                    .add_enabled(intervals.len() > 1, egui::Button::new("Remove interval"))
+                    .clicked()
+                {
+                    intervals.pop();
+                    changed = true;
+                }
+            });
+            ui.label("Select the Intervals producer to play the contour");
+        });
+    changed
+}
+
+// Returns true when a weight changed
+fn show_beat_density_window(ctx: &egui::Context, sequencer_model: &mut SequencerModel) -> bool {
+    let previous = sequencer_model.beat_weights.clone();
+    // a weight for each beat of the bar, the new beats as busy as the rest
+    let beats = sequencer_model
+        .selected_rhythm_pattern()
+        .notes_per_beat
+        .len();
+    let beat_weights = &mut sequencer_model.beat_weights;
+    beat_weights.resize(beats, BEAT_WEIGHT_DEFAULT_VALUE);
+    egui::Window::new("Beat density")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label("Trigger probability on each beat of the bar, times:");
+            ui.horizontal_wrapped(|ui| {
+                for (beat, weight) in beat_weights.iter_mut().enumerate() {
+                    ui.add(
+                        egui::DragValue::new(weight)
+                            .clamp_range(0.0..=MAX_BEAT_WEIGHT)
+                            .speed(0.01)
+                            .prefix(format!("{}: ", beat + 1)),
+                    );
+                }
+            });
+            if ui.button("Accent beats 1 and 3").clicked() {
+                for (beat, weight) in beat_weights.iter_mut().enumerate() {
+                    *weight = if beat % 2 == 0 {
+                        MAX_BEAT_WEIGHT
+                    } else {
+                        BEAT_WEIGHT_DEFAULT_VALUE
+                    };
+                }
+            }
+            ui.label("Select the Random trigger producer to weight its beats");
+        });
+    *beat_weights != previous
+}
+
+// The settings are parameters, sent along with the other changes of the frame
+fn show_note_processors_window(ctx: &egui::Context, settings: &mut NoteProcessorSettings) {
+    egui::Window::new("Note processors")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label("Applied in order to the notes of the main voice:");
+            egui::Grid::new("note_processors").show(ui, |ui| {
+                ui.checkbox(&mut settings.harmony, "Harmony");
+                ui.add(
+                    egui::Slider::new(
+                        &mut settings.harmony_degrees,
+                        -MAX_HARMONY_STEPS..=MAX_HARMONY_STEPS,
+                    )
+                    .text("degrees"),
+                );
+                ui.end_row();
+
+                ui.checkbox(&mut settings.strum, "Strum");
+                ui.add(egui::Slider::new(&mut settings.strum_ms, 0.0..=MAX_STRUM_MS).text("ms"));
+                ui.end_row();
+
+                ui.checkbox(&mut settings.echo, "Echo");
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.echo_repeats, 1..=MAX_ECHO_REPEATS)
+                            .text("repeats"),
+                    );
+                    ui.add(
+                        egui::Slider::new(
+                            &mut settings.echo_delay,
+                            MIN_ECHO_DELAY..=MAX_ECHO_DELAY,
+                        )
+                        .text("beats"),
+                    );
+                    ui.add(egui::Slider::new(&mut settings.echo_decay, 0.0..=1.0).text("decay"));
+                });
+                ui.end_row();
+
+                ui.checkbox(&mut settings.humanize, "Humanize");
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.humanize_ms, 0.0..=MAX_HUMANIZE_MS)
+                            .text("ms"),
+                    );
+                    ui.add(
+                        egui::Slider::new(
+                            &mut settings.humanize_velocity,
+                            0..=MAX_HUMANIZE_VELOCITY,
+                        )
+                        .text("velocity"),
+                    );
+                });
+                ui.end_row();
+            });
+            ui.label("The keyboard split routes the notes last");
+        });
+}
+
+// Returns true when a stage was moved or turned on or off, the amounts are parameters
+fn show_pitch_chain_window(ctx: &egui::Context, sequencer_model: &mut SequencerModel) -> bool {
+    let mut changed = false;
+    egui::Window::new("Pitch chain")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            let registry = producer_registry();
+            let producer = &registry.pitch_producers[sequencer_model.pitch_producer_index.unwrap()];
+            ui.label(format!("Producer: {}", producer.name));
+            let mut moved = None;
+            let stages = sequencer_model.pitch_chain.len();
+            egui::Grid::new("pitch_chain").show(ui, |ui| {
+                for (index, stage) in sequencer_model.pitch_chain.iter_mut().enumerate() {
+                    changed |= ui
+                        .checkbox(&mut stage.enabled, stage.stage.to_string())
+                        .changed();
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(index > 0, egui::Button::new("▲").small())
+                            .clicked()
+                        {
+                            moved = Some((index, index - 1));
+                        }
+                        if ui
+                            .add_enabled(index + 1 < stages, egui::Button::new("▼").small())
+                            .clicked()
+                        {
+                            moved = Some((index, index + 1));
+                        }
+                    });
+                    match stage.stage {
+                        PitchStage::Transpose => {
+                            ui.add(
+                                egui::DragValue::new(&mut sequencer_model.transpose)
+                                    .clamp_range(-MAX_TRANSPOSE..=MAX_TRANSPOSE)
+                                    .suffix(" st"),
+                            );
+                        }
+                        PitchStage::Harmony => {
+                            ui.add(
+                                egui::DragValue::new(&mut sequencer_model.harmony_steps)
+                                    .clamp_range(-MAX_HARMONY_STEPS..=MAX_HARMONY_STEPS)
+                                    .suffix(" steps"),
+                            );
+                        }
+                        PitchStage::VoiceLeading => {
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut sequencer_model.max_interval)
+                                        .clamp_range(1..=MAX_INTERVAL_LIMIT)
+                                        .suffix(" st"),
+                                )
+                                .on_hover_text("Largest leap between consecutive notes");
+                                ui.add(
+                                    egui::DragValue::new(&mut sequencer_model.repeat_avoidance)
+                                        .clamp_range(0.0..=1.0)
+                                        .speed(0.01),
+                                )
+                                .on_hover_text("Chance of a repeated note moving a scale degree");
+                            });
+                        }
+                        _ => {
+                            ui.label("");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some((from, to)) = moved {
+                sequencer_model.pitch_chain.swap(from, to);
+                changed = true;
+            }
+        });
+    changed
+}
+
+// Returns true when a stage was added, moved or removed or the logic pattern changed, the
+// other amounts are parameters
+fn show_trigger_chain_window(ctx: &egui::Context, sequencer_model: &mut SequencerModel) -> bool {
+    let mut changed = false;
+    egui::Window::new("Trigger chain")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            let registry = producer_registry();
+            let producer =
+                &registry.trigger_producers[sequencer_model.trigger_producer_index.unwrap()];
+            ui.label(format!("Source: {}", producer.name));
+            let mut moved = None;
+            let mut removed = None;
+            let stages = sequencer_model.trigger_chain.len();
+            egui::Grid::new("trigger_chain").show(ui, |ui| {
+                for (index, stage) in sequencer_model.trigger_chain.iter().enumerate() {
+                    ui.label(stage.to_string());
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(index > 0, egui::Button::new("▲").small())
+                            .clicked()
+                        {
+                            moved = Some((index, index - 1));
+                        }
+                        if ui
+                            .add_enabled(index + 1 < stages, egui::Button::new("▼").small())
+                            .clicked()
+                        {
+                            moved = Some((index, index + 1));
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                    match stage {
+                        TriggerStage::ClockDivider => {
+                            ui.add(egui::Slider::new(
+                                &mut sequencer_model.clock_division,
+                                1.0..=MAX_CLOCK_DIVISION as f32,
+                            ));
+                        }
+                        TriggerStage::Swing => {
+                            ui.add(egui::Slider::new(
+                                &mut sequencer_model.swing,
+                                0.0..=MAX_SWING,
+                            ));
+                        }
+                        TriggerStage::Ratchet => {
+                            ui.add(egui::Slider::new(
+                                &mut sequencer_model.ratchet_count,
+                                1.0..=MAX_RATCHET_COUNT as f32,
+                            ));
+                        }
+                        TriggerStage::RestGate => {
+                            ui.add(egui::Slider::new(
+                                &mut sequencer_model.rest_probability,
+                                0.0..=1.0,
+                            ));
+                        }
+                        TriggerStage::Logic => {
+                            let operation = &mut sequencer_model.logic_operation_index;
+                            egui::ComboBox::from_id_source("logic_operation")
+                                .selected_text(LOGIC_OPERATION_NAMES[operation.unwrap()])
+                                .show_ui(ui, |ui| {
+                                    for (index, name) in LOGIC_OPERATION_NAMES.iter().enumerate() {
+                                        ui.selectable_value(operation, Some(index), *name);
+                                    }
+                                });
+                        }
+                        TriggerStage::BeatDivision => {
+                            ui.label("");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if sequencer_model.trigger_chain.contains(&TriggerStage::Logic) {
+                ui.label("Logic pattern, in sixteenths:");
+                ui.horizontal_wrapped(|ui| {
+                    for sixteenth in 0..16 {
+                        let on = sequencer_model.logic_steps & 1 << sixteenth != 0;
+                        if ui
+                            .selectable_label(on, format!("{}", sixteenth + 1))
+                            .clicked()
+                        {
+                            sequencer_model.logic_steps ^= 1 << sixteenth;
+                            changed = true;
+                        }
+                    }
+                });
+            }
+            if let Some((from, to)) = moved {
+                sequencer_model.trigger_chain.swap(from, to);
+                changed = true;
+            }
+            if let Some(index) = removed {
+                sequencer_model.trigger_chain.remove(index);
+                changed = true;
+            }
+            let mut added = None;
+            egui::ComboBox::from_id_source("add_trigger_stage")
+                .selected_text("Add stage")
+                .show_ui(ui, |ui| {
+                    for stage in TRIGGER_STAGES {
+                        if !sequencer_model.trigger_chain.contains(stage)
+                            && ui.selectable_label(false, stage.to_string()).clicked()
+                        {
+                            added = Some(*stage);
+                        }
+                    }
+                });
+            if let Some(stage) = added {
+                sequencer_model.trigger_chain.push(stage);
+                changed = true;
+            }
+        });
+    changed
+}
+
+// Returns true when a guide was imported or removed
+// Returns true when a melody was imported or removed
+fn show_melody_window(ctx: &egui::Context, import: &mut MelodyImport) -> bool {
+    let mut changed = false;
+    egui::Window::new("Melody")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut import.path)
+                    .hint_text("Path to an .abc file")
+                    .desired_width(200.0),
+            );
+            ui.add(
+                egui::TextEdit::multiline(&mut import.text)
+                    .hint_text("Or paste ABC notation")
+                    .desired_rows(4)
+                    .desired_width(200.0),
+            );
+            if ui.button("Import").clicked() {
+                import.import();
+                changed = import.error.is_none();
+            }
+            if let Some(error) = &import.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            let mut removed = false;
+            if let Some(melody) = &import.melody {
+                ui.separator();
+                let title = if melody.title.is_empty() {
+                    "Untitled"
+                } else {
+                    &melody.title
+                };
+                ui.label(format!("{}: {} notes", title, melody.notes.len()));
+                ui.label(
+                    "Select the Melody producer to loop it, or take the phrase motifs from it",
+                );
+                removed = ui.button("Remove").clicked();
+            }
+            if removed {
+                import.melody = None;
+                changed = true;
+            }
+        });
+    changed
+}
+
+fn show_guide_window(
+    ctx: &egui::Context,
+    guide: &mut GuideImport,
+    guide_bar: Option<usize>,
+) -> bool {
+    let mut changed = false;
+    egui::Window::new("MIDI guide")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut guide.path)
+                        .hint_text("Path to a .mid file")
+                        .desired_width(160.0),
+                );
+                if ui.button("Import").clicked() {
+                    guide.import();
+                    changed = guide.error.is_none();
+                }
+            });
+            if let Some(error) = &guide.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            let mut removed = false;
+            if let Some(track) = &guide.track {
+                ui.separator();
+                let key = match track.key {
+                    Some(key) => format!("{} {}", pitch_class_name(key.tonic), key.mode.name()),
+                    None => "unknown key".to_string(),
+                };
+                ui.label(format!("{}: {:.0} BPM, {}", track.name, track.bpm, key));
+                if let Some(bar) = guide_bar {
+                    let chord = match track.bars.get(bar).copied().flatten() {
+                        Some(chord) => {
+                            format!("{}{}", pitch_class_name(chord.root), chord.quality.suffix())
+                        }
+                        None => "-".to_string(),
+                    };
+                    ui.label(format!("Bar {} / {}  {}", bar + 1, track.bars.len(), chord));
+                }
+                removed = ui.button("Remove").clicked();
+                ctx.request_repaint();
+            }
+            if removed {
+                guide.track = None;
+                changed = true;
+            }
+        });
+    changed
+}
+
+// Returns true when an export was requested
+fn show_export_window(ctx: &egui::Context, export: &mut ExportSettings) -> Option<ExportAction> {
+    let mut action = None;
+    egui::Window::new("Export")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.add(
+                egui::Slider::new(&mut export.bars, MIN_EXPORT_BARS..=MAX_EXPORT_BARS).text("Bars"),
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut export.path)
+                    .hint_text("Path of the .mid file")
+                    .desired_width(200.0),
+            );
+            ui.checkbox(&mut export.audio_stem, "Audio stem (.wav)");
+            ui.checkbox(&mut export.click_track, "Click track (.wav)");
+            let has_path = !export.path.trim().is_empty();
+            if ui
+                .add_enabled(has_path, egui::Button::new("Export"))
+                .clicked()
+            {
+                action = Some(ExportAction::Files);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Notation:");
+                for (format, name) in NOTATION_FORMATS {
+                    ui.selectable_value(&mut export.notation, *format, *name);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Copy").clicked() {
+                    action = Some(ExportAction::CopyNotation);
+                }
+                if ui
+                    .add_enabled(has_path, egui::Button::new("Save"))
+                    .clicked()
+                {
+                    action = Some(ExportAction::SaveNotation);
+                }
+            });
+            match &export.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => (),
+            }
+        });
+    action
+}
+
+// A table of the saved compact presets, narrowed down by the search box. A double click on a
+// row loads its preset on the next bar
+fn show_preset_browser_window(
+    ctx: &egui::Context,
+    browser: &mut PresetBrowser,
+    thumbnails: &mut HashMap<PathBuf, egui::TextureHandle>,
+) -> Option<BrowserAction> {
+    let mut action = None;
+    egui::Window::new("Preset browser")
+        .default_open(false)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut browser.name)
+                        .hint_text("Name of the preset")
+                        .desired_width(120.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut browser.tags)
+                        .hint_text("Tags, separated by commas")
+                        .desired_width(140.0),
+                );
+                if ui
+                    .add_enabled(!browser.name.trim().is_empty(), egui::Button::new("Save"))
+                    .on_hover_text("Stores the settings along with a picture of their first bars")
+                    .clicked()
+                {
+                    action = Some(BrowserAction::Save);
+                }
+                if ui.button("Rescan").clicked() {
+                    action = Some(BrowserAction::Rescan);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Search:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut browser.search)
+                        .hint_text("Name, tag or scale")
+                        .desired_width(200.0),
+                );
+                if ui.button("Clear").clicked() {
+                    browser.search.clear();
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("preset_browser")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("");
+                            ui.strong("Name");
+                            ui.strong("Tags");
+                            ui.strong("Tempo");
+                            ui.strong("Scale");
+                            ui.end_row();
+                            for (index, entry) in browser.entries.iter().enumerate() {
+                                if !entry.matches(&browser.search) {
+                                    continue;
+                                }
+                                let texture =
+                                    thumbnails.entry(entry.path.clone()).or_insert_with(|| {
+                                        let image = match decode_thumbnail(&entry.thumbnail) {
+                                            Some((size, pixels)) => {
+                                                egui::ColorImage::from_rgba_unmultiplied(
+                                                    size, &pixels,
+                                                )
+                                            }
+                                            None => egui::ColorImage::new(
+                                                [
+                                                    THUMBNAIL_WIDTH as usize,
+                                                    THUMBNAIL_HEIGHT as usize,
+                                                ],
+                                                egui::Color32::DARK_GRAY,
+                                            ),
+                                        };
+                                        ctx.load_texture(
+                                            &entry.name,
+                                            image,
+                                            egui::TextureOptions::NEAREST,
+                                        )
+                                    });
+                                let size =
+                                    egui::vec2(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32);
+                                let tempo = entry
+                                    .tempo()
+                                    .map_or("-".to_string(), |tempo| format!("{:.0}", tempo));
+                                let responses = [
+                                    ui.add(egui::ImageButton::new(egui::load::SizedTexture::new(
+                                        texture.id(),
+                                        size,
+                                    ))),
+                                    ui.add(
+                                        egui::Label::new(&entry.name).sense(egui::Sense::click()),
+                                    ),
+                                    ui.add(
+                                        egui::Label::new(entry.tags.join(", "))
+                                            .sense(egui::Sense::click()),
+                                    ),
+                                    ui.add(egui::Label::new(tempo).sense(egui::Sense::click())),
+                                    ui.add(
+                                        egui::Label::new(&entry.preset.scale)
+                                            .sense(egui::Sense::click()),
+                                    ),
+                                ];
+                                // a single press from the keyboard loads the focused entry
+                                if responses.iter().any(|response| {
+                                    response.double_clicked()
+                                        || (response.clicked() && response.has_focus())
+                                }) {
+                                    action = Some(BrowserAction::Load(index));
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            match &browser.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => (),
+            }
+        });
+    action
+}
+
+fn show_patch_window(ctx: &egui::Context, patch: &mut PatchPanel) -> Option<PatchAction> {
+    let mut action = None;
+    egui::Window::new("Patch")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label(
+                "A .sgpatch bundles the settings with the scale, rhythm and scripts they use. \
+                 Imported assets with the name of a different local one are renamed.",
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut patch.path)
+                    .hint_text("Path of the .sgpatch file")
+                    .desired_width(200.0),
+            );
+            let has_path = !patch.path.trim().is_empty();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(has_path, egui::Button::new("Export"))
+                    .clicked()
+                {
+                    action = Some(PatchAction::Export);
+                }
+                if ui
+                    .add_enabled(has_path, egui::Button::new("Import"))
+                    .clicked()
+                {
+                    action = Some(PatchAction::Import);
+                }
+            });
+            match &patch.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => (),
+            }
+        });
+    action
+}
+
+// Slot buttons along the bottom, a click recalls a slot and a shift-click stores the settings
+fn show_slot_bar(ctx: &egui::Context, slots: &PatternSlots) -> Option<SlotAction> {
+    let mut action = None;
+    egui::TopBottomPanel::bottom("slots").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Slots:");
+            for index in 0..SLOT_COUNT {
+                let clicked = ui
+                    .selectable_label(slots.is_filled(index), format!("{}", index + 1))
+                    .on_hover_text("Click to recall, shift-click to store")
+                    .clicked();
+                if clicked {
+                    action = Some(if ui.input(|input| input.modifiers.shift) {
+                        SlotAction::Store(index)
+                    } else {
+                        SlotAction::Recall(index)
+                    });
+                }
+            }
+        });
+    });
+    action
+}
+
+// Position, elapsed time, loop length and change quantizing along the top,
+// returns true to go back to bar 1
+fn show_transport_bar(
+    ctx: &egui::Context,
+    state: &SequencerState,
+    step_lights: &StepLights,
+    loop_bars: &mut Option<u32>,
+    quantize_changes: &mut bool,
+) -> bool {
+    let mut rewind = false;
+    let transport = &state.transport;
+    egui::TopBottomPanel::top("transport").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("|<").on_hover_text("Return to bar 1").clicked() {
+                rewind = true;
+            }
+            let (bar, beat, tick) = transport.bar_beat_tick();
+            // as many digits as the last tick of the beat
+            let digits = (transport.ticks_per_beat.max(2) - 1).to_string().len();
+            ui.label(
+                RichText::new(format!(
+                    "{}:{}:{:0digits$}",
+                    bar,
+                    beat,
+                    tick,
+                    digits = digits
+                ))
+                .monospace(),
+            );
+            let seconds = transport.elapsed.as_secs();
+            ui.label(
+                RichText::new(format!(
+                    "{:02}:{:02}.{}",
+                    seconds / 60,
+                    seconds % 60,
+                    transport.elapsed.subsec_millis() / 100
+                ))
+                .monospace(),
+            );
+            ui.separator();
+            ui.label("Loop:");
+            let loop_text = |bars: Option<u32>| match bars {
+                Some(1) => "1 bar".to_string(),
+                Some(bars) => format!("{} bars", bars),
+                None => "Off".to_string(),
+            };
+            egui::ComboBox::from_id_source("loop_bars")
+                .selected_text(loop_text(*loop_bars))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(loop_bars, None, loop_text(None));
+                    for bars in LOOP_BARS_OPTIONS {
+                        ui.selectable_value(loop_bars, Some(*bars), loop_text(Some(*bars)));
+                    }
+                });
+            if let Some(progress) = transport.loop_progress() {
+                ui.add(egui::ProgressBar::new(progress).desired_width(120.0));
+            }
+            ui.separator();
+            ui.checkbox(quantize_changes, "Quantize changes")
+                .on_hover_text("Scale, rhythm and instrument changes wait for the next bar");
+            ui.separator();
+            show_step_lights(ui, step_lights);
+            ui.separator();
+            ui.label(RichText::new(format!("{:.1} BPM", state.tempo)).monospace())
+                .on_hover_text("Tempo the engine plays at, glides and sync included");
+            if let Some(note) = state.last_note {
+                ui.label(
+                    RichText::new(format_letter_octave(
+                        Step(note.note as f32).to_letter_octave(),
+                    ))
+                    .monospace(),
+                )
+                .on_hover_text(format!(
+                    "Last note, velocity {} on channel {}",
+                    note.velocity,
+                    note.channel + 1
+                ));
+            }
+        });
+    });
+    rewind
+}
+
+// A light per step of the bar, grouped by beat: lit where a note played, dim where the step
+// passed in silence, outlined on the step passed last
+fn show_step_lights(ui: &mut egui::Ui, step_lights: &StepLights) {
+    let passed = step_lights.fired.len();
+    let mut step = 0;
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 2.0;
+        for notes in &step_lights.notes_per_beat {
+            for _ in 0..*notes {
+                let (rect, _) = ui.allocate_exact_size(
+                    egui::vec2(STEP_LIGHT_SIZE, STEP_LIGHT_SIZE),
+                    egui::Sense::hover(),
+                );
+                let color = match step_lights.fired.get(step) {
+                    Some(true) => egui::Color32::from_rgb(120, 220, 120),
+                    Some(false) => egui::Color32::from_gray(90),
+                    None => egui::Color32::from_gray(40),
+                };
+                let painter = ui.painter();
+                painter.circle_filled(rect.center(), STEP_LIGHT_SIZE / 2.0, color);
+                if step + 1 == passed {
+                    painter.circle_stroke(
+                        rect.center(),
+                        STEP_LIGHT_SIZE / 2.0,
+                        egui::Stroke::new(1.5, egui::Color32::WHITE),
+                    );
+                }
+                step += 1;
+            }
+            ui.add_space(STEP_LIGHT_SIZE / 2.0);
+        }
+    });
+}
+
+// Returns true when a variation, a switch or a cycle changed
+fn show_variations_window(
+    ctx: &egui::Context,
+    sequencer_model: &mut SequencerModel,
+    active: ActiveVariations,
+) -> bool {
+    let notes_per_beat = &sequencer_model.selected_rhythm_pattern().notes_per_beat;
+    let SequencerModel {
+        variations,
+        drums,
+        bass,
+        ..
+    } = sequencer_model;
+    let previous = variations.clone();
+    // the melody variations follow the beats of the bar
+    for variation in variations.melody.variations.iter_mut() {
+        variation.resize(notes_per_beat.len(), 1);
+    }
+    let own_bass = BassVariation {
+        pattern: bass.pattern,
+        octaves_below: bass.octaves_below,
+    };
+    egui::Window::new("Variations")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            show_track_variations(
+                ui,
+                "Melody rhythm",
+                &mut variations.melody,
+                active.melody,
+                notes_per_beat,
+                |notes_per_beat, rng| vary_melody(notes_per_beat, rng),
+                |ui, notes_per_beat| {
+                    ui.horizontal(|ui| {
+                        for notes in notes_per_beat.iter_mut() {
+                            ui.add(
+                                egui::DragValue::new(notes)
+                                    .clamp_range(0..=MAX_VARIATION_NOTES_PER_BEAT),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Notes on each beat of the bar");
+                },
+            );
+            ui.separator();
+            show_track_variations(
+                ui,
+                "Drums",
+                &mut variations.drums,
+                active.drums,
+                &drums.voices,
+                vary_drums,
+                |ui, voices| {
+                    for (name, voice) in DRUM_VOICE_NAMES.iter().zip(voices.iter_mut()) {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}:", name));
+                            ui.spacing_mut().item_spacing.x = 2.0;
+                            for step in 0..16 {
+                                let mut on = voice.steps & (1 << step) != 0;
+                                if ui.checkbox(&mut on, "").changed() {
+                                    voice.steps ^= 1 << step;
+                                }
+                            }
+                        });
+                    }
+                },
+            );
+            ui.separator();
+            show_track_variations(
+                ui,
+                "Bass",
+                &mut variations.bass,
+                active.bass,
+                &own_bass,
+                vary_bass,
+                |ui, bass| {
+                    ui.horizontal(|ui| {
+                        ui.label("Pattern:");
+                        egui::ComboBox::from_id_source("variation_bass_pattern")
+                            .selected_text(bass.pattern.to_string())
+                            .show_ui(ui, |ui| {
+                                for pattern in BASS_PATTERNS {
+                                    ui.selectable_value(
+                                        &mut bass.pattern,
+                                        pattern,
+                                        pattern.to_string(),
+                                    );
+                                }
+                            });
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut bass.octaves_below, 1..=MAX_BASS_OCTAVES_BELOW)
+                            .text("Octaves below the melody"),
+                    );
+                },
+            );
+        });
+    *variations != previous
+}
+
+// The switches and the cycle of a track, then the editor of its selected variation
+fn show_track_variations<T: Clone>(
+    ui: &mut egui::Ui,
+    name: &str,
+    track: &mut TrackVariations<T>,
+    active: Option<usize>,
+    own: &T,
+    vary: impl FnOnce(&T, &mut rand::rngs::ThreadRng) -> T,
+    edit: impl FnOnce(&mut egui::Ui, &mut T),
+) {
+    ui.label(RichText::new(name).strong());
+    ui.checkbox(&mut track.enabled, "Play the variations")
+        .on_hover_text("Off, the track plays its own settings");
+    ui.horizontal(|ui| {
+        for (index, variation_name) in VARIATION_NAMES.iter().enumerate() {
+            let text = if active == Some(index) {
+                format!("▶{}", variation_name)
+            } else {
+                variation_name.to_string()
+            };
+            ui.selectable_value(&mut track.selected, index, text);
+        }
+    })
+    .response
+    .on_hover_text("Switches at the next bar line, the one selected is edited below");
+    ui.horizontal(|ui| {
+        if ui
+            .button("Copy track")
+            .on_hover_text("The selected variation takes the settings of the track")
+            .clicked()
+        {
+            track.variations[track.selected] = own.clone();
+        }
+        if ui
+            .button("Generate")
+            .on_hover_text("The selected variation becomes a variation of the track")
+            .clicked()
+        {
+            track.variations[track.selected] = vary(own, &mut rand::thread_rng());
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Cycle:");
+        let mut cycle = cycle_text(&track.cycle);
+        if ui
+            .add(egui::TextEdit::singleline(&mut cycle).desired_width(80.0))
+            .on_hover_text("Variations played in turn, like AABA, empty to stay on one")
+            .changed()
+        {
+            track.cycle = parse_cycle(&cycle);
+        }
+        ui.add(
+            egui::DragValue::new(&mut track.bars)
+                .clamp_range(1..=MAX_VARIATION_BARS)
+                .suffix(" bars each"),
+        );
+    });
+    edit(ui, &mut track.variations[track.selected]);
+}
+
+// Returns true when the resolution changed
+fn show_timing_window(ctx: &egui::Context, resolution: &mut u32) -> bool {
+    let previous = *resolution;
+    egui::Window::new("Timing")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Resolution:");
+                egui::ComboBox::from_id_source("resolution")
+                    .selected_text(format!("{} PPQN", resolution))
+                    .show_ui(ui, |ui| {
+                        for option in RESOLUTION_OPTIONS {
+                            ui.selectable_value(resolution, *option, format!("{} PPQN", option));
+                        }
+                    });
+            })
+            .response
+            .on_hover_text(
+                "Ticks per quarter note. Divisions of the beat that do not fit \
+                 in its ticks are rounded, a higher resolution rounds them less",
+            );
+        });
+    *resolution != previous
+}
+
+fn show_visuals_window(ctx: &egui::Context, style: &mut VisualStyle) {
+    egui::Window::new("Visuals")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Style:");
+                for (visual_style, name) in VISUAL_STYLES {
+                    ui.selectable_value(style, *visual_style, *name);
+                }
+            });
+        });
+}
+
+fn show_scope_window(ctx: &egui::Context, scope: &mut Scope, has_audio: bool) {
+    egui::Window::new("Scope")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut scope.oscilloscope, "Oscilloscope");
+            ui.checkbox(&mut scope.spectrogram, "Spectrogram");
+            if !has_audio {
+                ui.label("Shows the internal audio once it is enabled in the Sound window");
+            }
+        });
+}
+
+fn show_recovery_window(ctx: &egui::Context, autosave: &Autosave) -> Option<RecoveryAction> {
+    let recovered = autosave.recovered.as_ref()?;
+    let mut action = None;
+    egui::Window::new("Recover session")
+        .collapsible(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "The last session did not end cleanly. It was autosaved at {}.",
+                recovered.saved_at
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    action = Some(RecoveryAction::Restore);
+                }
+                if ui.button("Discard").clicked() {
+                    action = Some(RecoveryAction::Discard);
+                }
+            });
+        });
+    action
+}
+
+fn show_statistics_window(
+    ctx: &egui::Context,
+    statistics: &StatisticsSummary,
+    statistics_bars: &mut usize,
+) {
+    egui::Window::new("Statistics")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Last bars:");
+                ui.add(egui::Slider::new(statistics_bars, 1..=MAX_STATISTICS_BARS));
+            });
+            ui.separator();
+            let total: u32 = statistics.pitch_classes.iter().sum();
+            let most = statistics.pitch_classes.iter().copied().max().unwrap_or(0);
+            egui::Grid::new("statistics_grid")
+                .num_columns(2)
+                .spacing([20.0, 2.0])
+                .show(ui, |ui| {
+                    for (pitch_class, count) in statistics.pitch_classes.iter().enumerate() {
+                        ui.label(pitch_class_name(pitch_class));
+                        // scaled to the most played pitch class, labelled with its share
+                        let share = if total > 0 {
+                            *count as f32 / total as f32
+                        } else {
+                            0.0
+                        };
+                        let fill = if most > 0 {
+                            *count as f32 / most as f32
+                        } else {
+                            0.0
+                        };
+                        ui.add(
+                            egui::ProgressBar::new(fill)
+                                .desired_width(160.0)
+                                .text(format!("{:.0}%", share * 100.0)),
+                        );
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            match statistics.average_interval {
+                Some(interval) => ui.label(format!("Average interval: {:.1} semitones", interval)),
+                None => ui.label("Average interval: -"),
+            };
+            let bars = statistics.notes_per_bar.len().max(1);
+            ui.label(format!("Notes per bar: {:.1}", total as f32 / bars as f32));
+            // one column per bar, the current bar on the right
+            let most_notes = statistics.notes_per_bar.iter().copied().max().unwrap_or(0);
+            let (rect, _) = ui
+                .allocate_exact_size(egui::vec2(ui.available_width(), 40.0), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            let width = rect.width() / *statistics_bars as f32;
+            let offset = *statistics_bars - statistics.notes_per_bar.len();
+            for (index, notes) in statistics.notes_per_bar.iter().enumerate() {
+                if most_notes == 0 {
+                    break;
+                }
+                let height = rect.height() * *notes as f32 / most_notes as f32;
+                let left = rect.left() + (index + offset) as f32 * width;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(left + 1.0, rect.bottom() - height),
+                        egui::pos2(left + width - 1.0, rect.bottom()),
+                    ),
+                    0.0,
+                    ui.visuals().selection.bg_fill,
+                );
+            }
+        });
+}
+
+// Returns true when the stream has to be restarted on the new selection
+fn show_audio_device_window(
+    ctx: &egui::Context,
+    panel: &mut AudioDevicePanel,
+    latency: Option<(usize, f32)>,
+    sample_rate: Option<u32>,
+) -> bool {
+    let previous = panel.settings.clone();
+    let mut retry = false;
+    egui::Window::new("Audio device")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("audio_device_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    let settings = &mut panel.settings;
+                    ui.label("Device:");
+                    egui::ComboBox::from_id_source("audio_device")
+                        .selected_text(settings.device.as_deref().unwrap_or("Default"))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut settings.device, None, "Default");
+                            for name in &panel.device_names {
+                                ui.selectable_value(&mut settings.device, Some(name.clone()), name);
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Sample rate:");
+                    egui::ComboBox::from_id_source("audio_sample_rate")
+                        .selected_text(match settings.sample_rate {
+                            Some(rate) => format!("{} Hz", rate),
+                            None => "Default".to_string(),
+                        })
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut settings.sample_rate, None, "Default");
+                            for rate in &panel.sample_rates {
+                                ui.selectable_value(
+                                    &mut settings.sample_rate,
+                                    Some(*rate),
+                                    format!("{} Hz", rate),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Buffer size:");
+                    egui::ComboBox::from_id_source("audio_buffer_size")
+                        .selected_text(match settings.buffer_size {
+                            Some(frames) => format!("{} frames", frames),
+                            None => "Default".to_string(),
+                        })
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut settings.buffer_size, None, "Default");
+                            for frames in AUDIO_BUFFER_SIZES {
+                                ui.selectable_value(
+                                    &mut settings.buffer_size,
+                                    Some(frames),
+                                    format!("{} frames", frames),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Latency:");
+                    match (latency, sample_rate) {
+                        (Some((frames, ms)), Some(rate)) => {
+                            ui.label(format!("{:.1} ms ({} frames at {} Hz)", ms, frames, rate))
+                        }
+                        _ => ui.label("-"),
+                    };
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Refresh devices").clicked() {
+                    panel.refresh();
+                }
+                retry = ui.button("Restart audio").clicked();
+            });
+            match &panel.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => (),
+            }
+        });
+    if panel.settings.device != previous.device {
+        // the rates depend on the device
+        panel.refresh();
+    }
+    retry || panel.settings != previous
+}
+
+// Returns true when Reload was clicked
+fn show_scripts_window(ctx: &egui::Context, scripts: &ScriptLibrary) -> bool {
+    let mut reload = false;
+    egui::Window::new("Scripts")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label(format!("Folder: {}", scripts.dir.display()));
+            ui.label(
+                "Scripts in pitch/ and trigger/ define tick(). A pitch script returns a MIDI \
+                 note from this.step, this.low, this.high and this.cycle, a trigger script \
+                 returns true to play from this.step and this.probability. Other fields of \
+                 this are kept between ticks, rand() gives a number from 0 to 1.",
+            );
+            ui.label(format!("{} scripts loaded", scripts.loaded));
+            for (file, error) in &scripts.errors {
+                ui.colored_label(egui::Color32::RED, format!("{}: {}", file, error));
+            }
+            reload = ui.button("Reload").clicked();
+        });
+    reload
+}
+
+// Returns true when Reload was clicked, parameter changes reach the plugins directly
+fn show_plugins_window(ctx: &egui::Context, plugins: &PluginLibrary) -> bool {
+    let mut reload = false;
+    egui::Window::new("Plugins")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.label(format!("Folder: {}", plugins.dir.display()));
+            for plugin in &plugins.plugins {
+                ui.separator();
+                let kinds: Vec<&str> = [(plugin.pitch, "pitch"), (plugin.trigger, "trigger")]
+                    .iter()
+                    .filter_map(|(has, kind)| has.then_some(*kind))
+                    .collect();
+                ui.label(RichText::new(format!("{} ({})", plugin.name, kinds.join(", "))).strong());
+                let mut values = plugin.values.lock().unwrap();
+                egui::Grid::new(&plugin.name)
+                    .num_columns(2)
+                    .spacing([20.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (parameter, value) in plugin.parameters.iter().zip(values.iter_mut()) {
+                            ui.label(format!("{}:", parameter.name));
+                            ui.add(egui::Slider::new(value, parameter.min..=parameter.max));
+                            ui.end_row();
+                        }
+                    });
+            }
+            for (file, error) in &plugins.errors {
+                ui.colored_label(egui::Color32::RED, format!("{}: {}", file, error));
+            }
+            reload = ui.button("Reload").clicked();
+        });
+    reload
+}
+
+// Returns true when the sync has to reconnect
+fn show_network_sync_window(ctx: &egui::Context, panel: &mut NetworkSyncPanel) -> bool {
+    let previous_role = panel.role;
+    let mut connect = false;
+    egui::Window::new("Network sync")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("network_sync_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Role:");
+                    let role_name = SYNC_ROLES
+                        .iter()
+                        .find(|(role, _)| *role == panel.role)
+                        .map_or("", |(_, name)| name);
+                    egui::ComboBox::from_id_source("sync_role")
+                        .selected_text(role_name)
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (role, name) in SYNC_ROLES {
+                                ui.selectable_value(&mut panel.role, *role, *name);
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Port:");
+                    ui.add(egui::DragValue::new(&mut panel.port).clamp_range(1024..=65535));
+                    ui.end_row();
+                    if panel.role == SyncRole::Follower {
+                        ui.label("Leader address:");
+                        ui.text_edit_singleline(&mut panel.leader_address);
+                        ui.end_row();
+                    }
+                });
+            if panel.role != SyncRole::Off && ui.button("Connect").clicked() {
+                connect = true;
+            }
+            if let Some(error) = &panel.error {
+                ui.colored_label(egui::Color32::RED, error);
+            } else if let Some(sync) = &panel.sync {
+                ui.label(sync.status());
+                if sync.role() == SyncRole::Follower {
+                    ui.label("Play, pause and tempo follow the leader");
+                }
+            }
+        });
+    // a follower needs its leader address first
+    connect || (panel.role != previous_role && panel.role != SyncRole::Follower)
+}
+
+// Returns true when the internal audio has to be updated
+fn show_sound_window(
+    ctx: &egui::Context,
+    sound: &mut SoundSettings,
+    has_audio: bool,
+    recording: Option<std::time::Duration>,
+    record_clicked: &mut bool,
+) -> bool {
+    let mut changed = false;
+    egui::Window::new("Sound")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            changed |= ui
+                .add_enabled(
+                    has_audio,
+                    egui::Checkbox::new(&mut sound.enabled, "Internal audio"),
+                )
+                .changed();
+            ui.horizontal(|ui| {
+                let label = match recording {
+                    Some(_) => "Stop recording",
+                    None => "Record audio",
+                };
+                *record_clicked = ui
+                    .add_enabled(has_audio, egui::Button::new(label))
+                    .clicked();
+                if let Some(elapsed) = recording {
+                    let seconds = elapsed.as_secs();
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("● {}:{:02}", seconds / 60, seconds % 60),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut sound.sound_font_path)
+                        .hint_text("Path to a .sf2 file")
+                        .desired_width(160.0),
+                );
+                if ui.button("Load").clicked() {
+                    sound.load_sound_font();
+                    changed = true;
+                }
+            });
+            if sound.sound_font.is_some() {
+                changed |= ui
+                    .checkbox(&mut sound.use_sound_font, "Play the SoundFont")
+                    .changed();
+            } else {
+                ui.label("No SoundFont loaded, playing the synth");
+            }
+            ui.separator();
+            for (voice, name) in DRUM_VOICE_NAMES.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", name));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut sound.drum_sample_paths[voice])
+                            .hint_text("Path to a .wav file")
+                            .desired_width(120.0),
+                    );
+                    if ui.button("Load").clicked() {
+                        sound.load_drum_sample(voice);
+                        changed = true;
+                    }
+                });
+            }
+            match &sound.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => (),
+            }
+        });
+    changed
+}
+
+// The changes are picked up with the other parameters
+fn show_drums_window(ctx: &egui::Context, drums: &mut DrumSettings) {
+    egui::Window::new("Drums")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut drums.enabled, "Play drums");
+            egui::Grid::new("drums_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for (name, voice) in DRUM_VOICE_NAMES.iter().zip(drums.voices.iter_mut()) {
+                        ui.label(format!("{}:", name));
+                        ui.horizontal(|ui| {
+                            ui.spacing_mut().item_spacing.x = 2.0;
+                            for step in 0..16 {
+                                let mut on = voice.steps & (1 << step) != 0;
+                                if ui.checkbox(&mut on, "").changed() {
+                                    voice.steps ^= 1 << step;
+                                }
+                            }
+                        });
+                        ui.end_row();
+                        ui.label("Gain:");
+                        ui.add(egui::Slider::new(&mut voice.gain, 0.0..=MAX_DRUM_GAIN));
+                        ui.end_row();
+                        ui.label("Pitch:");
+                        ui.add(
+                            egui::Slider::new(&mut voice.pitch, MIN_DRUM_PITCH..=MAX_DRUM_PITCH)
+                                .suffix(" st"),
+                        );
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+// Returns true when a voice parameter changed
+fn show_synth_window(ctx: &egui::Context, synth: &mut SynthSettings) -> bool {
+    let mut changed = false;
+    egui::Window::new("Synth")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("synth_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Waveform:");
+                    egui::ComboBox::from_id_source("waveform")
+                        .selected_text(synth.waveform.name())
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for (waveform, name) in WAVEFORMS {
+                                changed |= ui
+                                    .selectable_value(&mut synth.waveform, *waveform, *name)
+                                    .changed();
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("FM ratio:");
+                    changed |= ui
+                        .add(egui::Slider::new(
+                            &mut synth.fm_ratio,
+                            MIN_FM_RATIO..=MAX_FM_RATIO,
+                        ))
+                        .changed();
+                    ui.end_row();
+                    ui.label("FM index:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut synth.fm_index, 0.0..=MAX_FM_INDEX))
+                        .changed();
+                    ui.end_row();
+                    ui.label("Cutoff:");
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut synth.cutoff, MIN_CUTOFF..=MAX_CUTOFF)
+                                .logarithmic(true)
+                                .suffix(" Hz"),
+                        )
+                        .changed();
+                    ui.end_row();
+                    ui.label("Resonance:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut synth.resonance, 0.0..=MAX_RESONANCE))
+                        .changed();
+                    ui.end_row();
+                    for (label, value) in [
+                        ("Attack:", &mut synth.attack),
+                        ("Decay:", &mut synth.decay),
+                        ("Release:", &mut synth.release),
+                    ] {
+                        ui.label(label);
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(
+                                    value,
+                                    MIN_ENVELOPE_SECONDS..=MAX_ENVELOPE_SECONDS,
+                                )
+                                .logarithmic(true)
+                                .suffix(" s"),
+                            )
+                            .changed();
+                        ui.end_row();
+                    }
+                    ui.label("Sustain:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut synth.sustain, 0.0..=1.0))
+                        .changed();
+                    ui.end_row();
+                });
+        });
+    changed
+}
+
+// Returns true when an effect or send changed
+fn show_effects_window(
+    ctx: &egui::Context,
+    effects: &mut EffectSettings,
+    (peak, reduction): (f32, f32),
+) -> bool {
+    let mut changed = false;
+    egui::Window::new("Effects")
+        .default_open(false)
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("effects_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Delay (beats):");
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(
+                                &mut effects.delay_beats,
+                                MIN_DELAY_BEATS..=MAX_DELAY_BEATS,
+                            )
+                            .step_by(0.25),
+                        )
+                        .changed();
+                    ui.end_row();
+                    ui.label("Feedback:");
+                    changed |= ui
+                        .add(egui::Slider::new(
+                            &mut effects.delay_feedback,
+                            0.0..=MAX_DELAY_FEEDBACK,
+                        ))
+                        .changed();
+                    ui.end_row();
+                    ui.label("Reverb size:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut effects.reverb_size, 0.0..=1.0))
+                        .changed();
+                    ui.end_row();
+                    ui.label("Damping:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut effects.reverb_damping, 0.0..=1.0))
+                        .changed();
+                    ui.end_row();
+                    ui.label("Master volume:");
+                    changed |= ui
+                        .add(egui::Slider::new(
+                            &mut effects.master_volume,
+                            0.0..=MAX_MASTER_VOLUME,
+                        ))
+                        .changed();
+                    ui.end_row();
+                    ui.label("Limiter:");
+                    changed |= ui.checkbox(&mut effects.limiter, "").changed();
+                    ui.end_row();
+                    ui.label("Level:");
+                    let level_text = if peak > 0.0 {
+                        format!("{:.1} dB", 20.0 * peak.log10())
+                    } else {
+                        "-inf dB".to_string()
+                    };
+                    ui.add(egui::ProgressBar::new(peak.min(1.0)).text(level_text));
+                    ui.end_row();
+                    ui.label("Reduction:");
+                    ui.label(format!(
+                        "{:.1} dB",
+                        20.0 * (1.0 - reduction).max(1e-3).log10()
+                    ));
+                    ui.end_row();
+                    for (name, sends) in TRACK_NAMES.iter().zip(effects.sends.iter_mut()) {
+                        ui.label(format!("{} delay send:", name));
+                        changed |= ui
+                            .add(egui::Slider::new(&mut sends.delay, 0.0..=1.0))
+                            .changed();
+                        ui.end_row();
+                        ui.label(format!("{} reverb send:", name));
+                        changed |= ui
+                            .add(egui::Slider::new(&mut sends.reverb, 0.0..=1.0))
+                            .changed();
+                        ui.end_row();
+                    }
+                });
+        });
+    changed
 }
@@ -0,0 +1,23 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+// Presses of the player waiting for the sequencer thread, each one playing the next generated
+// note. Pressed from the UI and midir's callback thread without going through the commands,
+// the sequencer picks them up on its next timer tick.
+#[derive(Clone, Default)]
+pub struct ManualTrigger {
+    presses: Arc<AtomicU32>,
+}
+
+impl ManualTrigger {
+    pub fn press(&self) {
+        self.presses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Presses since the last call
+    pub fn take(&self) -> u32 {
+        self.presses.swap(0, Ordering::Relaxed)
+    }
+}
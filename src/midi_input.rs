@@ -0,0 +1,149 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+use crate::manual::ManualTrigger;
+
+//constants
+const NOTE_ON_MSG: u8 = 0x90;
+const NOTE_OFF_MSG: u8 = 0x80;
+const PROGRAM_CHANGE_MSG: u8 = 0xC0;
+const START_MSG: u8 = 0xFA;
+const CONTINUE_MSG: u8 = 0xFB;
+const STOP_MSG: u8 = 0xFC;
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+// MIDI Machine Control: F0 7F <device> 06 <command> F7
+const MMC_REALTIME_ID: u8 = 0x7F;
+const MMC_COMMAND: u8 = 0x06;
+const MMC_STOP: u8 = 0x01;
+const MMC_PLAY: u8 = 0x02;
+const MMC_DEFERRED_PLAY: u8 = 0x03;
+const MMC_PAUSE: u8 = 0x09;
+const MIDI_INPUT_CLIENT_NAME: &str = "Generative Sequencer Input";
+// note ons kept when nobody reads them
+const MAX_BUFFERED_NOTE_ONS: usize = 1024;
+
+// What another device asked the transport to do
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RemoteTransport {
+    // from the beginning
+    Start,
+    // from where it stopped
+    Continue,
+    Stop,
+}
+
+#[derive(Default)]
+struct InputState {
+    held_notes: Vec<u8>,
+    note_ons: Vec<u8>,
+    // when the note ons arrived, for the tempo follow
+    onsets: Vec<Instant>,
+    transport: Vec<RemoteTransport>,
+    // on any channel
+    program_changes: Vec<u8>,
+    // pressed by every note on, from the callback so it doesn't wait for the next frame
+    manual_trigger: Option<ManualTrigger>,
+}
+
+impl InputState {
+    fn handle(&mut self, message: &[u8]) {
+        match *message {
+            [status, note, velocity] if status & 0xF0 == NOTE_ON_MSG && velocity > 0 => {
+                if !self.held_notes.contains(&note) {
+                    self.held_notes.push(note);
+                }
+                if self.note_ons.len() >= MAX_BUFFERED_NOTE_ONS {
+                    self.note_ons.remove(0);
+                }
+                self.note_ons.push(note);
+                if self.onsets.len() >= MAX_BUFFERED_NOTE_ONS {
+                    self.onsets.remove(0);
+                }
+                self.onsets.push(Instant::now());
+                if let Some(manual_trigger) = self.manual_trigger.as_ref() {
+                    manual_trigger.press();
+                }
+            }
+            // a note on with velocity 0 is a note off
+            [status, note, _] if status & 0xF0 == NOTE_OFF_MSG || status & 0xF0 == NOTE_ON_MSG => {
+                self.held_notes.retain(|&n| n != note);
+            }
+            [status, program] if status & 0xF0 == PROGRAM_CHANGE_MSG => {
+                self.program_changes.push(program);
+            }
+            [START_MSG] => self.transport.push(RemoteTransport::Start),
+            [CONTINUE_MSG] => self.transport.push(RemoteTransport::Continue),
+            [STOP_MSG] => self.transport.push(RemoteTransport::Stop),
+            // any device id, 7F being all of them
+            [SYSEX_START, MMC_REALTIME_ID, _, MMC_COMMAND, command, SYSEX_END] => match command {
+                MMC_PLAY | MMC_DEFERRED_PLAY => self.transport.push(RemoteTransport::Continue),
+                MMC_STOP | MMC_PAUSE => self.transport.push(RemoteTransport::Stop),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+// Notes played on the first MIDI input port, collected from midir's callback thread
+pub struct MidiInputListener {
+    _connection: MidiInputConnection<()>,
+    state: Arc<Mutex<InputState>>,
+}
+
+impl MidiInputListener {
+    pub fn connect_first_port() -> Option<MidiInputListener> {
+        let mut midi_in = MidiInput::new(MIDI_INPUT_CLIENT_NAME).ok()?;
+        // the MMC commands are system exclusive messages, the clock ticks stay out
+        midi_in.ignore(Ignore::TimeAndActiveSense);
+        let in_port = midi_in.ports().into_iter().next()?;
+        let state = Arc::new(Mutex::new(InputState::default()));
+        let callback_state = state.clone();
+        let connection = midi_in
+            .connect(
+                &in_port,
+                MIDI_INPUT_CLIENT_NAME,
+                move |_timestamp, message, _| callback_state.lock().unwrap().handle(message),
+                (),
+            )
+            .ok()?;
+        Some(MidiInputListener {
+            _connection: connection,
+            state,
+        })
+    }
+
+    // Notes started since the last call
+    pub fn take_note_ons(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state.lock().unwrap().note_ons)
+    }
+
+    // Arrival times of the note ons since the last call
+    pub fn take_onsets(&self) -> Vec<Instant> {
+        std::mem::take(&mut self.state.lock().unwrap().onsets)
+    }
+
+    // Transport messages received since the last call, in order
+    pub fn take_transport(&self) -> Vec<RemoteTransport> {
+        std::mem::take(&mut self.state.lock().unwrap().transport)
+    }
+
+    // Programs selected since the last call, in order
+    pub fn take_program_changes(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state.lock().unwrap().program_changes)
+    }
+
+    // None stops the note ons from pressing the manual trigger
+    pub fn set_manual_trigger(&self, manual_trigger: Option<ManualTrigger>) {
+        self.state.lock().unwrap().manual_trigger = manual_trigger;
+    }
+
+    pub fn held_notes(&self) -> Vec<u8> {
+        self.state.lock().unwrap().held_notes.clone()
+    }
+}
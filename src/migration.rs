@@ -0,0 +1,204 @@
+use serde_json::{Map, Value};
+
+//constants
+// Steps bringing a stored preset up to date, in order: the first one takes version 1 to 2.
+// A new setting gets a step giving older presets the value that keeps them sounding the same.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+    add_version_2_parameters,
+    add_version_3_parameters,
+    add_version_4_parameters,
+    add_version_5_parameters,
+    add_version_6_parameters,
+    add_version_7_parameters,
+    add_version_8_parameters,
+    add_version_9_parameters,
+    add_version_10_velocity_curves,
+    add_version_11_parameters,
+    add_version_12_parameters,
+    add_version_13_parameters,
+];
+pub const PRESET_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+// presets saved before the version was written down are version 1
+const FIRST_VERSION: u32 = 1;
+
+// Runs the steps from the version a preset was saved with to the current one
+pub fn migrate(preset: &mut Value) -> Result<(), String> {
+    let preset = preset
+        .as_object_mut()
+        .ok_or("A preset is an object".to_string())?;
+    let version = preset
+        .get("version")
+        .and_then(Value::as_u64)
+        .map_or(FIRST_VERSION, |version| version as u32);
+    if version > PRESET_VERSION {
+        return Err(format!("Made by a newer version (format {})", version));
+    }
+    for step in &MIGRATIONS[(version.max(FIRST_VERSION) - FIRST_VERSION) as usize..] {
+        step(preset);
+    }
+    preset.insert("version".to_string(), PRESET_VERSION.into());
+    Ok(())
+}
+
+// Parameters missing from the preset take the value that leaves the sound as it was
+fn add_parameters(preset: &mut Map<String, Value>, defaults: &[(&str, f32)]) {
+    let parameters = preset
+        .entry("parameters")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Some(parameters) = parameters.as_object_mut() {
+        for (address, value) in defaults {
+            parameters
+                .entry(address.to_string())
+                .or_insert_with(|| (*value).into());
+        }
+    }
+}
+
+// Key modulation, playback direction, rotations, sample and hold, the chain stages,
+// the note length, the keyboard split and the bass line
+fn add_version_2_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(
+        preset,
+        &[
+            ("/pitch/modulation", 0.0),
+            ("/pitch/direction", 0.0),
+            ("/pitch/rotation", 0.0),
+            ("/pitch/sample_hold", 1.0),
+            ("/pitch/transpose", 0.0),
+            ("/rhythm/clock_division", 1.0),
+            ("/rhythm/swing", 0.0),
+            ("/rhythm/note_length", 1.0),
+            ("/rhythm/rotation", 0.0),
+            ("/split", 0.0),
+            ("/bass", 0.0),
+        ],
+    );
+}
+
+// Collision avoidance between the melody and the bass
+fn add_version_3_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/collisions", 0.0)]);
+}
+
+// Cadences at the phrase ends
+fn add_version_4_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/cadence", 0.0)]);
+}
+
+// Accents on the downbeats
+fn add_version_5_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/velocity/accent", 0.0)]);
+}
+
+// The note processors
+fn add_version_6_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(
+        preset,
+        &[
+            ("/notes/harmony", 0.0),
+            ("/notes/strum", 0.0),
+            ("/notes/echo", 0.0),
+            ("/notes/humanize", 0.0),
+        ],
+    );
+}
+
+// The fade of the start and the stop
+fn add_version_7_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/fade", 0.0)]);
+}
+
+// The scale lock
+fn add_version_8_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/pitch/scale_lock", 0.0)]);
+}
+
+// The seed per bar
+fn add_version_9_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/seed", 0.0)]);
+}
+
+// The velocity curves, none plays every instrument linear
+fn add_version_10_velocity_curves(preset: &mut Map<String, Value>) {
+    preset
+        .entry("velocity_curves")
+        .or_insert_with(|| Value::Object(Map::new()));
+}
+
+// The derived layers
+fn add_version_11_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/layers", 0.0)]);
+}
+
+// The speed of the main voice, at the tempo
+fn add_version_12_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/rhythm/speed", 2.0)]);
+}
+
+// The density compensation of the velocities
+fn add_version_13_parameters(preset: &mut Map<String, Value>) {
+    add_parameters(preset, &[("/velocity/density", 0.0)]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::Preset;
+
+    // a preset saved by each version, the first one at index 0
+    const FIXTURES: [&str; PRESET_VERSION as usize] = [
+        include_str!("../tests/fixtures/preset_v1.json"),
+        include_str!("../tests/fixtures/preset_v2.json"),
+        include_str!("../tests/fixtures/preset_v3.json"),
+        include_str!("../tests/fixtures/preset_v4.json"),
+        include_str!("../tests/fixtures/preset_v5.json"),
+        include_str!("../tests/fixtures/preset_v6.json"),
+        include_str!("../tests/fixtures/preset_v7.json"),
+        include_str!("../tests/fixtures/preset_v8.json"),
+        include_str!("../tests/fixtures/preset_v9.json"),
+        include_str!("../tests/fixtures/preset_v10.json"),
+        include_str!("../tests/fixtures/preset_v11.json"),
+        include_str!("../tests/fixtures/preset_v12.json"),
+        include_str!("../tests/fixtures/preset_v13.json"),
+    ];
+
+    fn fixture(version: u32) -> Value {
+        let preset: Value =
+            serde_json::from_str(FIXTURES[(version - FIRST_VERSION) as usize]).unwrap();
+        assert_eq!(preset["version"], version);
+        preset
+    }
+
+    #[test]
+    fn every_version_migrates_to_the_current_one() {
+        // every parameter some step adds, which a migrated preset has to hold
+        let mut added = Map::new();
+        for step in MIGRATIONS {
+            step(&mut added);
+        }
+        for version in FIRST_VERSION..=PRESET_VERSION {
+            let mut preset = fixture(version);
+            migrate(&mut preset).unwrap();
+            assert_eq!(preset["version"], PRESET_VERSION, "version {}", version);
+            assert!(preset["velocity_curves"].is_object(), "version {}", version);
+            for address in added["parameters"].as_object().unwrap().keys() {
+                assert!(
+                    preset["parameters"].get(address).is_some(),
+                    "version {} misses {}",
+                    version,
+                    address
+                );
+            }
+            let preset = Preset::try_from(fixture(version)).unwrap();
+            assert_eq!(preset.version, PRESET_VERSION);
+        }
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut preset = fixture(PRESET_VERSION);
+        preset["version"] = (PRESET_VERSION + 1).into();
+        assert!(migrate(&mut preset.clone()).is_err());
+        assert!(Preset::try_from(preset).is_err());
+    }
+}
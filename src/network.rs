@@ -0,0 +1,295 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+//constants
+const STATE_INTERVAL: Duration = Duration::from_millis(100);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+// followers not heard from for this long are dropped
+const FOLLOWER_TIMEOUT: Duration = Duration::from_secs(3);
+// a follower keeps its own transport once the leader is silent for this long
+const LEADER_TIMEOUT: Duration = Duration::from_secs(2);
+// round trips kept for the clock offset, the shortest one gives the best estimate
+const MAX_ROUND_TRIPS: usize = 8;
+const MAX_PACKET_SIZE: usize = 512;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SyncRole {
+    Off,
+    Leader,
+    Follower,
+}
+
+pub const SYNC_ROLES: &[(SyncRole, &str)] = &[
+    (SyncRole::Off, "Off"),
+    (SyncRole::Leader, "Leader"),
+    (SyncRole::Follower, "Follower"),
+];
+
+// Transport of the leader, times are in microseconds on the clock of the sender
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransportState {
+    pub is_playing: bool,
+    pub bpm: f32,
+    // beats played at time
+    pub beat: f64,
+    pub time: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SyncMessage {
+    // from a follower, also registers it with the leader
+    Ping { sent: u64 },
+    Pong { sent: u64, leader_time: u64 },
+    State(TransportState),
+}
+
+#[derive(Clone, Copy)]
+struct RoundTrip {
+    duration: u64,
+    // leader clock minus follower clock
+    offset: i64,
+}
+
+// One end of the sync over UDP, polled from the UI thread on every frame
+pub struct NetworkSync {
+    socket: UdpSocket,
+    role: SyncRole,
+    epoch: Instant,
+    followers: Vec<(SocketAddr, Instant)>,
+    leader: Option<SocketAddr>,
+    round_trips: VecDeque<RoundTrip>,
+    last_sent: Option<Instant>,
+    last_heard: Option<Instant>,
+}
+
+impl NetworkSync {
+    pub fn leader(port: u16) -> Result<NetworkSync, String> {
+        NetworkSync::bind(port, SyncRole::Leader, None)
+    }
+
+    // The address is "host" or "host:port", the port defaults to the given one
+    pub fn follower(leader_address: &str, port: u16) -> Result<NetworkSync, String> {
+        let address = leader_address.trim();
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, port)
+        };
+        let leader = address
+            .to_socket_addrs()
+            .map_err(|err| format!("{}: {}", address, err))?
+            .find(|address| address.is_ipv4())
+            .ok_or(format!("{} has no IPv4 address", address))?;
+        NetworkSync::bind(0, SyncRole::Follower, Some(leader))
+    }
+
+    fn bind(port: u16, role: SyncRole, leader: Option<SocketAddr>) -> Result<NetworkSync, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|err| err.to_string())?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        Ok(NetworkSync {
+            socket,
+            role,
+            epoch: Instant::now(),
+            followers: Vec::new(),
+            leader,
+            round_trips: VecDeque::new(),
+            last_sent: None,
+            last_heard: None,
+        })
+    }
+
+    pub fn role(&self) -> SyncRole {
+        self.role
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    fn send(&self, message: &SyncMessage, address: SocketAddr) {
+        let packet = serde_json::to_vec(message).unwrap();
+        if let Err(err) = self.socket.send_to(&packet, address) {
+            eprintln!("Could not send the sync to {}: {}", address, err);
+        }
+    }
+
+    fn receive(&self) -> Vec<(SyncMessage, SocketAddr)> {
+        let mut messages = Vec::new();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, address)) => {
+                    if let Ok(message) = serde_json::from_slice(&buffer[..size]) {
+                        messages.push((message, address));
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // a follower that went away can show up as a reset, the next packet still comes
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    eprintln!("Could not receive the sync: {}", err);
+                    break;
+                }
+            }
+        }
+        messages
+    }
+
+    fn interval_elapsed(&self, interval: Duration) -> bool {
+        self.last_sent
+            .is_none_or(|last_sent| last_sent.elapsed() >= interval)
+    }
+
+    // Answers the pings of the followers and sends them the transport at the state interval
+    pub fn lead(&mut self, is_playing: bool, bpm: f32, beat: f64) {
+        for (message, address) in self.receive() {
+            if let SyncMessage::Ping { sent } = message {
+                self.send(
+                    &SyncMessage::Pong {
+                        sent,
+                        leader_time: self.now(),
+                    },
+                    address,
+                );
+                match self.followers.iter_mut().find(|(a, _)| *a == address) {
+                    Some(follower) => follower.1 = Instant::now(),
+                    None => self.followers.push((address, Instant::now())),
+                }
+            }
+        }
+        self.followers
+            .retain(|(_, last_heard)| last_heard.elapsed() < FOLLOWER_TIMEOUT);
+        if !self.interval_elapsed(STATE_INTERVAL) {
+            return;
+        }
+        let state = SyncMessage::State(TransportState {
+            is_playing,
+            bpm,
+            beat,
+            time: self.now(),
+        });
+        for (address, _) in &self.followers {
+            self.send(&state, *address);
+        }
+        self.last_sent = Some(Instant::now());
+    }
+
+    // Offset of the round trip that took the least time, its reply waited the least
+    fn offset(&self) -> Option<RoundTrip> {
+        self.round_trips
+            .iter()
+            .min_by_key(|round_trip| round_trip.duration)
+            .copied()
+    }
+
+    // Pings the leader, and returns its latest transport with the beat moved to the present
+    pub fn follow(&mut self) -> Option<TransportState> {
+        let leader = self.leader?;
+        if self.interval_elapsed(PING_INTERVAL) {
+            self.send(&SyncMessage::Ping { sent: self.now() }, leader);
+            self.last_sent = Some(Instant::now());
+        }
+        let mut latest = None;
+        for (message, address) in self.receive() {
+            if address != leader {
+                continue;
+            }
+            self.last_heard = Some(Instant::now());
+            match message {
+                SyncMessage::Pong { sent, leader_time } => {
+                    let received = self.now();
+                    let duration = received.saturating_sub(sent);
+                    if self.round_trips.len() == MAX_ROUND_TRIPS {
+                        self.round_trips.pop_front();
+                    }
+                    // the leader answered halfway through the round trip
+                    self.round_trips.push_back(RoundTrip {
+                        duration,
+                        offset: leader_time as i64 - (sent + duration / 2) as i64,
+                    });
+                }
+                SyncMessage::State(state) => latest = Some(state),
+                SyncMessage::Ping { .. } => (),
+            }
+        }
+        // states are ignored until the clocks can be compared
+        let offset = self.offset()?.offset;
+        let mut state = latest?;
+        let now = self.now();
+        let leader_now = now as i64 + offset;
+        let elapsed = (leader_now - state.time as i64) as f64 / 1_000_000.0;
+        if state.is_playing {
+            state.beat += elapsed.max(0.0) * state.bpm as f64 / 60.0;
+        }
+        state.time = now;
+        Some(state)
+    }
+
+    pub fn status(&self) -> String {
+        match self.role {
+            SyncRole::Leader => format!("Leading {} followers", self.followers.len()),
+            _ => {
+                let lost = self
+                    .last_heard
+                    .is_none_or(|last_heard| last_heard.elapsed() >= LEADER_TIMEOUT);
+                match self.offset() {
+                    Some(round_trip) if !lost => format!(
+                        "Following, round trip {:.1} ms, clock offset {:.1} ms",
+                        round_trip.duration as f32 / 1000.0,
+                        round_trip.offset as f32 / 1000.0
+                    ),
+                    _ => "Waiting for the leader".to_string(),
+                }
+            }
+        }
+    }
+}
+
+// State of the "Network sync" window
+pub struct NetworkSyncPanel {
+    pub role: SyncRole,
+    pub port: u16,
+    pub leader_address: String,
+    pub sync: Option<NetworkSync>,
+    pub error: Option<String>,
+}
+
+impl NetworkSyncPanel {
+    pub fn new(port: u16) -> NetworkSyncPanel {
+        NetworkSyncPanel {
+            role: SyncRole::Off,
+            port,
+            leader_address: String::new(),
+            sync: None,
+            error: None,
+        }
+    }
+
+    // Opens the socket for the selected role, closing the previous one
+    pub fn connect(&mut self) {
+        self.sync = None;
+        let sync = match self.role {
+            SyncRole::Off => {
+                self.error = None;
+                return;
+            }
+            SyncRole::Leader => NetworkSync::leader(self.port),
+            SyncRole::Follower => NetworkSync::follower(&self.leader_address, self.port),
+        };
+        match sync {
+            Ok(sync) => {
+                self.sync = Some(sync);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use crate::drums::DRUM_CHANNEL;
+use crate::notes::{pitch_class_name, PITCH_CLASS_COUNT};
+use crate::sink::SinkEvent;
+
+//constants
+const STEPS_PER_BAR: u32 = 16;
+// the natural below each pitch class, and whether it is raised from it
+const ABC_LETTERS: [(char, bool); PITCH_CLASS_COUNT] = [
+    ('C', false),
+    ('C', true),
+    ('D', false),
+    ('D', true),
+    ('E', false),
+    ('F', false),
+    ('F', true),
+    ('G', false),
+    ('G', true),
+    ('A', false),
+    ('A', true),
+    ('B', false),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NotationFormat {
+    Tracker,
+    Abc,
+}
+
+impl NotationFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            NotationFormat::Tracker => "txt",
+            NotationFormat::Abc => "abc",
+        }
+    }
+}
+
+pub const NOTATION_FORMATS: &[(NotationFormat, &str)] = &[
+    (NotationFormat::Tracker, "Tracker"),
+    (NotationFormat::Abc, "ABC"),
+];
+
+// A played note placed on the sixteenth grid
+struct NotatedNote {
+    step: u32,
+    note: u8,
+    velocity: u8,
+    // in sixteenths, at least one
+    gate: u32,
+}
+
+// Pairs the note ons of the melodic channels with their note offs
+fn notated_notes(events: &[(Duration, SinkEvent)], bpm: f32) -> Vec<NotatedNote> {
+    let sixteenth = 15.0 / bpm as f64;
+    let to_step = |time: &Duration| (time.as_secs_f64() / sixteenth).round() as u32;
+    let mut notes = Vec::new();
+    for (index, (time, event)) in events.iter().enumerate() {
+        let SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        } = *event
+        else {
+            continue;
+        };
+        if channel == DRUM_CHANNEL {
+            continue;
+        }
+        let end = events[index + 1..]
+            .iter()
+            .find(|(_, e)| {
+                matches!(*e, SinkEvent::NoteOff { channel: c, note: n, .. } if c == channel && n == note)
+            })
+            .map_or(*time, |(end, _)| *end);
+        let step = to_step(time);
+        notes.push(NotatedNote {
+            step,
+            note,
+            velocity,
+            gate: to_step(&end).saturating_sub(step).max(1),
+        });
+    }
+    notes
+}
+
+fn note_name(note: u8) -> String {
+    format!(
+        "{}{}",
+        pitch_class_name(note as usize),
+        note as i32 / 12 - 1
+    )
+}
+
+// One row per sixteenth: bar.step | note | velocity | gate
+pub fn tracker_text(events: &[(Duration, SinkEvent)], bpm: f32, bars: u32) -> String {
+    let notes = notated_notes(events, bpm);
+    let mut text = format!("{:.0} BPM, gate in sixteenths\n", bpm);
+    text.push_str("step  | note | vel | gate\n");
+    for step in 0..bars * STEPS_PER_BAR {
+        if step % STEPS_PER_BAR == 0 {
+            text.push_str("------+------+-----+-----\n");
+        }
+        let position = format!(
+            "{:02}.{:02}",
+            step / STEPS_PER_BAR + 1,
+            step % STEPS_PER_BAR + 1
+        );
+        let mut row_notes = notes.iter().filter(|n| n.step == step).peekable();
+        if row_notes.peek().is_none() {
+            text.push_str(&format!("{} | ---  |     |\n", position));
+        }
+        for note in row_notes {
+            text.push_str(&format!(
+                "{} | {:<4} | {:>3} | {:>4}\n",
+                position,
+                note_name(note.note),
+                note.velocity,
+                note.gate
+            ));
+        }
+    }
+    text
+}
+
+// Spelled with sharps, explicit naturals cancel a sharp earlier in the bar
+fn abc_pitch(note: u8, sharpened: &mut Vec<u8>) -> String {
+    let (letter, raised) = ABC_LETTERS[note as usize % PITCH_CLASS_COUNT];
+    let natural = note - raised as u8;
+    let mut pitch = String::new();
+    if raised {
+        pitch.push('^');
+        if !sharpened.contains(&natural) {
+            sharpened.push(natural);
+        }
+    } else if let Some(index) = sharpened.iter().position(|n| *n == natural) {
+        pitch.push('=');
+        sharpened.remove(index);
+    }
+    // C4 is C, C5 is c
+    let octave = natural as i32 / 12 - 1;
+    if octave >= 5 {
+        pitch.push(letter.to_ascii_lowercase());
+        pitch.push_str(&"'".repeat((octave - 5) as usize));
+    } else {
+        pitch.push(letter);
+        pitch.push_str(&",".repeat((4 - octave) as usize));
+    }
+    pitch
+}
+
+fn abc_length(length: u32) -> String {
+    match length {
+        1 => String::new(),
+        length => length.to_string(),
+    }
+}
+
+// Notes starting together become a chord held until the next onset, split and tied across bar lines
+pub fn abc_text(events: &[(Duration, SinkEvent)], bpm: f32, bars: u32) -> String {
+    let notes = notated_notes(events, bpm);
+    let total = bars * STEPS_PER_BAR;
+    let mut text = format!(
+        "X:1\nT:Generated pattern\nM:4/4\nL:1/16\nQ:1/4={:.0}\nK:C\n",
+        bpm
+    );
+    let mut step = 0;
+    let mut sharpened = Vec::new();
+    while step < total {
+        let onset: Vec<&NotatedNote> = notes.iter().filter(|n| n.step == step).collect();
+        let next_onset = notes
+            .iter()
+            .map(|n| n.step)
+            .filter(|s| *s > step)
+            .min()
+            .unwrap_or(total)
+            .min(total);
+        let gate = onset.iter().map(|n| n.gate).min().unwrap_or(0);
+        // the notes, then a rest up to the next onset
+        let mut segments = Vec::new();
+        if !onset.is_empty() {
+            segments.push((Some(onset), gate.min(next_onset - step)));
+        }
+        let rest = next_onset - step - segments.first().map_or(0, |(_, length)| *length);
+        if rest > 0 {
+            segments.push((None, rest));
+        }
+        for (notes, mut length) in segments {
+            while length > 0 {
+                let bar_left = STEPS_PER_BAR - step % STEPS_PER_BAR;
+                let part = length.min(bar_left);
+                match &notes {
+                    Some(notes) => {
+                        let pitches: Vec<String> = notes
+                            .iter()
+                            .map(|n| abc_pitch(n.note, &mut sharpened))
+                            .collect();
+                        if pitches.len() == 1 {
+                            text.push_str(&pitches[0]);
+                        } else {
+                            text.push_str(&format!("[{}]", pitches.join("")));
+                        }
+                        text.push_str(&abc_length(part));
+                        if part < length {
+                            text.push('-');
+                        }
+                    }
+                    None => text.push_str(&format!("z{}", abc_length(part))),
+                }
+                text.push(' ');
+                step += part;
+                length -= part;
+                if step % STEPS_PER_BAR == 0 {
+                    sharpened.clear();
+                    text.push_str(if step % (STEPS_PER_BAR * 4) == 0 {
+                        "|\n"
+                    } else {
+                        "| "
+                    });
+                }
+            }
+        }
+    }
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
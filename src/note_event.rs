@@ -0,0 +1,281 @@
+use core::time::Duration;
+
+use rand::prelude::*;
+
+use crate::seed::{seeded_rng, SeedStream};
+use crate::split::KeyboardSplit;
+
+//constants
+// echoes quieter than this are not played
+const MIN_ECHO_VELOCITY: f32 = 1.0;
+
+// A note on its way to the sink, after the step picked it and before it is played
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NoteEvent {
+    // MIDI note
+    pub pitch: u8,
+    pub velocity: u8,
+    pub duration: Duration,
+    pub channel: u8,
+    // program of the channel
+    pub instrument: u8,
+    // time after the step the note starts at
+    pub offset: Duration,
+}
+
+// What the processors need to know about the music around the notes
+pub struct NoteContext {
+    // bit n for pitch class n, empty for a chromatic line
+    pub scale: u16,
+    pub beat: Duration,
+}
+
+// A stage between the step and the sink, adding, moving or changing the notes of a step
+pub trait NoteProcessor: Send {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, context: &NoteContext);
+    // for the processors drawing random numbers
+    fn reseed(&mut self, _seed: u64) {}
+}
+
+// Which processors run, the order they run in is fixed
+#[derive(Clone, Copy, PartialEq)]
+pub struct NoteProcessorSettings {
+    pub harmony: bool,
+    // scale degrees above the note, below when negative
+    pub harmony_degrees: i32,
+    pub strum: bool,
+    // between two notes of the strum
+    pub strum_ms: f32,
+    pub echo: bool,
+    pub echo_repeats: u32,
+    // beats between the echoes
+    pub echo_delay: f32,
+    // share of the velocity each echo keeps
+    pub echo_decay: f32,
+    pub humanize: bool,
+    // latest a note is pushed back
+    pub humanize_ms: f32,
+    // largest change of the velocity, up or down
+    pub humanize_velocity: u8,
+}
+
+// Adds a second voice a few degrees of the scale away from every note
+pub struct HarmonyProcessor {
+    degrees: i32,
+}
+
+impl HarmonyProcessor {
+    pub fn new(degrees: i32) -> HarmonyProcessor {
+        HarmonyProcessor { degrees }
+    }
+}
+
+impl NoteProcessor for HarmonyProcessor {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, context: &NoteContext) {
+        if self.degrees == 0 {
+            return;
+        }
+        for index in 0..notes.len() {
+            let note = notes[index];
+            if let Some(pitch) = move_by_degrees(note.pitch, self.degrees, context.scale) {
+                notes.push(NoteEvent { pitch, ..note });
+            }
+        }
+    }
+}
+
+// Steps of the scale up or down from a note, None out of the MIDI range. A note off the
+// scale counts from the degree below it.
+fn move_by_degrees(pitch: u8, degrees: i32, scale: u16) -> Option<u8> {
+    let in_scale = |note: i32| scale == 0 || scale & (1 << note.rem_euclid(12)) != 0;
+    let direction = degrees.signum();
+    let mut note = pitch as i32;
+    for _ in 0..degrees.abs() {
+        note += direction;
+        while !in_scale(note) {
+            note += direction;
+        }
+    }
+    (0..=127).contains(&note).then_some(note as u8)
+}
+
+// Notes starting together go out one after the other, from the lowest up
+pub struct StrumProcessor {
+    spread: Duration,
+}
+
+impl StrumProcessor {
+    pub fn new(spread: Duration) -> StrumProcessor {
+        StrumProcessor { spread }
+    }
+}
+
+impl NoteProcessor for StrumProcessor {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, _context: &NoteContext) {
+        notes.sort_by_key(|note| (note.offset, note.pitch));
+        let mut previous: Option<Duration> = None;
+        let mut strummed = 0;
+        for note in notes.iter_mut() {
+            if previous == Some(note.offset) {
+                strummed += 1;
+            } else {
+                previous = Some(note.offset);
+                strummed = 0;
+            }
+            note.offset += self.spread * strummed;
+        }
+    }
+}
+
+// Repeats every note a beat division later, quieter each time
+pub struct EchoProcessor {
+    repeats: u32,
+    // beats
+    delay: f32,
+    decay: f32,
+}
+
+impl EchoProcessor {
+    pub fn new(repeats: u32, delay: f32, decay: f32) -> EchoProcessor {
+        EchoProcessor {
+            repeats,
+            delay,
+            decay,
+        }
+    }
+}
+
+impl NoteProcessor for EchoProcessor {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, context: &NoteContext) {
+        let delay = context.beat.mul_f32(self.delay.max(0.0));
+        for index in 0..notes.len() {
+            let note = notes[index];
+            let mut velocity = note.velocity as f32;
+            for repeat in 1..=self.repeats {
+                velocity *= self.decay;
+                if velocity < MIN_ECHO_VELOCITY {
+                    break;
+                }
+                notes.push(NoteEvent {
+                    velocity: velocity.round() as u8,
+                    offset: note.offset + delay * repeat,
+                    ..note
+                });
+            }
+        }
+    }
+}
+
+// Pushes the notes back and changes their velocity by small random amounts
+pub struct HumanizeProcessor<R: Rng> {
+    rng: R,
+    timing: Duration,
+    velocity: u8,
+}
+
+impl HumanizeProcessor<SmallRng> {
+    pub fn new(timing: Duration, velocity: u8) -> HumanizeProcessor<SmallRng> {
+        HumanizeProcessor {
+            rng: SmallRng::from_entropy(),
+            timing,
+            velocity,
+        }
+    }
+}
+
+impl<R: Rng + SeedableRng + Send> NoteProcessor for HumanizeProcessor<R> {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, _context: &NoteContext) {
+        let velocity = self.velocity as i32;
+        for note in notes.iter_mut() {
+            note.offset += self.timing.mul_f64(self.rng.gen_range(0.0..=1.0));
+            let offset = self.rng.gen_range(-velocity..=velocity);
+            note.velocity = (note.velocity as i32 + offset).clamp(1, 127) as u8;
+        }
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed, SeedStream::Humanize);
+    }
+}
+
+// Moves every note onto the scale the harmony is in at the time, after the other
+// processors and before the routing, which goes by the pitch the note ends with
+pub struct ScaleLock;
+
+impl NoteProcessor for ScaleLock {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, context: &NoteContext) {
+        for note in notes.iter_mut() {
+            note.pitch = lock_to_scale(note.pitch, context.scale);
+        }
+    }
+}
+
+// The closest note of the scale, the upper one when two are as close, as the quantizer
+// does. An empty scale lets every pitch through.
+pub fn lock_to_scale(pitch: u8, scale: u16) -> u8 {
+    let pitch = pitch.min(127);
+    let in_scale = |note: &u8| scale == 0 || scale & (1 << (note % 12)) != 0;
+    (0..12)
+        .flat_map(|distance| [pitch.checked_add(distance), pitch.checked_sub(distance)])
+        .flatten()
+        .filter(|note| *note <= 127)
+        .find(in_scale)
+        .unwrap_or(pitch)
+}
+
+// Sends the notes of the main voice below the split point to the split channel and instrument
+pub struct ChannelRouting {
+    split: KeyboardSplit,
+    // the channel split, the others keep theirs
+    channel: u8,
+}
+
+impl ChannelRouting {
+    pub fn new(split: KeyboardSplit, channel: u8) -> ChannelRouting {
+        ChannelRouting { split, channel }
+    }
+}
+
+impl NoteProcessor for ChannelRouting {
+    fn process(&mut self, notes: &mut Vec<NoteEvent>, _context: &NoteContext) {
+        for note in notes.iter_mut().filter(|note| note.channel == self.channel) {
+            (note.channel, note.instrument) =
+                self.split.route(note.pitch, note.channel, note.instrument);
+        }
+    }
+}
+
+// Notes the processors moved later, waiting for their time
+pub struct DelayedNotes {
+    pending: Vec<(Duration, NoteEvent)>,
+}
+
+impl DelayedNotes {
+    pub fn new() -> DelayedNotes {
+        DelayedNotes {
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, at: Duration, note: NoteEvent) {
+        self.pending.push((at, note));
+    }
+
+    // The notes due by now, in the order they were pushed
+    pub fn take_due(&mut self, now: Duration) -> Vec<NoteEvent> {
+        let mut due = Vec::new();
+        self.pending.retain(|(at, note)| {
+            if *at <= now {
+                due.push(*note);
+                false
+            } else {
+                true
+            }
+        });
+        due
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
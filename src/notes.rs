@@ -0,0 +1,83 @@
+use std::sync::RwLock;
+
+use pitch_calc::*;
+
+use crate::pitch::letter_semitone;
+
+//constants
+pub const PITCH_CLASS_COUNT: usize = 12;
+const ENGLISH_SHARPS: [&str; PITCH_CLASS_COUNT] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const ENGLISH_FLATS: [&str; PITCH_CLASS_COUNT] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+// B natural is H, B flat is B
+const GERMAN_SHARPS: [&str; PITCH_CLASS_COUNT] = [
+    "C", "Cis", "D", "Dis", "E", "F", "Fis", "G", "Gis", "A", "Ais", "H",
+];
+const GERMAN_FLATS: [&str; PITCH_CLASS_COUNT] = [
+    "C", "Des", "D", "Es", "E", "F", "Ges", "G", "As", "A", "B", "H",
+];
+const SOLFEGE_SHARPS: [&str; PITCH_CLASS_COUNT] = [
+    "Do", "Do#", "Re", "Re#", "Mi", "Fa", "Fa#", "Sol", "Sol#", "La", "La#", "Si",
+];
+const SOLFEGE_FLATS: [&str; PITCH_CLASS_COUNT] = [
+    "Do", "Reb", "Re", "Mib", "Mi", "Fa", "Solb", "Sol", "Lab", "La", "Sib", "Si",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoteNaming {
+    English,
+    German,
+    Solfege,
+}
+
+pub const NOTE_NAMINGS: &[(NoteNaming, &str)] = &[
+    (NoteNaming::English, "English (C D E)"),
+    (NoteNaming::German, "German (C D E H)"),
+    (NoteNaming::Solfege, "Solfège (Do Re Mi)"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct NoteNameStyle {
+    pub naming: NoteNaming,
+    pub flats: bool,
+}
+
+// The display preference, read wherever a note name is shown
+static STYLE: RwLock<NoteNameStyle> = RwLock::new(NoteNameStyle {
+    naming: NoteNaming::English,
+    flats: false,
+});
+
+pub fn note_name_style() -> NoteNameStyle {
+    *STYLE.read().unwrap()
+}
+
+pub fn set_note_name_style(style: NoteNameStyle) {
+    *STYLE.write().unwrap() = style;
+}
+
+// Name of a pitch class, 0 being C
+pub fn pitch_class_name(pitch_class: usize) -> &'static str {
+    let style = note_name_style();
+    let names = match (style.naming, style.flats) {
+        (NoteNaming::English, false) => &ENGLISH_SHARPS,
+        (NoteNaming::English, true) => &ENGLISH_FLATS,
+        (NoteNaming::German, false) => &GERMAN_SHARPS,
+        (NoteNaming::German, true) => &GERMAN_FLATS,
+        (NoteNaming::Solfege, false) => &SOLFEGE_SHARPS,
+        (NoteNaming::Solfege, true) => &SOLFEGE_FLATS,
+    };
+    names[pitch_class % PITCH_CLASS_COUNT]
+}
+
+// Spelled by the preference rather than by the letter, so C#4 and Db4 show the same
+pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
+    format!(
+        "{}{}",
+        pitch_class_name(letter_semitone(letter_octave.letter()) as usize),
+        letter_octave.octave()
+    )
+}
@@ -0,0 +1,1181 @@
+use std::ops::RangeInclusive;
+
+use crate::assets::GROOVE_TEMPLATES;
+use crate::bass::BASS_PATTERNS;
+use crate::cadence::CADENCE_TARGETS;
+use crate::call_response::RESPONSE_TRANSFORMS;
+use crate::collision::COLLISION_AVOIDANCES;
+use crate::library::library;
+use crate::notes::PITCH_CLASS_COUNT;
+use crate::registry::producer_registry;
+use crate::SequencerModel;
+use crate::{
+    DIRECTION_NAMES, LOGIC_OPERATION_NAMES, MAX_ACCENT, MAX_AMBIENT_NOTE_LENGTH,
+    MAX_AMBIENT_VOICES, MAX_BASS_OCTAVES_BELOW, MAX_BPM_VALUE, MAX_CADENCE_PHRASE_BARS,
+    MAX_CLOCK_DIVISION, MAX_CYCLE_LENGTH, MAX_DENSITY_COMPENSATION, MAX_DRUM_GAIN, MAX_DRUM_PITCH,
+    MAX_ECHO_DELAY, MAX_ECHO_REPEATS, MAX_FADE_BARS, MAX_HARMONY_STEPS, MAX_HUMANIZE_MS,
+    MAX_HUMANIZE_VELOCITY, MAX_INTERVAL_LIMIT, MAX_LAYER_OCTAVES, MAX_LAYER_STEPS,
+    MAX_MODULATION_BARS, MAX_MOTIF_LENGTH, MAX_NOTE_LENGTH, MAX_PHRASE_STATEMENTS,
+    MAX_PRESSURE_ENVELOPE_TIME_MS, MAX_RATCHET_COUNT, MAX_RHYTHM_ROTATION, MAX_SAMPLE_HOLD_STEPS,
+    MAX_SEED, MAX_SLEW_BEATS, MAX_STRUM_MS, MAX_SUSTAIN_PHRASE_BARS, MAX_SWING,
+    MAX_TENSION_PHRASE_BARS, MAX_TRANSPOSE, MIN_AMBIENT_NOTE_LENGTH, MIN_BPM_VALUE,
+    MIN_CADENCE_PHRASE_BARS, MIN_CYCLE_LENGTH, MIN_DRUM_PITCH, MIN_ECHO_DELAY, MIN_MOTIF_LENGTH,
+    MIN_NOTE_LENGTH, MIN_PHRASE_STATEMENTS, MIN_SUSTAIN_PHRASE_BARS, MIN_TENSION_PHRASE_BARS,
+    PITCH_MAX_VALUE, PITCH_MIN_VALUE, RANGE_MODE_NAMES, SUSTAIN_MODE_NAMES, TENSION_SHAPE_NAMES,
+    TRACK_SPEEDS,
+};
+
+// What has to be rebuilt or resent on the sequencer when a parameter changes
+#[derive(Clone, Copy, PartialEq)]
+pub enum ParameterTarget {
+    PitchChain,
+    RhythmPattern,
+    // applied in place on the running chains
+    Tempo,
+    TriggerProbability,
+    RestProbability,
+    OctaveJumps,
+    SampleHold,
+    Groove,
+    VelocityJitter,
+    Accent,
+    DensityCompensation,
+    Fade,
+    NoteLength,
+    // takes effect at the next bar line
+    Speed,
+    Rotation,
+    PressureEnvelope,
+    KeyModulation,
+    Sustain,
+    Instrument,
+    Split,
+    NoteProcessors,
+    ScaleLock,
+    CollisionAvoidance,
+    Cadence,
+    // takes effect at the next bar line
+    Seed,
+    Bass,
+    // takes effect at the next bar line
+    Layers,
+    // takes effect at the next bar line
+    Drums,
+    // only read along with the next pitch or trigger chain change, nothing to send
+    Slew,
+}
+
+// Every sequencer setting, addressable by name for remote control, automation and presets
+pub struct Parameter {
+    pub name: &'static str,
+    pub address: &'static str,
+    pub unit: &'static str,
+    // stepped parameters hold an index and are rounded on set
+    pub stepped: bool,
+    pub target: ParameterTarget,
+    pub range: fn(&SequencerModel) -> RangeInclusive<f32>,
+    pub get: fn(&SequencerModel) -> f32,
+    pub set: fn(&mut SequencerModel, f32),
+}
+
+impl Parameter {
+    pub fn set_value(&self, model: &mut SequencerModel, value: f32) {
+        let range = (self.range)(model);
+        let mut value = value.clamp(*range.start(), *range.end());
+        if self.stepped {
+            value = value.round();
+        }
+        (self.set)(model, value);
+    }
+
+    pub fn get_normalized(&self, model: &SequencerModel) -> f32 {
+        let range = (self.range)(model);
+        let span = range.end() - range.start();
+        if span <= 0.0 {
+            0.0
+        } else {
+            ((self.get)(model) - range.start()) / span
+        }
+    }
+
+    pub fn set_normalized(&self, model: &mut SequencerModel, normalized: f32) {
+        let range = (self.range)(model);
+        let value = range.start() + normalized.clamp(0.0, 1.0) * (range.end() - range.start());
+        self.set_value(model, value);
+    }
+}
+
+pub static PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "Tempo",
+        address: "/tempo",
+        unit: "BPM",
+        stepped: false,
+        target: ParameterTarget::Tempo,
+        range: |_| MIN_BPM_VALUE..=MAX_BPM_VALUE,
+        get: |m| m.bpm,
+        set: |m, v| m.bpm = v,
+    },
+    Parameter {
+        name: "Slew",
+        address: "/slew",
+        unit: "beats",
+        stepped: false,
+        target: ParameterTarget::Slew,
+        range: |_| 0.0..=MAX_SLEW_BEATS,
+        get: |m| m.slew_beats,
+        set: |m, v| m.slew_beats = v,
+    },
+    Parameter {
+        name: "Scale",
+        address: "/pitch/scale",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(library().scales.len() - 1) as f32,
+        get: |m| m.quantizer_scale_index.unwrap() as f32,
+        set: |m, v| m.quantizer_scale_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Root",
+        address: "/pitch/root",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(PITCH_CLASS_COUNT - 1) as f32,
+        get: |m| m.scale_root_index.unwrap() as f32,
+        set: |m, v| m.scale_root_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Modulate",
+        address: "/pitch/modulation",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::KeyModulation,
+        range: |_| 0.0..=1.0,
+        get: |m| m.key_modulation.enabled as u8 as f32,
+        set: |m, v| m.key_modulation.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Modulate by",
+        address: "/pitch/modulation_interval",
+        unit: "st",
+        stepped: true,
+        target: ParameterTarget::KeyModulation,
+        range: |_| -11.0..=11.0,
+        get: |m| m.key_modulation.interval as f32,
+        set: |m, v| m.key_modulation.interval = v as i32,
+    },
+    Parameter {
+        name: "Modulate every",
+        address: "/pitch/modulation_bars",
+        unit: "bars",
+        stepped: true,
+        target: ParameterTarget::KeyModulation,
+        range: |_| 1.0..=MAX_MODULATION_BARS as f32,
+        get: |m| m.key_modulation.bars as f32,
+        set: |m, v| m.key_modulation.bars = v as u32,
+    },
+    Parameter {
+        name: "Pitch",
+        address: "/pitch/producer",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(producer_registry().pitch_producers.len() - 1) as f32,
+        get: |m| m.pitch_producer_index.unwrap() as f32,
+        set: |m, v| m.pitch_producer_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Cycle length",
+        address: "/pitch/cycle_length",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| MIN_CYCLE_LENGTH as f32..=MAX_CYCLE_LENGTH as f32,
+        get: |m| m.cycle_length,
+        set: |m, v| m.cycle_length = v,
+    },
+    Parameter {
+        name: "Direction",
+        address: "/pitch/direction",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(DIRECTION_NAMES.len() - 1) as f32,
+        get: |m| m.direction_index.unwrap() as f32,
+        set: |m, v| m.direction_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Pitch rotation",
+        address: "/pitch/rotation",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::Rotation,
+        range: |_| 0.0..=MAX_CYCLE_LENGTH as f32,
+        get: |m| m.pitch_rotation,
+        set: |m, v| m.pitch_rotation = v,
+    },
+    Parameter {
+        name: "Min",
+        address: "/pitch/min",
+        unit: "step",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |m| PITCH_MIN_VALUE.step()..=m.max_pitch,
+        get: |m| m.min_pitch,
+        set: |m, v| m.min_pitch = v,
+    },
+    Parameter {
+        name: "Max",
+        address: "/pitch/max",
+        unit: "step",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |m| m.min_pitch..=PITCH_MAX_VALUE.step(),
+        get: |m| m.max_pitch,
+        set: |m, v| m.max_pitch = v,
+    },
+    Parameter {
+        name: "Range",
+        address: "/pitch/range_mode",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(RANGE_MODE_NAMES.len() - 1) as f32,
+        get: |m| m.range_mode_index.unwrap() as f32,
+        set: |m, v| m.range_mode_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Octave jumps",
+        address: "/pitch/octave_jumps",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::OctaveJumps,
+        range: |_| 0.0..=1.0,
+        get: |m| m.octave_jump_probability as f32,
+        set: |m, v| m.octave_jump_probability = v as f64,
+    },
+    Parameter {
+        name: "Sample & hold",
+        address: "/pitch/sample_hold",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::SampleHold,
+        range: |_| 1.0..=MAX_SAMPLE_HOLD_STEPS as f32,
+        get: |m| m.sample_hold_steps,
+        set: |m, v| m.sample_hold_steps = v,
+    },
+    Parameter {
+        name: "Transpose",
+        address: "/pitch/transpose",
+        unit: "st",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| -MAX_TRANSPOSE as f32..=MAX_TRANSPOSE as f32,
+        get: |m| m.transpose,
+        set: |m, v| m.transpose = v,
+    },
+    Parameter {
+        name: "Harmony",
+        address: "/pitch/harmony",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| -MAX_HARMONY_STEPS as f32..=MAX_HARMONY_STEPS as f32,
+        get: |m| m.harmony_steps,
+        set: |m, v| m.harmony_steps = v,
+    },
+    Parameter {
+        name: "Max interval",
+        address: "/pitch/max_interval",
+        unit: "st",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 1.0..=MAX_INTERVAL_LIMIT as f32,
+        get: |m| m.max_interval,
+        set: |m, v| m.max_interval = v,
+    },
+    Parameter {
+        name: "Repeat avoidance",
+        address: "/pitch/repeat_avoidance",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.repeat_avoidance as f32,
+        set: |m, v| m.repeat_avoidance = v as f64,
+    },
+    Parameter {
+        name: "Phrase",
+        address: "/pitch/phrase",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.phrase.enabled as u8 as f32,
+        set: |m, v| m.phrase.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Motif length",
+        address: "/pitch/phrase/motif_length",
+        unit: "notes",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| MIN_MOTIF_LENGTH as f32..=MAX_MOTIF_LENGTH as f32,
+        get: |m| m.phrase.motif_length as f32,
+        set: |m, v| m.phrase.motif_length = v as usize,
+    },
+    Parameter {
+        name: "Motif statements",
+        address: "/pitch/phrase/statements",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| MIN_PHRASE_STATEMENTS as f32..=MAX_PHRASE_STATEMENTS as f32,
+        get: |m| m.phrase.statements as f32,
+        set: |m, v| m.phrase.statements = v as usize,
+    },
+    Parameter {
+        name: "Motifs from melody",
+        address: "/pitch/phrase/seed_from_melody",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.phrase.seed_from_melody as u8 as f32,
+        set: |m, v| m.phrase.seed_from_melody = v >= 0.5,
+    },
+    Parameter {
+        name: "Tension",
+        address: "/tension/shape",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(TENSION_SHAPE_NAMES.len() - 1) as f32,
+        get: |m| m.tension_shape_index.unwrap() as f32,
+        set: |m, v| m.tension_shape_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Tension bars",
+        address: "/tension/phrase_bars",
+        unit: "bars",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| MIN_TENSION_PHRASE_BARS as f32..=MAX_TENSION_PHRASE_BARS as f32,
+        get: |m| m.tension_phrase_bars,
+        set: |m, v| m.tension_phrase_bars = v,
+    },
+    Parameter {
+        name: "Tension depth",
+        address: "/tension/depth",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.tension_depth,
+        set: |m, v| m.tension_depth = v,
+    },
+    Parameter {
+        name: "Seed per bar",
+        address: "/seed",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Seed,
+        range: |_| 0.0..=1.0,
+        get: |m| m.seed.per_bar as u8 as f32,
+        set: |m, v| m.seed.per_bar = v >= 0.5,
+    },
+    Parameter {
+        name: "Master seed",
+        address: "/seed/master",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Seed,
+        range: |_| 0.0..=MAX_SEED as f32,
+        get: |m| m.seed.master as f32,
+        set: |m, v| m.seed.master = v as u32,
+    },
+    Parameter {
+        name: "Cadence",
+        address: "/cadence",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Cadence,
+        range: |_| 0.0..=1.0,
+        get: |m| m.cadence.enabled as u8 as f32,
+        set: |m, v| m.cadence.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Cadence bars",
+        address: "/cadence/phrase_bars",
+        unit: "bars",
+        stepped: true,
+        target: ParameterTarget::Cadence,
+        range: |_| MIN_CADENCE_PHRASE_BARS as f32..=MAX_CADENCE_PHRASE_BARS as f32,
+        get: |m| m.cadence.phrase_bars as f32,
+        set: |m, v| m.cadence.phrase_bars = v as u32,
+    },
+    Parameter {
+        name: "Cadence target",
+        address: "/cadence/target",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Cadence,
+        range: |_| 0.0..=(CADENCE_TARGETS.len() - 1) as f32,
+        get: |m| {
+            CADENCE_TARGETS
+                .iter()
+                .position(|t| *t == m.cadence.target)
+                .unwrap_or(0) as f32
+        },
+        set: |m, v| m.cadence.target = CADENCE_TARGETS[v as usize],
+    },
+    Parameter {
+        name: "Call and response",
+        address: "/call_response",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.call_response.enabled as u8 as f32,
+        set: |m, v| m.call_response.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Response",
+        address: "/call_response/transform",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(RESPONSE_TRANSFORMS.len() - 1) as f32,
+        get: |m| {
+            RESPONSE_TRANSFORMS
+                .iter()
+                .position(|t| *t == m.call_response.transform)
+                .unwrap() as f32
+        },
+        set: |m, v| m.call_response.transform = RESPONSE_TRANSFORMS[v as usize],
+    },
+    Parameter {
+        name: "Response instrument",
+        address: "/call_response/instrument",
+        unit: "program",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=(library().instruments.len() - 1) as f32,
+        get: |m| m.call_response.instrument as f32,
+        set: |m, v| m.call_response.instrument = v as u8,
+    },
+    Parameter {
+        name: "Ambient",
+        address: "/ambient",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.ambient.enabled as u8 as f32,
+        set: |m, v| m.ambient.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Density",
+        address: "/ambient/density",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.ambient.density as f32,
+        set: |m, v| m.ambient.density = v as f64,
+    },
+    Parameter {
+        name: "Note length",
+        address: "/ambient/note_length",
+        unit: "x",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |_| MIN_AMBIENT_NOTE_LENGTH..=MAX_AMBIENT_NOTE_LENGTH,
+        get: |m| m.ambient.note_length,
+        set: |m, v| m.ambient.note_length = v,
+    },
+    Parameter {
+        name: "Drift",
+        address: "/ambient/drift",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::PitchChain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.ambient.drift as f32,
+        set: |m, v| m.ambient.drift = v as f64,
+    },
+    Parameter {
+        name: "Voices",
+        address: "/ambient/voices",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PitchChain,
+        range: |_| 1.0..=MAX_AMBIENT_VOICES as f32,
+        get: |m| m.ambient.max_voices as f32,
+        set: |m, v| m.ambient.max_voices = v as usize,
+    },
+    Parameter {
+        name: "Rhythm",
+        address: "/rhythm/pattern",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::RhythmPattern,
+        range: |m| {
+            0.0..=(library().rhythm_patterns.len() + m.custom_rhythm_patterns.len() - 1) as f32
+        },
+        get: |m| m.rhythm_pattern.unwrap() as f32,
+        set: |m, v| m.rhythm_pattern = Some(v as usize),
+    },
+    Parameter {
+        name: "Trigger",
+        address: "/rhythm/trigger",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::RhythmPattern,
+        range: |_| 0.0..=(producer_registry().trigger_producers.len() - 1) as f32,
+        get: |m| m.trigger_producer_index.unwrap() as f32,
+        set: |m, v| m.trigger_producer_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Trigger probability",
+        address: "/rhythm/trigger_probability",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::TriggerProbability,
+        range: |_| 0.0..=1.0,
+        get: |m| m.trigger_probability as f32,
+        set: |m, v| m.trigger_probability = v as f64,
+    },
+    Parameter {
+        name: "Clock division",
+        address: "/rhythm/clock_division",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::RhythmPattern,
+        range: |_| 1.0..=MAX_CLOCK_DIVISION as f32,
+        get: |m| m.clock_division,
+        set: |m, v| m.clock_division = v,
+    },
+    Parameter {
+        name: "Swing",
+        address: "/rhythm/swing",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::RhythmPattern,
+        range: |_| 0.0..=MAX_SWING,
+        get: |m| m.swing,
+        set: |m, v| m.swing = v,
+    },
+    Parameter {
+        name: "Ratchet",
+        address: "/rhythm/ratchet",
+        unit: "notes",
+        stepped: true,
+        target: ParameterTarget::RhythmPattern,
+        range: |_| 1.0..=MAX_RATCHET_COUNT as f32,
+        get: |m| m.ratchet_count,
+        set: |m, v| m.ratchet_count = v,
+    },
+    Parameter {
+        name: "Logic",
+        address: "/rhythm/logic",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::RhythmPattern,
+        range: |_| 0.0..=(LOGIC_OPERATION_NAMES.len() - 1) as f32,
+        get: |m| m.logic_operation_index.unwrap() as f32,
+        set: |m, v| m.logic_operation_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Rests",
+        address: "/rhythm/rests",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::RestProbability,
+        range: |_| 0.0..=1.0,
+        get: |m| m.rest_probability as f32,
+        set: |m, v| m.rest_probability = v as f64,
+    },
+    Parameter {
+        name: "Groove",
+        address: "/rhythm/groove",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Groove,
+        range: |_| 0.0..=(GROOVE_TEMPLATES.len() - 1) as f32,
+        get: |m| m.groove_index.unwrap() as f32,
+        set: |m, v| m.groove_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Note length",
+        address: "/rhythm/note_length",
+        unit: "x",
+        stepped: false,
+        target: ParameterTarget::NoteLength,
+        range: |_| MIN_NOTE_LENGTH..=MAX_NOTE_LENGTH,
+        get: |m| m.note_length,
+        set: |m, v| m.note_length = v,
+    },
+    Parameter {
+        name: "Speed",
+        address: "/rhythm/speed",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Speed,
+        range: |_| 0.0..=(TRACK_SPEEDS.len() - 1) as f32,
+        get: |m| m.speed_index as f32,
+        set: |m, v| m.speed_index = v as usize,
+    },
+    Parameter {
+        name: "Rhythm rotation",
+        address: "/rhythm/rotation",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::Rotation,
+        range: |_| 0.0..=MAX_RHYTHM_ROTATION as f32,
+        get: |m| m.rhythm_rotation,
+        set: |m, v| m.rhythm_rotation = v,
+    },
+    Parameter {
+        name: "Velocity jitter",
+        address: "/velocity/jitter",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::VelocityJitter,
+        range: |_| 0.0..=1.0,
+        get: |m| m.velocity_jitter,
+        set: |m, v| m.velocity_jitter = v,
+    },
+    Parameter {
+        name: "Downbeat accent",
+        address: "/velocity/accent",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Accent,
+        range: |_| 0.0..=1.0,
+        get: |m| m.accent.enabled as u8 as f32,
+        set: |m, v| m.accent.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Accent on beat 3",
+        address: "/velocity/accent/beat3",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Accent,
+        range: |_| 0.0..=1.0,
+        get: |m| m.accent.third_beat as u8 as f32,
+        set: |m, v| m.accent.third_beat = v >= 0.5,
+    },
+    Parameter {
+        name: "Accent amount",
+        address: "/velocity/accent/amount",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Accent,
+        range: |_| 0.0..=MAX_ACCENT as f32,
+        get: |m| m.accent.amount as f32,
+        set: |m, v| m.accent.amount = v as u8,
+    },
+    Parameter {
+        name: "Density compensation",
+        address: "/velocity/density",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::DensityCompensation,
+        range: |_| 0.0..=1.0,
+        get: |m| m.density_compensation.enabled as u8 as f32,
+        set: |m, v| m.density_compensation.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Density compensation amount",
+        address: "/velocity/density/amount",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::DensityCompensation,
+        range: |_| 0.0..=MAX_DENSITY_COMPENSATION as f32,
+        get: |m| m.density_compensation.amount as f32,
+        set: |m, v| m.density_compensation.amount = v as u8,
+    },
+    Parameter {
+        name: "Start and stop fade",
+        address: "/fade",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Fade,
+        range: |_| 0.0..=1.0,
+        get: |m| m.fade.enabled as u8 as f32,
+        set: |m, v| m.fade.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Fade length",
+        address: "/fade/bars",
+        unit: "bars",
+        stepped: true,
+        target: ParameterTarget::Fade,
+        range: |_| 1.0..=MAX_FADE_BARS as f32,
+        get: |m| m.fade.bars as f32,
+        set: |m, v| m.fade.bars = v as u32,
+    },
+    Parameter {
+        name: "Aftertouch",
+        address: "/velocity/aftertouch",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::PressureEnvelope,
+        range: |_| 0.0..=1.0,
+        get: |m| m.pressure_envelope.enabled as u8 as f32,
+        set: |m, v| m.pressure_envelope.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Aftertouch attack",
+        address: "/velocity/aftertouch/attack",
+        unit: "ms",
+        stepped: false,
+        target: ParameterTarget::PressureEnvelope,
+        range: |_| 0.0..=MAX_PRESSURE_ENVELOPE_TIME_MS,
+        get: |m| m.pressure_envelope.attack_ms,
+        set: |m, v| m.pressure_envelope.attack_ms = v,
+    },
+    Parameter {
+        name: "Aftertouch decay",
+        address: "/velocity/aftertouch/decay",
+        unit: "ms",
+        stepped: false,
+        target: ParameterTarget::PressureEnvelope,
+        range: |_| 0.0..=MAX_PRESSURE_ENVELOPE_TIME_MS,
+        get: |m| m.pressure_envelope.decay_ms,
+        set: |m, v| m.pressure_envelope.decay_ms = v,
+    },
+    Parameter {
+        name: "Aftertouch depth",
+        address: "/velocity/aftertouch/depth",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::PressureEnvelope,
+        range: |_| 0.0..=127.0,
+        get: |m| m.pressure_envelope.depth,
+        set: |m, v| m.pressure_envelope.depth = v,
+    },
+    Parameter {
+        name: "Sustain",
+        address: "/sustain/mode",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Sustain,
+        range: |_| 0.0..=(SUSTAIN_MODE_NAMES.len() - 1) as f32,
+        get: |m| m.sustain_mode_index.unwrap() as f32,
+        set: |m, v| m.sustain_mode_index = Some(v as usize),
+    },
+    Parameter {
+        name: "Phrase bars",
+        address: "/sustain/phrase_bars",
+        unit: "bars",
+        stepped: true,
+        target: ParameterTarget::Sustain,
+        range: |_| MIN_SUSTAIN_PHRASE_BARS as f32..=MAX_SUSTAIN_PHRASE_BARS as f32,
+        get: |m| m.sustain_phrase_bars,
+        set: |m, v| m.sustain_phrase_bars = v,
+    },
+    Parameter {
+        name: "Sustain probability",
+        address: "/sustain/probability",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::Sustain,
+        range: |_| 0.0..=1.0,
+        get: |m| m.sustain_probability as f32,
+        set: |m, v| m.sustain_probability = v as f64,
+    },
+    Parameter {
+        name: "Instrument",
+        address: "/instrument",
+        unit: "program",
+        stepped: true,
+        target: ParameterTarget::Instrument,
+        range: |_| 0.0..=(library().instruments.len() - 1) as f32,
+        get: |m| m.instrument as f32,
+        set: |m, v| m.instrument = v as u8,
+    },
+    Parameter {
+        name: "Split",
+        address: "/split",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Split,
+        range: |_| 0.0..=1.0,
+        get: |m| m.split.enabled as u8 as f32,
+        set: |m, v| m.split.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Split point",
+        address: "/split/point",
+        unit: "note",
+        stepped: true,
+        target: ParameterTarget::Split,
+        range: |_| PITCH_MIN_VALUE.step()..=PITCH_MAX_VALUE.step(),
+        get: |m| m.split.point as f32,
+        set: |m, v| m.split.point = v as u8,
+    },
+    Parameter {
+        name: "Split instrument",
+        address: "/split/instrument",
+        unit: "program",
+        stepped: true,
+        target: ParameterTarget::Split,
+        range: |_| 0.0..=(library().instruments.len() - 1) as f32,
+        get: |m| m.split.instrument as f32,
+        set: |m, v| m.split.instrument = v as u8,
+    },
+    Parameter {
+        name: "Harmony voice",
+        address: "/notes/harmony",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=1.0,
+        get: |m| m.note_processors.harmony as u8 as f32,
+        set: |m, v| m.note_processors.harmony = v >= 0.5,
+    },
+    Parameter {
+        name: "Harmony voice degrees",
+        address: "/notes/harmony/degrees",
+        unit: "steps",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| -MAX_HARMONY_STEPS as f32..=MAX_HARMONY_STEPS as f32,
+        get: |m| m.note_processors.harmony_degrees as f32,
+        set: |m, v| m.note_processors.harmony_degrees = v as i32,
+    },
+    Parameter {
+        name: "Strum",
+        address: "/notes/strum",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=1.0,
+        get: |m| m.note_processors.strum as u8 as f32,
+        set: |m, v| m.note_processors.strum = v >= 0.5,
+    },
+    Parameter {
+        name: "Strum spread",
+        address: "/notes/strum/spread",
+        unit: "ms",
+        stepped: false,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=MAX_STRUM_MS,
+        get: |m| m.note_processors.strum_ms,
+        set: |m, v| m.note_processors.strum_ms = v,
+    },
+    Parameter {
+        name: "Echo",
+        address: "/notes/echo",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=1.0,
+        get: |m| m.note_processors.echo as u8 as f32,
+        set: |m, v| m.note_processors.echo = v >= 0.5,
+    },
+    Parameter {
+        name: "Echo repeats",
+        address: "/notes/echo/repeats",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 1.0..=MAX_ECHO_REPEATS as f32,
+        get: |m| m.note_processors.echo_repeats as f32,
+        set: |m, v| m.note_processors.echo_repeats = v as u32,
+    },
+    Parameter {
+        name: "Echo delay",
+        address: "/notes/echo/delay",
+        unit: "beats",
+        stepped: false,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| MIN_ECHO_DELAY..=MAX_ECHO_DELAY,
+        get: |m| m.note_processors.echo_delay,
+        set: |m, v| m.note_processors.echo_delay = v,
+    },
+    Parameter {
+        name: "Echo decay",
+        address: "/notes/echo/decay",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=1.0,
+        get: |m| m.note_processors.echo_decay,
+        set: |m, v| m.note_processors.echo_decay = v,
+    },
+    Parameter {
+        name: "Humanize",
+        address: "/notes/humanize",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=1.0,
+        get: |m| m.note_processors.humanize as u8 as f32,
+        set: |m, v| m.note_processors.humanize = v >= 0.5,
+    },
+    Parameter {
+        name: "Humanize timing",
+        address: "/notes/humanize/timing",
+        unit: "ms",
+        stepped: false,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=MAX_HUMANIZE_MS,
+        get: |m| m.note_processors.humanize_ms,
+        set: |m, v| m.note_processors.humanize_ms = v,
+    },
+    Parameter {
+        name: "Humanize velocity",
+        address: "/notes/humanize/velocity",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::NoteProcessors,
+        range: |_| 0.0..=MAX_HUMANIZE_VELOCITY as f32,
+        get: |m| m.note_processors.humanize_velocity as f32,
+        set: |m, v| m.note_processors.humanize_velocity = v as u8,
+    },
+    Parameter {
+        name: "Scale lock",
+        address: "/pitch/scale_lock",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::ScaleLock,
+        range: |_| 0.0..=1.0,
+        get: |m| m.scale_lock as u8 as f32,
+        set: |m, v| m.scale_lock = v >= 0.5,
+    },
+    Parameter {
+        name: "Collision avoidance",
+        address: "/collisions",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::CollisionAvoidance,
+        range: |_| 0.0..=(COLLISION_AVOIDANCES.len() - 1) as f32,
+        get: |m| {
+            COLLISION_AVOIDANCES
+                .iter()
+                .position(|a| *a == m.collision_avoidance)
+                .unwrap_or(0) as f32
+        },
+        set: |m, v| m.collision_avoidance = COLLISION_AVOIDANCES[v as usize],
+    },
+    Parameter {
+        name: "Bass",
+        address: "/bass",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Bass,
+        range: |_| 0.0..=1.0,
+        get: |m| m.bass.enabled as u8 as f32,
+        set: |m, v| m.bass.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Bass pattern",
+        address: "/bass/pattern",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Bass,
+        range: |_| 0.0..=(BASS_PATTERNS.len() - 1) as f32,
+        get: |m| {
+            BASS_PATTERNS
+                .iter()
+                .position(|p| *p == m.bass.pattern)
+                .unwrap_or(0) as f32
+        },
+        set: |m, v| m.bass.pattern = BASS_PATTERNS[v as usize],
+    },
+    Parameter {
+        name: "Bass octaves",
+        address: "/bass/octaves",
+        unit: "oct",
+        stepped: true,
+        target: ParameterTarget::Bass,
+        range: |_| 1.0..=MAX_BASS_OCTAVES_BELOW as f32,
+        get: |m| m.bass.octaves_below as f32,
+        set: |m, v| m.bass.octaves_below = v as u32,
+    },
+    Parameter {
+        name: "Bass instrument",
+        address: "/bass/instrument",
+        unit: "program",
+        stepped: true,
+        target: ParameterTarget::Bass,
+        range: |_| 0.0..=(library().instruments.len() - 1) as f32,
+        get: |m| m.bass.instrument as f32,
+        set: |m, v| m.bass.instrument = v as u8,
+    },
+    Parameter {
+        name: "Derived layers",
+        address: "/layers",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Layers,
+        range: |_| 0.0..=1.0,
+        get: |m| m.layers.enabled as u8 as f32,
+        set: |m, v| m.layers.enabled = v >= 0.5,
+    },
+    Parameter {
+        name: "Derived bass division",
+        address: "/layers/bass_steps",
+        unit: "16th",
+        stepped: true,
+        target: ParameterTarget::Layers,
+        range: |_| 1.0..=MAX_LAYER_STEPS as f32,
+        get: |m| m.layers.bass_steps as f32,
+        set: |m, v| m.layers.bass_steps = v as u32,
+    },
+    Parameter {
+        name: "Derived bass octaves",
+        address: "/layers/bass_octaves",
+        unit: "oct",
+        stepped: true,
+        target: ParameterTarget::Layers,
+        range: |_| 0.0..=MAX_LAYER_OCTAVES as f32,
+        get: |m| m.layers.bass_octaves as f32,
+        set: |m, v| m.layers.bass_octaves = v as u32,
+    },
+    Parameter {
+        name: "Derived hats division",
+        address: "/layers/hat_steps",
+        unit: "16th",
+        stepped: true,
+        target: ParameterTarget::Layers,
+        range: |_| 1.0..=MAX_LAYER_STEPS as f32,
+        get: |m| m.layers.hat_steps as f32,
+        set: |m, v| m.layers.hat_steps = v as u32,
+    },
+    Parameter {
+        name: "Drums",
+        address: "/drums/enabled",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=1.0,
+        get: |m| m.drums.enabled as u8 as f32,
+        set: |m, v| m.drums.enabled = v > 0.0,
+    },
+    Parameter {
+        name: "Kick steps",
+        address: "/drums/kick/steps",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=u16::MAX as f32,
+        get: |m| m.drums.voices[0].steps as f32,
+        set: |m, v| m.drums.voices[0].steps = v as u16,
+    },
+    Parameter {
+        name: "Kick gain",
+        address: "/drums/kick/gain",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=MAX_DRUM_GAIN,
+        get: |m| m.drums.voices[0].gain,
+        set: |m, v| m.drums.voices[0].gain = v,
+    },
+    Parameter {
+        name: "Kick pitch",
+        address: "/drums/kick/pitch",
+        unit: "semitones",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| MIN_DRUM_PITCH..=MAX_DRUM_PITCH,
+        get: |m| m.drums.voices[0].pitch,
+        set: |m, v| m.drums.voices[0].pitch = v,
+    },
+    Parameter {
+        name: "Snare steps",
+        address: "/drums/snare/steps",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=u16::MAX as f32,
+        get: |m| m.drums.voices[1].steps as f32,
+        set: |m, v| m.drums.voices[1].steps = v as u16,
+    },
+    Parameter {
+        name: "Snare gain",
+        address: "/drums/snare/gain",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=MAX_DRUM_GAIN,
+        get: |m| m.drums.voices[1].gain,
+        set: |m, v| m.drums.voices[1].gain = v,
+    },
+    Parameter {
+        name: "Snare pitch",
+        address: "/drums/snare/pitch",
+        unit: "semitones",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| MIN_DRUM_PITCH..=MAX_DRUM_PITCH,
+        get: |m| m.drums.voices[1].pitch,
+        set: |m, v| m.drums.voices[1].pitch = v,
+    },
+    Parameter {
+        name: "Hi-hat steps",
+        address: "/drums/hat/steps",
+        unit: "",
+        stepped: true,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=u16::MAX as f32,
+        get: |m| m.drums.voices[2].steps as f32,
+        set: |m, v| m.drums.voices[2].steps = v as u16,
+    },
+    Parameter {
+        name: "Hi-hat gain",
+        address: "/drums/hat/gain",
+        unit: "",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| 0.0..=MAX_DRUM_GAIN,
+        get: |m| m.drums.voices[2].gain,
+        set: |m, v| m.drums.voices[2].gain = v,
+    },
+    Parameter {
+        name: "Hi-hat pitch",
+        address: "/drums/hat/pitch",
+        unit: "semitones",
+        stepped: false,
+        target: ParameterTarget::Drums,
+        range: |_| MIN_DRUM_PITCH..=MAX_DRUM_PITCH,
+        get: |m| m.drums.voices[2].pitch,
+        set: |m, v| m.drums.voices[2].pitch = v,
+    },
+];
+
+pub fn find_parameter(address: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|p| p.address == address)
+}
+
+// Values of every parameter, cheaper to keep between frames than a clone of the model
+pub fn parameter_values(model: &SequencerModel) -> Vec<f32> {
+    PARAMETERS.iter().map(|p| (p.get)(model)).collect()
+}
+
+// Whether the parameter at the address changed since the snapshot
+pub fn parameter_changed(previous: &[f32], current: &SequencerModel, address: &str) -> bool {
+    PARAMETERS
+        .iter()
+        .zip(previous)
+        .any(|(parameter, previous_value)| {
+            parameter.address == address && *previous_value != (parameter.get)(current)
+        })
+}
+
+// Targets touched by the parameters that changed since the snapshot, without duplicates
+pub fn changed_targets(previous: &[f32], current: &SequencerModel) -> Vec<ParameterTarget> {
+    let mut targets = Vec::new();
+    for (parameter, previous_value) in PARAMETERS.iter().zip(previous) {
+        if *previous_value != (parameter.get)(current) && !targets.contains(&parameter.target) {
+            targets.push(parameter.target);
+        }
+    }
+    targets
+}
@@ -0,0 +1,376 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use pitch_calc::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::assets::{letter_from_name, letter_name, RhythmPattern};
+use crate::library::{add_user_scale, library, reload_library};
+use crate::preset::Preset;
+use crate::registry::ReloadedProducers;
+use crate::rhythm::{is_playable, rhythm_pattern, save_custom_rhythm_patterns};
+use crate::scripts::{producer_name, ScriptKind, ScriptLibrary};
+use crate::SequencerModel;
+
+//constants
+const PATCH_EXTENSION: &str = "sgpatch";
+const PRESET_ENTRY: &str = "preset.json";
+const SCALES_ENTRY: &str = "scales.json";
+const RHYTHMS_ENTRY: &str = "rhythms.json";
+const SCRIPTS_ENTRY_DIR: &str = "scripts";
+const SCRIPT_KINDS: [ScriptKind; 2] = [ScriptKind::Pitch, ScriptKind::Trigger];
+
+pub enum PatchAction {
+    Export,
+    Import,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PatchScale {
+    name: String,
+    notes: Vec<String>,
+}
+
+// A .sgpatch bundle is a zip holding preset.json, scales.json and rhythms.json with the
+// scale and rhythm pattern it uses, and scripts/pitch or scripts/trigger with its scripts
+fn export_patch(
+    path: &Path,
+    model: &SequencerModel,
+    scripts: &ScriptLibrary,
+) -> Result<(), String> {
+    let (scale, rhythm_pattern) = {
+        let library = library();
+        let scale = &library.scales[model.quantizer_scale_index.unwrap()];
+        let scale = PatchScale {
+            name: scale.name.clone(),
+            notes: scale
+                .notes
+                .iter()
+                .map(|n| letter_name(*n).to_string())
+                .collect(),
+        };
+        let rhythm_pattern = rhythm_pattern(
+            &library,
+            &model.custom_rhythm_patterns,
+            model.rhythm_pattern.unwrap(),
+        )
+        .clone();
+        (scale, rhythm_pattern)
+    };
+    let preset = Preset::capture(model);
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    write_json(&mut zip, PRESET_ENTRY, &preset)?;
+    write_json(&mut zip, SCALES_ENTRY, &vec![scale])?;
+    write_json(&mut zip, RHYTHMS_ENTRY, &vec![rhythm_pattern])?;
+    for (kind, producer) in SCRIPT_KINDS
+        .iter()
+        .zip([&preset.pitch_producer, &preset.trigger_producer])
+    {
+        let Some(script) = scripts.script_file(*kind, producer) else {
+            continue;
+        };
+        let contents = fs::read(&script).map_err(|err| err.to_string())?;
+        let name = script.file_name().unwrap_or_default().to_string_lossy();
+        let entry = format!("{}/{}/{}", SCRIPTS_ENTRY_DIR, kind.dir_name(), name);
+        write_entry(&mut zip, &entry, &contents)?;
+    }
+    zip.finish().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+fn write_entry(zip: &mut ZipWriter<File>, name: &str, contents: &[u8]) -> Result<(), String> {
+    zip.start_file(name, SimpleFileOptions::default())
+        .map_err(|err| err.to_string())?;
+    zip.write_all(contents).map_err(|err| err.to_string())
+}
+
+fn write_json<T: Serialize>(
+    zip: &mut ZipWriter<File>,
+    name: &str,
+    value: &T,
+) -> Result<(), String> {
+    let contents = serde_json::to_vec_pretty(value).map_err(|err| err.to_string())?;
+    write_entry(zip, name, &contents)
+}
+
+// None when the bundle has no such entry
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<String>, String> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
+    let mut contents = String::new();
+    entry
+        .read_to_string(&mut contents)
+        .map_err(|err| format!("{}: {}", name, err))?;
+    Ok(Some(contents))
+}
+
+fn read_json<T: DeserializeOwned>(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+) -> Result<Option<T>, String> {
+    match read_entry(archive, name)? {
+        Some(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|err| format!("{}: {}", name, err)),
+        None => Ok(None),
+    }
+}
+
+// "name (2)", "name (3)" and so on, the first one not taken
+fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+// Names of the assets the bundle had to rename, to apply to the preset
+#[derive(Default)]
+struct Renames(Vec<(String, String)>);
+
+impl Renames {
+    fn add(&mut self, from: &str, to: &str, notes: &mut Vec<String>) {
+        notes.push(format!("\"{}\" exists, imported as \"{}\"", from, to));
+        self.0.push((from.to_string(), to.to_string()));
+    }
+
+    fn apply(&self, name: &str) -> String {
+        self.0
+            .iter()
+            .find(|(from, _)| from == name)
+            .map_or(name, |(_, to)| to)
+            .to_string()
+    }
+}
+
+// Scales already present with the same notes are used as they are, others under a new name
+fn install_scales(
+    scales: Vec<PatchScale>,
+    renames: &mut Renames,
+    notes: &mut Vec<String>,
+) -> Result<(), String> {
+    let mut added = false;
+    for scale in scales {
+        let letters: Option<Vec<Letter>> =
+            scale.notes.iter().map(|n| letter_from_name(n)).collect();
+        let Some(letters) = letters.filter(|letters| !letters.is_empty()) else {
+            notes.push(format!(
+                "Skipped scale \"{}\": invalid note names",
+                scale.name
+            ));
+            continue;
+        };
+        let name = {
+            let library = library();
+            match library.scales.iter().find(|s| s.name == scale.name) {
+                Some(local) if local.notes == letters => continue,
+                Some(_) => unique_name(&scale.name, |n| library.scales.iter().any(|s| s.name == n)),
+                None => scale.name.clone(),
+            }
+        };
+        if name != scale.name {
+            renames.add(&scale.name, &name, notes);
+        }
+        add_user_scale(&name, &letters).map_err(|err| err.to_string())?;
+        added = true;
+    }
+    if added {
+        reload_library();
+    }
+    Ok(())
+}
+
+// Rhythm patterns join the custom ones unless an identical one exists
+fn install_rhythm_patterns(
+    patterns: Vec<RhythmPattern>,
+    model: &mut SequencerModel,
+    renames: &mut Renames,
+    notes: &mut Vec<String>,
+) {
+    let mut added = false;
+    for mut pattern in patterns {
+        if !is_playable(&pattern.durations, &pattern.notes_per_beat) {
+            notes.push(format!(
+                "Rhythm \"{}\" has no beats, no sounding step or starts with a tie, skipped",
+                pattern.name
+            ));
+            continue;
+        }
+        let name = {
+            let library = library();
+            let local = library
+                .rhythm_patterns
+                .iter()
+                .map(|p| (&p.name, &p.durations, &p.notes_per_beat))
+                .chain(
+                    model
+                        .custom_rhythm_patterns
+                        .iter()
+                        .map(|p| (&p.name, &p.durations, &p.notes_per_beat)),
+                )
+                .collect::<Vec<_>>();
+            match local.iter().find(|(name, _, _)| **name == pattern.name) {
+                Some((_, durations, notes_per_beat))
+                    if **durations == pattern.durations
+                        && **notes_per_beat == pattern.notes_per_beat =>
+                {
+                    continue
+                }
+                Some(_) => unique_name(&pattern.name, |n| {
+                    local.iter().any(|(name, _, _)| name.as_str() == n)
+                }),
+                None => pattern.name.clone(),
+            }
+        };
+        if name != pattern.name {
+            renames.add(&pattern.name, &name, notes);
+        }
+        pattern.name = name;
+        model.custom_rhythm_patterns.push(pattern);
+        added = true;
+    }
+    if added {
+        save_custom_rhythm_patterns(&model.custom_rhythm_patterns);
+    }
+}
+
+// Writes the scripts into the scripts folder, a different script of the same name is kept
+fn install_scripts(
+    archive: &mut ZipArchive<File>,
+    scripts: &ScriptLibrary,
+    kind: ScriptKind,
+    renames: &mut Renames,
+    notes: &mut Vec<String>,
+) -> Result<(), String> {
+    let entries: Vec<String> = archive.file_names().map(|n| n.to_string()).collect();
+    for entry in entries {
+        // only scripts/<kind>/<file>, whatever else the bundle holds stays out of the folder
+        let parts: Vec<&str> = entry.split('/').collect();
+        let [SCRIPTS_ENTRY_DIR, dir, file] = parts[..] else {
+            continue;
+        };
+        if dir != kind.dir_name() {
+            continue;
+        }
+        let Some(stem) = Path::new(file).file_stem().map(|s| s.to_string_lossy()) else {
+            continue;
+        };
+        if file.contains('\\') || stem.starts_with('.') {
+            continue;
+        }
+        let Some(contents) = read_entry(archive, &entry)? else {
+            continue;
+        };
+        let mut path = scripts.script_path(kind, &stem);
+        match fs::read_to_string(&path) {
+            Ok(local) if local == contents => continue,
+            Ok(_) => {
+                let new_stem = unique_name(&stem, |s| scripts.script_path(kind, s).exists());
+                let renamed = scripts.script_path(kind, &new_stem);
+                renames.add(&producer_name(&path), &producer_name(&renamed), notes);
+                path = renamed;
+            }
+            Err(_) => (),
+        }
+        let dir = path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+// Installs the assets of the bundle, then applies its preset. Returns what to report and
+// the script producers that were reloaded.
+fn import_patch(
+    path: &Path,
+    model: &mut SequencerModel,
+    scripts: &mut ScriptLibrary,
+) -> Result<(Vec<String>, ReloadedProducers), String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|err| err.to_string())?;
+    // older versions are migrated, newer ones refused
+    let mut preset: Preset = read_json(&mut archive, PRESET_ENTRY)?
+        .ok_or(format!("Not a patch, {} is missing", PRESET_ENTRY))?;
+    let mut notes = Vec::new();
+    // names are only unique within a kind of asset
+    let mut scale_renames = Renames::default();
+    let mut rhythm_renames = Renames::default();
+    let mut script_renames = [Renames::default(), Renames::default()];
+    let scales = read_json(&mut archive, SCALES_ENTRY)?.unwrap_or_default();
+    install_scales(scales, &mut scale_renames, &mut notes)?;
+    let rhythm_patterns = read_json(&mut archive, RHYTHMS_ENTRY)?.unwrap_or_default();
+    install_rhythm_patterns(rhythm_patterns, model, &mut rhythm_renames, &mut notes);
+    for (kind, renames) in SCRIPT_KINDS.iter().zip(script_renames.iter_mut()) {
+        install_scripts(&mut archive, scripts, *kind, renames, &mut notes)?;
+    }
+    let reloaded = scripts.reload_all();
+
+    preset.scale = scale_renames.apply(&preset.scale);
+    preset.rhythm_pattern = rhythm_renames.apply(&preset.rhythm_pattern);
+    preset.pitch_producer = script_renames[0].apply(&preset.pitch_producer);
+    preset.trigger_producer = script_renames[1].apply(&preset.trigger_producer);
+    notes.extend(preset.apply(model));
+    Ok((notes, reloaded))
+}
+
+// State of the "Patch" window
+pub struct PatchPanel {
+    pub path: String,
+    pub status: Option<Result<String, String>>,
+}
+
+impl PatchPanel {
+    pub fn new() -> PatchPanel {
+        PatchPanel {
+            path: String::new(),
+            status: None,
+        }
+    }
+
+    // The extension is added when missing
+    fn patch_path(&self) -> PathBuf {
+        let path = PathBuf::from(self.path.trim());
+        match path.extension() {
+            Some(_) => path,
+            None => path.with_extension(PATCH_EXTENSION),
+        }
+    }
+
+    pub fn export(&mut self, model: &SequencerModel, scripts: &ScriptLibrary) {
+        let path = self.patch_path();
+        self.status = Some(
+            export_patch(&path, model, scripts).map(|_| format!("Exported {}", path.display())),
+        );
+    }
+
+    pub fn import(
+        &mut self,
+        model: &mut SequencerModel,
+        scripts: &mut ScriptLibrary,
+    ) -> ReloadedProducers {
+        let path = self.patch_path();
+        match import_patch(&path, model, scripts) {
+            Ok((notes, reloaded)) => {
+                let mut message = format!("Imported {}", path.display());
+                for note in notes {
+                    message.push_str(&format!("\n{}", note));
+                }
+                self.status = Some(Ok(message));
+                reloaded
+            }
+            Err(err) => {
+                self.status = Some(Err(err));
+                ReloadedProducers::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use pitch_calc::*;
+use rand::prelude::*;
+
+use crate::pitch::{fit_to_range, ScaleGrid};
+use crate::seed::{seeded_rng, SeedStream};
+
+// Ways a motif statement can differ from the original motif
+#[derive(Clone, Copy, PartialEq)]
+pub enum Variation {
+    Repeat,
+    Transpose,
+    Inversion,
+    Retrograde,
+    Displacement,
+}
+
+const VARIATIONS: [Variation; 5] = [
+    Variation::Repeat,
+    Variation::Transpose,
+    Variation::Inversion,
+    Variation::Retrograde,
+    Variation::Displacement,
+];
+const MAX_TRANSPOSITION_DEGREES: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct PhraseSettings {
+    pub enabled: bool,
+    // notes in the motif
+    pub motif_length: usize,
+    // motif statements per phrase, the first one being the motif itself
+    pub statements: usize,
+    // take the motifs from the imported melody rather than from the pitch chain
+    pub seed_from_melody: bool,
+}
+
+// Composition layer above the pitch chain: records a short motif from it,
+// then restates it with variations until the phrase ends and a new motif is recorded
+pub struct PhraseGenerator<R: Rng + Send + Sync> {
+    rng: R,
+    grid: ScaleGrid,
+    min: i32,
+    max: i32,
+    motif_length: usize,
+    statements: usize,
+    // motif in scale degrees
+    motif: Vec<i32>,
+    // current statement, None being a displaced (rest) slot
+    statement: Vec<Option<i32>>,
+    statement_index: usize,
+    position: usize,
+    // melody in scale degrees, consecutive motifs are taken from it when not empty
+    seed: Vec<i32>,
+    seed_position: usize,
+}
+
+impl PhraseGenerator<SmallRng> {
+    pub fn new(
+        settings: PhraseSettings,
+        scale: Vec<Letter>,
+        min: LetterOctave,
+        max: LetterOctave,
+        melody: &[u8],
+    ) -> PhraseGenerator<SmallRng> {
+        let grid = ScaleGrid::new(scale);
+        let seed = if settings.seed_from_melody {
+            melody
+                .iter()
+                .map(|note| grid.to_degree(*note as i32))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        PhraseGenerator {
+            rng: SmallRng::from_entropy(),
+            grid,
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+            motif_length: settings.motif_length.max(1),
+            statements: settings.statements.max(1),
+            motif: Vec::new(),
+            statement: Vec::new(),
+            statement_index: 0,
+            position: 0,
+            seed,
+            seed_position: 0,
+        }
+    }
+}
+
+impl<R: Rng + SeedableRng + Send + Sync> PhraseGenerator<R> {
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed, SeedStream::Phrase);
+    }
+
+    // Next note of the phrase, None for a rest; source is the note the pitch chain produced
+    pub fn next_note(&mut self, source: LetterOctave) -> Option<LetterOctave> {
+        if self.motif.len() < self.motif_length && !self.seed.is_empty() {
+            let degree = self.seed[self.seed_position];
+            self.seed_position = (self.seed_position + 1) % self.seed.len();
+            self.motif.push(degree);
+            return Some(self.degree_note(degree));
+        }
+        // The first statement is recorded straight from the pitch chain
+        if self.motif.len() < self.motif_length {
+            self.motif
+                .push(self.grid.to_degree(source.step().round() as i32));
+            return Some(source);
+        }
+
+        if self.position >= self.statement.len() {
+            self.statement_index += 1;
+            if self.statement_index >= self.statements {
+                // start a new phrase with a new motif
+                self.motif.clear();
+                self.statement.clear();
+                self.statement_index = 0;
+                self.position = 0;
+                return self.next_note(source);
+            }
+            let variation = *VARIATIONS.choose(&mut self.rng).unwrap();
+            self.statement = self.vary(variation);
+            self.position = 0;
+        }
+
+        let degree = self.statement[self.position];
+        self.position += 1;
+        degree.map(|degree| self.degree_note(degree))
+    }
+
+    fn degree_note(&self, degree: i32) -> LetterOctave {
+        Step(fit_to_range(self.grid.to_step(degree), self.min, self.max) as f32).to_letter_octave()
+    }
+
+    fn vary(&mut self, variation: Variation) -> Vec<Option<i32>> {
+        let motif = self.motif.iter().copied();
+        match variation {
+            Variation::Repeat => motif.map(Some).collect(),
+            Variation::Transpose => {
+                let mut degrees = 0;
+                while degrees == 0 {
+                    degrees = self
+                        .rng
+                        .gen_range(-MAX_TRANSPOSITION_DEGREES..=MAX_TRANSPOSITION_DEGREES);
+                }
+                motif.map(|d| Some(d + degrees)).collect()
+            }
+            Variation::Inversion => {
+                let axis = self.motif[0];
+                motif.map(|d| Some(2 * axis - d)).collect()
+            }
+            Variation::Retrograde => motif.rev().map(Some).collect(),
+            // the same notes one slot later
+            Variation::Displacement => std::iter::once(None).chain(motif.map(Some)).collect(),
+        }
+    }
+}
@@ -2,41 +2,13 @@ use pitch_calc::*;
 use rand::prelude::*;
 use std::{f32::consts::PI, fmt::Display, str::FromStr};
 
-// producers
-#[derive(PartialEq)]
-pub enum PitchProducerType {
-    Random,
-    RampUp,
-    Square,
-    Sine,
-}
-
-impl Display for PitchProducerType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            PitchProducerType::Random => write!(f, "Random"),
-            PitchProducerType::RampUp => write!(f, "Ramp"),
-            PitchProducerType::Square => write!(f, "Square"),
-            PitchProducerType::Sine => write!(f, "Sine"),
-        }
-    }
-}
-
-impl FromStr for PitchProducerType {
-    type Err = ();
+use crate::chain::ChainParameter;
+use crate::seed::{seeded_rng, SeedStream};
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "Random" => Ok(PitchProducerType::Random),
-            "Ramp" => Ok(PitchProducerType::RampUp),
-            "Square" => Ok(PitchProducerType::Square),
-            "Sine" => Ok(PitchProducerType::Sine),
-            _ => Err(()),
-        }
-    }
-}
+// producers
 pub trait PitchModule: Send + Sync {
     fn tick(&mut self) -> LetterOctave;
+    fn update(&mut self, _parameter: ChainParameter) {}
 }
 
 pub struct RandomPitchProducer<R: Rng + Send + Sync> {
@@ -55,7 +27,7 @@ impl RandomPitchProducer<SmallRng> {
     }
 }
 
-impl<R: Rng + Send + Sync> PitchModule for RandomPitchProducer<R> {
+impl<R: Rng + SeedableRng + Send + Sync> PitchModule for RandomPitchProducer<R> {
     fn tick(&mut self) -> LetterOctave {
         if self.min != self.max {
             let r: f32 = self.rng.gen_range(self.min..self.max);
@@ -64,22 +36,122 @@ impl<R: Rng + Send + Sync> PitchModule for RandomPitchProducer<R> {
             Step(self.min).to_letter_octave()
         }
     }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        if let ChainParameter::Seed(seed) = parameter {
+            self.rng = seeded_rng(seed, SeedStream::RandomPitch);
+        }
+    }
+}
+
+// direction
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    PingPong,
+    Random,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Direction::Forward => write!(f, "Forward"),
+            Direction::Reverse => write!(f, "Reverse"),
+            Direction::PingPong => write!(f, "Ping-pong"),
+            Direction::Random => write!(f, "Random"),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Forward" => Ok(Direction::Forward),
+            "Reverse" => Ok(Direction::Reverse),
+            "Ping-pong" => Ok(Direction::PingPong),
+            "Random" => Ok(Direction::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+// Walks the positions of a cycle in a direction. Ping-pong does not repeat the end positions,
+// random order picks any position each step.
+pub struct CycleCursor {
+    length: usize,
+    direction: Direction,
+    // steps taken since the start of the cycle
+    counter: usize,
+    random_position: usize,
+    rng: SmallRng,
+}
+
+impl CycleCursor {
+    pub fn new(length: usize, direction: Direction) -> CycleCursor {
+        CycleCursor {
+            length: length.max(1),
+            direction,
+            counter: 0,
+            random_position: 0,
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    // From 0 to length - 1
+    pub fn position(&self) -> usize {
+        match self.direction {
+            Direction::Forward => self.counter,
+            Direction::Reverse => self.length - 1 - self.counter,
+            Direction::PingPong if self.counter < self.length => self.counter,
+            Direction::PingPong => self.period() - self.counter,
+            Direction::Random => self.random_position,
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed, SeedStream::Cycle);
+    }
+
+    pub fn advance(&mut self) {
+        self.counter = (self.counter + 1) % self.period();
+        if self.direction == Direction::Random {
+            self.random_position = self.rng.gen_range(0..self.length);
+        }
+    }
+
+    fn period(&self) -> usize {
+        match self.direction {
+            Direction::PingPong if self.length > 1 => 2 * (self.length - 1),
+            _ => self.length,
+        }
+    }
 }
 
 pub struct RampPitchProducer {
     cycle_length: u32,
     min: f32,
     max: f32,
-    counter: u32,
+    cursor: CycleCursor,
+    // steps the cycle starts late by
+    offset: u32,
 }
 
 impl RampPitchProducer {
-    pub fn new(cycle_length: u32, min: LetterOctave, max: LetterOctave) -> RampPitchProducer {
+    pub fn new(
+        cycle_length: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+        direction: Direction,
+    ) -> RampPitchProducer {
         RampPitchProducer {
             cycle_length: cycle_length,
             min: min.step(),
             max: max.step(),
-            counter: 0,
+            cursor: CycleCursor::new(cycle_length as usize, direction),
+            offset: 0,
         }
     }
 }
@@ -91,64 +163,91 @@ impl PitchModule for RampPitchProducer {
         } else {
             0.
         };
-        let step = Step(self.min + slope * self.counter as f32);
+        let position = (self.cursor.position() as u32 + self.offset) % self.cycle_length;
+        let step = Step(self.min + slope * position as f32);
         let pitch = step.to_letter_octave();
-        if self.counter == self.cycle_length - 1 {
-            self.counter = 0;
-        } else {
-            self.counter += 1;
-        }
+        self.cursor.advance();
         pitch
     }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::Rotation(steps) => self.offset = steps,
+            ChainParameter::Seed(seed) => self.cursor.reseed(seed),
+            _ => (),
+        }
+    }
 }
 
 pub struct SquarePitchProducer {
     cycle_length: u32,
     min: f32,
     max: f32,
-    counter: u32,
+    cursor: CycleCursor,
+    // steps the cycle starts late by
+    offset: u32,
 }
 
 impl SquarePitchProducer {
-    pub fn new(cycle_length: u32, min: LetterOctave, max: LetterOctave) -> SquarePitchProducer {
+    pub fn new(
+        cycle_length: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+        direction: Direction,
+    ) -> SquarePitchProducer {
         SquarePitchProducer {
             cycle_length: cycle_length,
             min: min.step(),
             max: max.step(),
-            counter: 0,
+            cursor: CycleCursor::new(cycle_length as usize, direction),
+            offset: 0,
         }
     }
 }
 
 impl PitchModule for SquarePitchProducer {
     fn tick(&mut self) -> LetterOctave {
-        self.counter += 1;
-        let pitch = if self.counter <= self.cycle_length / 2 {
+        let position = (self.cursor.position() as u32 + self.offset) % self.cycle_length + 1;
+        let pitch = if position <= self.cycle_length / 2 {
             Step(self.min).to_letter_octave()
         } else {
-            if self.counter == self.cycle_length {
-                self.counter = 0;
-            }
             Step(self.max).to_letter_octave()
         };
+        self.cursor.advance();
         pitch
     }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::Rotation(steps) => self.offset = steps,
+            ChainParameter::Seed(seed) => self.cursor.reseed(seed),
+            _ => (),
+        }
+    }
 }
 
 pub struct SinePitchProducer {
     cycle_length: u32,
     min: f32,
     max: f32,
-    counter: u32,
+    cursor: CycleCursor,
+    // steps the cycle starts late by
+    offset: u32,
 }
 
 impl SinePitchProducer {
-    pub fn new(cycle_length: u32, min: LetterOctave, max: LetterOctave) -> SinePitchProducer {
+    pub fn new(
+        cycle_length: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+        direction: Direction,
+    ) -> SinePitchProducer {
         SinePitchProducer {
             cycle_length: cycle_length,
             min: min.step(),
             max: max.step(),
-            counter: 0,
+            cursor: CycleCursor::new(cycle_length as usize, direction),
+            offset: 0,
         }
     }
 }
@@ -156,7 +255,8 @@ impl SinePitchProducer {
 impl PitchModule for SinePitchProducer {
     fn tick(&mut self) -> LetterOctave {
         // Calculate the angle in radians
-        let angle = 2.0 * PI * self.counter as f32 / self.cycle_length as f32;
+        let position = (self.cursor.position() as u32 + self.offset) % self.cycle_length;
+        let angle = 2.0 * PI * position as f32 / self.cycle_length as f32;
 
         // Calculate the sine value, map it to [min, max]
         let normalized_sine: f32 = (angle.sin() + 1.0) / 2.0; // Map sin [-1, 1] to [0, 1]
@@ -166,40 +266,838 @@ impl PitchModule for SinePitchProducer {
         let step = Step(pitch).to_letter_octave();
 
         // Update counter
-        self.counter = (self.counter + 1) % self.cycle_length;
+        self.cursor.advance();
+
+        step
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::Rotation(steps) => self.offset = steps,
+            ChainParameter::Seed(seed) => self.cursor.reseed(seed),
+            _ => (),
+        }
+    }
+}
+
+pub struct MelodyPitchProducer {
+    notes: Vec<LetterOctave>,
+    cursor: CycleCursor,
+    offset: usize,
+}
+
+impl MelodyPitchProducer {
+    // The melody must not be empty
+    pub fn new(notes: &[u8], direction: Direction) -> MelodyPitchProducer {
+        MelodyPitchProducer {
+            notes: notes
+                .iter()
+                .map(|note| Step(*note as f32).to_letter_octave())
+                .collect(),
+            cursor: CycleCursor::new(notes.len(), direction),
+            offset: 0,
+        }
+    }
+}
+
+impl PitchModule for MelodyPitchProducer {
+    fn tick(&mut self) -> LetterOctave {
+        self.notes[(self.cursor.position() + self.offset) % self.notes.len()]
+    }
+
+    // Moves on a note per rhythm step, like the degree lane
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::AdvanceStep => self.cursor.advance(),
+            ChainParameter::Rotation(steps) => self.offset = steps as usize,
+            ChainParameter::Seed(seed) => self.cursor.reseed(seed),
+            _ => (),
+        }
+    }
+}
+
+// Scale degrees set per step, 1 for the root, resolved against the scale being played when the
+// step comes so a change of key or scale re-harmonizes the same steps.
+// Degrees past the end of the scale continue in the octave above.
+pub struct DegreeSequencePitchProducer {
+    degrees: Vec<u8>,
+    cursor: CycleCursor,
+    offset: usize,
+    root: i32,
+    // semitones the root was moved by with the key
+    key_offset: i32,
+    // pitch classes from the root up, the root first
+    pitch_classes: Vec<i32>,
+    min: i32,
+    max: i32,
+}
+
+impl DegreeSequencePitchProducer {
+    // The degrees must not be empty, the scale starts on its root
+    pub fn new(
+        degrees: &[u8],
+        scale: &[Letter],
+        min: LetterOctave,
+        max: LetterOctave,
+        direction: Direction,
+    ) -> DegreeSequencePitchProducer {
+        let root = scale.first().map_or(0, |letter| letter_semitone(*letter));
+        let mut producer = DegreeSequencePitchProducer {
+            degrees: degrees.to_vec(),
+            cursor: CycleCursor::new(degrees.len(), direction),
+            offset: 0,
+            root,
+            key_offset: 0,
+            pitch_classes: Vec::new(),
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+        };
+        producer.set_scale(scale.iter().map(|letter| letter_semitone(*letter)));
+        producer
+    }
+
+    fn set_scale(&mut self, pitch_classes: impl Iterator<Item = i32>) {
+        let root = self.root;
+        let mut pitch_classes: Vec<i32> = pitch_classes.map(|pc| pc.rem_euclid(12)).collect();
+        pitch_classes.sort_by_key(|pc| (pc - root).rem_euclid(12));
+        pitch_classes.dedup();
+        if pitch_classes.is_empty() {
+            pitch_classes = (0..12).map(|offset| (root + offset) % 12).collect();
+        }
+        self.pitch_classes = pitch_classes;
+    }
+
+    fn resolve(&self, degree: u8) -> i32 {
+        let index = degree.max(1) as usize - 1;
+        let len = self.pitch_classes.len();
+        let interval = (self.pitch_classes[index % len] - self.root).rem_euclid(12);
+        // the lowest root in range, so degree 1 is the bottom of the pattern
+        let lowest_root = self.min + (self.root - self.min).rem_euclid(12);
+        fit_to_range(
+            lowest_root + interval + (index / len) as i32 * 12,
+            self.min,
+            self.max,
+        )
+    }
+}
+
+impl PitchModule for DegreeSequencePitchProducer {
+    fn tick(&mut self) -> LetterOctave {
+        let degree = self.degrees[(self.cursor.position() + self.offset) % self.degrees.len()];
+        Step(self.resolve(degree) as f32).to_letter_octave()
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::AdvanceStep => self.cursor.advance(),
+            ChainParameter::Seed(seed) => self.cursor.reseed(seed),
+            ChainParameter::Rotation(steps) => self.offset = steps as usize,
+            ChainParameter::KeyOffset(offset) => {
+                self.root = (self.root + offset - self.key_offset).rem_euclid(12);
+                self.key_offset = offset;
+            }
+            // a chord or guide bar keeps the root when it contains it, else starts on its lowest note
+            ChainParameter::Scale(mask) => {
+                let pitch_classes: Vec<i32> = (0..12).filter(|pc| mask & 1 << pc != 0).collect();
+                if !pitch_classes.is_empty() && !pitch_classes.contains(&self.root) {
+                    self.root = pitch_classes[0];
+                }
+                self.set_scale(pitch_classes.into_iter());
+            }
+            _ => (),
+        }
+    }
+}
+
+// Moves a current pitch by a repeating list of intervals in scale steps, folding it back off
+// the edges of the range, so the contour stays recognizable whatever the scale.
+// Moves on a step per rhythm step, like the degree lane.
+pub struct IntervalPitchProducer {
+    intervals: Vec<i32>,
+    position: usize,
+    grid: ScaleGrid,
+    // MIDI step in the scale
+    current: i32,
+    min: i32,
+    max: i32,
+}
+
+impl IntervalPitchProducer {
+    // The intervals must not be empty, the contour starts in the middle of the range
+    pub fn new(
+        intervals: &[i32],
+        scale: Vec<Letter>,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> IntervalPitchProducer {
+        let min = min.step().round() as i32;
+        let max = max.step().round() as i32;
+        let mut producer = IntervalPitchProducer {
+            intervals: intervals.to_vec(),
+            position: 0,
+            grid: ScaleGrid::new(scale),
+            current: (min + max) / 2,
+            min,
+            max,
+        };
+        producer.current = producer.snap(producer.current);
+        producer
+    }
+
+    // Onto the scale at or below the step, or above when that leaves the range
+    fn snap(&self, step: i32) -> i32 {
+        let degree = self.grid.to_degree(step);
+        let snapped = self.grid.to_step(degree);
+        if snapped < self.min {
+            self.grid.to_step(degree + 1).min(self.max)
+        } else {
+            snapped
+        }
+    }
+}
+
+impl PitchModule for IntervalPitchProducer {
+    fn tick(&mut self) -> LetterOctave {
+        Step(self.current as f32).to_letter_octave()
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::AdvanceStep => {
+                let degree = self.grid.to_degree(self.current) + self.intervals[self.position];
+                self.position = (self.position + 1) % self.intervals.len();
+                let step = fold_to_range(self.grid.to_step(degree), self.min, self.max);
+                self.current = self.snap(step);
+            }
+            ChainParameter::Scale(mask) => {
+                self.grid = ScaleGrid::new(scale_from_mask(mask));
+                self.current = self.snap(self.current);
+            }
+            _ => (),
+        }
+    }
+}
+
+// Holds the pitch of its input for a number of rhythm steps, taking a new one at the first
+// tick after them, for stepped plateaus from any producer. Passes everything through below 2.
+pub struct SampleHoldModule {
+    input: Box<dyn PitchModule>,
+    hold_steps: u32,
+    held: Option<LetterOctave>,
+    steps_held: u32,
+}
+
+impl SampleHoldModule {
+    pub fn new(input: Box<dyn PitchModule>, hold_steps: u32) -> SampleHoldModule {
+        SampleHoldModule {
+            input,
+            hold_steps,
+            held: None,
+            steps_held: 0,
+        }
+    }
+}
+
+impl PitchModule for SampleHoldModule {
+    fn tick(&mut self) -> LetterOctave {
+        // the input keeps moving while held
+        let pitch = self.input.tick();
+        if self.hold_steps < 2 {
+            return pitch;
+        }
+        *self.held.get_or_insert(pitch)
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::HoldSteps(steps) => self.hold_steps = steps,
+            ChainParameter::AdvanceStep => {
+                self.steps_held += 1;
+                if self.steps_held >= self.hold_steps {
+                    self.steps_held = 0;
+                    self.held = None;
+                }
+            }
+            _ => (),
+        }
+        self.input.update(parameter);
+    }
+}
+
+// Moves every pitch by a fixed number of semitones
+pub struct TransposeModule {
+    input: Box<dyn PitchModule>,
+    semitones: i32,
+}
+
+impl TransposeModule {
+    pub fn new(input: Box<dyn PitchModule>, semitones: i32) -> TransposeModule {
+        TransposeModule { input, semitones }
+    }
+}
+
+impl PitchModule for TransposeModule {
+    fn tick(&mut self) -> LetterOctave {
+        let step = self.input.tick().step().round() as i32;
+        Step((step + self.semitones) as f32).to_letter_octave()
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        self.input.update(parameter);
+    }
+}
+
+// Moves every pitch by a number of steps of the scale, a diatonic harmony above or below the line
+pub struct HarmonyModule {
+    input: Box<dyn PitchModule>,
+    grid: ScaleGrid,
+    scale_steps: i32,
+}
+
+impl HarmonyModule {
+    pub fn new(input: Box<dyn PitchModule>, scale: Vec<Letter>, scale_steps: i32) -> HarmonyModule {
+        HarmonyModule {
+            input,
+            grid: ScaleGrid::new(scale),
+            scale_steps,
+        }
+    }
+}
+
+impl PitchModule for HarmonyModule {
+    fn tick(&mut self) -> LetterOctave {
+        let note = self.input.tick();
+        if self.scale_steps == 0 {
+            return note;
+        }
+        let degree = self.grid.to_degree(note.step().round() as i32);
+        Step(self.grid.to_step(degree + self.scale_steps) as f32).to_letter_octave()
+    }
 
+    fn update(&mut self, parameter: ChainParameter) {
+        if let ChainParameter::Scale(mask) = parameter {
+            self.grid = ScaleGrid::new(scale_from_mask(mask));
+        }
+        self.input.update(parameter);
+    }
+}
+
+// Voice leading: leaps from one note to the next are brought within an interval, by octaves
+// first and then to the farthest scale degree allowed, and a repeated note moves a degree
+// up or down with the given probability
+pub struct VoiceLeadingModule<R: Rng + Send + Sync> {
+    input: Box<dyn PitchModule>,
+    rng: R,
+    grid: ScaleGrid,
+    // semitones
+    max_interval: i32,
+    repeat_avoidance: f64,
+    previous: Option<i32>,
+}
+
+impl VoiceLeadingModule<SmallRng> {
+    pub fn new(
+        input: Box<dyn PitchModule>,
+        scale: Vec<Letter>,
+        max_interval: i32,
+        repeat_avoidance: f64,
+    ) -> VoiceLeadingModule<SmallRng> {
+        VoiceLeadingModule {
+            input,
+            rng: SmallRng::from_entropy(),
+            grid: ScaleGrid::new(scale),
+            max_interval: max_interval.max(1),
+            repeat_avoidance: repeat_avoidance.clamp(0.0, 1.0),
+            previous: None,
+        }
+    }
+}
+
+impl<R: Rng + Send + Sync> VoiceLeadingModule<R> {
+    // The step within the interval of the previous note, in the scale where one fits
+    fn lead(&mut self, step: i32, previous: i32) -> i32 {
+        let mut step = step;
+        while step - previous > self.max_interval && step - 12 >= previous - self.max_interval {
+            step -= 12;
+        }
+        while previous - step > self.max_interval && step + 12 <= previous + self.max_interval {
+            step += 12;
+        }
+        if step - previous > self.max_interval {
+            step = self
+                .grid
+                .to_step(self.grid.to_degree(previous + self.max_interval));
+        } else if previous - step > self.max_interval {
+            let limit = previous - self.max_interval;
+            let degree = self.grid.to_degree(limit);
+            step = self.grid.to_step(degree);
+            if step < limit {
+                step = self.grid.to_step(degree + 1);
+            }
+        }
+        if step == previous && self.rng.gen_bool(self.repeat_avoidance) {
+            let degree = self.grid.to_degree(step);
+            let directions = if self.rng.gen_bool(0.5) {
+                [1, -1]
+            } else {
+                [-1, 1]
+            };
+            for direction in directions {
+                let moved = self.grid.to_step(degree + direction);
+                if (moved - previous).abs() <= self.max_interval {
+                    return moved;
+                }
+            }
+        }
         step
     }
 }
 
+impl<R: Rng + SeedableRng + Send + Sync> PitchModule for VoiceLeadingModule<R> {
+    fn tick(&mut self) -> LetterOctave {
+        let note = self.input.tick();
+        let step = note.step().round() as i32;
+        let led = match self.previous {
+            Some(previous) => self.lead(step, previous),
+            None => step,
+        };
+        self.previous = Some(led);
+        if led == step {
+            note
+        } else {
+            Step(led as f32).to_letter_octave()
+        }
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::Scale(mask) => self.grid = ScaleGrid::new(scale_from_mask(mask)),
+            ChainParameter::Seed(seed) => self.rng = seeded_rng(seed, SeedStream::VoiceLeading),
+            _ => (),
+        }
+        self.input.update(parameter);
+    }
+}
+
+// Transposes a note one octave up or down with the given probability, staying in range
+pub struct OctaveJumpModule<R: Rng + Send + Sync> {
+    input: Box<dyn PitchModule>,
+    rng: R,
+    probability: f64,
+    min: i32,
+    max: i32,
+}
+
+impl OctaveJumpModule<SmallRng> {
+    pub fn new(
+        input: Box<dyn PitchModule>,
+        probability: f64,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> OctaveJumpModule<SmallRng> {
+        OctaveJumpModule {
+            input,
+            rng: SmallRng::from_entropy(),
+            probability,
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+        }
+    }
+}
+
+impl<R: Rng + SeedableRng + Send + Sync> PitchModule for OctaveJumpModule<R> {
+    fn tick(&mut self) -> LetterOctave {
+        let note = self.input.tick();
+        if !self.rng.gen_bool(self.probability) {
+            return note;
+        }
+
+        let step = note.step().round() as i32;
+        let offsets = if self.rng.gen_bool(0.5) {
+            [12, -12]
+        } else {
+            [-12, 12]
+        };
+        for offset in offsets {
+            let jumped = step + offset;
+            if jumped >= self.min && jumped <= self.max {
+                return Step(jumped as f32).to_letter_octave();
+            }
+        }
+        note
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::OctaveJumpProbability(probability) => self.probability = probability,
+            ChainParameter::Seed(seed) => self.rng = seeded_rng(seed, SeedStream::OctaveJumps),
+            _ => (),
+        }
+        self.input.update(parameter);
+    }
+}
+
 //quantizer
+pub const fn letter_semitone(letter: Letter) -> i32 {
+    match letter {
+        Letter::C => 0,
+        Letter::Csh | Letter::Db => 1,
+        Letter::D => 2,
+        Letter::Dsh | Letter::Eb => 3,
+        Letter::E => 4,
+        Letter::F => 5,
+        Letter::Fsh | Letter::Gb => 6,
+        Letter::G => 7,
+        Letter::Gsh | Letter::Ab => 8,
+        Letter::A => 9,
+        Letter::Ash | Letter::Bb => 10,
+        Letter::B => 11,
+    }
+}
+
 pub struct PitchQuantizer {
     input: Box<dyn PitchModule>,
-    scale: Vec<Letter>,
+    // pitch classes of the scale, 0 = C
+    pitch_classes: Vec<i32>,
 }
 
 impl PitchQuantizer {
     pub fn new(input: Box<dyn PitchModule>, scale: Vec<Letter>) -> PitchQuantizer {
-        PitchQuantizer { input, scale }
+        PitchQuantizer {
+            input,
+            pitch_classes: Self::pitch_classes(&scale),
+        }
+    }
+
+    fn pitch_classes(scale: &[Letter]) -> Vec<i32> {
+        let mut pitch_classes: Vec<i32> = scale.iter().copied().map(letter_semitone).collect();
+        pitch_classes.sort();
+        pitch_classes.dedup();
+        pitch_classes
+    }
+
+    fn in_scale(&self, step: i32) -> bool {
+        self.pitch_classes.contains(&step.rem_euclid(12))
     }
 }
 
 impl PitchModule for PitchQuantizer {
     fn tick(&mut self) -> LetterOctave {
         let unquantized_note = self.input.tick();
-        self.scale.sort();
-        for letter in &self.scale {
-            if *letter == unquantized_note.letter() {
-                return unquantized_note;
-            } else if *letter > unquantized_note.letter() {
-                // quantize up to the next note in scale
-                let quantized_note = LetterOctave(letter.clone(), unquantized_note.octave());
-                return quantized_note;
+        if self.pitch_classes.is_empty() {
+            return unquantized_note;
+        }
+
+        // search outwards from the input for the nearest step in scale,
+        // preferring the upper neighbour on ties; octave boundaries need no special case
+        let step = unquantized_note.step().round() as i32;
+        for distance in 0..12 {
+            if self.in_scale(step + distance) {
+                return Step((step + distance) as f32).to_letter_octave();
+            }
+            if self.in_scale(step - distance) {
+                return Step((step - distance) as f32).to_letter_octave();
+            }
+        }
+        unquantized_note
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        if let ChainParameter::Scale(mask) = parameter {
+            self.pitch_classes = Self::pitch_classes(&scale_from_mask(mask));
+        }
+        self.input.update(parameter);
+    }
+}
+
+pub fn letter_from_semitone(semitone: i32) -> Letter {
+    match semitone.rem_euclid(12) {
+        0 => Letter::C,
+        1 => Letter::Csh,
+        2 => Letter::D,
+        3 => Letter::Dsh,
+        4 => Letter::E,
+        5 => Letter::F,
+        6 => Letter::Fsh,
+        7 => Letter::G,
+        8 => Letter::Gsh,
+        9 => Letter::A,
+        10 => Letter::Ash,
+        _ => Letter::B,
+    }
+}
+
+// Scales are written from C, this moves them to another root
+pub fn transpose_scale(scale: &[Letter], semitones: i32) -> Vec<Letter> {
+    scale
+        .iter()
+        .map(|letter| letter_from_semitone(letter_semitone(*letter) + semitones))
+        .collect()
+}
+
+pub fn transpose_mask(mask: u16, semitones: i32) -> u16 {
+    scale_mask(&transpose_scale(&scale_from_mask(mask), semitones))
+}
+
+// Moves the key by an interval every few bars, counted from bar 1
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyModulation {
+    pub enabled: bool,
+    // semitones
+    pub interval: i32,
+    pub bars: u32,
+}
+
+impl KeyModulation {
+    // Semitones the key moved by at a bar, within an octave
+    pub fn offset_at(&self, bar: u64) -> i32 {
+        if !self.enabled {
+            return 0;
+        }
+        let steps = (bar / self.bars.max(1) as u64 % 12) as i32;
+        (steps * self.interval).rem_euclid(12)
+    }
+}
+
+pub fn scale_mask(scale: &[Letter]) -> u16 {
+    scale
+        .iter()
+        .fold(0, |mask, letter| mask | 1 << letter_semitone(*letter))
+}
+
+pub fn scale_from_mask(mask: u16) -> Vec<Letter> {
+    (0..12)
+        .filter(|pc| mask & 1 << pc != 0)
+        .map(letter_from_semitone)
+        .collect()
+}
+
+// Maps MIDI steps to scale degrees so transformed notes stay in the scale
+pub struct ScaleGrid {
+    pitch_classes: Vec<i32>,
+}
+
+impl ScaleGrid {
+    pub fn new(scale: Vec<Letter>) -> ScaleGrid {
+        let mut pitch_classes: Vec<i32> = scale.into_iter().map(letter_semitone).collect();
+        pitch_classes.sort();
+        pitch_classes.dedup();
+        if pitch_classes.is_empty() {
+            pitch_classes = (0..12).collect();
+        }
+        ScaleGrid { pitch_classes }
+    }
+
+    pub fn to_degree(&self, step: i32) -> i32 {
+        let pitch_class = step.rem_euclid(12);
+        let index = self
+            .pitch_classes
+            .iter()
+            .rposition(|&pc| pc <= pitch_class)
+            .unwrap_or(0);
+        step.div_euclid(12) * self.pitch_classes.len() as i32 + index as i32
+    }
+
+    pub fn to_step(&self, degree: i32) -> i32 {
+        let len = self.pitch_classes.len() as i32;
+        degree.div_euclid(len) * 12 + self.pitch_classes[degree.rem_euclid(len) as usize]
+    }
+}
+
+// Move a step by octaves until it lies in [min, max], which keeps it in the scale
+pub fn fit_to_range(mut step: i32, min: i32, max: i32) -> i32 {
+    if max - min < 12 {
+        return step.clamp(min, max);
+    }
+    while step > max {
+        step -= 12;
+    }
+    while step < min {
+        step += 12;
+    }
+    step
+}
+
+// Reflect a step off the range edges until it lands inside
+pub fn fold_to_range(mut step: i32, min: i32, max: i32) -> i32 {
+    while max > min && (step < min || step > max) {
+        if step > max {
+            step = 2 * max - step;
+        } else {
+            step = 2 * min - step;
+        }
+    }
+    step
+}
+
+// chain
+// The modules between the producer and the sequencer, in the order they can be arranged
+#[derive(Clone, Copy, PartialEq)]
+pub enum PitchStage {
+    SampleHold,
+    Quantizer,
+    OctaveJumps,
+    Transpose,
+    RangeClamp,
+    Harmony,
+    VoiceLeading,
+}
+
+impl Display for PitchStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PitchStage::SampleHold => write!(f, "Sample & hold"),
+            PitchStage::Quantizer => write!(f, "Quantizer"),
+            PitchStage::OctaveJumps => write!(f, "Octave jumps"),
+            PitchStage::Transpose => write!(f, "Transpose"),
+            PitchStage::RangeClamp => write!(f, "Range"),
+            PitchStage::Harmony => write!(f, "Harmony"),
+            PitchStage::VoiceLeading => write!(f, "Voice leading"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct PitchChainStage {
+    pub stage: PitchStage,
+    // bypassed when off
+    pub enabled: bool,
+}
+
+// range
+#[derive(Clone, Copy, PartialEq)]
+pub enum RangeMode {
+    Clamp,
+    Fold,
+    Wrap,
+}
+
+impl Display for RangeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RangeMode::Clamp => write!(f, "Clamp"),
+            RangeMode::Fold => write!(f, "Fold"),
+            RangeMode::Wrap => write!(f, "Wrap"),
+        }
+    }
+}
+
+impl FromStr for RangeMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Clamp" => Ok(RangeMode::Clamp),
+            "Fold" => Ok(RangeMode::Fold),
+            "Wrap" => Ok(RangeMode::Wrap),
+            _ => Err(()),
+        }
+    }
+}
+
+// Keeps the end of the pitch chain inside [min, max]
+pub struct RangeClampModule {
+    input: Box<dyn PitchModule>,
+    mode: RangeMode,
+    min: i32,
+    max: i32,
+}
+
+impl RangeClampModule {
+    pub fn new(
+        input: Box<dyn PitchModule>,
+        mode: RangeMode,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> RangeClampModule {
+        RangeClampModule {
+            input,
+            mode,
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+        }
+    }
+}
+
+impl PitchModule for RangeClampModule {
+    fn tick(&mut self) -> LetterOctave {
+        let note = self.input.tick();
+        let mut step = note.step().round() as i32;
+        if step >= self.min && step <= self.max {
+            return note;
+        }
+
+        match self.mode {
+            RangeMode::Clamp => {}
+            RangeMode::Fold => step = fold_to_range(step, self.min, self.max),
+            RangeMode::Wrap => {
+                // move by whole octaves so quantized notes stay in scale
+                while step > self.max {
+                    step -= 12;
+                }
+                while step < self.min {
+                    step += 12;
+                }
+            }
+        }
+        Step(step.clamp(self.min, self.max) as f32).to_letter_octave()
+    }
+    fn update(&mut self, parameter: ChainParameter) {
+        self.input.update(parameter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::SCALES;
+
+    // Plays the same step on every tick
+    struct FixedPitch(i32);
+
+    impl PitchModule for FixedPitch {
+        fn tick(&mut self) -> LetterOctave {
+            Step(self.0 as f32).to_letter_octave()
+        }
+    }
+
+    // Every built-in scale on every root, the quantized step is in the scale and no step of
+    // the scale is closer to the input
+    #[test]
+    fn quantizer_moves_to_the_nearest_scale_step() {
+        for scale in SCALES {
+            for root in 0..12 {
+                let letters: Vec<Letter> = scale
+                    .notes
+                    .iter()
+                    .map(|letter| letter_from_semitone(letter_semitone(*letter) + root))
+                    .collect();
+                let pitch_classes = PitchQuantizer::pitch_classes(&letters);
+                let in_scale = |step: i32| pitch_classes.contains(&step.rem_euclid(12));
+                for input in 0..=127 {
+                    let mut quantizer =
+                        PitchQuantizer::new(Box::new(FixedPitch(input)), letters.clone());
+                    let output = quantizer.tick().step().round() as i32;
+                    let context = format!("{} on {}, step {}", scale.name, root, input);
+                    assert!(in_scale(output), "{}: {} is off the scale", context, output);
+                    let distance = (output - input).abs();
+                    assert!(distance < 12, "{}: moved to {}", context, output);
+                    assert!(
+                        (input - distance + 1..input + distance).all(|step| !in_scale(step)),
+                        "{}: {} is past a closer step of the scale",
+                        context,
+                        output
+                    );
+                }
             }
         }
+    }
 
-        // handle case when the unquantized note is above the highest note in scale by wrapping around
-        let quantized = LetterOctave(self.scale[0], unquantized_note.octave() + 1);
-        return quantized;
+    // Halfway between two steps of the scale the upper one wins
+    #[test]
+    fn quantizer_breaks_ties_upwards() {
+        let mut quantizer =
+            PitchQuantizer::new(Box::new(FixedPitch(61)), vec![Letter::C, Letter::D]);
+        assert_eq!(quantizer.tick().step().round() as i32, 62);
     }
 }
@@ -0,0 +1,328 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use pitch_calc::*;
+use wasmi::{Config, Engine, Instance, Linker, Module, Store, WasmParams, WasmResults};
+
+use crate::chain::ChainParameter;
+use crate::pitch::PitchModule;
+use crate::registry::{
+    register_pitch_producer, register_trigger_producer, PitchProducerEntry, ProducerSetting,
+    ReloadedProducers, TriggerProducerEntry,
+};
+use crate::storage::config_dir;
+use crate::trigger::{Trigger, TriggerEvent, TriggerModule};
+
+//constants
+const PLUGINS_DIR: &str = "plugins";
+const PLUGIN_EXTENSION: &str = "wasm";
+// instructions a call may run before it traps, so a stuck plugin cannot stall the sequencer thread
+const MAX_PLUGIN_FUEL: u64 = 1_000_000;
+const MAX_PARAMETER_NAME_LENGTH: usize = 64;
+// each parameter takes a few calls on the UI thread when the plugin loads
+const MAX_PLUGIN_PARAMETERS: i32 = 64;
+
+// An entry of the parameter descriptor a plugin exports
+#[derive(Clone)]
+pub struct PluginParameter {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+// A compiled plugin. It exports pitch_tick() -> i32 returning a MIDI note and/or
+// trigger_tick() -> i32 returning non-zero to play. Optional exports:
+// init(low, high, cycle), set_probability(f32), and the descriptor param_count() -> i32,
+// param_name(i32) -> i32 (offset of a NUL-terminated name in "memory"), param_min(i32) -> f32,
+// param_max(i32) -> f32, param_default(i32) -> f32 with set_param(i32, f32) to change them.
+pub struct Plugin {
+    pub name: String,
+    engine: Engine,
+    module: Module,
+    pub pitch: bool,
+    pub trigger: bool,
+    pub parameters: Vec<PluginParameter>,
+    // set from the UI, sent to the instances before their next tick
+    pub values: Arc<Mutex<Vec<f32>>>,
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Plugin, String> {
+        let bytes = fs::read(path).map_err(|err| err.to_string())?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes).map_err(|err| err.to_string())?;
+        let exports = |name: &str| module.exports().any(|export| export.name() == name);
+        let (pitch, trigger) = (exports("pitch_tick"), exports("trigger_tick"));
+        if !pitch && !trigger {
+            return Err("exports neither pitch_tick nor trigger_tick".to_string());
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut plugin = Plugin {
+            name: format!("{} (plugin)", stem),
+            engine,
+            module,
+            pitch,
+            trigger,
+            parameters: Vec::new(),
+            values: Arc::new(Mutex::new(Vec::new())),
+        };
+        plugin.parameters = PluginInstance::new(&plugin)?.parameters()?;
+        *plugin.values.lock().unwrap() = plugin.parameters.iter().map(|p| p.default).collect();
+        Ok(plugin)
+    }
+}
+
+// A running plugin, one per producer so each keeps its own state
+struct PluginInstance {
+    name: String,
+    store: Store<()>,
+    instance: Instance,
+    values: Arc<Mutex<Vec<f32>>>,
+    // values last sent with set_param
+    sent: Vec<f32>,
+    failed: bool,
+}
+
+impl PluginInstance {
+    fn new(plugin: &Plugin) -> Result<PluginInstance, String> {
+        let mut store = Store::new(&plugin.engine, ());
+        store
+            .set_fuel(MAX_PLUGIN_FUEL)
+            .map_err(|err| err.to_string())?;
+        let instance = Linker::<()>::new(&plugin.engine)
+            .instantiate(&mut store, &plugin.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|err| err.to_string())?;
+        Ok(PluginInstance {
+            name: plugin.name.clone(),
+            store,
+            instance,
+            values: plugin.values.clone(),
+            sent: Vec::new(),
+            failed: false,
+        })
+    }
+
+    // None when the export is missing, or once the plugin trapped
+    fn call<P: WasmParams, R: WasmResults>(&mut self, export: &str, params: P) -> Option<R> {
+        if self.failed {
+            return None;
+        }
+        let function = self
+            .instance
+            .get_typed_func::<P, R>(&self.store, export)
+            .ok()?;
+        self.store.set_fuel(MAX_PLUGIN_FUEL).ok()?;
+        match function.call(&mut self.store, params) {
+            Ok(result) => Some(result),
+            Err(err) => {
+                eprintln!("Plugin {} failed in {}: {}", self.name, export, err);
+                self.failed = true;
+                None
+            }
+        }
+    }
+
+    fn parameter_name(&mut self, index: i32) -> String {
+        let Some(offset) = self.call::<i32, i32>("param_name", index) else {
+            return format!("Parameter {}", index + 1);
+        };
+        let memory = self.instance.get_memory(&self.store, "memory");
+        let data = memory.map_or(&[][..], |memory| memory.data(&self.store));
+        let name = data
+            .iter()
+            .skip(offset as usize)
+            .take(MAX_PARAMETER_NAME_LENGTH)
+            .take_while(|byte| **byte != 0)
+            .copied()
+            .collect::<Vec<u8>>();
+        String::from_utf8_lossy(&name).into_owned()
+    }
+
+    fn parameters(&mut self) -> Result<Vec<PluginParameter>, String> {
+        let count = self.call::<(), i32>("param_count", ()).unwrap_or(0);
+        if count > MAX_PLUGIN_PARAMETERS {
+            return Err(format!(
+                "declares {} parameters, at most {} are allowed",
+                count, MAX_PLUGIN_PARAMETERS
+            ));
+        }
+        Ok((0..count)
+            .map(|index| {
+                let min = self.call("param_min", index).unwrap_or(0.0);
+                let max = self.call("param_max", index).unwrap_or(1.0);
+                PluginParameter {
+                    name: self.parameter_name(index),
+                    min,
+                    max,
+                    default: self.call("param_default", index).unwrap_or(min),
+                }
+            })
+            .collect())
+    }
+
+    fn send_parameters(&mut self) {
+        let values = self.values.lock().unwrap().clone();
+        for (index, value) in values.iter().enumerate() {
+            if self.sent.get(index) != Some(value) {
+                self.call::<(i32, f32), ()>("set_param", (index as i32, *value));
+            }
+        }
+        self.sent = values;
+    }
+
+    fn tick(&mut self, export: &str) -> Option<i32> {
+        self.send_parameters();
+        self.call(export, ())
+    }
+}
+
+fn instantiate(plugin: &Plugin) -> Option<PluginInstance> {
+    PluginInstance::new(plugin)
+        .map_err(|err| eprintln!("Plugin {} failed to start: {}", plugin.name, err))
+        .ok()
+}
+
+// Plays the MIDI note returned by pitch_tick(), the lowest note of the range when the plugin fails
+pub struct PluginPitchProducer {
+    instance: Option<PluginInstance>,
+    min: LetterOctave,
+}
+
+impl PluginPitchProducer {
+    fn new(
+        plugin: &Plugin,
+        cycle_length: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> PluginPitchProducer {
+        let mut instance = instantiate(plugin);
+        if let Some(instance) = instance.as_mut() {
+            let range = (min.step() as i32, max.step() as i32, cycle_length as i32);
+            instance.call::<(i32, i32, i32), ()>("init", range);
+        }
+        PluginPitchProducer { instance, min }
+    }
+}
+
+impl PitchModule for PluginPitchProducer {
+    fn tick(&mut self) -> LetterOctave {
+        match self.instance.as_mut().and_then(|i| i.tick("pitch_tick")) {
+            Some(note) => Step(note.clamp(0, 127) as f32).to_letter_octave(),
+            None => self.min,
+        }
+    }
+}
+
+// Fires when trigger_tick() returns non-zero, silent when the plugin fails
+pub struct PluginTriggerProducer {
+    instance: Option<PluginInstance>,
+}
+
+impl PluginTriggerProducer {
+    fn new(plugin: &Plugin, probability: f64) -> PluginTriggerProducer {
+        let mut producer = PluginTriggerProducer {
+            instance: instantiate(plugin),
+        };
+        producer.update(ChainParameter::TriggerProbability(probability));
+        producer
+    }
+}
+
+impl TriggerModule for PluginTriggerProducer {
+    fn tick(&mut self) -> TriggerEvent {
+        match self.instance.as_mut().and_then(|i| i.tick("trigger_tick")) {
+            Some(on) if on != 0 => Trigger::On.into(),
+            _ => TriggerEvent::OFF,
+        }
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        if let (ChainParameter::TriggerProbability(probability), Some(instance)) =
+            (parameter, self.instance.as_mut())
+        {
+            instance.call::<f32, ()>("set_probability", probability as f32);
+        }
+    }
+}
+
+fn register(plugin: &Arc<Plugin>, reloaded: &mut ReloadedProducers) {
+    if plugin.pitch {
+        let plugin_ref = plugin.clone();
+        register_pitch_producer(PitchProducerEntry::new(
+            &plugin.name,
+            &[ProducerSetting::CycleLength],
+            move |config| {
+                Box::new(PluginPitchProducer::new(
+                    &plugin_ref,
+                    config.cycle_length,
+                    config.min_pitch,
+                    config.max_pitch,
+                ))
+            },
+        ));
+        reloaded.pitch_producers.push(plugin.name.clone());
+    }
+    if plugin.trigger {
+        let plugin_ref = plugin.clone();
+        register_trigger_producer(TriggerProducerEntry::new(
+            &plugin.name,
+            &[ProducerSetting::TriggerProbability],
+            move |config| {
+                Box::new(PluginTriggerProducer::new(
+                    &plugin_ref,
+                    config.trigger_probability,
+                ))
+            },
+        ));
+        reloaded.trigger_producers.push(plugin.name.clone());
+    }
+}
+
+// The .wasm plugins of the plugins folder in the config directory, registered as producers.
+// A removed plugin stays registered until restart.
+pub struct PluginLibrary {
+    pub dir: PathBuf,
+    pub plugins: Vec<Arc<Plugin>>,
+    // by file, for the plugins that did not load
+    pub errors: Vec<(String, String)>,
+}
+
+impl PluginLibrary {
+    pub fn new() -> PluginLibrary {
+        PluginLibrary {
+            dir: config_dir().join(PLUGINS_DIR),
+            plugins: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn reload(&mut self) -> ReloadedProducers {
+        let mut reloaded = ReloadedProducers::default();
+        self.plugins.clear();
+        self.errors.clear();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return reloaded;
+        };
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.extension().is_none_or(|e| e != PLUGIN_EXTENSION) {
+                continue;
+            }
+            match Plugin::load(&path) {
+                Ok(plugin) => {
+                    let plugin = Arc::new(plugin);
+                    register(&plugin, &mut reloaded);
+                    self.plugins.push(plugin);
+                }
+                Err(err) => self.errors.push((path.display().to_string(), err)),
+            }
+        }
+        reloaded
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::library::library;
+use crate::migration::{migrate, PRESET_VERSION};
+use crate::params::{find_parameter, PARAMETERS};
+use crate::registry::producer_registry;
+use crate::rhythm::rhythm_pattern;
+use crate::velocity::VelocityCurves;
+use crate::SequencerModel;
+
+//constants
+// parameters holding an index into a list that differs between installs, stored by name
+const NAMED_PARAMETERS: &[&str] = &[
+    "/pitch/scale",
+    "/rhythm/pattern",
+    "/pitch/producer",
+    "/rhythm/trigger",
+];
+
+// The sequencer settings, as saved in patches and in the recovery file.
+// Presets of older versions are migrated as they are read.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+pub struct Preset {
+    pub version: u32,
+    // by parameter address
+    pub parameters: BTreeMap<String, f32>,
+    pub scale: String,
+    pub rhythm_pattern: String,
+    pub pitch_producer: String,
+    pub trigger_producer: String,
+    pub velocity_curves: VelocityCurves,
+}
+
+// The fields of a preset of the current version
+#[derive(Deserialize)]
+struct StoredPreset {
+    version: u32,
+    parameters: BTreeMap<String, f32>,
+    scale: String,
+    rhythm_pattern: String,
+    pitch_producer: String,
+    trigger_producer: String,
+    velocity_curves: VelocityCurves,
+}
+
+impl TryFrom<serde_json::Value> for Preset {
+    type Error = String;
+
+    fn try_from(mut value: serde_json::Value) -> Result<Self, Self::Error> {
+        migrate(&mut value)?;
+        let stored: StoredPreset = serde_json::from_value(value).map_err(|err| err.to_string())?;
+        Ok(Preset {
+            version: stored.version,
+            parameters: stored.parameters,
+            scale: stored.scale,
+            rhythm_pattern: stored.rhythm_pattern,
+            pitch_producer: stored.pitch_producer,
+            trigger_producer: stored.trigger_producer,
+            velocity_curves: stored.velocity_curves,
+        })
+    }
+}
+
+impl Preset {
+    pub fn capture(model: &SequencerModel) -> Preset {
+        let library = library();
+        let registry = producer_registry();
+        let rhythm_pattern = &rhythm_pattern(
+            &library,
+            &model.custom_rhythm_patterns,
+            model.rhythm_pattern.unwrap(),
+        )
+        .name;
+        Preset {
+            version: PRESET_VERSION,
+            parameters: PARAMETERS
+                .iter()
+                .filter(|p| !NAMED_PARAMETERS.contains(&p.address))
+                .map(|p| (p.address.to_string(), (p.get)(model)))
+                .collect(),
+            scale: library.scales[model.quantizer_scale_index.unwrap()]
+                .name
+                .clone(),
+            rhythm_pattern: rhythm_pattern.clone(),
+            pitch_producer: registry.pitch_producers[model.pitch_producer_index.unwrap()]
+                .name
+                .clone(),
+            trigger_producer: registry.trigger_producers[model.trigger_producer_index.unwrap()]
+                .name
+                .clone(),
+            velocity_curves: model.velocity_curves.clone(),
+        }
+    }
+
+    // Sets the model from the preset, returns what could not be applied
+    pub fn apply(&self, model: &mut SequencerModel) -> Vec<String> {
+        let mut notes = Vec::new();
+        // a second pass settles the ranges that depend on other parameters, like min and max pitch
+        for _ in 0..2 {
+            for (address, value) in &self.parameters {
+                if let Some(parameter) = find_parameter(address) {
+                    parameter.set_value(model, *value);
+                }
+            }
+        }
+        let unknown = self
+            .parameters
+            .keys()
+            .filter(|address| find_parameter(address).is_none())
+            .count();
+        if unknown > 0 {
+            notes.push(format!("{} unknown parameters ignored", unknown));
+        }
+
+        model.velocity_curves = self.velocity_curves.clone();
+
+        let library = library();
+        let registry = producer_registry();
+        match library.scales.iter().position(|s| s.name == self.scale) {
+            Some(index) => model.quantizer_scale_index = Some(index),
+            None => notes.push(format!("Scale \"{}\" not found", self.scale)),
+        }
+        let rhythm_index = library
+            .rhythm_patterns
+            .iter()
+            .map(|p| &p.name)
+            .chain(model.custom_rhythm_patterns.iter().map(|p| &p.name))
+            .position(|name| *name == self.rhythm_pattern);
+        match rhythm_index {
+            Some(index) => model.rhythm_pattern = Some(index),
+            None => notes.push(format!("Rhythm \"{}\" not found", self.rhythm_pattern)),
+        }
+        match registry
+            .pitch_producers
+            .iter()
+            .position(|e| e.name == self.pitch_producer)
+        {
+            Some(index) => model.pitch_producer_index = Some(index),
+            None => notes.push(format!(
+                "Pitch producer \"{}\" not found",
+                self.pitch_producer
+            )),
+        }
+        match registry
+            .trigger_producers
+            .iter()
+            .position(|e| e.name == self.trigger_producer)
+        {
+            Some(index) => model.trigger_producer_index = Some(index),
+            None => notes.push(format!(
+                "Trigger producer \"{}\" not found",
+                self.trigger_producer
+            )),
+        }
+        notes
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::drums::DRUM_CHANNEL;
+use crate::layers::LAYER_HATS_CHANNEL;
+use crate::preset::Preset;
+use crate::sequencer::Sequencer;
+use crate::sink::SinkEvent;
+use crate::storage::config_dir;
+use crate::velocity::VelocityCurves;
+use crate::SequencerModel;
+
+//constants
+const PRESETS_DIR_NAME: &str = "presets";
+const PRESET_EXTENSION: &str = "sgpreset";
+// of the parameter shown in the tempo column
+const TEMPO_ADDRESS: &str = "/tempo";
+// ahead of the bincode data, so other files are told apart from presets
+const MAGIC: &[u8; 4] = b"SGPR";
+// bars rendered for the thumbnail
+const THUMBNAIL_BARS: u32 = 2;
+pub const THUMBNAIL_WIDTH: u32 = 96;
+pub const THUMBNAIL_HEIGHT: u32 = 48;
+const BACKGROUND: [u8; 4] = [24, 24, 28, 255];
+// taken in turn by the channels
+const NOTE_COLORS: [[u8; 4]; 4] = [
+    [120, 200, 255, 255],
+    [255, 170, 90, 255],
+    [150, 230, 140, 255],
+    [230, 130, 220, 255],
+];
+
+// A preset as stored in a .sgpreset file: the fields of the preset and a PNG picture of the
+// notes its first bars play
+#[derive(Serialize, Deserialize)]
+struct CompactPreset {
+    version: u32,
+    parameters: BTreeMap<String, f32>,
+    scale: String,
+    rhythm_pattern: String,
+    pitch_producer: String,
+    trigger_producer: String,
+    thumbnail: Vec<u8>,
+}
+
+// The compact preset is followed by the fields added since, in the order they came: the tags
+// and the velocity curves. bincode has no field names, an older file simply ends before them.
+fn write_compact_preset(
+    path: &Path,
+    preset: Preset,
+    thumbnail: Vec<u8>,
+    tags: Vec<String>,
+) -> Result<(), String> {
+    let compact = CompactPreset {
+        version: preset.version,
+        parameters: preset.parameters,
+        scale: preset.scale,
+        rhythm_pattern: preset.rhythm_pattern,
+        pitch_producer: preset.pitch_producer,
+        trigger_producer: preset.trigger_producer,
+        thumbnail,
+    };
+    let mut contents = MAGIC.to_vec();
+    contents.extend(
+        bincode::serialize(&(compact, tags, preset.velocity_curves))
+            .map_err(|err| err.to_string())?,
+    );
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+// Older versions go through the migrations of the JSON presets
+fn read_compact_preset(path: &Path) -> Result<(Preset, Vec<u8>, Vec<String>), String> {
+    let contents = fs::read(path).map_err(|err| err.to_string())?;
+    let data = contents
+        .strip_prefix(MAGIC)
+        .ok_or("Not a compact preset".to_string())?;
+    // the options of bincode::serialize
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut deserializer = bincode::Deserializer::from_slice(data, options);
+    let compact = CompactPreset::deserialize(&mut deserializer).map_err(|err| err.to_string())?;
+    let tags = Vec::<String>::deserialize(&mut deserializer).unwrap_or_default();
+    let velocity_curves = VelocityCurves::deserialize(&mut deserializer).ok();
+    let mut value = serde_json::json!({
+        "version": compact.version,
+        "parameters": compact.parameters,
+        "scale": compact.scale,
+        "rhythm_pattern": compact.rhythm_pattern,
+        "pitch_producer": compact.pitch_producer,
+        "trigger_producer": compact.trigger_producer,
+    });
+    // missing from the files of before the curves, the migration gives them none
+    if let Some(velocity_curves) = velocity_curves {
+        value["velocity_curves"] =
+            serde_json::to_value(velocity_curves).map_err(|err| err.to_string())?;
+    }
+    Ok((Preset::try_from(value)?, compact.thumbnail, tags))
+}
+
+// A piano roll of the pitched notes, time across and pitch up, as PNG bytes
+fn render_thumbnail(events: &[(Duration, SinkEvent)]) -> Result<Vec<u8>, String> {
+    let mut notes = Vec::new();
+    for (index, (start, event)) in events.iter().enumerate() {
+        let SinkEvent::NoteOn { channel, note, .. } = *event else {
+            continue;
+        };
+        if channel == DRUM_CHANNEL || channel == LAYER_HATS_CHANNEL {
+            continue;
+        }
+        let end = events[index + 1..]
+            .iter()
+            .find(|(_, e)| {
+                matches!(*e, SinkEvent::NoteOff { channel: c, note: n, .. } if c == channel && n == note)
+            })
+            .map_or(*start, |(end, _)| *end);
+        notes.push((*start, end, channel, note));
+    }
+    let (width, height) = (THUMBNAIL_WIDTH as usize, THUMBNAIL_HEIGHT as usize);
+    let mut pixels = BACKGROUND.repeat(width * height);
+    let length = notes.iter().map(|(_, end, _, _)| *end).max();
+    let lowest = notes.iter().map(|(_, _, _, note)| *note).min();
+    let highest = notes.iter().map(|(_, _, _, note)| *note).max();
+    if let (Some(length), Some(lowest), Some(highest)) = (length, lowest, highest) {
+        let length = length.as_secs_f32().max(f32::EPSILON);
+        // a note a row high at least, with a row of margin above and below
+        let rows = (highest - lowest) as usize + 3;
+        let row_height = (height / rows).max(1);
+        for (start, end, channel, note) in notes {
+            let x0 = (start.as_secs_f32() / length * width as f32) as usize;
+            let x1 = ((end.as_secs_f32() / length * width as f32) as usize).max(x0 + 1);
+            let row = (highest - note) as usize + 1;
+            let color = NOTE_COLORS[channel as usize % NOTE_COLORS.len()];
+            for y in row * row_height..((row + 1) * row_height).min(height) {
+                for x in x0..x1.min(width) {
+                    let offset = (y * width + x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+// The width, height and RGBA pixels of a thumbnail, None when it can't be read
+pub fn decode_thumbnail(png: &[u8]) -> Option<([usize; 2], Vec<u8>)> {
+    let (info, mut reader) = png::Decoder::new(png).read_info().ok()?;
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        return None;
+    }
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).ok()?;
+    Some(([info.width as usize, info.height as usize], pixels))
+}
+
+pub enum BrowserAction {
+    Save,
+    Load(usize),
+    Rescan,
+}
+
+// Tags are typed separated by commas, blanks are dropped
+fn parse_tags(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// A compact preset found in the presets folder
+pub struct PresetEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub preset: Preset,
+    pub thumbnail: Vec<u8>,
+    pub tags: Vec<String>,
+}
+
+impl PresetEntry {
+    pub fn tempo(&self) -> Option<f32> {
+        self.preset.parameters.get(TEMPO_ADDRESS).copied()
+    }
+
+    // Every word of the search is found in the name, a tag or the scale, whatever the case
+    pub fn matches(&self, search: &str) -> bool {
+        let fields: Vec<String> = [&self.name, &self.preset.scale]
+            .into_iter()
+            .chain(&self.tags)
+            .map(|field| field.to_lowercase())
+            .collect();
+        search
+            .to_lowercase()
+            .split_whitespace()
+            .all(|word| fields.iter().any(|field| field.contains(word)))
+    }
+}
+
+// State of the "Preset browser" window, listing the compact presets of the presets folder
+pub struct PresetBrowser {
+    // of the preset to save
+    pub name: String,
+    // of the preset to save, separated by commas
+    pub tags: String,
+    // narrows the list down to the matching presets
+    pub search: String,
+    pub entries: Vec<PresetEntry>,
+    pub status: Option<Result<String, String>>,
+}
+
+impl PresetBrowser {
+    pub fn new() -> PresetBrowser {
+        let mut browser = PresetBrowser {
+            name: String::new(),
+            tags: String::new(),
+            search: String::new(),
+            entries: Vec::new(),
+            status: None,
+        };
+        browser.rescan();
+        browser
+    }
+
+    fn presets_dir() -> PathBuf {
+        config_dir().join(PRESETS_DIR_NAME)
+    }
+
+    // Files that can't be read are reported and left out
+    pub fn rescan(&mut self) {
+        self.entries.clear();
+        let Ok(dir) = fs::read_dir(Self::presets_dir()) else {
+            return;
+        };
+        let mut unreadable = Vec::new();
+        for path in dir.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some(PRESET_EXTENSION) {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            match read_compact_preset(&path) {
+                Ok((preset, thumbnail, tags)) => self.entries.push(PresetEntry {
+                    name,
+                    path,
+                    preset,
+                    thumbnail,
+                    tags,
+                }),
+                Err(err) => unreadable.push(format!("{}: {}", name, err)),
+            }
+        }
+        self.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        if !unreadable.is_empty() {
+            self.status = Some(Err(unreadable.join("\n")));
+        }
+    }
+
+    // Renders the first bars of the model for the thumbnail, a preset of the same name
+    // is replaced
+    pub fn save(&mut self, model: &SequencerModel) {
+        let name = self.name.trim().to_string();
+        let path = Self::presets_dir().join(format!("{}.{}", name, PRESET_EXTENSION));
+        let events = Sequencer::render(model.clone().into(), THUMBNAIL_BARS);
+        let result = fs::create_dir_all(Self::presets_dir())
+            .map_err(|err| err.to_string())
+            .and_then(|_| render_thumbnail(&events))
+            .and_then(|thumbnail| {
+                write_compact_preset(
+                    &path,
+                    Preset::capture(model),
+                    thumbnail,
+                    parse_tags(&self.tags),
+                )
+            });
+        self.status = Some(result.map(|_| format!("Saved {}", path.display())));
+        self.rescan();
+    }
+
+    // Sets the model from the preset, reporting what could not be applied. The name and the
+    // tags are kept for saving it again
+    pub fn load(&mut self, index: usize, model: &mut SequencerModel) {
+        let entry = &self.entries[index];
+        self.name = entry.name.clone();
+        self.tags = entry.tags.join(", ");
+        let mut message = format!("Loaded {}", entry.name);
+        for note in entry.preset.apply(model) {
+            message.push_str(&format!("\n{}", note));
+        }
+        self.status = Some(Ok(message));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::clock::tick_length;
+use crate::registry::producer_registry;
+use crate::sequencer::{Sequencer, SequencerConfiguration};
+
+//constants
+pub const PROFILE_TICKS_DEFAULT_VALUE: u64 = 1_000_000;
+const NAME_WIDTH: usize = 28;
+
+// Counts every allocation of the program, so a run can tell how many its ticks made
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+struct Measurement {
+    name: String,
+    mean: Duration,
+    // None when the ticks could not be timed one by one
+    worst: Option<Duration>,
+    allocations_per_tick: f64,
+}
+
+impl Measurement {
+    fn print(&self, budget: Duration) {
+        let worst = self
+            .worst
+            .map_or("-".to_string(), |worst| format!("{:.2}", micros(worst)));
+        let over = self.worst.unwrap_or(self.mean) > budget;
+        println!(
+            "{:<width$} {:>10.1} {:>10} {:>12.3}{}",
+            self.name,
+            self.mean.as_secs_f64() * 1e9,
+            worst,
+            self.allocations_per_tick,
+            if over { "  over budget" } else { "" },
+            width = NAME_WIDTH,
+        );
+    }
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+// Runs a tick the given times, at least once, timing each one
+fn measure(name: String, ticks: u64, mut tick: impl FnMut()) -> Measurement {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let ticks = ticks.max(1);
+    let start = Instant::now();
+    let mut worst = Duration::ZERO;
+    for _ in 0..ticks {
+        let tick_start = Instant::now();
+        tick();
+        worst = worst.max(tick_start.elapsed());
+    }
+    let elapsed = start.elapsed();
+    Measurement {
+        name,
+        mean: Duration::from_secs_f64(elapsed.as_secs_f64() / ticks as f64),
+        worst: Some(worst),
+        allocations_per_tick: (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64
+            / ticks as f64,
+    }
+}
+
+// Runs every pitch and trigger producer for the given ticks, then the whole sequencer on a
+// manual clock, and prints what a tick costs next to the time a tick lasts at the top tempo.
+// A producer has to fit there with room to spare, or the notes come late.
+pub fn run(config: SequencerConfiguration, ticks: u64, max_bpm: f32) {
+    let ticks = ticks.max(1);
+    let budget = Duration::from_secs_f64(tick_length(max_bpm, config.resolution));
+    println!(
+        "{} ticks each, budget {:.2} µs per tick at {} BPM and {} PPQN",
+        ticks,
+        micros(budget),
+        max_bpm,
+        config.resolution
+    );
+    println!(
+        "{:<width$} {:>10} {:>10} {:>12}",
+        "",
+        "mean ns",
+        "worst µs",
+        "allocations",
+        width = NAME_WIDTH
+    );
+
+    // built outside the registry lock, a script producer may take it again
+    let (pitch_producers, trigger_producers): (Vec<_>, Vec<_>) = {
+        let registry = producer_registry();
+        (
+            registry
+                .pitch_producers
+                .iter()
+                .map(|entry| (entry.name.clone(), entry.build.clone()))
+                .collect(),
+            registry
+                .trigger_producers
+                .iter()
+                .map(|entry| (entry.name.clone(), entry.build.clone()))
+                .collect(),
+        )
+    };
+    for (name, build) in pitch_producers {
+        let mut producer = build(&config);
+        measure(format!("pitch: {}", name), ticks, || {
+            black_box(producer.tick());
+        })
+        .print(budget);
+    }
+    for (name, build) in trigger_producers {
+        let mut producer = build(&config);
+        measure(format!("trigger: {}", name), ticks, || {
+            black_box(producer.tick());
+        })
+        .print(budget);
+    }
+
+    // the whole chain with the drums, the bass and the sink, at least one bar of it
+    let ticks_per_bar =
+        config.resolution as u64 * config.rhythm_pattern.beats_per_bar().max(1) as u64;
+    let bars = ticks.div_ceil(ticks_per_bar).min(u32::MAX as u64) as u32;
+    let rendered_ticks = bars as u64 * ticks_per_bar;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    black_box(Sequencer::render(config, bars));
+    let elapsed = start.elapsed();
+    Measurement {
+        name: "sequencer".to_string(),
+        mean: Duration::from_secs_f64(elapsed.as_secs_f64() / rendered_ticks as f64),
+        worst: None,
+        allocations_per_tick: (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64
+            / rendered_ticks as f64,
+    }
+    .print(budget);
+}
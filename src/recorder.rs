@@ -0,0 +1,74 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+
+//constants
+// buffers waiting for the writer, the callback drops buffers past this rather than block
+const MAX_PENDING_BUFFERS: usize = 256;
+
+// Writes the interleaved stereo buffers of the audio callback to a WAV file on its own thread
+pub struct Recorder {
+    pub path: PathBuf,
+    started: Instant,
+    writer: thread::JoinHandle<Result<(), String>>,
+}
+
+impl Recorder {
+    // Returns the recorder along with the sender to hand to the audio callback
+    pub fn start(
+        path: PathBuf,
+        sample_rate: u32,
+    ) -> Result<(Recorder, mpsc::SyncSender<Vec<f32>>), String> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).map_err(|err| err.to_string())?;
+        let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(MAX_PENDING_BUFFERS);
+        // runs until the callback drops its sender
+        let writer = thread::spawn(move || {
+            for buffer in receiver {
+                for sample in buffer {
+                    writer
+                        .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                        .map_err(|err| err.to_string())?;
+                }
+            }
+            writer.finalize().map_err(|err| err.to_string())
+        });
+        let recorder = Recorder {
+            path,
+            started: Instant::now(),
+            writer,
+        };
+        Ok((recorder, sender))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // Waits for the writer to finish the file, once the callback let go of the sender
+    pub fn finish(self) -> Result<PathBuf, String> {
+        self.writer
+            .join()
+            .map_err(|_| "The recording writer failed".to_string())??;
+        Ok(self.path)
+    }
+}
+
+// A new file in the working directory for every recording
+pub fn recording_path() -> PathBuf {
+    PathBuf::from(
+        Local::now()
+            .format("recording_%Y%m%d_%H%M%S.wav")
+            .to_string(),
+    )
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, LazyLock, RwLock, RwLockReadGuard};
+
+use crate::pitch::*;
+use crate::sequencer::SequencerConfiguration;
+use crate::trigger::*;
+
+// Parts of the configuration a producer reads besides the pitch range,
+// the UI only shows the settings of the selected producer
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProducerSetting {
+    CycleLength,
+    Melody,
+    DegreeLane,
+    Intervals,
+    // playback direction of a cycle or a sequence
+    Direction,
+    TriggerProbability,
+    // weights of the trigger probability on each beat of the bar
+    BeatWeights,
+}
+
+pub type ProducerBuilder<M> = Arc<dyn Fn(&SequencerConfiguration) -> Box<M> + Send + Sync>;
+
+// A producer as offered in the UI, built by the sequencer at the head of its chain
+pub struct ProducerEntry<M: ?Sized> {
+    pub name: String,
+    pub settings: &'static [ProducerSetting],
+    pub build: ProducerBuilder<M>,
+}
+
+impl<M: ?Sized> ProducerEntry<M> {
+    pub fn uses(&self, setting: ProducerSetting) -> bool {
+        self.settings.contains(&setting)
+    }
+}
+
+pub type PitchProducerEntry = ProducerEntry<dyn PitchModule>;
+pub type TriggerProducerEntry = ProducerEntry<dyn TriggerModule>;
+
+impl PitchProducerEntry {
+    pub fn new(
+        name: &str,
+        settings: &'static [ProducerSetting],
+        build: impl Fn(&SequencerConfiguration) -> Box<dyn PitchModule> + Send + Sync + 'static,
+    ) -> PitchProducerEntry {
+        ProducerEntry {
+            name: name.to_string(),
+            settings,
+            build: Arc::new(build),
+        }
+    }
+}
+
+impl TriggerProducerEntry {
+    pub fn new(
+        name: &str,
+        settings: &'static [ProducerSetting],
+        build: impl Fn(&SequencerConfiguration) -> Box<dyn TriggerModule> + Send + Sync + 'static,
+    ) -> TriggerProducerEntry {
+        ProducerEntry {
+            name: name.to_string(),
+            settings,
+            build: Arc::new(build),
+        }
+    }
+}
+
+// Producers in the order of the combo boxes, the first one is the fallback
+pub struct ProducerRegistry {
+    pub pitch_producers: Vec<PitchProducerEntry>,
+    pub trigger_producers: Vec<TriggerProducerEntry>,
+}
+
+impl ProducerRegistry {
+    fn built_in() -> ProducerRegistry {
+        let mut registry = ProducerRegistry {
+            pitch_producers: Vec::new(),
+            trigger_producers: Vec::new(),
+        };
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Ramp",
+            &[ProducerSetting::CycleLength, ProducerSetting::Direction],
+            |config| {
+                Box::new(RampPitchProducer::new(
+                    config.cycle_length,
+                    config.min_pitch,
+                    config.max_pitch,
+                    config.direction,
+                ))
+            },
+        ));
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Square",
+            &[ProducerSetting::CycleLength, ProducerSetting::Direction],
+            |config| {
+                Box::new(SquarePitchProducer::new(
+                    config.cycle_length,
+                    config.min_pitch,
+                    config.max_pitch,
+                    config.direction,
+                ))
+            },
+        ));
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Sine",
+            &[ProducerSetting::CycleLength, ProducerSetting::Direction],
+            |config| {
+                Box::new(SinePitchProducer::new(
+                    config.cycle_length,
+                    config.min_pitch,
+                    config.max_pitch,
+                    config.direction,
+                ))
+            },
+        ));
+        registry.register_pitch_producer(PitchProducerEntry::new("Random", &[], |config| {
+            Box::new(RandomPitchProducer::new(config.min_pitch, config.max_pitch))
+        }));
+        // an imported melody, looped, random until one is imported
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Melody",
+            &[ProducerSetting::Melody, ProducerSetting::Direction],
+            |config| {
+                if config.melody.is_empty() {
+                    Box::new(RandomPitchProducer::new(config.min_pitch, config.max_pitch))
+                } else {
+                    Box::new(MelodyPitchProducer::new(&config.melody, config.direction))
+                }
+            },
+        ));
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Degree lane",
+            &[ProducerSetting::DegreeLane, ProducerSetting::Direction],
+            |config| {
+                if config.degree_lane.is_empty() {
+                    Box::new(RandomPitchProducer::new(config.min_pitch, config.max_pitch))
+                } else {
+                    Box::new(DegreeSequencePitchProducer::new(
+                        &config.degree_lane,
+                        &config.quantizer_scale,
+                        config.min_pitch,
+                        config.max_pitch,
+                        config.direction,
+                    ))
+                }
+            },
+        ));
+        registry.register_pitch_producer(PitchProducerEntry::new(
+            "Intervals",
+            &[ProducerSetting::Intervals],
+            |config| {
+                if config.intervals.is_empty() {
+                    Box::new(RandomPitchProducer::new(config.min_pitch, config.max_pitch))
+                } else {
+                    Box::new(IntervalPitchProducer::new(
+                        &config.intervals,
+                        config.quantizer_scale.clone(),
+                        config.min_pitch,
+                        config.max_pitch,
+                    ))
+                }
+            },
+        ));
+        registry.register_trigger_producer(TriggerProducerEntry::new(
+            "Random",
+            &[
+                ProducerSetting::TriggerProbability,
+                ProducerSetting::BeatWeights,
+            ],
+            |config| {
+                Box::new(RandomTriggerProducer::new(
+                    config.trigger_probability,
+                    config.beat_weights.clone(),
+                ))
+            },
+        ));
+        registry
+    }
+
+    // A name already registered is replaced in place, keeping the indexes of the UI
+    pub fn register_pitch_producer(&mut self, entry: PitchProducerEntry) {
+        register(&mut self.pitch_producers, entry);
+    }
+
+    pub fn register_trigger_producer(&mut self, entry: TriggerProducerEntry) {
+        register(&mut self.trigger_producers, entry);
+    }
+
+    pub fn pitch_producer(&self, name: &str) -> &PitchProducerEntry {
+        find(&self.pitch_producers, name)
+    }
+
+    pub fn trigger_producer(&self, name: &str) -> &TriggerProducerEntry {
+        find(&self.trigger_producers, name)
+    }
+}
+
+fn register<M: ?Sized>(entries: &mut Vec<ProducerEntry<M>>, entry: ProducerEntry<M>) {
+    match entries.iter_mut().find(|e| e.name == entry.name) {
+        Some(existing) => *existing = entry,
+        None => entries.push(entry),
+    }
+}
+
+// A producer that went away falls back to the first one
+fn find<'a, M: ?Sized>(entries: &'a [ProducerEntry<M>], name: &str) -> &'a ProducerEntry<M> {
+    entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .unwrap_or(&entries[0])
+}
+
+// Producers registered again at runtime, the chains using them have to be rebuilt
+#[derive(Default)]
+pub struct ReloadedProducers {
+    pub pitch_producers: Vec<String>,
+    pub trigger_producers: Vec<String>,
+}
+
+impl ReloadedProducers {
+    pub fn is_empty(&self) -> bool {
+        self.pitch_producers.is_empty() && self.trigger_producers.is_empty()
+    }
+}
+
+static REGISTRY: LazyLock<RwLock<ProducerRegistry>> =
+    LazyLock::new(|| RwLock::new(ProducerRegistry::built_in()));
+
+pub fn producer_registry() -> RwLockReadGuard<'static, ProducerRegistry> {
+    REGISTRY.read().unwrap()
+}
+
+pub fn register_pitch_producer(entry: PitchProducerEntry) {
+    REGISTRY.write().unwrap().register_pitch_producer(entry);
+}
+
+pub fn register_trigger_producer(entry: TriggerProducerEntry) {
+    REGISTRY.write().unwrap().register_trigger_producer(entry);
+}
@@ -0,0 +1,67 @@
+use crate::assets::{NoteDurationLetter, RhythmPattern};
+use crate::library::AssetLibrary;
+use crate::storage;
+
+//constants
+const CUSTOM_RHYTHM_PATTERNS_FILE: &str = "rhythm_patterns.json";
+pub const DEFAULT_CUSTOM_NOTES_PER_BEAT: &[u32] = &[1, 1, 1, 1];
+
+// Pattern being composed in the rhythm editor, not yet saved
+pub struct RhythmEditor {
+    pub name: String,
+    pub durations: Vec<NoteDurationLetter>,
+    pub notes_per_beat: Vec<u32>,
+}
+
+impl RhythmEditor {
+    pub fn new() -> RhythmEditor {
+        RhythmEditor {
+            name: String::new(),
+            durations: Vec::new(),
+            notes_per_beat: DEFAULT_CUSTOM_NOTES_PER_BEAT.to_vec(),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.name.trim().is_empty() && is_playable(&self.durations, &self.notes_per_beat)
+    }
+
+    pub fn to_pattern(&self) -> RhythmPattern {
+        RhythmPattern {
+            name: self.name.trim().to_string(),
+            durations: self.durations.clone(),
+            notes_per_beat: self.notes_per_beat.clone(),
+        }
+    }
+}
+
+// A pattern needs beats, at least one sounding step and can't start with a tie
+pub fn is_playable(durations: &[NoteDurationLetter], notes_per_beat: &[u32]) -> bool {
+    !notes_per_beat.is_empty()
+        && durations.first() != Some(&NoteDurationLetter::Tie)
+        && durations
+            .iter()
+            .any(|d| *d != NoteDurationLetter::Tie && *d != NoteDurationLetter::Rest)
+}
+
+// Rhythm patterns are indexed library patterns first, then the user's custom patterns
+pub fn rhythm_pattern<'a>(
+    library: &'a AssetLibrary,
+    custom: &'a [RhythmPattern],
+    index: usize,
+) -> &'a RhythmPattern {
+    match library.rhythm_patterns.get(index) {
+        Some(pattern) => pattern,
+        None => &custom[index - library.rhythm_patterns.len()],
+    }
+}
+
+pub fn load_custom_rhythm_patterns() -> Vec<RhythmPattern> {
+    storage::load_json(CUSTOM_RHYTHM_PATTERNS_FILE).unwrap_or_default()
+}
+
+pub fn save_custom_rhythm_patterns(patterns: &Vec<RhythmPattern>) {
+    if let Err(err) = storage::save_json(CUSTOM_RHYTHM_PATTERNS_FILE, patterns) {
+        eprintln!("Could not save custom rhythm patterns: {}", err);
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::Display;
+
+//constants
+// C1 to D#1 on the MIDI input hold the rolls, from the slowest up
+const ROLL_KEYS: [u8; 4] = [36, 37, 38, 39];
+
+// How fast a held roll repeats the note
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RollRate {
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+pub const ROLL_RATES: [RollRate; 4] = [
+    RollRate::Quarter,
+    RollRate::Eighth,
+    RollRate::Sixteenth,
+    RollRate::ThirtySecond,
+];
+
+impl Display for RollRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RollRate::Quarter => write!(f, "1/4"),
+            RollRate::Eighth => write!(f, "1/8"),
+            RollRate::Sixteenth => write!(f, "1/16"),
+            RollRate::ThirtySecond => write!(f, "1/32"),
+        }
+    }
+}
+
+impl RollRate {
+    fn per_beat(&self) -> u64 {
+        match *self {
+            RollRate::Quarter => 1,
+            RollRate::Eighth => 2,
+            RollRate::Sixteenth => 4,
+            RollRate::ThirtySecond => 8,
+        }
+    }
+
+    // Ticks between two notes of the roll, a coarse resolution repeats on every tick
+    pub fn interval(&self, ticks_per_beat: u64) -> u64 {
+        (ticks_per_beat / self.per_beat()).max(1)
+    }
+
+    // The fastest roll whose key is held
+    pub fn from_held_keys(held_notes: &[u8]) -> Option<RollRate> {
+        ROLL_KEYS
+            .iter()
+            .zip(ROLL_RATES)
+            .rev()
+            .find(|(key, _)| held_notes.contains(key))
+            .map(|(_, rate)| rate)
+    }
+}
+
+// A roll held down, overriding the triggers of the main voice
+#[derive(Clone, Copy)]
+pub struct Roll {
+    pub rate: RollRate,
+    // MIDI note repeated for as long as the roll is held
+    pub note: u8,
+    // tick the roll started on, the repeats count from it
+    pub start_tick: u64,
+}
+
+impl Roll {
+    pub fn is_due(&self, tick: u64, ticks_per_beat: u64) -> bool {
+        tick.saturating_sub(self.start_tick) % self.rate.interval(ticks_per_beat) == 0
+    }
+}
@@ -0,0 +1,155 @@
+use crate::audio::AudioSink;
+use crate::effects::{TRACK_CHANNELS, TRACK_COUNT};
+use crate::sink::{MidiSink, NoteSink, NullSink};
+use crate::ump::MidiProtocol;
+
+// Where the events of a track go
+#[derive(Clone, PartialEq)]
+pub struct TrackRoute {
+    // None for the first port, the one used without routing
+    pub port: Option<String>,
+    // MIDI channel the track is sent on, 0 to 15
+    pub channel: u8,
+    pub midi_muted: bool,
+    // whether the internal synth plays the track as well
+    pub synth: bool,
+    pub synth_muted: bool,
+}
+
+// Per track output port, channel and destinations, off sends every track to the first port
+// and the internal synth on its own channel
+#[derive(Clone, PartialEq)]
+pub struct RoutingTable {
+    pub enabled: bool,
+    pub routes: [TrackRoute; TRACK_COUNT],
+}
+
+impl RoutingTable {
+    pub fn new() -> RoutingTable {
+        RoutingTable {
+            enabled: false,
+            routes: TRACK_CHANNELS.map(|channel| TrackRoute {
+                port: None,
+                channel,
+                midi_muted: false,
+                synth: true,
+                synth_muted: false,
+            }),
+        }
+    }
+}
+
+pub enum RoutingAction {
+    Changed,
+    RescanPorts,
+}
+
+// Sends each event to the destinations of the track playing on its channel. The synth keeps
+// the channel of the track, its mixer tracks are laid out by channel.
+pub struct RoutingSink {
+    routes: [TrackRoute; TRACK_COUNT],
+    // one per port in use, the first one being the default port
+    ports: Vec<(Option<String>, Box<dyn NoteSink>)>,
+    // index in ports of each track
+    track_ports: [usize; TRACK_COUNT],
+    synth: Option<AudioSink>,
+}
+
+impl RoutingSink {
+    // Connects to the ports of the table, a port that is gone is reported and left silent
+    pub fn new(
+        table: &RoutingTable,
+        synth: Option<AudioSink>,
+        protocol: MidiProtocol,
+    ) -> RoutingSink {
+        let mut ports: Vec<(Option<String>, Box<dyn NoteSink>)> = vec![(
+            None,
+            match MidiSink::connect_first_port(protocol) {
+                Some(sink) => Box::new(sink),
+                None => Box::new(NullSink),
+            },
+        )];
+        let track_ports = std::array::from_fn(|track| {
+            let port = &table.routes[track].port;
+            if let Some(index) = ports.iter().position(|(name, _)| name == port) {
+                return index;
+            }
+            let name = port.clone().unwrap_or_default();
+            let sink: Box<dyn NoteSink> = match MidiSink::connect_port(&name, protocol) {
+                Some(sink) => Box::new(sink),
+                None => {
+                    eprintln!("MIDI output port {} not available, not routing to it", name);
+                    Box::new(NullSink)
+                }
+            };
+            ports.push((port.clone(), sink));
+            ports.len() - 1
+        });
+        RoutingSink {
+            routes: table.routes.clone(),
+            ports,
+            track_ports,
+            synth,
+        }
+    }
+
+    // Calls send with the MIDI and then the synth sink the event goes to, and the channel on
+    // each. Channels no track plays on go to the default port and the synth unchanged.
+    fn route(&mut self, channel: u8, mut send: impl FnMut(&mut dyn NoteSink, u8)) {
+        let Some(track) = TRACK_CHANNELS.iter().position(|c| *c == channel) else {
+            send(self.ports[0].1.as_mut(), channel);
+            if let Some(synth) = self.synth.as_mut() {
+                send(synth, channel);
+            }
+            return;
+        };
+        let route = &self.routes[track];
+        if !route.midi_muted {
+            send(
+                self.ports[self.track_ports[track]].1.as_mut(),
+                route.channel,
+            );
+        }
+        if route.synth && !route.synth_muted {
+            if let Some(synth) = self.synth.as_mut() {
+                send(synth, channel);
+            }
+        }
+    }
+}
+
+impl NoteSink for RoutingSink {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.route(channel, |sink, channel| {
+            sink.send_note_on(channel, note, velocity)
+        });
+    }
+
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.route(channel, |sink, channel| {
+            sink.send_note_off(channel, note, velocity)
+        });
+    }
+
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8) {
+        self.route(channel, |sink, channel| {
+            sink.send_cc(channel, controller, value)
+        });
+    }
+
+    fn send_program(&mut self, channel: u8, program: u8) {
+        self.route(channel, |sink, channel| sink.send_program(channel, program));
+    }
+
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8) {
+        self.route(channel, |sink, channel| {
+            sink.send_channel_pressure(channel, pressure)
+        });
+    }
+
+    fn send_note_pressure(&mut self, channel: u8, note: u8, pressure: u8) {
+        self.route(channel, |sink, channel| {
+            sink.send_note_pressure(channel, note, pressure)
+        });
+    }
+}
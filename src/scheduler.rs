@@ -0,0 +1,226 @@
+use core::time::Duration;
+
+use crate::sink::NoteSink;
+use crate::voices::{StealPolicy, VoiceSettings};
+
+struct ScheduledNoteOff {
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    at: Duration,
+}
+
+// Notes currently sounding and when to release them, so notes can overlap
+// without the sequencer thread sleeping through them
+pub struct NoteOffScheduler {
+    pending: Vec<ScheduledNoteOff>,
+}
+
+impl NoteOffScheduler {
+    pub fn new() -> NoteOffScheduler {
+        NoteOffScheduler {
+            pending: Vec::new(),
+        }
+    }
+
+    // A note already sounding on the same channel is released before being scheduled again
+    pub fn schedule(
+        &mut self,
+        sink: &mut dyn NoteSink,
+        channel: u8,
+        note: u8,
+        velocity: u8,
+        at: Duration,
+    ) {
+        if let Some(index) = self
+            .pending
+            .iter()
+            .position(|n| n.channel == channel && n.note == note)
+        {
+            let retriggered = self.pending.remove(index);
+            sink.send_note_off(retriggered.channel, retriggered.note, retriggered.velocity);
+        }
+        self.pending.push(ScheduledNoteOff {
+            channel,
+            note,
+            velocity,
+            at,
+        });
+    }
+
+    pub fn release_due(&mut self, sink: &mut dyn NoteSink, now: Duration) {
+        self.pending.retain(|n| {
+            if n.at <= now {
+                sink.send_note_off(n.channel, n.note, n.velocity);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // Makes room for a note within the voices, releasing the one the policy steals. Returns
+    // false when the note can't play. A note sounding again takes its own voice back, and
+    // the notes of the unvoiced channels neither count nor get stolen.
+    pub fn allocate(
+        &mut self,
+        sink: &mut dyn NoteSink,
+        channel: u8,
+        note: u8,
+        voices: VoiceSettings,
+        unvoiced: &[u8],
+    ) -> bool {
+        if unvoiced.contains(&channel)
+            || self
+                .pending
+                .iter()
+                .any(|n| n.channel == channel && n.note == note)
+        {
+            return true;
+        }
+        let voiced = |n: &ScheduledNoteOff| !unvoiced.contains(&n.channel);
+        while self.pending.iter().filter(|n| voiced(n)).count() >= voices.max_voices {
+            // the notes are kept in the order they started
+            let stolen = match voices.steal {
+                StealPolicy::Oldest => self.pending.iter().position(voiced),
+                StealPolicy::Quietest => self
+                    .pending
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, n)| voiced(n))
+                    .min_by_key(|(_, n)| n.velocity)
+                    .map(|(index, _)| index),
+                StealPolicy::None => None,
+            };
+            let Some(index) = stolen else {
+                return false;
+            };
+            let stolen = self.pending.remove(index);
+            sink.send_note_off(stolen.channel, stolen.note, stolen.velocity);
+        }
+        true
+    }
+
+    pub fn release_all(&mut self, sink: &mut dyn NoteSink) {
+        for n in self.pending.drain(..) {
+            sink.send_note_off(n.channel, n.note, n.velocity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::{RecordingSink, SinkEvent};
+
+    const CHANNEL: u8 = 0;
+    const DRUM_CHANNEL: u8 = 9;
+
+    fn off(channel: u8, note: u8, velocity: u8) -> SinkEvent {
+        SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        }
+    }
+
+    // Two notes sounding on the pitched channel and one drum, the first note the loudest
+    fn sounding(sink: &mut RecordingSink) -> NoteOffScheduler {
+        let mut scheduler = NoteOffScheduler::new();
+        let at = Duration::from_secs(1);
+        scheduler.schedule(sink, CHANNEL, 60, 100, at);
+        scheduler.schedule(sink, DRUM_CHANNEL, 36, 120, at);
+        scheduler.schedule(sink, CHANNEL, 62, 50, at);
+        scheduler
+    }
+
+    // Allocates a note with every voice taken and returns whether it plays, the events sent
+    // and the notes released at the end
+    fn allocate(
+        channel: u8,
+        note: u8,
+        steal: StealPolicy,
+    ) -> (bool, Vec<SinkEvent>, Vec<SinkEvent>) {
+        let mut sink = RecordingSink::new();
+        let events = sink.events();
+        let mut scheduler = sounding(&mut sink);
+        let voices = VoiceSettings {
+            max_voices: 2,
+            steal,
+        };
+        let allocated = scheduler.allocate(&mut sink, channel, note, voices, &[DRUM_CHANNEL]);
+        let sent = events.lock().unwrap().drain(..).collect();
+        scheduler.release_all(&mut sink);
+        let left = events.lock().unwrap().drain(..).collect();
+        (allocated, sent, left)
+    }
+
+    #[test]
+    fn oldest_policy_steals_the_first_note() {
+        let (allocated, sent, left) = allocate(CHANNEL, 64, StealPolicy::Oldest);
+        assert!(allocated);
+        assert_eq!(sent, vec![off(CHANNEL, 60, 100)]);
+        assert_eq!(left, vec![off(DRUM_CHANNEL, 36, 120), off(CHANNEL, 62, 50)]);
+    }
+
+    #[test]
+    fn quietest_policy_steals_the_softest_note() {
+        let (allocated, sent, left) = allocate(CHANNEL, 64, StealPolicy::Quietest);
+        assert!(allocated);
+        assert_eq!(sent, vec![off(CHANNEL, 62, 50)]);
+        assert_eq!(
+            left,
+            vec![off(CHANNEL, 60, 100), off(DRUM_CHANNEL, 36, 120)]
+        );
+    }
+
+    #[test]
+    fn no_policy_drops_the_new_note() {
+        let (allocated, sent, left) = allocate(CHANNEL, 64, StealPolicy::None);
+        assert!(!allocated);
+        assert!(sent.is_empty());
+        assert_eq!(left.len(), 3);
+    }
+
+    // Struck again, a sounding note takes its own voice back, nothing is stolen
+    #[test]
+    fn restruck_note_keeps_its_voice() {
+        for steal in [
+            StealPolicy::Oldest,
+            StealPolicy::Quietest,
+            StealPolicy::None,
+        ] {
+            let (allocated, sent, left) = allocate(CHANNEL, 62, steal);
+            assert!(allocated, "{:?}", steal);
+            assert!(sent.is_empty(), "{:?}", steal);
+            assert_eq!(left.len(), 3, "{:?}", steal);
+        }
+    }
+
+    // The drum doesn't take a voice: two pitched notes fill the voices, a drum always plays
+    // and is never stolen, even as the oldest or the loudest note
+    #[test]
+    fn unvoiced_channels_neither_count_nor_get_stolen() {
+        for steal in [
+            StealPolicy::Oldest,
+            StealPolicy::Quietest,
+            StealPolicy::None,
+        ] {
+            let (allocated, sent, _) = allocate(DRUM_CHANNEL, 38, steal);
+            assert!(allocated, "{:?}", steal);
+            assert!(sent.is_empty(), "{:?}", steal);
+        }
+        let mut sink = RecordingSink::new();
+        let events = sink.events();
+        let mut scheduler = NoteOffScheduler::new();
+        let at = Duration::from_secs(1);
+        scheduler.schedule(&mut sink, DRUM_CHANNEL, 36, 1, at);
+        scheduler.schedule(&mut sink, CHANNEL, 60, 100, at);
+        let voices = VoiceSettings {
+            max_voices: 1,
+            steal: StealPolicy::Quietest,
+        };
+        assert!(scheduler.allocate(&mut sink, CHANNEL, 62, voices, &[DRUM_CHANNEL]));
+        assert_eq!(*events.lock().unwrap(), vec![off(CHANNEL, 60, 100)]);
+    }
+}
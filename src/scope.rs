@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use nannou::prelude::*;
+
+//constants
+// a power of two, so the write position can wrap around usize
+const TAP_SIZE: usize = 8192;
+const OSCILLOSCOPE_SAMPLES: usize = 1024;
+const FFT_SIZE: usize = 2048;
+const SPECTROGRAM_BANDS: usize = 48;
+const SPECTROGRAM_COLUMNS: usize = 96;
+// levels below are drawn black
+const SPECTROGRAM_FLOOR_DB: f32 = -80.0;
+
+// The latest samples of the audio output, written by the audio callback without locking.
+// A read racing a write can mix two buffers, which a display does not mind.
+#[derive(Clone)]
+pub struct AudioTap {
+    samples: Arc<Vec<AtomicU32>>,
+    // samples written since the start
+    position: Arc<AtomicUsize>,
+}
+
+impl AudioTap {
+    pub fn new() -> AudioTap {
+        AudioTap {
+            samples: Arc::new((0..TAP_SIZE).map(|_| AtomicU32::new(0)).collect()),
+            position: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // From the audio callback, mixed down to mono
+    pub fn write(&self, left: &[f32], right: &[f32]) {
+        let mut position = self.position.load(Ordering::Relaxed);
+        for (l, r) in left.iter().zip(right.iter()) {
+            self.samples[position % TAP_SIZE].store(((l + r) / 2.0).to_bits(), Ordering::Relaxed);
+            position = position.wrapping_add(1);
+        }
+        self.position.store(position, Ordering::Release);
+    }
+
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Acquire)
+    }
+
+    // Oldest first
+    pub fn latest(&self, count: usize) -> Vec<f32> {
+        let count = count.min(TAP_SIZE);
+        let start = self.position().wrapping_sub(count);
+        (0..count)
+            .map(|i| {
+                let sample = &self.samples[start.wrapping_add(i) % TAP_SIZE];
+                f32::from_bits(sample.load(Ordering::Relaxed))
+            })
+            .collect()
+    }
+}
+
+// In place, radix 2, the length a power of two
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let n = real.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_real = real[b] * cos - imaginary[b] * sin;
+                let t_imaginary = real[b] * sin + imaginary[b] * cos;
+                real[b] = real[a] - t_real;
+                imaginary[b] = imaginary[a] - t_imaginary;
+                real[a] += t_real;
+                imaginary[a] += t_imaginary;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+// Levels from 0 to 1 in bands spaced evenly in pitch, lowest first
+fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    // Hann window against the leakage of the cut at both ends
+    let mut real: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()))
+        .collect();
+    let mut imaginary = vec![0.0; n];
+    fft(&mut real, &mut imaginary);
+    let bins = n / 2;
+    (0..SPECTROGRAM_BANDS)
+        .map(|band| {
+            let bin = |band: usize| (bins as f32).powf(band as f32 / SPECTROGRAM_BANDS as f32);
+            let low = bin(band) as usize;
+            let high = (bin(band + 1) as usize).max(low + 1).min(bins);
+            let magnitude = (low..high)
+                .map(|i| (real[i] * real[i] + imaginary[i] * imaginary[i]).sqrt())
+                .fold(0.0, f32::max);
+            // a full scale sine peaks at a quarter of the length through the window
+            let db = 20.0 * (magnitude / (n as f32 / 4.0)).max(1e-9).log10();
+            (1.0 - db / SPECTROGRAM_FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+// Oscilloscope and spectrogram of the internal audio, drawn behind the UI
+pub struct Scope {
+    pub oscilloscope: bool,
+    pub spectrogram: bool,
+    waveform: Vec<f32>,
+    // newest last
+    columns: VecDeque<Vec<f32>>,
+    last_position: usize,
+}
+
+impl Scope {
+    pub fn new() -> Scope {
+        Scope {
+            oscilloscope: false,
+            spectrogram: false,
+            waveform: Vec::new(),
+            columns: VecDeque::new(),
+            last_position: 0,
+        }
+    }
+
+    // None when the internal audio is not playing
+    pub fn update(&mut self, tap: Option<&AudioTap>) {
+        let Some(tap) = tap else {
+            self.waveform.clear();
+            self.columns.clear();
+            return;
+        };
+        if self.oscilloscope {
+            // starts on a rising zero crossing so a steady tone stands still
+            let samples = tap.latest(OSCILLOSCOPE_SAMPLES * 2);
+            let start = (1..OSCILLOSCOPE_SAMPLES)
+                .find(|i| samples[i - 1] <= 0.0 && samples[*i] > 0.0)
+                .unwrap_or(0);
+            self.waveform = samples[start..start + OSCILLOSCOPE_SAMPLES].to_vec();
+        }
+        let position = tap.position();
+        if self.spectrogram && position != self.last_position {
+            if self.columns.len() >= SPECTROGRAM_COLUMNS {
+                self.columns.pop_front();
+            }
+            self.columns.push_back(spectrum(&tap.latest(FFT_SIZE)));
+        }
+        self.last_position = position;
+    }
+
+    pub fn draw(&self, draw: &Draw, rect: Rect) {
+        if self.oscilloscope && !self.waveform.is_empty() {
+            let step = rect.w() / (self.waveform.len() - 1) as f32;
+            let points = self.waveform.iter().enumerate().map(|(i, sample)| {
+                pt2(
+                    rect.left() + i as f32 * step,
+                    rect.y() + sample.clamp(-1.0, 1.0) * rect.h() / 4.0,
+                )
+            });
+            draw.polyline()
+                .weight(1.5)
+                .points(points)
+                .color(rgba(0.4, 1.0, 0.6, 0.8));
+        }
+        if self.spectrogram {
+            // over the bottom third, scrolling to the left
+            let width = rect.w() / SPECTROGRAM_COLUMNS as f32;
+            let height = rect.h() / 3.0 / SPECTROGRAM_BANDS as f32;
+            let first_column = SPECTROGRAM_COLUMNS - self.columns.len();
+            for (column, levels) in self.columns.iter().enumerate() {
+                let x = rect.left() + ((first_column + column) as f32 + 0.5) * width;
+                for (band, level) in levels.iter().enumerate() {
+                    if *level < 0.05 {
+                        continue;
+                    }
+                    draw.rect()
+                        .x_y(x, rect.bottom() + (band as f32 + 0.5) * height)
+                        .w_h(width, height)
+                        .color(hsla(0.7 - level * 0.7, 0.9, level * 0.5, 0.9));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use pitch_calc::*;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+
+use crate::chain::ChainParameter;
+use crate::pitch::PitchModule;
+use crate::registry::{
+    register_pitch_producer, register_trigger_producer, PitchProducerEntry, ProducerSetting,
+    ReloadedProducers, TriggerProducerEntry,
+};
+use crate::storage::config_dir;
+use crate::trigger::{Trigger, TriggerEvent, TriggerModule};
+
+//constants
+const SCRIPTS_DIR: &str = "scripts";
+const SCRIPT_EXTENSION: &str = "rhai";
+const PRODUCER_SUFFIX: &str = " (script)";
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+// a runaway loop ends here rather than stalling the sequencer thread
+const MAX_SCRIPT_OPERATIONS: u64 = 100_000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScriptKind {
+    Pitch,
+    Trigger,
+}
+
+impl ScriptKind {
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            ScriptKind::Pitch => "pitch",
+            ScriptKind::Trigger => "trigger",
+        }
+    }
+}
+
+fn script_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+    engine.register_fn("rand", rand::random::<f64>);
+    engine
+}
+
+// Runs the tick() of a script with `this` bound to a map kept between ticks,
+// holding the inputs of the producer and whatever the script stores there
+struct ScriptRunner {
+    name: String,
+    engine: Engine,
+    ast: Arc<AST>,
+    this: Dynamic,
+    step: i64,
+    failed: bool,
+}
+
+impl ScriptRunner {
+    fn new(name: &str, ast: Arc<AST>, inputs: Map) -> ScriptRunner {
+        ScriptRunner {
+            name: name.to_string(),
+            engine: script_engine(),
+            ast,
+            this: Dynamic::from_map(inputs),
+            step: 0,
+            failed: false,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Dynamic) {
+        if let Some(mut map) = self.this.write_lock::<Map>() {
+            map.insert(key.into(), value);
+        }
+    }
+
+    // None once the script failed, it is not run again until it is reloaded
+    fn tick(&mut self) -> Option<Dynamic> {
+        if self.failed {
+            return None;
+        }
+        self.set("step", Dynamic::from_int(self.step));
+        self.step += 1;
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.this);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            "tick",
+            (),
+        );
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                eprintln!("Script {} failed: {}", self.name, err);
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
+
+// Plays the MIDI note returned by tick(), the lowest note of the range when the script fails
+pub struct ScriptPitchProducer {
+    runner: ScriptRunner,
+    min: LetterOctave,
+}
+
+impl ScriptPitchProducer {
+    fn new(
+        name: &str,
+        ast: Arc<AST>,
+        cycle_length: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> ScriptPitchProducer {
+        let mut inputs = Map::new();
+        inputs.insert("low".into(), Dynamic::from_int(min.step() as i64));
+        inputs.insert("high".into(), Dynamic::from_int(max.step() as i64));
+        inputs.insert("cycle".into(), Dynamic::from_int(cycle_length as i64));
+        ScriptPitchProducer {
+            runner: ScriptRunner::new(name, ast, inputs),
+            min,
+        }
+    }
+}
+
+impl PitchModule for ScriptPitchProducer {
+    fn tick(&mut self) -> LetterOctave {
+        let note = self.runner.tick().and_then(|value| {
+            value
+                .as_int()
+                .map(|note| note as f32)
+                .or_else(|_| value.as_float().map(|note| note as f32))
+                .ok()
+        });
+        match note {
+            Some(note) => Step(note.clamp(0.0, 127.0)).to_letter_octave(),
+            None => self.min,
+        }
+    }
+}
+
+// Fires when tick() returns true, silent when the script fails
+pub struct ScriptTriggerProducer {
+    runner: ScriptRunner,
+}
+
+impl ScriptTriggerProducer {
+    fn new(name: &str, ast: Arc<AST>, probability: f64) -> ScriptTriggerProducer {
+        let mut inputs = Map::new();
+        inputs.insert("probability".into(), Dynamic::from_float(probability));
+        ScriptTriggerProducer {
+            runner: ScriptRunner::new(name, ast, inputs),
+        }
+    }
+}
+
+impl TriggerModule for ScriptTriggerProducer {
+    fn tick(&mut self) -> TriggerEvent {
+        let on = self
+            .runner
+            .tick()
+            .and_then(|value| value.as_bool().ok())
+            .unwrap_or(false);
+        Trigger::from_bool(on).into()
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        if let ChainParameter::TriggerProbability(probability) = parameter {
+            self.runner
+                .set("probability", Dynamic::from_float(probability));
+        }
+    }
+}
+
+pub fn producer_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    format!("{}{}", stem, PRODUCER_SUFFIX)
+}
+
+fn compile(path: &Path) -> Result<Arc<AST>, String> {
+    let ast = script_engine()
+        .compile_file(path.to_path_buf())
+        .map_err(|err| err.to_string())?;
+    if !ast
+        .iter_functions()
+        .any(|function| function.name == "tick" && function.params.is_empty())
+    {
+        return Err("no tick() function".to_string());
+    }
+    Ok(Arc::new(ast))
+}
+
+fn register(kind: ScriptKind, name: &str, ast: Arc<AST>) {
+    let script = name.to_string();
+    match kind {
+        ScriptKind::Pitch => register_pitch_producer(PitchProducerEntry::new(
+            name,
+            &[ProducerSetting::CycleLength],
+            move |config| {
+                Box::new(ScriptPitchProducer::new(
+                    &script,
+                    ast.clone(),
+                    config.cycle_length,
+                    config.min_pitch,
+                    config.max_pitch,
+                ))
+            },
+        )),
+        ScriptKind::Trigger => register_trigger_producer(TriggerProducerEntry::new(
+            name,
+            &[ProducerSetting::TriggerProbability],
+            move |config| {
+                Box::new(ScriptTriggerProducer::new(
+                    &script,
+                    ast.clone(),
+                    config.trigger_probability,
+                ))
+            },
+        )),
+    }
+}
+
+// Watches scripts/pitch and scripts/trigger in the config directory and registers every
+// script defining tick() as a producer. A deleted script stays registered until restart.
+pub struct ScriptLibrary {
+    pub dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_scan: Option<Instant>,
+    // by file, for the scripts that did not compile
+    pub errors: Vec<(String, String)>,
+    pub loaded: usize,
+}
+
+impl ScriptLibrary {
+    pub fn new() -> ScriptLibrary {
+        ScriptLibrary {
+            dir: config_dir().join(SCRIPTS_DIR),
+            modified: HashMap::new(),
+            last_scan: None,
+            errors: Vec::new(),
+            loaded: 0,
+        }
+    }
+
+    pub fn script_path(&self, kind: ScriptKind, stem: &str) -> PathBuf {
+        self.dir
+            .join(kind.dir_name())
+            .join(format!("{}.{}", stem, SCRIPT_EXTENSION))
+    }
+
+    // The file behind a script producer, None for the other producers
+    pub fn script_file(&self, kind: ScriptKind, producer: &str) -> Option<PathBuf> {
+        let stem = producer.strip_suffix(PRODUCER_SUFFIX)?;
+        let path = self.script_path(kind, stem);
+        path.exists().then_some(path)
+    }
+
+    // Compiles the scripts that changed since the last scan, at most once per scan interval
+    pub fn poll(&mut self) -> ReloadedProducers {
+        if self
+            .last_scan
+            .is_some_and(|last_scan| last_scan.elapsed() < SCAN_INTERVAL)
+        {
+            return ReloadedProducers::default();
+        }
+        self.reload()
+    }
+
+    // Compiles every script again
+    pub fn reload_all(&mut self) -> ReloadedProducers {
+        self.modified.clear();
+        self.errors.clear();
+        self.reload()
+    }
+
+    fn reload(&mut self) -> ReloadedProducers {
+        self.last_scan = Some(Instant::now());
+        let mut reloaded = ReloadedProducers::default();
+        for kind in [ScriptKind::Pitch, ScriptKind::Trigger] {
+            let Ok(entries) = fs::read_dir(self.dir.join(kind.dir_name())) else {
+                continue;
+            };
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                if path.extension().is_none_or(|e| e != SCRIPT_EXTENSION) {
+                    continue;
+                }
+                let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                    continue;
+                };
+                if self.modified.get(&path) == Some(&modified) {
+                    continue;
+                }
+                self.modified.insert(path.clone(), modified);
+                let file = path.display().to_string();
+                self.errors.retain(|(f, _)| *f != file);
+                let ast = match compile(&path) {
+                    Ok(ast) => ast,
+                    Err(err) => {
+                        self.errors.push((file, err));
+                        continue;
+                    }
+                };
+                let name = producer_name(&path);
+                register(kind, &name, ast);
+                match kind {
+                    ScriptKind::Pitch => reloaded.pitch_producers.push(name),
+                    ScriptKind::Trigger => reloaded.trigger_producers.push(name),
+                }
+            }
+        }
+        self.loaded = self.modified.len() - self.errors.len();
+        reloaded
+    }
+}
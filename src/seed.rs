@@ -0,0 +1,44 @@
+use rand::prelude::*;
+
+// The random modules seeded together each draw from a stream of their own
+#[derive(Clone, Copy)]
+pub enum SeedStream {
+    RandomPitch = 1,
+    Cycle,
+    VoiceLeading,
+    OctaveJumps,
+    RandomTrigger,
+    RestGate,
+    Ambient,
+    Phrase,
+    Humanize,
+    Sequencer,
+    Evolve,
+}
+
+// Repeatable randomness: the random modules start every bar over from a seed made of the
+// master seed and the bar, so a looped bar plays the same notes on each pass
+#[derive(Clone, Copy, PartialEq)]
+pub struct SeedSettings {
+    pub per_bar: bool,
+    pub master: u32,
+}
+
+impl SeedSettings {
+    // None while the modules draw from the entropy they started with
+    pub fn bar_seed(&self, bar: u64) -> Option<u64> {
+        self.per_bar.then(|| mix(mix(self.master as u64) ^ bar))
+    }
+}
+
+pub fn seeded_rng<R: SeedableRng>(seed: u64, stream: SeedStream) -> R {
+    R::seed_from_u64(mix(seed.wrapping_add(stream as u64)))
+}
+
+// SplitMix64 finalizer, close seeds give unrelated numbers
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
@@ -1,79 +1,459 @@
-use std::{sync::mpsc, thread::sleep};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex, RwLock},
+};
 
 use chrono::Duration;
 use pitch_calc::*;
+use rand::prelude::*;
 use timer::Timer;
 
-use midir::MidiOutputConnection;
-
-use crate::assets::{NoteDurationLetter, NOTE_DURATION};
+use crate::accent::DownbeatAccent;
+use crate::ambient::*;
+use crate::assets::{GrooveTemplate, NoteDurationLetter, RhythmPattern};
+use crate::audio::AudioSink;
+use crate::bass::{BassSettings, BASS_CHANNEL};
+use crate::cadence::CadenceSettings;
+use crate::call_response::*;
+use crate::chain::ChainParameter;
+use crate::clock::*;
+use crate::collision::{CollisionAvoidance, TickNotes};
+use crate::density::{DensityCompensation, DensityEstimator};
+use crate::drums::{DrumMachine, DrumSettings, DRUM_CHANNEL};
+use crate::effects::TRACK_CHANNELS;
+use crate::envelope::PressureEnvelope;
+use crate::fade::{Fade, FadeSettings, TransportPhase};
+use crate::layers::{DerivedLayers, LayerSettings, LAYER_BASS_CHANNEL, LAYER_HATS_CHANNEL};
+use crate::manual::ManualTrigger;
+use crate::note_event::*;
+use crate::phrase::*;
 use crate::pitch::*;
+use crate::registry::producer_registry;
+use crate::roll::{Roll, RollRate};
+use crate::routing::{RoutingSink, RoutingTable};
+use crate::scheduler::NoteOffScheduler;
+use crate::seed::{seeded_rng, SeedSettings, SeedStream};
+use crate::sink::*;
+use crate::slew::{GlideChanges, ParameterGlide, SlewedParameter};
+use crate::split::{KeyboardSplit, SPLIT_CHANNEL};
+use crate::statistics::{NoteStatistics, StatisticsSummary};
+use crate::sustain::SustainAutomation;
+use crate::tension::*;
+use crate::timbre::{TimbreDrift, BANK_SELECT_LSB, BANK_SELECT_MSB, GM_BANK};
+use crate::transport::{Transport, TransportPosition};
 use crate::trigger::*;
+use crate::ump::MidiProtocol;
+use crate::variations::{ActiveVariations, PatternVariations};
+use crate::velocity::VelocityCurves;
+use crate::voices::VoiceSettings;
 
 //constants
-const NOTE_ON_MSG: u8 = 0x90;
-const NOTE_OFF_MSG: u8 = 0x80;
-const PROGRAM_CHANGE_MSG: u8 = 0xC0;
+const MIDI_CHANNEL: u8 = 0;
+const RESPONSE_CHANNEL: u8 = 1;
 const VELOCITY: u8 = 0x64;
-const BPM: f32 = 60.0;
-const TICKS_PER_QUARTER_NOTE: u32 = 40;
+const VELOCITY_JITTER_RANGE: f32 = 32.0;
+const SUSTAIN_CONTROLLER: u8 = 64;
+const ALL_NOTES_OFF_CONTROLLER: u8 = 123;
+const PEDAL_DOWN: u8 = 127;
+const PEDAL_UP: u8 = 0;
+const DRUM_NOTE_LENGTH: core::time::Duration = core::time::Duration::from_millis(100);
 const CLOCK_DIVIDER_MAX: u32 = 32;
 const CLOCK_DIVIDER_MIN: u32 = 1;
-const SCHEDULE_REPEATING_DURATION: i64 = (60_000.0 / BPM / TICKS_PER_QUARTER_NOTE as f32) as i64;
+// drift from the network sync leader allowed before jumping to its position
+const SYNC_TOLERANCE_BEATS: f64 = 0.05;
+// longest wait for the notes to be released when the sequencer goes away
+const SHUTDOWN_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(500);
+// the timer wakes up this often and runs the ticks due by then
+const TIMER_INTERVAL_MS: i64 = 1;
+// a tick later than this was held up by a stall, the ticks missed are skipped
+const MAX_TICK_LATENESS: core::time::Duration = core::time::Duration::from_millis(500);
+// the auditioned notes are short, a drag plays many of them
+const PREVIEW_NOTE_LENGTH: core::time::Duration = core::time::Duration::from_millis(150);
+// short hits, outside the voice allocation
+const UNVOICED_CHANNELS: [u8; 2] = [DRUM_CHANNEL, LAYER_HATS_CHANNEL];
+
+// A bar of an imported guide: the pitch classes to quantize to and the root of its chord
+#[derive(Clone, Copy)]
+pub struct GuideBar {
+    pub mask: u16,
+    pub root: usize,
+}
 
+#[derive(Clone)]
 pub struct SequencerConfiguration {
     pub min_pitch: LetterOctave,
     pub max_pitch: LetterOctave,
-    pub pitch_producer_type: PitchProducerType,
+    // names in the producer registry
+    pub pitch_producer: String,
+    pub trigger_producer: String,
     pub cycle_length: u32,
-    pub rhythm_pattern: Vec<NoteDurationLetter>,
-    pub notes_per_beat: [u32; 4],
+    pub rhythm_pattern: RhythmPattern,
+    // steps the rhythm pattern and its beats start late by
+    pub rhythm_rotation: u32,
+    // steps the cycle of the pitch producer starts late by
+    pub pitch_rotation: u32,
     pub instrument: u8,
+    pub timbre_drift: TimbreDrift,
     pub quantizer_scale: Vec<Letter>,
+    // pitch class of the root of the scale or of the chord followed, 0 being C
+    pub root: usize,
+    pub range_mode: RangeMode,
+    pub key_modulation: KeyModulation,
+    // playback direction of the cyclic and sequence pitch producers
+    pub direction: Direction,
+    pub octave_jump_probability: f64,
+    // rhythm steps a pitch is held for, 1 for none
+    pub sample_hold_steps: u32,
+    // modules the producer output goes through, in order
+    pub pitch_chain: Vec<PitchChainStage>,
+    pub transpose: i32,
+    // scale steps the harmony stage moves the line by
+    pub harmony_steps: i32,
+    // semitones the voice leading stage allows between consecutive notes
+    pub max_interval: i32,
+    // chance of the voice leading stage moving a repeated note
+    pub repeat_avoidance: f64,
+    pub rest_probability: f64,
+    pub trigger_probability: f64,
+    // weights of the trigger probability on each beat of the bar, for the random producer
+    pub beat_weights: Vec<f32>,
+    // modules wrapped around the trigger producer, in order
+    pub trigger_chain: Vec<TriggerStage>,
+    pub clock_division: u32,
+    pub swing: f32,
+    // notes played per trigger by the ratchet
+    pub ratchet_count: u32,
+    pub logic_operation: LogicOperation,
+    // sixteenths of the bar combined by the logic stage, bit n for sixteenth n
+    pub logic_steps: u16,
+    pub velocity_jitter: f32,
+    pub accent: DownbeatAccent,
+    pub density_compensation: DensityCompensation,
+    pub fade: FadeSettings,
+    // share of its rhythm step a note sounds for, above 1 the notes overlap
+    pub note_length: f32,
+    // how fast the main voice runs against the tempo, 1 for the tempo itself
+    pub speed: f32,
+    pub pressure_envelope: PressureEnvelope,
+    pub sustain: SustainAutomation,
+    pub split: KeyboardSplit,
+    // echo, strum, harmony and humanize between the steps and the sink
+    pub note_processors: NoteProcessorSettings,
+    // every pitched note is moved onto the scale before it is played
+    pub scale_lock: bool,
+    pub bass: BassSettings,
+    pub collision_avoidance: CollisionAvoidance,
+    pub cadence: CadenceSettings,
+    pub drums: DrumSettings,
+    pub variations: PatternVariations,
+    pub phrase: PhraseSettings,
+    pub seed: SeedSettings,
+    pub routing: RoutingTable,
+    pub midi_protocol: MidiProtocol,
+    pub velocity_curves: VelocityCurves,
+    pub layers: LayerSettings,
+    pub voices: VoiceSettings,
+    // imported melody, played by the melody producer and seeding the phrase motifs
+    pub melody: Vec<u8>,
+    // scale degrees played by the degree lane producer, 1 for the root
+    pub degree_lane: Vec<u8>,
+    // scale steps the interval producer moves by, in turn
+    pub intervals: Vec<i32>,
+    pub tension: TensionSettings,
+    pub call_response: CallResponseSettings,
+    pub ambient: AmbientSettings,
+    pub groove: &'static GrooveTemplate,
     pub bpm: f32, // beats per minutes
+    // ticks per quarter note
+    pub resolution: u32,
+    // beats taken to glide to a new pitch range, tempo or density
+    pub slew_beats: f32,
 }
 
 enum SequencerCommand {
     Start,
     Stop,
-    SetPitchProducer(Box<dyn PitchModule>),
-    SetTriggerProducer(Box<dyn TriggerModule>),
+    // rebuild the pitch and/or trigger chain, gliding the slewed parameters to the new values
+    GlideTo {
+        config: Box<SequencerConfiguration>,
+        pitch_chain: bool,
+        trigger_chain: bool,
+    },
+    // glide a parameter applied in place on the running chains
+    SetSlewed {
+        parameter: SlewedParameter,
+        value: f32,
+        slew_beats: f32,
+    },
+    UpdateChain(ChainParameter),
+    SetPhraseGenerator(Option<PhraseGenerator<SmallRng>>),
+    // takes effect at the next bar line
+    SetSeed(SeedSettings),
+    SetTensionModulator(Option<TensionModulator>),
+    SetCallResponse(Option<CallResponse>),
+    SetAmbientEngine(Option<AmbientEngine<SmallRng>>),
     SetInstrument(u8),
+    SetTimbreDrift(TimbreDrift),
     SetRhythmPattern(Vec<NoteDurationLetter>),
-    SetTempo(f32),
+    SetRotation {
+        rhythm: u32,
+        pitch: u32,
+    },
+    SetGroove(&'static GrooveTemplate),
+    SetVelocityJitter(f32),
+    SetAccent(DownbeatAccent),
+    SetDensityCompensation(DensityCompensation),
+    SetFade(FadeSettings),
+    SetNoteLength(f32),
+    // takes effect at the next bar line, where the main voice starts its next step
+    SetSpeed(f32),
+    SetPressureEnvelope(PressureEnvelope),
+    SetSustain(SustainAutomation),
+    SetSplit(KeyboardSplit),
+    SetNoteProcessors(NoteProcessorSettings),
+    SetScaleLock(bool),
+    SetCollisionAvoidance(CollisionAvoidance),
+    SetCadence(CadenceSettings),
+    SetBass(BassSettings),
+    // takes effect at the next bar line
+    SetLayers(LayerSettings),
+    // the followed chord moved the root without a new configuration
+    SetRoot(usize),
+    SetKeyModulation(KeyModulation),
+    // takes effect at the next bar line
+    SetDrums(DrumSettings),
+    // takes effect at the next bar line, so are the variation switches
+    SetVariations(Box<PatternVariations>),
+    // the internal audio started after the sequencer
+    SetAudioSink(AudioSink),
+    SetRouting(Box<RoutingTable>),
+    SetMidiProtocol(MidiProtocol),
+    SetVoices(VoiceSettings),
+    SetVelocityCurves(VelocityCurves),
+    // each bar of an imported guide, restarting from its first bar
+    SetGuide(Option<Vec<GuideBar>>),
+    // beat the network sync leader is at
+    SyncTo(f64),
+    // ticks per quarter note, the position in the bar is kept
+    SetResolution(u32),
+    // bars after which the transport goes back to bar 1, None to play on
+    SetLoopBars(Option<u32>),
+    // back to bar 1, restarting the rhythm and the drums
+    Rewind,
+    // the roll overrides the triggers of the main voice until released
+    PressRoll(RollRate),
+    ReleaseRoll,
+    // the presses of the player trigger the main voice instead of the trigger producer
+    SetManualTrigger(bool),
+    // a short note on the main voice, playing or not, to hear a pitch being picked
+    PreviewNote(u8),
+    // hold the changes sent with AtNextBar until the next bar line
+    SetQuantizeChanges(bool),
+    // applied at the next bar line while quantizing changes, right away otherwise
+    AtNextBar(Box<SequencerCommand>),
+    // every command sent until holding stops is sent with AtNextBar, so they land together
+    HoldChanges(bool),
+    // stop and release every note for good, answering once done
+    Shutdown(mpsc::Sender<()>),
+}
+
+impl SequencerCommand {
+    // Built from a whole configuration, so it carries the changes held for the next bar too
+    fn rebuilds_chains(&self) -> bool {
+        matches!(
+            self,
+            SequencerCommand::GlideTo { .. }
+                | SequencerCommand::SetPhraseGenerator(_)
+                | SequencerCommand::SetTensionModulator(_)
+                | SequencerCommand::SetCallResponse(_)
+                | SequencerCommand::SetAmbientEngine(_)
+        )
+    }
 }
 
 pub struct Sequencer {
     sender: mpsc::Sender<SequencerCommand>,
     _timer: Timer,
+    // set by the sequencer thread when it panicked, the thread does nothing afterwards
+    failure: Arc<Mutex<Option<String>>>,
+    shared: SharedState,
+}
+
+// Releases the notes still sounding before the timer, and the thread with it, goes away
+impl Drop for Sequencer {
+    fn drop(&mut self) {
+        // a failed thread released them when it panicked
+        if self.failure().is_some() {
+            return;
+        }
+        let (done, finished) = mpsc::channel();
+        if self.sender.send(SequencerCommand::Shutdown(done)).is_ok() {
+            let _ = finished.recv_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+// A note the main voice started
+#[derive(Clone, Copy, PartialEq)]
+pub struct PlayedNote {
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+// What the engine is doing, published on every timer wake-up for the UI and the other readers
+#[derive(Clone, Copy, Default)]
+pub struct SequencerState {
+    pub transport: TransportPosition,
+    pub is_playing: bool,
+    // fading in or out on the way
+    pub phase: TransportPhase,
+    pub last_note: Option<PlayedNote>,
+    // the ticks run at, glides, tempo following and the network sync included
+    pub tempo: f32,
+    // the program of the main voice, the timbre drift included
+    pub instrument: u8,
+}
+
+// Written by the sequencer thread, read by the UI
+#[derive(Clone, Default)]
+struct SharedState {
+    // bar of the guide being played
+    guide_bar: Arc<Mutex<Option<usize>>>,
+    statistics: Arc<Mutex<NoteStatistics>>,
+    // transport, playing flag, last note and tempo, for the transport bar and the network sync
+    state: Arc<RwLock<SequencerState>>,
+    // what was sent to the MIDI output, for the visuals
+    note_ons: Arc<Mutex<VecDeque<SinkEvent>>>,
+    // variation each track plays in the current bar
+    variations: Arc<Mutex<ActiveVariations>>,
+    // steps of the bar played by the rhythm divider
+    step_lights: Arc<Mutex<StepLights>>,
+    // presses waiting to be played in manual trigger mode
+    manual_trigger: ManualTrigger,
 }
 
 impl Sequencer {
-    pub fn new(config: SequencerConfiguration, is_playing: bool) -> Sequencer {
+    pub fn new(
+        config: SequencerConfiguration,
+        is_playing: bool,
+        audio_sink: Option<AudioSink>,
+    ) -> Sequencer {
         // Create async communication channel to the sequencer thread
         let (tx, rx) = mpsc::channel();
+        let shared = SharedState::default();
+        *shared.state.write().unwrap() = SequencerState {
+            is_playing,
+            tempo: config.bpm,
+            ..SequencerState::default()
+        };
+        let note_sink = Sequencer::build_note_sink(
+            audio_sink.clone(),
+            NoteOnTap::new(shared.note_ons.clone()),
+            &config.routing,
+            config.midi_protocol,
+        );
         let mut thread = SequencerThread::new(
             rx,
-            Sequencer::build_pitch_producer(&config),
-            Sequencer::build_trigger_producer(&config),
+            config,
             is_playing,
-            config.instrument,
-            config.bpm,
-            config.rhythm_pattern,
+            Box::new(SystemClock::new()),
+            note_sink,
+            shared.clone(),
         );
+        thread.audio_sink = audio_sink;
 
-        // Schedule the sequencer thread
+        // Schedule the sequencer thread, catching panics so the UI can report them and restart it
+        let failure = Arc::new(Mutex::new(None));
+        let thread_failure = failure.clone();
         let timer = Timer::new();
-        let guard = timer.schedule_repeating(
-            Duration::milliseconds(SCHEDULE_REPEATING_DURATION),
-            move || thread.tick(),
-        );
+        let guard =
+            timer.schedule_repeating(Duration::milliseconds(TIMER_INTERVAL_MS), move || {
+                if thread_failure.lock().unwrap().is_some() {
+                    return;
+                }
+                if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| thread.tick())) {
+                    // the sink may be what failed, so this can panic too
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| thread.all_notes_off()));
+                    *thread_failure.lock().unwrap() = Some(panic_message(&panic));
+                }
+            });
         guard.ignore();
 
         Sequencer {
             sender: tx,
             _timer: timer,
+            failure,
+            shared,
+        }
+    }
+
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+
+    // Plays a configuration for a number of bars as fast as possible, on a ManualClock,
+    // and returns everything sent with the time it was sent at
+    pub fn render(
+        config: SequencerConfiguration,
+        bars: u32,
+    ) -> Vec<(core::time::Duration, SinkEvent)> {
+        let (_tx, rx) = mpsc::channel();
+        let sink = RecordingSink::new();
+        let events = sink.events();
+        let mut thread = SequencerThread::new(
+            rx,
+            config,
+            true,
+            Box::new(ManualClock::new()),
+            Box::new(sink),
+            SharedState::default(),
+        );
+        let ticks = bars as u64 * thread.ticks_per_bar();
+        let mut rendered = Vec::new();
+        while thread.schedule.ticks() < ticks {
+            thread.tick();
+            let now = thread.clock.now();
+            rendered.extend(events.lock().unwrap().drain(..).map(|event| (now, event)));
+            let next_tick = thread.schedule.next_tick();
+            thread.clock.sleep(next_tick.saturating_sub(now));
         }
+        thread.all_notes_off();
+        let now = thread.clock.now();
+        rendered.extend(events.lock().unwrap().drain(..).map(|event| (now, event)));
+        rendered
+    }
+
+    pub fn guide_bar(&self) -> Option<usize> {
+        *self.shared.guide_bar.lock().unwrap()
+    }
+
+    // Of the notes played over the last bars
+    pub fn statistics(&self, bars: usize) -> StatisticsSummary {
+        self.shared.statistics.lock().unwrap().summary(bars)
+    }
+
+    pub fn transport(&self) -> TransportPosition {
+        self.state().transport
+    }
+
+    pub fn state(&self) -> SequencerState {
+        *self.shared.state.read().unwrap()
+    }
+
+    pub fn step_lights(&self) -> StepLights {
+        self.shared.step_lights.lock().unwrap().clone()
+    }
+
+    pub fn active_variations(&self) -> ActiveVariations {
+        *self.shared.variations.lock().unwrap()
+    }
+
+    // Note ons sent since the last call
+    pub fn take_note_ons(&self) -> Vec<SinkEvent> {
+        self.shared.note_ons.lock().unwrap().drain(..).collect()
     }
 
     pub fn start(&self) {
@@ -84,179 +464,2061 @@ impl Sequencer {
         self.sender.send(SequencerCommand::Stop).unwrap();
     }
 
-    fn build_pitch_producer(config: &SequencerConfiguration) -> Box<dyn PitchModule> {
-        let pitch_producer: Box<dyn PitchModule> = match config.pitch_producer_type {
-            PitchProducerType::Random => {
-                Box::new(RandomPitchProducer::new(config.min_pitch, config.max_pitch))
-            }
-
-            PitchProducerType::RampUp => Box::new(RampPitchProducer::new(
-                config.cycle_length,
-                config.min_pitch,
-                config.max_pitch,
-            )),
-
-            PitchProducerType::Square => Box::new(SquarePitchProducer::new(
-                config.cycle_length,
-                config.min_pitch,
-                config.max_pitch,
-            )),
-
-            PitchProducerType::Sine => Box::new(SinePitchProducer::new(
-                config.cycle_length,
-                config.min_pitch,
-                config.max_pitch,
-            )),
+    pub fn press_roll(&self, rate: RollRate) {
+        self.sender.send(SequencerCommand::PressRoll(rate)).unwrap();
+    }
+
+    pub fn release_roll(&self) {
+        self.sender.send(SequencerCommand::ReleaseRoll).unwrap();
+    }
+
+    pub fn set_manual_trigger(&self, manual: bool) {
+        self.sender
+            .send(SequencerCommand::SetManualTrigger(manual))
+            .unwrap();
+    }
+
+    pub fn preview_note(&self, note: u8) {
+        self.sender
+            .send(SequencerCommand::PreviewNote(note))
+            .unwrap();
+    }
+
+    // Pressed to play the next note in manual trigger mode, shared with the MIDI input
+    pub fn manual_trigger(&self) -> ManualTrigger {
+        self.shared.manual_trigger.clone()
+    }
+
+    fn build_note_sink(
+        audio_sink: Option<AudioSink>,
+        tap: NoteOnTap,
+        routing: &RoutingTable,
+        protocol: MidiProtocol,
+    ) -> Box<dyn NoteSink> {
+        if protocol.negotiate() != protocol {
+            eprintln!("{} is not available, sending MIDI 1.0", protocol);
+        }
+        if routing.enabled {
+            let routing_sink = RoutingSink::new(routing, audio_sink, protocol);
+            return Box::new(FanOutSink::new(vec![Box::new(routing_sink), Box::new(tap)]));
+        }
+        let midi_sink: Box<dyn NoteSink> = match MidiSink::connect_first_port(protocol) {
+            Some(sink) => Box::new(sink),
+            None => {
+                eprintln!("No MIDI output port available, notes will not be sent");
+                Box::new(NullSink)
+            }
         };
-        Box::new(PitchQuantizer::new(
-            pitch_producer,
+        // the internal audio and the visuals follow everything sent to MIDI
+        let mut sinks: Vec<Box<dyn NoteSink>> = vec![midi_sink, Box::new(tap)];
+        if let Some(audio_sink) = audio_sink {
+            sinks.push(Box::new(audio_sink));
+        }
+        Box::new(FanOutSink::new(sinks))
+    }
+
+    fn build_pitch_producer(config: &SequencerConfiguration) -> Box<dyn PitchModule> {
+        let build = producer_registry()
+            .pitch_producer(&config.pitch_producer)
+            .build
+            .clone();
+        let mut chain = build(config);
+        chain.update(ChainParameter::Rotation(config.pitch_rotation));
+        for stage in config.pitch_chain.iter().filter(|stage| stage.enabled) {
+            chain = match stage.stage {
+                PitchStage::SampleHold => {
+                    Box::new(SampleHoldModule::new(chain, config.sample_hold_steps))
+                }
+                PitchStage::Quantizer => {
+                    Box::new(PitchQuantizer::new(chain, config.quantizer_scale.clone()))
+                }
+                PitchStage::OctaveJumps => Box::new(OctaveJumpModule::new(
+                    chain,
+                    config.octave_jump_probability,
+                    config.min_pitch,
+                    config.max_pitch,
+                )),
+                PitchStage::Transpose => Box::new(TransposeModule::new(chain, config.transpose)),
+                PitchStage::RangeClamp => Box::new(RangeClampModule::new(
+                    chain,
+                    config.range_mode,
+                    config.min_pitch,
+                    config.max_pitch,
+                )),
+                PitchStage::Harmony => Box::new(HarmonyModule::new(
+                    chain,
+                    config.quantizer_scale.clone(),
+                    config.harmony_steps,
+                )),
+                PitchStage::VoiceLeading => Box::new(VoiceLeadingModule::new(
+                    chain,
+                    config.quantizer_scale.clone(),
+                    config.max_interval,
+                    config.repeat_avoidance,
+                )),
+            };
+        }
+        chain
+    }
+
+    fn build_phrase_generator(
+        config: &SequencerConfiguration,
+    ) -> Option<PhraseGenerator<SmallRng>> {
+        if !config.phrase.enabled {
+            return None;
+        }
+        Some(PhraseGenerator::new(
+            config.phrase,
+            config.quantizer_scale.clone(),
+            config.min_pitch,
+            config.max_pitch,
+            &config.melody,
+        ))
+    }
+
+    fn build_tension_modulator(config: &SequencerConfiguration) -> Option<TensionModulator> {
+        if config.tension.shape == TensionShape::Off {
+            return None;
+        }
+        Some(TensionModulator::new(
+            config.tension,
+            config.quantizer_scale.clone(),
+            config.min_pitch,
+            config.max_pitch,
+        ))
+    }
+
+    fn build_call_response(config: &SequencerConfiguration) -> Option<CallResponse> {
+        if !config.call_response.enabled {
+            return None;
+        }
+        Some(CallResponse::new(
+            config.call_response,
             config.quantizer_scale.clone(),
+            config.min_pitch,
+            config.max_pitch,
         ))
     }
 
-    fn build_trigger_producer(config: &SequencerConfiguration) -> Box<dyn TriggerModule> {
-        Box::new(RhythmDivider::new(
-            Box::new(RandomTriggerProducer::new()),
-            (TICKS_PER_QUARTER_NOTE * BPM as u32) / config.bpm as u32,
-            config.notes_per_beat,
+    fn build_ambient_engine(config: &SequencerConfiguration) -> Option<AmbientEngine<SmallRng>> {
+        if !config.ambient.enabled {
+            return None;
+        }
+        Some(AmbientEngine::new(
+            config.ambient,
+            config.quantizer_scale.clone(),
+            config.min_pitch,
+            config.max_pitch,
         ))
     }
 
+    fn build_trigger_producer(
+        config: &SequencerConfiguration,
+        lights: &Arc<Mutex<StepLights>>,
+    ) -> Box<dyn TriggerModule> {
+        let build = producer_registry()
+            .trigger_producer(&config.trigger_producer)
+            .build
+            .clone();
+        let mut chain = build(config);
+        for stage in &config.trigger_chain {
+            chain = match stage {
+                TriggerStage::BeatDivision => Box::new(
+                    RhythmDivider::new(
+                        chain,
+                        track_beat_length(config.resolution, config.speed),
+                        config.rhythm_pattern.notes_per_beat.clone(),
+                    )
+                    .with_lights(lights.clone()),
+                ),
+                TriggerStage::ClockDivider => {
+                    Box::new(ClockDivider::new(chain, config.clock_division.max(1)))
+                }
+                TriggerStage::Swing => Box::new(SwingModule::new(chain, config.swing)),
+                TriggerStage::Ratchet => Box::new(RatchetModule::new(chain, config.ratchet_count)),
+                TriggerStage::RestGate => Box::new(RestGate::new(chain, config.rest_probability)),
+                TriggerStage::Logic => Box::new(LogicModule::new(
+                    chain,
+                    StepTrigger::new(config.logic_steps, config.resolution),
+                    config.logic_operation,
+                )),
+            };
+        }
+        chain.update(ChainParameter::Rotation(config.rhythm_rotation));
+        chain
+    }
+
+    // The processors the notes of the main voice go through, the scale lock and the split
+    // routing last whatever the settings
+    fn build_note_processors(
+        settings: &NoteProcessorSettings,
+        scale_lock: bool,
+        split: KeyboardSplit,
+    ) -> Vec<Box<dyn NoteProcessor>> {
+        let mut processors: Vec<Box<dyn NoteProcessor>> = Vec::new();
+        if settings.harmony {
+            processors.push(Box::new(HarmonyProcessor::new(settings.harmony_degrees)));
+        }
+        if settings.strum {
+            processors.push(Box::new(StrumProcessor::new(
+                core::time::Duration::from_secs_f32(settings.strum_ms.max(0.0) / 1000.0),
+            )));
+        }
+        if settings.echo {
+            processors.push(Box::new(EchoProcessor::new(
+                settings.echo_repeats,
+                settings.echo_delay,
+                settings.echo_decay,
+            )));
+        }
+        if settings.humanize {
+            processors.push(Box::new(HumanizeProcessor::new(
+                core::time::Duration::from_secs_f32(settings.humanize_ms.max(0.0) / 1000.0),
+                settings.humanize_velocity,
+            )));
+        }
+        if scale_lock {
+            processors.push(Box::new(ScaleLock));
+        }
+        processors.push(Box::new(ChannelRouting::new(split, MIDI_CHANNEL)));
+        processors
+    }
+
+    pub fn update_guide(&self, guide: Option<Vec<GuideBar>>) {
+        self.sender.send(SequencerCommand::SetGuide(guide)).unwrap();
+    }
+
     pub fn update_instrument(&self, instrument: u8) {
+        self.send_at_next_bar(SequencerCommand::SetInstrument(instrument));
+    }
+
+    pub fn update_timbre_drift(&self, drift: TimbreDrift) {
         self.sender
-            .send(SequencerCommand::SetInstrument(instrument))
+            .send(SequencerCommand::SetTimbreDrift(drift))
             .unwrap();
     }
 
-    pub fn update_rhythm_pattern(&self, rhythm_pattern: Vec<NoteDurationLetter>) {
+    pub fn update_split(&self, split: KeyboardSplit) {
+        self.send_at_next_bar(SequencerCommand::SetSplit(split));
+    }
+
+    pub fn update_note_processors(&self, settings: NoteProcessorSettings) {
         self.sender
-            .send(SequencerCommand::SetRhythmPattern(rhythm_pattern))
+            .send(SequencerCommand::SetNoteProcessors(settings))
             .unwrap();
     }
 
-    pub fn update_pitch_producer(&self, config: SequencerConfiguration) {
+    pub fn update_scale_lock(&self, scale_lock: bool) {
         self.sender
-            .send(SequencerCommand::SetPitchProducer(
-                Sequencer::build_pitch_producer(&config),
-            ))
+            .send(SequencerCommand::SetScaleLock(scale_lock))
             .unwrap();
     }
 
-    pub fn update_trigger_producer(&self, config: SequencerConfiguration) {
+    pub fn update_collision_avoidance(&self, avoidance: CollisionAvoidance) {
+        self.sender
+            .send(SequencerCommand::SetCollisionAvoidance(avoidance))
+            .unwrap();
+    }
+
+    pub fn update_seed(&self, seed: SeedSettings) {
+        self.sender.send(SequencerCommand::SetSeed(seed)).unwrap();
+    }
+
+    pub fn update_routing(&self, routing: RoutingTable) {
+        self.sender
+            .send(SequencerCommand::SetRouting(Box::new(routing)))
+            .unwrap();
+    }
+
+    pub fn update_velocity_curves(&self, curves: VelocityCurves) {
+        self.sender
+            .send(SequencerCommand::SetVelocityCurves(curves))
+            .unwrap();
+    }
+
+    pub fn update_voices(&self, voices: VoiceSettings) {
+        self.sender
+            .send(SequencerCommand::SetVoices(voices))
+            .unwrap();
+    }
+
+    pub fn update_midi_protocol(&self, protocol: MidiProtocol) {
+        self.sender
+            .send(SequencerCommand::SetMidiProtocol(protocol))
+            .unwrap();
+    }
+
+    pub fn update_cadence(&self, cadence: CadenceSettings) {
+        self.sender
+            .send(SequencerCommand::SetCadence(cadence))
+            .unwrap();
+    }
+
+    pub fn update_bass(&self, bass: BassSettings) {
+        self.send_at_next_bar(SequencerCommand::SetBass(bass));
+    }
+
+    pub fn update_layers(&self, layers: LayerSettings) {
+        self.send_at_next_bar(SequencerCommand::SetLayers(layers));
+    }
+
+    pub fn update_root(&self, root: usize) {
+        self.sender.send(SequencerCommand::SetRoot(root)).unwrap();
+    }
+
+    pub fn set_audio_sink(&self, audio_sink: AudioSink) {
+        self.sender
+            .send(SequencerCommand::SetAudioSink(audio_sink))
+            .unwrap();
+    }
+
+    // The durations go now, the notes per beat with the trigger chain rebuilt for them
+    pub fn update_rhythm_pattern(&self, rhythm_pattern: &RhythmPattern) {
+        self.send_at_next_bar(SequencerCommand::SetRhythmPattern(
+            rhythm_pattern.durations.clone(),
+        ));
+    }
+
+    pub fn update_rotation(&self, rhythm: u32, pitch: u32) {
+        self.sender
+            .send(SequencerCommand::SetRotation { rhythm, pitch })
+            .unwrap();
+    }
+
+    pub fn hold_changes(&self, hold: bool) {
+        self.sender
+            .send(SequencerCommand::HoldChanges(hold))
+            .unwrap();
+    }
+
+    pub fn set_quantize_changes(&self, quantize: bool) {
+        self.sender
+            .send(SequencerCommand::SetQuantizeChanges(quantize))
+            .unwrap();
+    }
+
+    fn send_at_next_bar(&self, command: SequencerCommand) {
+        self.sender
+            .send(SequencerCommand::AtNextBar(Box::new(command)))
+            .unwrap();
+    }
+
+    pub fn update_groove(&self, groove: &'static GrooveTemplate) {
+        self.sender
+            .send(SequencerCommand::SetGroove(groove))
+            .unwrap();
+    }
+
+    pub fn update_velocity_jitter(&self, velocity_jitter: f32) {
+        self.sender
+            .send(SequencerCommand::SetVelocityJitter(velocity_jitter))
+            .unwrap();
+    }
+
+    pub fn update_accent(&self, accent: DownbeatAccent) {
+        self.sender
+            .send(SequencerCommand::SetAccent(accent))
+            .unwrap();
+    }
+
+    pub fn update_density_compensation(&self, compensation: DensityCompensation) {
+        self.sender
+            .send(SequencerCommand::SetDensityCompensation(compensation))
+            .unwrap();
+    }
+
+    pub fn update_fade(&self, fade: FadeSettings) {
+        self.sender.send(SequencerCommand::SetFade(fade)).unwrap();
+    }
+
+    pub fn update_note_length(&self, note_length: f32) {
+        self.sender
+            .send(SequencerCommand::SetNoteLength(note_length))
+            .unwrap();
+    }
+
+    pub fn update_speed(&self, speed: f32) {
+        self.sender.send(SequencerCommand::SetSpeed(speed)).unwrap();
+    }
+
+    pub fn update_pressure_envelope(&self, envelope: PressureEnvelope) {
+        self.sender
+            .send(SequencerCommand::SetPressureEnvelope(envelope))
+            .unwrap();
+    }
+
+    pub fn update_sustain(&self, sustain: SustainAutomation) {
+        self.sender
+            .send(SequencerCommand::SetSustain(sustain))
+            .unwrap();
+    }
+
+    pub fn update_key_modulation(&self, key_modulation: KeyModulation) {
+        self.sender
+            .send(SequencerCommand::SetKeyModulation(key_modulation))
+            .unwrap();
+    }
+
+    pub fn update_drums(&self, drums: DrumSettings) {
+        self.sender.send(SequencerCommand::SetDrums(drums)).unwrap();
+    }
+
+    pub fn update_variations(&self, variations: PatternVariations) {
+        self.sender
+            .send(SequencerCommand::SetVariations(Box::new(variations)))
+            .unwrap();
+    }
+
+    pub fn sync_to(&self, beat: f64) {
+        self.sender.send(SequencerCommand::SyncTo(beat)).unwrap();
+    }
+
+    pub fn set_resolution(&self, resolution: u32) {
+        self.sender
+            .send(SequencerCommand::SetResolution(resolution))
+            .unwrap();
+    }
+
+    pub fn set_loop_bars(&self, loop_bars: Option<u32>) {
+        self.sender
+            .send(SequencerCommand::SetLoopBars(loop_bars))
+            .unwrap();
+    }
+
+    pub fn rewind(&self) {
+        self.sender.send(SequencerCommand::Rewind).unwrap();
+    }
+
+    pub fn update_slewed(&self, parameter: SlewedParameter, value: f32, slew_beats: f32) {
         self.sender
-            .send(SequencerCommand::SetTriggerProducer(
-                Sequencer::build_trigger_producer(&config),
-            ))
+            .send(SequencerCommand::SetSlewed {
+                parameter,
+                value,
+                slew_beats,
+            })
             .unwrap();
+    }
+
+    pub fn update_chain(&self, parameter: ChainParameter) {
+        self.sender
+            .send(SequencerCommand::UpdateChain(parameter))
+            .unwrap();
+    }
+
+    pub fn update_pitch_producer(&self, config: SequencerConfiguration) {
+        for command in Sequencer::pitch_producer_commands(config) {
+            self.sender.send(command).unwrap();
+        }
+    }
+
+    // Like update_pitch_producer, held until the next bar line while quantizing changes
+    pub fn queue_pitch_producer(&self, config: SequencerConfiguration) {
+        for command in Sequencer::pitch_producer_commands(config) {
+            self.send_at_next_bar(command);
+        }
+    }
+
+    fn pitch_producer_commands(config: SequencerConfiguration) -> Vec<SequencerCommand> {
+        // the composition layers depend on the scale and range, rebuild them with the pitch chain
+        vec![
+            SequencerCommand::SetPhraseGenerator(Sequencer::build_phrase_generator(&config)),
+            SequencerCommand::SetTensionModulator(Sequencer::build_tension_modulator(&config)),
+            SequencerCommand::SetCallResponse(Sequencer::build_call_response(&config)),
+            SequencerCommand::SetAmbientEngine(Sequencer::build_ambient_engine(&config)),
+            SequencerCommand::GlideTo {
+                config: Box::new(config),
+                pitch_chain: true,
+                trigger_chain: false,
+            },
+        ]
+    }
+
+    pub fn update_trigger_producer(&self, config: SequencerConfiguration) {
         self.sender
-            .send(SequencerCommand::SetTempo(config.bpm))
+            .send(SequencerCommand::GlideTo {
+                config: Box::new(config),
+                pitch_chain: false,
+                trigger_chain: true,
+            })
             .unwrap();
     }
+
+    // Like update_trigger_producer, held until the next bar line while quantizing changes
+    pub fn queue_trigger_producer(&self, config: SequencerConfiguration) {
+        self.send_at_next_bar(SequencerCommand::GlideTo {
+            config: Box::new(config),
+            pitch_chain: false,
+            trigger_chain: true,
+        });
+    }
+}
+
+// The note whose channel pressure follows the envelope
+#[derive(Clone, Copy)]
+struct PressureNote {
+    channel: u8,
+    // MIDI 2.0 gives the pressure to this note alone
+    note: u8,
+    start: core::time::Duration,
+    end: core::time::Duration,
+    // last value sent, at a high resolution most ticks would repeat it
+    sent: Option<u8>,
 }
 
 struct SequencerThread {
     receiver: mpsc::Receiver<SequencerCommand>,
     pitch_producer: Box<dyn PitchModule>,
+    phrase_generator: Option<PhraseGenerator<SmallRng>>,
+    tension_modulator: Option<TensionModulator>,
+    call_response: Option<CallResponse>,
+    ambient_engine: Option<AmbientEngine<SmallRng>>,
     trigger_producer: Box<dyn TriggerModule>,
-    midi_output_conn: MidiOutputConnection,
+    glide: ParameterGlide,
+    note_sink: Box<dyn NoteSink>,
+    // kept to rebuild the note sink when the routing changes
+    audio_sink: Option<AudioSink>,
+    routing: RoutingTable,
+    midi_protocol: MidiProtocol,
+    voices: VoiceSettings,
     is_playing: bool,
     instrument: u8,
+    timbre_drift: TimbreDrift,
+    // where the timbre drift took the instrument to, until another one is picked
+    drifted_instrument: Option<u8>,
     tempo: f32,
+    // ticks per quarter note
+    resolution: u32,
+    schedule: TickSchedule,
     rhythm_pattern: Vec<NoteDurationLetter>,
     current_rhythm_index: usize,
+    rhythm_rotation: usize,
+    groove: &'static GrooveTemplate,
+    velocity_jitter: f32,
+    accent: DownbeatAccent,
+    density_compensation: DensityCompensation,
+    // onsets of the main voice, for the density compensation
+    density_estimator: DensityEstimator,
+    fade_settings: FadeSettings,
+    // velocity ramp of the start and the stop
+    fade: Fade,
+    note_length: f32,
+    speed: f32,
+    pending_speed: Option<f32>,
+    pressure_envelope: PressureEnvelope,
+    pressure_note: Option<PressureNote>,
+    note_offs: NoteOffScheduler,
+    sustain: SustainAutomation,
+    split: KeyboardSplit,
+    note_processor_settings: NoteProcessorSettings,
+    note_processors: Vec<Box<dyn NoteProcessor>>,
+    scale_lock: bool,
+    // the last stage of the velocities, by instrument
+    velocity_curves: VelocityCurves,
+    // notes the processors moved after their step
+    delayed_notes: DelayedNotes,
+    roll: Option<Roll>,
+    manual_trigger: bool,
+    // kept between the steps so the processors don't allocate on every note
+    step_notes: Vec<NoteEvent>,
+    bass: BassSettings,
+    // the bass and hats derived from the main voice
+    layers: DerivedLayers,
+    collision_avoidance: CollisionAvoidance,
+    cadence: CadenceSettings,
+    seed: SeedSettings,
+    // pitches started on the current tick, by every pitched track
+    tick_notes: TickNotes,
+    sustain_down: bool,
+    drum_machine: Option<DrumMachine>,
+    drums: DrumSettings,
+    pending_drums: Option<DrumSettings>,
+    variations: PatternVariations,
+    pending_variations: Option<PatternVariations>,
+    active_variations: ActiveVariations,
+    rng: SmallRng,
+    // places the notes in the bar
+    transport: Transport,
+    // bar the sustain automation was last evaluated for
+    current_bar: Option<u64>,
+    guide: Option<Vec<GuideBar>>,
+    key_modulation: KeyModulation,
+    // semitones the key moved by at the current bar
+    key_offset: i32,
+    quantize_changes: bool,
+    holding_changes: bool,
+    // nothing plays anymore, the timer is about to go away
+    shut_down: bool,
+    // changes held for the next bar line, with the bar they were sent in
+    pending_changes: Vec<(u64, SequencerCommand)>,
+    shared: SharedState,
+    clock: Box<dyn Clock>,
 }
 
 impl SequencerThread {
     fn new(
         receiver: mpsc::Receiver<SequencerCommand>,
-        pitch_producer: Box<dyn PitchModule>,
-        trigger_producer: Box<dyn TriggerModule>,
+        config: SequencerConfiguration,
         is_playing: bool,
-        instrument: u8,
-        tempo: f32,
-        rhythm_pattern: Vec<NoteDurationLetter>,
+        clock: Box<dyn Clock>,
+        note_sink: Box<dyn NoteSink>,
+        shared: SharedState,
     ) -> SequencerThread {
-        // Create MIDI output
-        let midi_out = midir::MidiOutput::new("Generative Sequencer").unwrap();
-
-        // Connect to the first available MIDI output port (IAC Bus 1)
-        let out_port = &midi_out.ports()[0];
-        let out_conn = midi_out.connect(out_port, "Generative Sequencer").unwrap();
-
         SequencerThread {
             receiver,
-            pitch_producer,
-            trigger_producer,
-            midi_output_conn: out_conn,
+            pitch_producer: Sequencer::build_pitch_producer(&config),
+            phrase_generator: Sequencer::build_phrase_generator(&config),
+            tension_modulator: Sequencer::build_tension_modulator(&config),
+            call_response: Sequencer::build_call_response(&config),
+            ambient_engine: Sequencer::build_ambient_engine(&config),
+            trigger_producer: Sequencer::build_trigger_producer(&config, &shared.step_lights),
+            glide: ParameterGlide::new(config.clone()),
+            note_sink,
+            audio_sink: None,
+            routing: config.routing.clone(),
+            midi_protocol: config.midi_protocol,
+            voices: config.voices,
             is_playing,
-            instrument,
-            tempo,
-            rhythm_pattern,
+            instrument: config.instrument,
+            timbre_drift: config.timbre_drift,
+            drifted_instrument: None,
+            tempo: config.bpm,
+            resolution: config.resolution,
+            schedule: TickSchedule::new(config.bpm, config.resolution),
+            rhythm_pattern: config.rhythm_pattern.durations.clone(),
             current_rhythm_index: 0,
+            rhythm_rotation: config.rhythm_rotation as usize,
+            groove: config.groove,
+            velocity_jitter: config.velocity_jitter,
+            accent: config.accent,
+            density_compensation: config.density_compensation,
+            density_estimator: DensityEstimator::new(),
+            fade_settings: config.fade,
+            fade: Fade::new(is_playing),
+            note_length: config.note_length,
+            speed: config.speed,
+            pending_speed: None,
+            pressure_envelope: config.pressure_envelope,
+            pressure_note: None,
+            note_offs: NoteOffScheduler::new(),
+            sustain: config.sustain,
+            split: config.split,
+            note_processor_settings: config.note_processors,
+            note_processors: Sequencer::build_note_processors(
+                &config.note_processors,
+                config.scale_lock,
+                config.split,
+            ),
+            scale_lock: config.scale_lock,
+            velocity_curves: config.velocity_curves.clone(),
+            delayed_notes: DelayedNotes::new(),
+            roll: None,
+            manual_trigger: false,
+            step_notes: Vec::new(),
+            bass: config.bass,
+            layers: DerivedLayers::new(config.layers),
+            collision_avoidance: config.collision_avoidance,
+            cadence: config.cadence,
+            seed: config.seed,
+            tick_notes: TickNotes::default(),
+            drum_machine: config
+                .drums
+                .enabled
+                .then(|| DrumMachine::new(&config.drums, config.resolution)),
+            drums: config.drums,
+            pending_drums: None,
+            variations: config.variations.clone(),
+            pending_variations: None,
+            active_variations: ActiveVariations::default(),
+            sustain_down: false,
+            rng: SmallRng::from_entropy(),
+            transport: Transport::new(config.rhythm_pattern.beats_per_bar() as u64),
+            current_bar: None,
+            guide: None,
+            key_modulation: config.key_modulation,
+            key_offset: 0,
+            quantize_changes: false,
+            holding_changes: false,
+            shut_down: false,
+            pending_changes: Vec::new(),
+            shared,
+            clock,
         }
     }
 
-    fn tick(&mut self) {
-        // Process all pending commands
-        for command in self.receiver.try_iter() {
-            match command {
-                SequencerCommand::Start => {
-                    if !self.is_playing {
-                        self.is_playing = true
-                    }
-                }
-                SequencerCommand::Stop => {
-                    if self.is_playing {
-                        self.is_playing = false
-                    }
-                }
-                SequencerCommand::SetPitchProducer(pp) => {
-                    self.pitch_producer = pp;
-                }
-                SequencerCommand::SetTriggerProducer(tp) => {
-                    self.trigger_producer = tp;
-                }
-                SequencerCommand::SetInstrument(i) => {
-                    self.instrument = i;
-                }
-                SequencerCommand::SetRhythmPattern(rp) => {
-                    self.rhythm_pattern = rp;
-                    self.current_rhythm_index = 0;
-                }
-                SequencerCommand::SetTempo(t) => {
-                    self.tempo = t;
-                }
-            };
-        }
+    // The notes sounding are released where they were sent before the sink changes
+    fn rebuild_note_sink(&mut self) {
+        self.note_offs.release_all(self.note_sink.as_mut());
+        self.set_sustain_pedal(false);
+        let tap = NoteOnTap::new(self.shared.note_ons.clone());
+        self.note_sink = Sequencer::build_note_sink(
+            self.audio_sink.clone(),
+            tap,
+            &self.routing,
+            self.midi_protocol,
+        );
+    }
 
-        // Play note
-        if self.is_playing {
-            let pitch = self.pitch_producer.tick();
-            match self.trigger_producer.tick() {
-                Trigger::On => {
-                    // Play the generated MIDI note
-                    let note = pitch.step() as u8;
-
-                    self.midi_output_conn
-                        .send(&[PROGRAM_CHANGE_MSG, self.instrument])
-                        .unwrap();
-
-                    self.midi_output_conn
-                        .send(&[NOTE_ON_MSG, note, VELOCITY])
-                        .unwrap();
-                    let note_duration_letter = &self.rhythm_pattern[self.current_rhythm_index];
-                    let note_duration = NOTE_DURATION[note_duration_letter.clone() as usize];
-                    sleep(core::time::Duration::from_millis(
-                        (note_duration * 60_000.0 / self.tempo as f32) as u64,
-                    ));
-                    self.midi_output_conn
-                        .send(&[NOTE_OFF_MSG, note, VELOCITY])
-                        .unwrap();
-                    self.current_rhythm_index =
-                        (self.current_rhythm_index + 1) % self.rhythm_pattern.len();
-                }
-                Trigger::Off => (),
-            }
+    // Duration at the current rhythm index, rotated
+    fn rhythm_step(&self) -> NoteDurationLetter {
+        self.rhythm_step_at(0)
+    }
+
+    // Duration that many steps after the current rhythm index, rotated
+    fn rhythm_step_at(&self, steps: usize) -> NoteDurationLetter {
+        self.rhythm_pattern
+            [(self.current_rhythm_index + self.rhythm_rotation + steps) % self.rhythm_pattern.len()]
+    }
+
+    // Whether a note starts on the current step. Rests and ties don't, nor does the step a tie
+    // merges into the note before it: they take their triggers in silence.
+    fn sounding_step(&self) -> bool {
+        let previous = self.rhythm_step_at(self.rhythm_pattern.len() - 1);
+        !matches!(
+            self.rhythm_step(),
+            NoteDurationLetter::Rest | NoteDurationLetter::Tie
+        ) && previous != NoteDurationLetter::Tie
+    }
+
+    fn advance_rhythm_index(&mut self) {
+        self.current_rhythm_index = (self.current_rhythm_index + 1) % self.rhythm_pattern.len();
+    }
+
+    // Duration in beats of the note at the current rhythm index, summing any tied durations.
+    // Moves on by a single step, the steps tied to the note come with their own triggers.
+    fn next_note_duration(&mut self) -> f32 {
+        let mut duration = self.rhythm_step().beats();
+        let mut steps = 1;
+        while steps + 1 < self.rhythm_pattern.len()
+            && self.rhythm_step_at(steps) == NoteDurationLetter::Tie
+        {
+            duration += self.rhythm_step_at(steps + 1).beats();
+            steps += 2;
+        }
+        self.advance_rhythm_index();
+        duration
+    }
+
+    // Like next_note_duration, leaving the rhythm index where it is
+    fn peek_note_duration(&mut self) -> f32 {
+        let index = self.current_rhythm_index;
+        let duration = self.next_note_duration();
+        self.current_rhythm_index = index;
+        duration
+    }
+
+    // True when a note starting now lasts to the end of the cadence phrase
+    fn ends_phrase(&mut self) -> bool {
+        let ticks_per_phrase = self.ticks_per_bar() * self.cadence.phrase_bars.max(1) as u64;
+        let ticks_left = ticks_per_phrase - self.transport.tick() % ticks_per_phrase;
+        let note_ticks = self.peek_note_duration() * self.ticks_per_beat() as f32;
+        note_ticks >= ticks_left as f32
+    }
+
+    fn ticks_per_beat(&self) -> u64 {
+        self.resolution as u64
+    }
+
+    // The beats keep their ticks, the ticks get shorter or longer
+    fn set_tempo(&mut self, bpm: f32) {
+        self.tempo = bpm;
+        self.schedule.set_tempo(bpm, self.resolution);
+    }
+
+    // The tempo of the main voice, running at its speed
+    fn track_tempo(&self) -> f32 {
+        self.tempo * self.speed
+    }
+
+    fn track_beat_length(&self) -> u32 {
+        track_beat_length(self.resolution, self.speed)
+    }
+
+    fn ticks_per_bar(&self) -> u64 {
+        self.ticks_per_beat() * self.transport.beats_per_bar()
+    }
+
+    // Index of the sixteenth note in the groove template the current tick falls in,
+    // the template covers four beats and starts again on every bar
+    fn sixteenth_index(&self) -> usize {
+        let ticks_per_beat = self.ticks_per_beat();
+        let tick_in_bar = self.transport.tick() % self.ticks_per_bar();
+        let beat = tick_in_bar / ticks_per_beat % 4;
+        (beat * 4 + tick_in_bar % ticks_per_beat * 4 / ticks_per_beat) as usize
+    }
+
+    // Current level of the tension curve, from the position in its N-bar phrase
+    fn tension_level(&self) -> f32 {
+        let Some(tension_modulator) = self.tension_modulator.as_ref() else {
+            return 0.0;
+        };
+        let ticks_per_phrase = self.ticks_per_bar() * tension_modulator.phrase_bars() as u64;
+        let position = (self.transport.tick() % ticks_per_phrase) as f32 / ticks_per_phrase as f32;
+        tension_modulator.level(position)
+    }
+
+    // The split half of the main voice shares its pedal
+    fn set_sustain_pedal(&mut self, down: bool) {
+        for channel in [MIDI_CHANNEL, SPLIT_CHANNEL] {
+            if self.sustain_down {
+                self.note_sink
+                    .send_cc(channel, SUSTAIN_CONTROLLER, PEDAL_UP);
+            }
+            if down {
+                self.note_sink
+                    .send_cc(channel, SUSTAIN_CONTROLLER, PEDAL_DOWN);
+            }
+        }
+        self.sustain_down = down;
+    }
+
+    // Runs the bar-aware automation (sustain, call and response) once at the start of every bar
+    fn update_bar(&mut self) {
+        let bar = self.transport.bar(self.ticks_per_beat());
+        if self.current_bar == Some(bar) {
+            return;
+        }
+        self.current_bar = Some(bar);
+        self.shared.statistics.lock().unwrap().start_bar();
+        self.reseed(bar);
+        self.drift_timbre(bar);
+        if let Some(down) = self.sustain.pedal_at_bar(bar, &mut self.rng) {
+            self.set_sustain_pedal(down);
+        }
+        if let Some(call_response) = self.call_response.as_mut() {
+            call_response.start_bar(bar);
+        }
+        if let Some(ambient_engine) = self.ambient_engine.as_mut() {
+            ambient_engine.drift();
+        }
+        self.apply_variations(bar);
+        if let Some(drums) = self.pending_drums.take() {
+            self.drums = drums;
+            let drums = self.variations.drums(&self.active_variations, drums);
+            self.drum_machine = drums
+                .enabled
+                .then(|| DrumMachine::new(&drums, self.resolution));
+        }
+        if let Some(speed) = self.pending_speed.take() {
+            self.speed = speed;
+            self.trigger_producer
+                .update(ChainParameter::BeatLength(self.track_beat_length()));
+        }
+        if !self.update_key_offset(bar) {
+            self.apply_guide();
+        }
+    }
+
+    fn main_instrument(&self) -> u8 {
+        self.drifted_instrument.unwrap_or(self.instrument)
+    }
+
+    // Moves the instrument along the timbre drift, selecting the GM bank before the program
+    // for the synths holding more than one
+    fn drift_timbre(&mut self, bar: u64) {
+        let Some(program) =
+            self.timbre_drift
+                .next_program(bar, self.main_instrument(), &mut self.rng)
+        else {
+            return;
+        };
+        self.drifted_instrument = Some(program);
+        self.note_sink
+            .send_cc(MIDI_CHANNEL, BANK_SELECT_MSB, GM_BANK);
+        self.note_sink
+            .send_cc(MIDI_CHANNEL, BANK_SELECT_LSB, GM_BANK);
+        self.note_sink.send_program(MIDI_CHANNEL, program);
+    }
+
+    // With repeatable randomness every random module starts the bar over from its seed
+    fn reseed(&mut self, bar: u64) {
+        let Some(seed) = self.seed.bar_seed(bar) else {
+            return;
+        };
+        self.rng = seeded_rng(seed, SeedStream::Sequencer);
+        self.pitch_producer.update(ChainParameter::Seed(seed));
+        self.trigger_producer.update(ChainParameter::Seed(seed));
+        if let Some(phrase_generator) = self.phrase_generator.as_mut() {
+            phrase_generator.reseed(seed);
+        }
+        if let Some(ambient_engine) = self.ambient_engine.as_mut() {
+            ambient_engine.reseed(seed);
+        }
+        for processor in self.note_processors.iter_mut() {
+            processor.reseed(seed);
+        }
+    }
+
+    // Each track switches to the variation of the bar, the melody rhythm is rebuilt
+    // and the drums start over with their new steps
+    fn apply_variations(&mut self, bar: u64) {
+        let changed = match self.pending_variations.take() {
+            Some(variations) => {
+                self.variations = variations;
+                true
+            }
+            None => false,
+        };
+        let active = self.variations.active_at(bar);
+        let previous = std::mem::replace(&mut self.active_variations, active);
+        *self.shared.variations.lock().unwrap() = active;
+        if changed || active.melody != previous.melody {
+            self.apply_glide(false, true);
+        }
+        if changed || active.drums != previous.drums {
+            self.restart_drums();
+        }
+    }
+
+    // Returns true when the key moved and was applied
+    fn update_key_offset(&mut self, bar: u64) -> bool {
+        let key_offset = self.key_modulation.offset_at(bar);
+        if key_offset == self.key_offset {
+            return false;
+        }
+        self.key_offset = key_offset;
+        self.apply_key();
+        true
+    }
+
+    // Quantize to the chord of the current guide bar, the guide loops once it ends
+    fn apply_guide(&mut self) {
+        let (Some(guide), Some(bar)) = (self.guide.as_ref(), self.current_bar) else {
+            return;
+        };
+        let guide_bar = bar as usize % guide.len();
+        self.pitch_producer
+            .update(ChainParameter::Scale(transpose_mask(
+                guide[guide_bar].mask,
+                self.key_offset,
+            )));
+        *self.shared.guide_bar.lock().unwrap() = Some(guide_bar);
+    }
+
+    // Root of the guide bar or of the scale, in the key reached by the modulation
+    fn harmonic_root(&self) -> usize {
+        let root = match (self.guide.as_ref(), self.current_bar) {
+            (Some(guide), Some(bar)) => guide[bar as usize % guide.len()].root,
+            _ => self.glide.root(),
+        };
+        (root as i32 + self.key_offset).rem_euclid(12) as usize
+    }
+
+    // Pitch classes of the guide bar or of the scale, in the key reached by the modulation
+    fn harmonic_scale(&self) -> u16 {
+        let mask = match (self.guide.as_ref(), self.current_bar) {
+            (Some(guide), Some(bar)) => guide[bar as usize % guide.len()].mask,
+            _ => scale_mask(self.glide.scale()),
+        };
+        transpose_mask(mask, self.key_offset)
+    }
+
+    // Moves the pitch chain to the key reached by the modulation, the scale or guide bar with it
+    fn apply_key(&mut self) {
+        self.pitch_producer
+            .update(ChainParameter::KeyOffset(self.key_offset));
+        if self.guide.is_some() {
+            self.apply_guide();
+        } else {
+            let mask = transpose_mask(scale_mask(self.glide.scale()), self.key_offset);
+            self.pitch_producer.update(ChainParameter::Scale(mask));
+        }
+    }
+
+    // Jumps to the position of the network sync leader once it drifted too far,
+    // the drums start over at the next bar line to stay on its steps
+    fn sync_to(&mut self, beat: f64) {
+        let target = (beat.max(0.0) * self.ticks_per_beat() as f64).round() as u64;
+        let tolerance = (SYNC_TOLERANCE_BEATS * self.ticks_per_beat() as f64) as u64;
+        if target.abs_diff(self.transport.tick()) <= tolerance {
+            return;
+        }
+        self.transport.jump_to(target, self.ticks_per_beat());
+        self.restart_drums();
+    }
+
+    // The drums start over at the next bar line
+    fn restart_drums(&mut self) {
+        if self.pending_drums.is_none() {
+            self.pending_drums = Some(self.drums);
+        }
+    }
+
+    // The position and the beat length of the chains are scaled to the new resolution
+    fn set_resolution(&mut self, resolution: u32) {
+        let tick = self.transport.tick() * resolution as u64 / self.resolution as u64;
+        self.resolution = resolution;
+        self.glide
+            .update_chain(ChainParameter::BeatLength(resolution));
+        self.transport.jump_to(tick, self.ticks_per_beat());
+        self.trigger_producer
+            .update(ChainParameter::BeatLength(self.track_beat_length()));
+        if let Some(drum_machine) = self.drum_machine.as_mut() {
+            drum_machine.update(ChainParameter::BeatLength(resolution));
+        }
+        self.schedule.set_tempo(self.tempo, resolution);
+    }
+
+    // At the loop start, the rhythm pattern and the drums start over with bar 1
+    fn restart_loop(&mut self) {
+        self.current_bar = None;
+        self.current_rhythm_index = 0;
+        self.restart_drums();
+    }
+
+    fn all_notes_off(&mut self) {
+        self.delayed_notes.clear();
+        self.note_offs.release_all(self.note_sink.as_mut());
+        self.set_sustain_pedal(false);
+        // every track, the derived layers too, whatever note_offs lost track of
+        for channel in TRACK_CHANNELS {
+            self.note_sink.send_cc(channel, ALL_NOTES_OFF_CONTROLLER, 0);
+        }
+    }
+
+    // Rebuild the chains from the current values of the glide
+    fn apply_glide(&mut self, pitch_chain: bool, trigger_chain: bool) {
+        if !pitch_chain && !trigger_chain {
+            return;
+        }
+        let mut config = self.glide.current();
+        config.rhythm_pattern.notes_per_beat = self.variations.melody(
+            &self.active_variations,
+            &config.rhythm_pattern.notes_per_beat,
+        );
+        // a chain rebuilt within a bar draws from the seed of the bar too
+        let seed = self
+            .current_bar
+            .and_then(|bar| self.seed.bar_seed(bar))
+            .map(ChainParameter::Seed);
+        if pitch_chain {
+            self.pitch_producer = Sequencer::build_pitch_producer(&config);
+            self.apply_key();
+            if let Some(seed) = seed {
+                self.pitch_producer.update(seed);
+            }
+        }
+        if trigger_chain {
+            self.trigger_producer =
+                Sequencer::build_trigger_producer(&config, &self.shared.step_lights);
+            // at the speed playing, a new one waits for the bar line
+            self.trigger_producer
+                .update(ChainParameter::BeatLength(self.track_beat_length()));
+            self.transport
+                .set_beats_per_bar(config.rhythm_pattern.beats_per_bar() as u64);
+            self.set_tempo(config.bpm);
+            if let Some(seed) = seed {
+                self.trigger_producer.update(seed);
+            }
+        }
+    }
+
+    // Apply what moved during a glide tick, in place where the chains allow it
+    fn apply_glide_changes(&mut self, changes: GlideChanges) {
+        self.apply_glide(changes.pitch_chain, false);
+        if let Some(bpm) = changes.bpm {
+            self.set_tempo(bpm);
+        }
+        if let Some(probability) = changes.trigger_probability {
+            self.trigger_producer
+                .update(ChainParameter::TriggerProbability(probability));
+        }
+    }
+
+    // Channel pressure follows the envelope of the last note started, one value per tick
+    fn update_pressure(&mut self, now: core::time::Duration) {
+        let Some(pressure_note) = self.pressure_note.as_mut() else {
+            return;
+        };
+        if now >= pressure_note.end || !self.pressure_envelope.enabled {
+            self.note_sink
+                .send_note_pressure(pressure_note.channel, pressure_note.note, 0);
+            self.pressure_note = None;
+        } else {
+            // a note the groove delays starts after the tick
+            let value = self
+                .pressure_envelope
+                .value_at(now.saturating_sub(pressure_note.start));
+            if pressure_note.sent != Some(value) {
+                self.note_sink
+                    .send_note_pressure(pressure_note.channel, pressure_note.note, value);
+                pressure_note.sent = Some(value);
+            }
+        }
+    }
+
+    fn start_pressure(&mut self, pressure_note: PressureNote) {
+        if let Some(previous) = self.pressure_note {
+            if previous.channel != pressure_note.channel {
+                self.note_sink
+                    .send_note_pressure(previous.channel, previous.note, 0);
+            }
+        }
+        self.pressure_note = Some(pressure_note);
+    }
+
+    // The held changes go out on the first tick of a bar, or once the transport jumped past
+    // the bar they were sent in
+    fn apply_due_changes(&mut self) {
+        let bar = self.transport.bar(self.ticks_per_beat());
+        let at_bar_line = self.transport.tick() % self.ticks_per_bar() == 0;
+        if at_bar_line
+            || self
+                .pending_changes
+                .iter()
+                .any(|(sent_in, _)| *sent_in != bar)
+        {
+            self.apply_pending_changes();
+        }
+    }
+
+    fn apply_pending_changes(&mut self) {
+        for (_, command) in std::mem::take(&mut self.pending_changes) {
+            self.handle_command(command);
+        }
+    }
+
+    fn stop_playing(&mut self) {
+        // no bar line is coming while stopped
+        self.apply_pending_changes();
+        self.is_playing = false;
+        self.fade.stopped();
+        self.delayed_notes.clear();
+        self.density_estimator.clear();
+        self.note_offs.release_all(self.note_sink.as_mut());
+        self.set_sustain_pedal(false);
+        self.current_bar = None;
+        self.shared.statistics.lock().unwrap().stop();
+    }
+
+    fn handle_command(&mut self, command: SequencerCommand) {
+        match command {
+            SequencerCommand::Start => {
+                self.is_playing = true;
+                self.fade.start(&self.fade_settings, self.ticks_per_bar());
+            }
+            SequencerCommand::Stop => {
+                if self.is_playing && self.fade.stop(&self.fade_settings, self.ticks_per_bar()) {
+                    self.stop_playing();
+                }
+            }
+            SequencerCommand::GlideTo {
+                config,
+                pitch_chain,
+                trigger_chain,
+            } => {
+                let ticks = config.slew_beats * self.ticks_per_beat() as f32;
+                self.glide.set_target(*config, ticks);
+                self.apply_glide(pitch_chain, trigger_chain);
+            }
+            SequencerCommand::SetSlewed {
+                parameter,
+                value,
+                slew_beats,
+            } => {
+                let ticks = slew_beats * self.ticks_per_beat() as f32;
+                self.glide.set_parameter(parameter, value, ticks);
+            }
+            SequencerCommand::UpdateChain(parameter) => {
+                self.glide.update_chain(parameter);
+                // a new scale is played in the key reached by the modulation
+                let parameter = match parameter {
+                    ChainParameter::Scale(mask) => {
+                        ChainParameter::Scale(transpose_mask(mask, self.key_offset))
+                    }
+                    parameter => parameter,
+                };
+                self.pitch_producer.update(parameter);
+                self.trigger_producer.update(parameter);
+            }
+            SequencerCommand::SetSeed(seed) => self.seed = seed,
+            SequencerCommand::SetPhraseGenerator(pg) => {
+                self.phrase_generator = pg;
+            }
+            SequencerCommand::SetTensionModulator(tm) => {
+                self.tension_modulator = tm;
+            }
+            SequencerCommand::SetAmbientEngine(ae) => {
+                self.ambient_engine = ae;
+            }
+            SequencerCommand::SetCallResponse(mut cr) => {
+                if let (Some(cr), Some(bar)) = (cr.as_mut(), self.current_bar) {
+                    cr.start_bar(bar);
+                }
+                self.call_response = cr;
+            }
+            SequencerCommand::SetAudioSink(audio_sink) => {
+                self.audio_sink = Some(audio_sink);
+                self.rebuild_note_sink();
+            }
+            SequencerCommand::SetRouting(routing) => {
+                self.routing = *routing;
+                self.rebuild_note_sink();
+            }
+            SequencerCommand::SetVelocityCurves(curves) => self.velocity_curves = curves,
+            SequencerCommand::SetMidiProtocol(protocol) => {
+                self.midi_protocol = protocol;
+                self.rebuild_note_sink();
+            }
+            // the notes over the new limit play out
+            SequencerCommand::SetVoices(voices) => self.voices = voices,
+            SequencerCommand::SetInstrument(i) => {
+                self.instrument = i;
+                // the drift walks on from the instrument picked
+                self.drifted_instrument = None;
+            }
+            SequencerCommand::SetTimbreDrift(drift) => {
+                if !drift.enabled {
+                    self.drifted_instrument = None;
+                }
+                self.timbre_drift = drift;
+            }
+            SequencerCommand::SetRhythmPattern(rp) => {
+                self.rhythm_pattern = rp;
+                self.current_rhythm_index = 0;
+            }
+            SequencerCommand::SetRotation { rhythm, pitch } => {
+                self.rhythm_rotation = rhythm as usize;
+                self.glide.set_rotation(rhythm, pitch);
+                self.trigger_producer
+                    .update(ChainParameter::Rotation(rhythm));
+                self.pitch_producer.update(ChainParameter::Rotation(pitch));
+            }
+            SequencerCommand::SetGroove(g) => {
+                self.groove = g;
+            }
+            SequencerCommand::SetVelocityJitter(j) => {
+                self.velocity_jitter = j;
+            }
+            SequencerCommand::SetFade(fade) => {
+                self.fade_settings = fade;
+            }
+            SequencerCommand::SetAccent(accent) => {
+                self.accent = accent;
+            }
+            SequencerCommand::SetDensityCompensation(compensation) => {
+                self.density_compensation = compensation;
+            }
+            SequencerCommand::SetNoteLength(l) => {
+                self.note_length = l;
+            }
+            SequencerCommand::SetSpeed(speed) => {
+                self.pending_speed = Some(speed);
+            }
+            SequencerCommand::SetPressureEnvelope(e) => {
+                self.pressure_envelope = e;
+            }
+            SequencerCommand::SetKeyModulation(key_modulation) => {
+                self.key_modulation = key_modulation;
+                // the key moves right away rather than at the next bar
+                if let Some(bar) = self.current_bar {
+                    self.update_key_offset(bar);
+                }
+            }
+            SequencerCommand::SetSustain(s) => {
+                self.sustain = s;
+                // re-evaluate the pedal right away rather than at the next bar
+                self.current_bar = None;
+            }
+            SequencerCommand::SetSplit(split) => {
+                self.split = split;
+                self.note_processors = Sequencer::build_note_processors(
+                    &self.note_processor_settings,
+                    self.scale_lock,
+                    split,
+                );
+            }
+            SequencerCommand::SetNoteProcessors(settings) => {
+                self.note_processor_settings = settings;
+                self.note_processors =
+                    Sequencer::build_note_processors(&settings, self.scale_lock, self.split);
+            }
+            SequencerCommand::SetScaleLock(scale_lock) => {
+                self.scale_lock = scale_lock;
+                self.note_processors = Sequencer::build_note_processors(
+                    &self.note_processor_settings,
+                    scale_lock,
+                    self.split,
+                );
+            }
+            SequencerCommand::SetCollisionAvoidance(avoidance) => {
+                self.collision_avoidance = avoidance;
+            }
+            SequencerCommand::SetCadence(cadence) => {
+                self.cadence = cadence;
+            }
+            SequencerCommand::SetBass(bass) => {
+                self.bass = bass;
+            }
+            SequencerCommand::SetLayers(layers) => self.layers.settings = layers,
+            SequencerCommand::SetRoot(root) => {
+                self.glide.set_root(root);
+            }
+            SequencerCommand::SetDrums(drums) => {
+                self.pending_drums = Some(drums);
+            }
+            SequencerCommand::SetVariations(variations) => {
+                self.pending_variations = Some(*variations);
+            }
+            SequencerCommand::SetGuide(guide) => {
+                self.guide = guide.filter(|bars| !bars.is_empty());
+                *self.shared.guide_bar.lock().unwrap() = None;
+                if self.guide.is_some() {
+                    self.transport.jump_to(0, self.ticks_per_beat());
+                    self.current_bar = None;
+                }
+            }
+            SequencerCommand::SyncTo(beat) => {
+                self.sync_to(beat);
+            }
+            SequencerCommand::SetResolution(resolution) => {
+                self.set_resolution(resolution.max(1));
+            }
+            SequencerCommand::SetLoopBars(loop_bars) => {
+                if self
+                    .transport
+                    .set_loop_bars(loop_bars, self.ticks_per_beat())
+                {
+                    self.restart_loop();
+                }
+            }
+            SequencerCommand::Rewind => {
+                self.transport.rewind();
+                self.restart_loop();
+            }
+            SequencerCommand::PressRoll(rate) => {
+                // another rate keeps repeating the note of the roll held
+                self.roll = Some(match self.roll {
+                    Some(roll) => Roll { rate, ..roll },
+                    None => Roll {
+                        rate,
+                        note: self.pitch_producer.tick().step() as u8,
+                        start_tick: self.transport.tick(),
+                    },
+                });
+            }
+            SequencerCommand::ReleaseRoll => {
+                // the triggers take over on the next tick
+                self.roll = None;
+            }
+            SequencerCommand::PreviewNote(note) => {
+                // left out of the statistics and the last note, it isn't part of the music
+                let instrument = self.main_instrument();
+                let velocity = self.velocity_curves.apply(instrument, VELOCITY);
+                self.note_sink.send_program(MIDI_CHANNEL, instrument);
+                self.note_offs.schedule(
+                    self.note_sink.as_mut(),
+                    MIDI_CHANNEL,
+                    note,
+                    velocity,
+                    self.clock.now() + PREVIEW_NOTE_LENGTH,
+                );
+                self.note_sink.send_note_on(MIDI_CHANNEL, note, velocity);
+            }
+            SequencerCommand::SetManualTrigger(manual) => {
+                self.manual_trigger = manual;
+                // presses made before the mode was on are dropped
+                self.shared.manual_trigger.take();
+            }
+            SequencerCommand::SetQuantizeChanges(quantize) => {
+                self.quantize_changes = quantize;
+                if !quantize {
+                    self.apply_pending_changes();
+                }
+            }
+            SequencerCommand::HoldChanges(hold) => self.holding_changes = hold,
+            SequencerCommand::Shutdown(done) => {
+                self.is_playing = false;
+                self.fade.stopped();
+                self.pending_changes.clear();
+                self.all_notes_off();
+                self.shut_down = true;
+                let _ = done.send(());
+            }
+            SequencerCommand::AtNextBar(command) => {
+                if self.quantize_changes && self.is_playing {
+                    let bar = self.transport.bar(self.ticks_per_beat());
+                    self.pending_changes.push((bar, *command));
+                } else {
+                    self.handle_command(*command);
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        // Process all pending commands
+        let commands: Vec<SequencerCommand> = self.receiver.try_iter().collect();
+        for command in commands {
+            let immediate = matches!(
+                command,
+                SequencerCommand::HoldChanges(_)
+                    | SequencerCommand::PreviewNote(_)
+                    | SequencerCommand::Shutdown(_)
+            );
+            if self.holding_changes && !immediate {
+                let command = match command {
+                    SequencerCommand::AtNextBar(command) => *command,
+                    command => command,
+                };
+                self.handle_command(SequencerCommand::AtNextBar(Box::new(command)));
+            } else if !self.pending_changes.is_empty() && command.rebuilds_chains() {
+                // waits behind the held changes rather than bringing them in early
+                self.handle_command(SequencerCommand::AtNextBar(Box::new(command)));
+            } else {
+                self.handle_command(command);
+            }
+        }
+
+        if self.shut_down {
+            return;
+        }
+
+        // Release the notes that are over, even while paused
+        self.note_offs
+            .release_due(self.note_sink.as_mut(), self.clock.now());
+        while self.schedule.take_due(self.clock.now(), MAX_TICK_LATENESS) {
+            self.run_tick();
+        }
+        // Presses play right away rather than on the next tick of the grid, a held roll wins
+        let presses = self.shared.manual_trigger.take();
+        if self.manual_trigger && self.is_playing && self.roll.is_none() {
+            for _ in 0..presses {
+                self.play_step(true);
+            }
+        }
+        let mut state = self.shared.state.write().unwrap();
+        state.transport = self.transport.position(self.ticks_per_beat());
+        state.is_playing = self.is_playing;
+        state.phase = self.fade.phase();
+        state.tempo = self.tempo;
+        state.instrument = self.main_instrument();
+    }
+
+    fn run_tick(&mut self) {
+        let changes = self.glide.tick();
+        self.apply_glide_changes(changes);
+
+        let now = self.clock.now();
+        self.note_offs.release_due(self.note_sink.as_mut(), now);
+        self.update_pressure(now);
+
+        if self.is_playing {
+            self.apply_due_changes();
+            self.update_bar();
+            self.tick_notes.clear();
+            self.play_drums(now);
+            self.play_bass(now);
+            for note in self.delayed_notes.take_due(now) {
+                self.start_note(note, now);
+            }
+            match self.roll {
+                Some(roll) => self.play_roll(roll, now),
+                // the presses of the player play the steps, see tick
+                None if self.manual_trigger => (),
+                // the trigger chain counts every tick, notes still sounding overlap the new one
+                None => self.play_step(false),
+            }
+            // after the main voice, so the layers follow a note starting on this tick
+            self.play_layers(now);
+            if self
+                .transport
+                .advance(self.ticks_per_beat(), self.schedule.tick_length())
+            {
+                self.restart_loop();
+            }
+            if self.fade.tick() {
+                self.stop_playing();
+            }
+        }
+    }
+
+    // Drums run on their own triggers, unaffected by the note length of the main voice
+    fn play_drums(&mut self, now: core::time::Duration) {
+        let Some(drum_machine) = self.drum_machine.as_mut() else {
+            return;
+        };
+        let velocity = self.fade.apply(VELOCITY);
+        for note in drum_machine.tick() {
+            self.note_offs.schedule(
+                self.note_sink.as_mut(),
+                DRUM_CHANNEL,
+                note,
+                velocity,
+                now + DRUM_NOTE_LENGTH,
+            );
+            self.note_sink.send_note_on(DRUM_CHANNEL, note, velocity);
+        }
+    }
+
+    // The bass plays the root of the harmony on the downbeats, below the melody range
+    fn play_bass(&mut self, now: core::time::Duration) {
+        let ticks_per_beat = self.ticks_per_beat();
+        let tick = self.transport.tick();
+        if tick % ticks_per_beat != 0 {
+            return;
+        }
+        let beats_per_bar = self.transport.beats_per_bar();
+        let bass = self.variations.bass(&self.active_variations, self.bass);
+        let Some((note, beats)) = bass.note_at(
+            tick / ticks_per_beat % beats_per_bar,
+            beats_per_bar,
+            self.harmonic_root(),
+            self.glide.min_pitch(),
+        ) else {
+            return;
+        };
+        let note = if self.scale_lock {
+            lock_to_scale(note, self.harmonic_scale())
+        } else {
+            note
+        };
+        // the bass goes before the melody, it keeps its pitch
+        let Some(note) =
+            self.tick_notes
+                .claim(note, self.collision_avoidance, self.harmonic_scale())
+        else {
+            return;
+        };
+        let length = core::time::Duration::from_secs_f32(beats * 60.0 / self.tempo);
+        if !self.allocate_voice(BASS_CHANNEL, note) {
+            return;
+        }
+        let velocity = self
+            .velocity_curves
+            .apply(bass.instrument, self.fade.apply(VELOCITY));
+        self.note_sink.send_program(BASS_CHANNEL, bass.instrument);
+        self.note_offs.schedule(
+            self.note_sink.as_mut(),
+            BASS_CHANNEL,
+            note,
+            velocity,
+            now + length,
+        );
+        self.note_sink.send_note_on(BASS_CHANNEL, note, velocity);
+    }
+
+    // The layers take the bass instrument, the hats go without a program like the drums
+    fn play_layers(&mut self, now: core::time::Duration) {
+        let tick = self.transport.tick();
+        for note in self.layers.tick(tick, self.ticks_per_beat()) {
+            if !self.allocate_voice(note.channel, note.note) {
+                continue;
+            }
+            let mut velocity = self.fade.apply(note.velocity);
+            if note.channel == LAYER_BASS_CHANNEL {
+                velocity = self.velocity_curves.apply(self.bass.instrument, velocity);
+                self.note_sink
+                    .send_program(LAYER_BASS_CHANNEL, self.bass.instrument);
+            }
+            let length = core::time::Duration::from_secs_f32(note.beats * 60.0 / self.tempo);
+            self.note_offs.schedule(
+                self.note_sink.as_mut(),
+                note.channel,
+                note.note,
+                velocity,
+                now + length,
+            );
+            self.note_sink
+                .send_note_on(note.channel, note.note, velocity);
+        }
+    }
+
+    // A held roll repeats its note at a fixed rate instead of the steps of the main voice.
+    // The repeats go straight out, echoes and strums would smear them.
+    fn play_roll(&mut self, roll: Roll, now: core::time::Duration) {
+        let ticks_per_beat = self.ticks_per_beat();
+        if !roll.is_due(self.transport.tick(), ticks_per_beat) {
+            return;
+        }
+        let ticks = roll.rate.interval(ticks_per_beat);
+        let length = core::time::Duration::from_secs_f32(
+            ticks as f32 / ticks_per_beat as f32 * 60.0 / self.tempo,
+        )
+        .mul_f32(self.note_length.min(1.0));
+        let (channel, instrument) =
+            self.split
+                .route(roll.note, MIDI_CHANNEL, self.main_instrument());
+        self.layers.follow(roll.note);
+        self.start_note(
+            NoteEvent {
+                pitch: roll.note,
+                velocity: VELOCITY,
+                duration: length,
+                channel,
+                instrument,
+                offset: core::time::Duration::ZERO,
+            },
+            now,
+        );
+    }
+
+    // Room for a pitched note, within the voices of the ambient mode while it is on
+    fn allocate_voice(&mut self, channel: u8, note: u8) -> bool {
+        let mut voices = self.voices;
+        if let Some(ambient_engine) = self.ambient_engine.as_ref() {
+            voices.max_voices = voices.max_voices.min(ambient_engine.max_voices());
+        }
+        self.note_offs.allocate(
+            self.note_sink.as_mut(),
+            channel,
+            note,
+            voices,
+            &UNVOICED_CHANNELS,
+        )
+    }
+
+    // Plays a note of the main voice that went through the processors
+    fn start_note(&mut self, mut note: NoteEvent, now: core::time::Duration) {
+        note.velocity = self
+            .velocity_curves
+            .apply(note.instrument, self.fade.apply(note.velocity));
+        // a delayed note follows the scale of the time it starts at
+        if self.scale_lock {
+            note.pitch = lock_to_scale(note.pitch, self.harmonic_scale());
+        }
+        if !self.allocate_voice(note.channel, note.pitch) {
+            return;
+        }
+        self.note_sink.send_program(note.channel, note.instrument);
+        self.note_offs.schedule(
+            self.note_sink.as_mut(),
+            note.channel,
+            note.pitch,
+            note.velocity,
+            now + note.duration,
+        );
+        self.note_sink
+            .send_note_on(note.channel, note.pitch, note.velocity);
+        self.shared.statistics.lock().unwrap().add_note(note.pitch);
+        self.shared.state.write().unwrap().last_note = Some(PlayedNote {
+            channel: note.channel,
+            note: note.pitch,
+            velocity: note.velocity,
+        });
+    }
+
+    // Plays the next step of the main voice, triggered by the player when pressed
+    fn play_step(&mut self, pressed: bool) {
+        let mut pitch = self.pitch_producer.tick();
+        let event = if pressed {
+            // a press always plays a note, the rests and ties of the rhythm are skipped
+            for _ in 0..self.rhythm_pattern.len() {
+                if self.sounding_step() {
+                    break;
+                }
+                self.advance_rhythm_index();
+            }
+            TriggerEvent::from(Trigger::On)
+        } else {
+            self.trigger_producer.tick()
+        };
+        let mut trigger = event.fired;
+        let rhythm_step = trigger != Trigger::Off;
+        // The phrase generator picks the notes actually played, and may displace one to a rest
+        if trigger == Trigger::On && self.sounding_step() {
+            if let Some(phrase_generator) = self.phrase_generator.as_mut() {
+                match phrase_generator.next_note(pitch) {
+                    Some(note) => pitch = note,
+                    None => trigger = Trigger::Rest,
+                }
+            }
+        }
+        // Thin out, lower and soften the notes away from the peak of the tension curve
+        let tension = self.tension_level();
+        if trigger == Trigger::On {
+            if let Some(tension_modulator) = self.tension_modulator.as_ref() {
+                if self
+                    .rng
+                    .gen_bool(tension_modulator.thinning_probability(tension))
+                {
+                    trigger = Trigger::Rest;
+                } else {
+                    pitch = tension_modulator.raise_pitch(pitch, tension);
+                }
+            }
+        }
+        // In response bars the second voice replays the transformed call
+        let mut channel = MIDI_CHANNEL;
+        let mut instrument = self.main_instrument();
+        if trigger == Trigger::On && self.sounding_step() {
+            if let Some(call_response) = self.call_response.as_mut() {
+                if call_response.is_responding() {
+                    channel = RESPONSE_CHANNEL;
+                    instrument = call_response.instrument();
+                }
+                match call_response.next_note(pitch) {
+                    Some(note) => pitch = note,
+                    None => trigger = Trigger::Rest,
+                }
+            }
+        }
+        // Ambient mode lets few triggers through and moves them around the drifting center
+        if trigger == Trigger::On {
+            if let Some(ambient_engine) = self.ambient_engine.as_mut() {
+                if ambient_engine.accepts_trigger() {
+                    pitch = ambient_engine.shift(pitch);
+                } else {
+                    trigger = Trigger::Rest;
+                }
+            }
+        }
+        // The note closing the phrase resolves it, in the key reached by the modulation
+        if trigger == Trigger::On
+            && self.sounding_step()
+            && self.cadence.enabled
+            && self.ends_phrase()
+        {
+            let note = self.cadence.resolve(
+                pitch.step().round() as i32,
+                self.harmonic_root(),
+                self.glide.min_pitch(),
+                self.glide.max_pitch(),
+            );
+            pitch = Step(note as f32).to_letter_octave();
+        }
+        // Whatever the stages above did, the locked note is on the scale before it is claimed
+        let mut note = pitch.step() as u8;
+        if self.scale_lock {
+            note = lock_to_scale(note, self.harmonic_scale());
+        }
+        // A pitch another track started on this tick is moved or dropped, doubling sounds phasey
+        if trigger == Trigger::On && self.sounding_step() {
+            match self
+                .tick_notes
+                .claim(note, self.collision_avoidance, self.harmonic_scale())
+            {
+                Some(claimed) => note = claimed,
+                None => trigger = Trigger::Rest,
+            }
+        }
+        match trigger {
+            Trigger::On if !self.sounding_step() => {
+                self.advance_rhythm_index();
+            }
+            Trigger::On => {
+                // Play the generated MIDI note
+                self.layers.follow(note);
+
+                // Apply the groove: delay the note inside its sixteenth and offset its velocity,
+                // a pressed note plays when pressed
+                let sixteenth = self.sixteenth_index();
+                let sixteenth_ms = 15_000.0 / self.tempo;
+                let delay = if pressed {
+                    core::time::Duration::ZERO
+                } else {
+                    core::time::Duration::from_secs_f32(
+                        (self.groove.timing[sixteenth] * sixteenth_ms).max(0.0) / 1000.0,
+                    )
+                };
+                let jitter = (self.rng.gen_range(-1.0..=1.0)
+                    * self.velocity_jitter
+                    * VELOCITY_JITTER_RANGE) as i32;
+                let tension_offset = self
+                    .tension_modulator
+                    .as_ref()
+                    .map_or(0, |t| t.velocity_offset(tension));
+                let accent = self.accent.boost(
+                    &event,
+                    self.transport.tick() % self.ticks_per_bar(),
+                    self.ticks_per_beat(),
+                );
+                let density = self.density_estimator.add(
+                    self.clock.now(),
+                    core::time::Duration::from_secs_f32(60.0 / self.track_tempo()),
+                );
+                let density_offset = self.density_compensation.offset(density);
+                let velocity = (VELOCITY as i32
+                    + self.groove.velocity[sixteenth] as i32
+                    + jitter
+                    + tension_offset
+                    + accent
+                    + density_offset)
+                    .clamp(1, 127) as u8;
+
+                // The note lasts its step of the rhythm, its note-off is scheduled and the
+                // triggers keep coming while it sounds
+                let note_duration = self.next_note_duration();
+                let step = core::time::Duration::from_millis(
+                    (note_duration * 60_000.0 / self.track_tempo()) as u64,
+                );
+                // the note length multiplier stretches the note, not its step in the rhythm
+                let mut length = step.mul_f32(self.note_length);
+                let now = self.clock.now();
+                if let Some(ambient_engine) = self.ambient_engine.as_ref() {
+                    length = length.mul_f32(ambient_engine.note_length());
+                }
+
+                // The processors may add notes and move them after the step
+                let mut notes = std::mem::take(&mut self.step_notes);
+                notes.push(NoteEvent {
+                    pitch: note,
+                    velocity,
+                    duration: length,
+                    channel,
+                    instrument,
+                    // the groove delay, played from the delayed notes rather than waited for
+                    offset: delay,
+                });
+                let context = NoteContext {
+                    scale: self.harmonic_scale(),
+                    beat: core::time::Duration::from_secs_f32(60.0 / self.track_tempo()),
+                };
+                for processor in self.note_processors.iter_mut() {
+                    processor.process(&mut notes, &context);
+                }
+                // the pressure follows the channel the note was routed to
+                if let Some(routed) = notes.iter().find(|n| n.pitch == note) {
+                    channel = routed.channel;
+                }
+                for note in notes.drain(..) {
+                    if note.offset.is_zero() {
+                        self.start_note(note, now);
+                    } else {
+                        self.delayed_notes.push(now + note.offset, note);
+                    }
+                }
+                self.step_notes = notes;
+                if self.pressure_envelope.enabled {
+                    self.start_pressure(PressureNote {
+                        channel,
+                        note,
+                        start: now + delay,
+                        end: now + delay + length,
+                        sent: None,
+                    });
+                }
+            }
+            Trigger::Rest => {
+                // Skip the note but keep the rhythm pattern moving
+                self.advance_rhythm_index();
+            }
+            Trigger::Off => (),
+        }
+        if rhythm_step {
+            self.pitch_producer.update(ChainParameter::AdvanceStep);
+        }
+    }
+}
+
+// Ticks of a beat of the main voice, its triggers come faster or slower than the beats
+fn track_beat_length(resolution: u32, speed: f32) -> u32 {
+    ((resolution as f32 / speed).round() as u32).max(1)
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS_PER_BEAT: u64 = 96;
+    // long enough for a drift of a tick a beat to show
+    const BARS: u64 = 4;
+    const PROGRAM: [u8; 2] = [0xC0, 10];
+    const NOTE_ON: [u8; 3] = [0x90, 60, VELOCITY];
+    const NOTE_OFF: [u8; 3] = [0x80, 60, VELOCITY];
+
+    // The main voice alone, repeating middle C through the beat division only
+    fn config(durations: &[NoteDurationLetter], notes_per_beat: &[u32]) -> SequencerConfiguration {
+        crate::library::reload_library();
+        let mut config: SequencerConfiguration = crate::default_sequencer_model().into();
+        config.pitch_producer = "Melody".to_string();
+        config.melody = vec![60];
+        config.pitch_chain = Vec::new();
+        config.trigger_chain = vec![TriggerStage::BeatDivision];
+        config.beat_weights = vec![1.0; notes_per_beat.len()];
+        config.resolution = TICKS_PER_BEAT as u32;
+        config.rhythm_pattern = RhythmPattern {
+            name: "Test".to_string(),
+            durations: durations.to_vec(),
+            notes_per_beat: notes_per_beat.to_vec(),
+        };
+        config
+    }
+
+    // Runs the ticks one by one as they fall due on a ManualClock and returns the bytes sent,
+    // with the tick they were sent on
+    fn run(config: SequencerConfiguration, ticks: u64) -> Vec<(u64, Vec<u8>)> {
+        let (_tx, rx) = mpsc::channel();
+        let sink = RecordingSink::new();
+        let events = sink.events();
+        let mut thread = SequencerThread::new(
+            rx,
+            config,
+            true,
+            Box::new(ManualClock::new()),
+            Box::new(sink),
+            SharedState::default(),
+        );
+        let mut sent = Vec::new();
+        for tick in 0..ticks {
+            let now = thread.clock.now();
+            let next_tick = thread.schedule.next_tick();
+            thread.clock.sleep(next_tick.saturating_sub(now));
+            thread.tick();
+            sent.extend(
+                events
+                    .lock()
+                    .unwrap()
+                    .drain(..)
+                    .map(|event| (tick, event.to_bytes())),
+            );
+        }
+        sent
+    }
+
+    // The notes starting and ending on the ticks given, a note ending on the tick another
+    // starts released first
+    fn notes(ons: &[u64], offs: &[u64]) -> Vec<(u64, Vec<u8>)> {
+        let mut ticks: Vec<u64> = ons.iter().chain(offs).copied().collect();
+        ticks.sort();
+        ticks.dedup();
+        let mut sent = Vec::new();
+        for tick in ticks {
+            if offs.contains(&tick) {
+                sent.push((tick, NOTE_OFF.to_vec()));
+            }
+            if ons.contains(&tick) {
+                sent.push((tick, PROGRAM.to_vec()));
+                sent.push((tick, NOTE_ON.to_vec()));
+            }
+        }
+        sent
+    }
+
+    // A note on every beat, bar after bar without drifting off the grid
+    #[test]
+    fn straight_pattern() {
+        use NoteDurationLetter::*;
+        let sent = run(
+            config(&[Q, Q, Q, Q], &[1, 1, 1, 1]),
+            BARS * 4 * TICKS_PER_BEAT + 1,
+        );
+        let ons: Vec<u64> = (0..=BARS * 4).map(|beat| beat * TICKS_PER_BEAT).collect();
+        assert_eq!(sent, notes(&ons, &ons[1..]));
+    }
+
+    // Two notes on every beat, the second one half way through it
+    #[test]
+    fn fast_pattern() {
+        use NoteDurationLetter::*;
+        let sent = run(
+            config(&[E; 8], &[2, 2, 2, 2]),
+            BARS * 4 * TICKS_PER_BEAT + 1,
+        );
+        let ons: Vec<u64> = (0..=BARS * 8)
+            .map(|note| note * TICKS_PER_BEAT / 2)
+            .collect();
+        assert_eq!(sent, notes(&ons, &ons[1..]));
+    }
+
+    // The trigger landing on the rest plays nothing and doesn't move the next one
+    #[test]
+    fn rest_skips_a_trigger() {
+        use NoteDurationLetter::*;
+        let sent = run(
+            config(&[Q, Rest, Q, Q], &[1, 1, 1, 1]),
+            4 * TICKS_PER_BEAT + 1,
+        );
+        let expected = notes(&[0, 192, 288, 384], &[96, 288, 384]);
+        assert_eq!(sent, expected);
+    }
+
+    // The half note tied to a quarter sounds for three beats, the triggers of the tie and of
+    // the quarter stay silent
+    #[test]
+    fn tie_merges_durations() {
+        use NoteDurationLetter::*;
+        let sent = run(
+            config(&[H, Tie, Q, Q], &[1, 1, 1, 1]),
+            4 * TICKS_PER_BEAT + 1,
+        );
+        let expected = notes(&[0, 288, 384], &[288, 384]);
+        assert_eq!(sent, expected);
+    }
+
+    // Rotated onto the tie, the steps it carries over from the end of the pattern stay silent
+    #[test]
+    fn rotation_skips_a_leading_tie() {
+        use NoteDurationLetter::*;
+        let mut config = config(&[H, Tie, Q, Q], &[1, 1, 1, 1]);
+        config.rhythm_rotation = 1;
+        let sent = run(config, 4 * TICKS_PER_BEAT + 1);
+        let expected = notes(&[192, 288], &[288]);
+        assert_eq!(sent, expected);
+    }
+
+    // At twice the speed the pattern plays twice in a bar, with notes half as long
+    #[test]
+    fn double_speed_doubles_the_notes() {
+        use NoteDurationLetter::*;
+        let mut config = config(&[Q, Q, Q, Q], &[1, 1, 1, 1]);
+        config.speed = 2.0;
+        let sent = run(config, 4 * TICKS_PER_BEAT + 1);
+        let ons: Vec<u64> = (0..=8).map(|note| note * TICKS_PER_BEAT / 2).collect();
+        assert_eq!(sent, notes(&ons, &ons[1..]));
+    }
+
+    // The melody moves on a note per step of the rhythm, not per tick
+    #[test]
+    fn melody_plays_in_order() {
+        use NoteDurationLetter::*;
+        let melody = vec![60, 62, 64, 65];
+        let mut config = config(&[Q, Q, Q, Q], &[1, 1, 1, 1]);
+        config.melody = melody.clone();
+        let played: Vec<u8> = run(config, 2 * 4 * TICKS_PER_BEAT)
+            .into_iter()
+            .filter(|(_, bytes)| bytes[0] == NOTE_ON[0])
+            .map(|(_, bytes)| bytes[1])
+            .collect();
+        assert_eq!(played, [melody.clone(), melody].concat());
+    }
+
+    // Shutting down releases the note sounding and sends all notes off on every track
+    #[test]
+    fn shutdown_silences_every_track() {
+        use NoteDurationLetter::*;
+        let (tx, rx) = mpsc::channel();
+        let sink = RecordingSink::new();
+        let events = sink.events();
+        let mut thread = SequencerThread::new(
+            rx,
+            config(&[Q, Q, Q, Q], &[1, 1, 1, 1]),
+            true,
+            Box::new(ManualClock::new()),
+            Box::new(sink),
+            SharedState::default(),
+        );
+        thread.tick();
+        events.lock().unwrap().clear();
+        let (done, finished) = mpsc::channel();
+        tx.send(SequencerCommand::Shutdown(done)).unwrap();
+        thread.tick();
+        assert!(finished.try_recv().is_ok());
+        let sent: Vec<Vec<u8>> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(SinkEvent::to_bytes)
+            .collect();
+        assert_eq!(sent[0], NOTE_OFF);
+        for channel in TRACK_CHANNELS {
+            assert!(
+                sent.contains(&vec![0xB0 | channel, ALL_NOTES_OFF_CONTROLLER, 0]),
+                "channel {}",
+                channel
+            );
         }
     }
 }
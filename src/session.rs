@@ -0,0 +1,127 @@
+use std::{
+    fs,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::abc::Melody;
+use crate::preset::Preset;
+use crate::storage::{self, config_dir};
+use crate::SequencerModel;
+
+//constants
+const RECOVERY_FILE: &str = "recovery.json";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub enum RecoveryAction {
+    Restore,
+    Discard,
+}
+
+// What a crash would lose: the settings and the looped melody
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub preset: Preset,
+    pub melody_title: String,
+    pub melody: Vec<u8>,
+}
+
+impl Session {
+    fn capture(model: &SequencerModel, melody: Option<&Melody>) -> Session {
+        Session {
+            preset: Preset::capture(model),
+            melody_title: melody.map_or(String::new(), |melody| melody.title.clone()),
+            melody: model.melody.clone(),
+        }
+    }
+
+    pub fn melody(&self) -> Option<Melody> {
+        (!self.melody.is_empty()).then(|| Melody {
+            title: self.melody_title.clone(),
+            notes: self.melody.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryFile {
+    pub saved_at: String,
+    pub session: Session,
+}
+
+// Saves the session to the recovery file from a writer thread whenever it changed, at the
+// autosave interval. A clean exit removes the file, so finding it at startup means the last
+// run did not end that way.
+pub struct Autosave {
+    sender: Option<mpsc::Sender<RecoveryFile>>,
+    writer: Option<thread::JoinHandle<()>>,
+    last_check: Option<Instant>,
+    last_saved: Option<Session>,
+    // found at startup, kept until restored or discarded and not overwritten meanwhile
+    pub recovered: Option<RecoveryFile>,
+}
+
+impl Autosave {
+    pub fn start() -> Autosave {
+        let recovered = storage::load_json(RECOVERY_FILE);
+        let (sender, receiver) = mpsc::channel::<RecoveryFile>();
+        let writer = thread::spawn(move || {
+            for file in receiver {
+                if let Err(err) = storage::save_json(RECOVERY_FILE, &file) {
+                    eprintln!("Could not autosave: {}", err);
+                }
+            }
+        });
+        Autosave {
+            sender: Some(sender),
+            writer: Some(writer),
+            last_check: Some(Instant::now()),
+            last_saved: None,
+            recovered,
+        }
+    }
+
+    pub fn update(&mut self, model: &SequencerModel, melody: Option<&Melody>) {
+        let due = self
+            .last_check
+            .is_none_or(|last_check| last_check.elapsed() >= AUTOSAVE_INTERVAL);
+        if self.recovered.is_some() || !due {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+        let session = Session::capture(model, melody);
+        if self.last_saved.as_ref() == Some(&session) {
+            return;
+        }
+        let file = RecoveryFile {
+            saved_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            session: session.clone(),
+        };
+        if let Some(sender) = &self.sender {
+            if sender.send(file).is_ok() {
+                self.last_saved = Some(session);
+            }
+        }
+    }
+
+    // The recovered session goes away and the next update saves the current one
+    pub fn take_recovered(&mut self) -> Option<Session> {
+        self.last_check = None;
+        self.recovered.take().map(|file| file.session)
+    }
+
+    // On a clean exit, once the pending saves are written
+    pub fn finish(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        if self.recovered.is_none() {
+            let _ = fs::remove_file(config_dir().join(RECOVERY_FILE));
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::ump::{note_pressure_words, ump_words, MidiProtocol};
+
+//constants
+const NOTE_ON_MSG: u8 = 0x90;
+const NOTE_OFF_MSG: u8 = 0x80;
+const CONTROL_CHANGE_MSG: u8 = 0xB0;
+const PROGRAM_CHANGE_MSG: u8 = 0xC0;
+const CHANNEL_PRESSURE_MSG: u8 = 0xD0;
+const MIDI_CLIENT_NAME: &str = "Generative Sequencer";
+// note ons kept for the UI when it does not take them
+const MAX_TAPPED_NOTE_ONS: usize = 256;
+
+// Everything the sequencer emits goes through a NoteSink
+pub trait NoteSink: Send {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8);
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8);
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8);
+    fn send_program(&mut self, channel: u8, program: u8);
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8);
+
+    // Pressure meant for one note, the whole channel takes it unless the sink can do better
+    fn send_note_pressure(&mut self, channel: u8, _note: u8, pressure: u8) {
+        self.send_channel_pressure(channel, pressure);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SinkEvent {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+}
+
+impl SinkEvent {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            SinkEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => vec![NOTE_ON_MSG | channel, note, velocity],
+            SinkEvent::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => vec![NOTE_OFF_MSG | channel, note, velocity],
+            SinkEvent::ControlChange {
+                channel,
+                controller,
+                value,
+            } => vec![CONTROL_CHANGE_MSG | channel, controller, value],
+            SinkEvent::ProgramChange { channel, program } => {
+                vec![PROGRAM_CHANGE_MSG | channel, program]
+            }
+            SinkEvent::ChannelPressure { channel, pressure } => {
+                vec![CHANNEL_PRESSURE_MSG | channel, pressure]
+            }
+        }
+    }
+}
+
+pub struct MidiSink {
+    connection: MidiOutputConnection,
+    // the one negotiated, not the one asked for
+    protocol: MidiProtocol,
+    // the port went away, reported once until it takes messages again
+    failing: bool,
+}
+
+impl MidiSink {
+    // Connect to the first available MIDI output port (IAC Bus 1)
+    pub fn connect_first_port(protocol: MidiProtocol) -> Option<MidiSink> {
+        let midi_out = MidiOutput::new(MIDI_CLIENT_NAME).ok()?;
+        let out_port = midi_out.ports().into_iter().next()?;
+        let connection = midi_out.connect(&out_port, MIDI_CLIENT_NAME).ok()?;
+        Some(MidiSink {
+            connection,
+            protocol: protocol.negotiate(),
+            failing: false,
+        })
+    }
+
+    pub fn connect_port(name: &str, protocol: MidiProtocol) -> Option<MidiSink> {
+        let midi_out = MidiOutput::new(MIDI_CLIENT_NAME).ok()?;
+        let out_port = midi_out
+            .ports()
+            .into_iter()
+            .find(|port| midi_out.port_name(port).ok().as_deref() == Some(name))?;
+        let connection = midi_out.connect(&out_port, MIDI_CLIENT_NAME).ok()?;
+        Some(MidiSink {
+            connection,
+            protocol: protocol.negotiate(),
+            failing: false,
+        })
+    }
+
+    pub fn port_names() -> Vec<String> {
+        let Ok(midi_out) = MidiOutput::new(MIDI_CLIENT_NAME) else {
+            return Vec::new();
+        };
+        midi_out
+            .ports()
+            .iter()
+            .filter_map(|port| midi_out.port_name(port).ok())
+            .collect()
+    }
+
+    fn send(&mut self, event: SinkEvent) {
+        match self.protocol {
+            MidiProtocol::Midi1 => self.send_bytes(&event.to_bytes()),
+            MidiProtocol::Midi2 => self.send_ump(ump_words(&event)),
+        }
+    }
+
+    // Most significant byte first, the words as laid out in the specification
+    fn send_ump(&mut self, words: [u32; 2]) {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        self.send_bytes(&bytes);
+    }
+
+    // An unplugged port drops the messages rather than stopping the sequencer
+    fn send_bytes(&mut self, bytes: &[u8]) {
+        match self.connection.send(bytes) {
+            Ok(()) => self.failing = false,
+            Err(err) if !self.failing => {
+                eprintln!("Could not send to the MIDI output: {}", err);
+                self.failing = true;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+impl NoteSink for MidiSink {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.send(SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.send(SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8) {
+        self.send(SinkEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        });
+    }
+
+    fn send_program(&mut self, channel: u8, program: u8) {
+        self.send(SinkEvent::ProgramChange { channel, program });
+    }
+
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8) {
+        self.send(SinkEvent::ChannelPressure { channel, pressure });
+    }
+
+    fn send_note_pressure(&mut self, channel: u8, note: u8, pressure: u8) {
+        match self.protocol {
+            MidiProtocol::Midi1 => self.send_channel_pressure(channel, pressure),
+            MidiProtocol::Midi2 => self.send_ump(note_pressure_words(channel, note, pressure)),
+        }
+    }
+}
+
+// Keeps every event in memory; the shared buffer can be inspected from another thread
+pub struct RecordingSink {
+    events: Arc<Mutex<Vec<SinkEvent>>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> RecordingSink {
+        RecordingSink {
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn events(&self) -> Arc<Mutex<Vec<SinkEvent>>> {
+        self.events.clone()
+    }
+
+    fn record(&mut self, event: SinkEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl NoteSink for RecordingSink {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.record(SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.record(SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8) {
+        self.record(SinkEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        });
+    }
+
+    fn send_program(&mut self, channel: u8, program: u8) {
+        self.record(SinkEvent::ProgramChange { channel, program });
+    }
+
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8) {
+        self.record(SinkEvent::ChannelPressure { channel, pressure });
+    }
+}
+
+// Passes the note ons on to the UI, the oldest go when they are not taken
+pub struct NoteOnTap {
+    note_ons: Arc<Mutex<VecDeque<SinkEvent>>>,
+}
+
+impl NoteOnTap {
+    pub fn new(note_ons: Arc<Mutex<VecDeque<SinkEvent>>>) -> NoteOnTap {
+        NoteOnTap { note_ons }
+    }
+}
+
+impl NoteSink for NoteOnTap {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        let mut note_ons = self.note_ons.lock().unwrap();
+        if note_ons.len() >= MAX_TAPPED_NOTE_ONS {
+            note_ons.pop_front();
+        }
+        note_ons.push_back(SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        });
+    }
+
+    fn send_note_off(&mut self, _channel: u8, _note: u8, _velocity: u8) {}
+    fn send_cc(&mut self, _channel: u8, _controller: u8, _value: u8) {}
+    fn send_program(&mut self, _channel: u8, _program: u8) {}
+    fn send_channel_pressure(&mut self, _channel: u8, _pressure: u8) {}
+}
+
+// Sends everything to several sinks, e.g. MIDI out and the internal audio
+pub struct FanOutSink {
+    sinks: Vec<Box<dyn NoteSink>>,
+}
+
+impl FanOutSink {
+    pub fn new(sinks: Vec<Box<dyn NoteSink>>) -> FanOutSink {
+        FanOutSink { sinks }
+    }
+}
+
+impl NoteSink for FanOutSink {
+    fn send_note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_note_on(channel, note, velocity);
+        }
+    }
+
+    fn send_note_off(&mut self, channel: u8, note: u8, velocity: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_note_off(channel, note, velocity);
+        }
+    }
+
+    fn send_cc(&mut self, channel: u8, controller: u8, value: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_cc(channel, controller, value);
+        }
+    }
+
+    fn send_program(&mut self, channel: u8, program: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_program(channel, program);
+        }
+    }
+
+    fn send_channel_pressure(&mut self, channel: u8, pressure: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_channel_pressure(channel, pressure);
+        }
+    }
+
+    fn send_note_pressure(&mut self, channel: u8, note: u8, pressure: u8) {
+        for sink in self.sinks.iter_mut() {
+            sink.send_note_pressure(channel, note, pressure);
+        }
+    }
+}
+
+// Discards everything, used when no MIDI output port is available
+pub struct NullSink;
+
+impl NoteSink for NullSink {
+    fn send_note_on(&mut self, _channel: u8, _note: u8, _velocity: u8) {}
+    fn send_note_off(&mut self, _channel: u8, _note: u8, _velocity: u8) {}
+    fn send_cc(&mut self, _channel: u8, _controller: u8, _value: u8) {}
+    fn send_program(&mut self, _channel: u8, _program: u8) {}
+    fn send_channel_pressure(&mut self, _channel: u8, _pressure: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_bytes() {
+        let events = [
+            (
+                SinkEvent::NoteOn {
+                    channel: 2,
+                    note: 60,
+                    velocity: 100,
+                },
+                vec![0x92, 60, 100],
+            ),
+            (
+                SinkEvent::NoteOff {
+                    channel: 2,
+                    note: 60,
+                    velocity: 64,
+                },
+                vec![0x82, 60, 64],
+            ),
+            (
+                SinkEvent::ControlChange {
+                    channel: 15,
+                    controller: 64,
+                    value: 127,
+                },
+                vec![0xBF, 64, 127],
+            ),
+            (
+                SinkEvent::ProgramChange {
+                    channel: 0,
+                    program: 33,
+                },
+                vec![0xC0, 33],
+            ),
+            (
+                SinkEvent::ChannelPressure {
+                    channel: 9,
+                    pressure: 80,
+                },
+                vec![0xD9, 80],
+            ),
+        ];
+        for (event, bytes) in events {
+            assert_eq!(event.to_bytes(), bytes, "{:?}", event);
+        }
+    }
+
+    // The recording sink stands in for a MIDI port, note pressure falls back to the channel
+    #[test]
+    fn recording_sink_keeps_events_in_order() {
+        let mut sink = RecordingSink::new();
+        let events = sink.events();
+        sink.send_program(1, 5);
+        sink.send_note_on(1, 64, 90);
+        sink.send_note_pressure(1, 64, 30);
+        sink.send_cc(1, 7, 100);
+        sink.send_note_off(1, 64, 0);
+        let bytes: Vec<Vec<u8>> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(SinkEvent::to_bytes)
+            .collect();
+        assert_eq!(
+            bytes,
+            vec![
+                vec![0xC1, 5],
+                vec![0x91, 64, 90],
+                vec![0xD1, 30],
+                vec![0xB1, 7, 100],
+                vec![0x81, 64, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn fan_out_sends_to_every_sink() {
+        let (first, second) = (RecordingSink::new(), RecordingSink::new());
+        let (first_events, second_events) = (first.events(), second.events());
+        let mut sink = FanOutSink::new(vec![Box::new(first), Box::new(second)]);
+        sink.send_note_on(0, 48, 100);
+        let expected = vec![SinkEvent::NoteOn {
+            channel: 0,
+            note: 48,
+            velocity: 100,
+        }];
+        assert_eq!(*first_events.lock().unwrap(), expected);
+        assert_eq!(*second_events.lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,182 @@
+use pitch_calc::*;
+
+use crate::chain::ChainParameter;
+use crate::pitch::scale_from_mask;
+use crate::sequencer::SequencerConfiguration;
+
+// A value that moves towards its target at a fixed rate per tick
+#[derive(Clone, Copy)]
+struct SlewedValue {
+    current: f32,
+    target: f32,
+    rate: f32,
+}
+
+impl SlewedValue {
+    fn new(value: f32) -> SlewedValue {
+        SlewedValue {
+            current: value,
+            target: value,
+            rate: 0.0,
+        }
+    }
+
+    // Reach the target in the given number of ticks, or immediately for 0
+    fn set_target(&mut self, target: f32, ticks: f32) {
+        self.target = target;
+        if ticks < 1.0 {
+            self.current = target;
+            self.rate = 0.0;
+        } else {
+            self.rate = (target - self.current).abs() / ticks;
+        }
+    }
+
+    fn step(&mut self) {
+        if self.current < self.target {
+            self.current = (self.current + self.rate).min(self.target);
+        } else if self.current > self.target {
+            self.current = (self.current - self.rate).max(self.target);
+        }
+    }
+}
+
+// Parameters applied in place on the running chains as they glide
+#[derive(Clone, Copy, PartialEq)]
+pub enum SlewedParameter {
+    Bpm,
+    TriggerProbability,
+}
+
+// What moved during a glide tick
+pub struct GlideChanges {
+    // the pitch range moved, the pitch chain has to be rebuilt
+    pub pitch_chain: bool,
+    pub bpm: Option<f32>,
+    pub trigger_probability: Option<f64>,
+}
+
+// Target versus current state of the parameters the sequencer glides between when they change:
+// pitch range, tempo and trigger density. Everything else in the target applies immediately.
+pub struct ParameterGlide {
+    target: SequencerConfiguration,
+    min_pitch: SlewedValue,
+    max_pitch: SlewedValue,
+    bpm: SlewedValue,
+    trigger_probability: SlewedValue,
+}
+
+impl ParameterGlide {
+    pub fn new(config: SequencerConfiguration) -> ParameterGlide {
+        ParameterGlide {
+            min_pitch: SlewedValue::new(config.min_pitch.step()),
+            max_pitch: SlewedValue::new(config.max_pitch.step()),
+            bpm: SlewedValue::new(config.bpm),
+            trigger_probability: SlewedValue::new(config.trigger_probability as f32),
+            target: config,
+        }
+    }
+
+    pub fn set_target(&mut self, config: SequencerConfiguration, ticks: f32) {
+        self.min_pitch.set_target(config.min_pitch.step(), ticks);
+        self.max_pitch.set_target(config.max_pitch.step(), ticks);
+        self.bpm.set_target(config.bpm, ticks);
+        self.trigger_probability
+            .set_target(config.trigger_probability as f32, ticks);
+        self.target = config;
+    }
+
+    pub fn set_parameter(&mut self, parameter: SlewedParameter, value: f32, ticks: f32) {
+        match parameter {
+            SlewedParameter::Bpm => {
+                self.bpm.set_target(value, ticks);
+                self.target.bpm = value;
+            }
+            SlewedParameter::TriggerProbability => {
+                self.trigger_probability.set_target(value, ticks);
+                self.target.trigger_probability = value as f64;
+            }
+        }
+    }
+
+    // The scale of the target, before any key modulation
+    pub fn scale(&self) -> &[Letter] {
+        &self.target.quantizer_scale
+    }
+
+    // Pitch class of the root, 0 being C
+    pub fn root(&self) -> usize {
+        self.target.root
+    }
+
+    pub fn set_root(&mut self, root: usize) {
+        self.target.root = root;
+    }
+
+    // Lowest note of the melody range, where it is now
+    pub fn min_pitch(&self) -> i32 {
+        self.min_pitch.current.round() as i32
+    }
+
+    pub fn max_pitch(&self) -> i32 {
+        self.max_pitch.current.round() as i32
+    }
+
+    // Rotations are applied in place, kept here for the next rebuild
+    pub fn set_rotation(&mut self, rhythm: u32, pitch: u32) {
+        self.target.rhythm_rotation = rhythm;
+        self.target.pitch_rotation = pitch;
+    }
+
+    // Keep the target in sync with values changed in place, for the next rebuild
+    pub fn update_chain(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::RestProbability(probability) => {
+                self.target.rest_probability = probability
+            }
+            ChainParameter::OctaveJumpProbability(probability) => {
+                self.target.octave_jump_probability = probability
+            }
+            ChainParameter::HoldSteps(steps) => self.target.sample_hold_steps = steps,
+            ChainParameter::BeatLength(ticks) => self.target.resolution = ticks,
+            ChainParameter::Scale(mask) => self.target.quantizer_scale = scale_from_mask(mask),
+            _ => (),
+        }
+    }
+
+    pub fn tick(&mut self) -> GlideChanges {
+        let before = self.current_values();
+        self.min_pitch.step();
+        self.max_pitch.step();
+        self.bpm.step();
+        self.trigger_probability.step();
+        let after = self.current_values();
+        GlideChanges {
+            pitch_chain: before.0 != after.0 || before.1 != after.1,
+            bpm: (before.2 != after.2).then_some(after.2 as f32),
+            trigger_probability: (before.3 != after.3).then_some(after.3 as f64 / 100.0),
+        }
+    }
+
+    // The target configuration with the slewed parameters at their current value
+    pub fn current(&self) -> SequencerConfiguration {
+        let (min_pitch, max_pitch, bpm, trigger_probability) = self.current_values();
+        SequencerConfiguration {
+            min_pitch: Step(min_pitch as f32).to_letter_octave(),
+            max_pitch: Step(max_pitch as f32).to_letter_octave(),
+            bpm: bpm as f32,
+            trigger_probability: trigger_probability as f64 / 100.0,
+            ..self.target.clone()
+        }
+    }
+
+    // Rounded to whole semitones, whole BPM and percents so the chains are not rebuilt every tick
+    fn current_values(&self) -> (i32, i32, i32, i32) {
+        (
+            self.min_pitch.current.round() as i32,
+            self.max_pitch.current.round() as i32,
+            self.bpm.current.round() as i32,
+            (self.trigger_probability.current * 100.0).round() as i32,
+        )
+    }
+}
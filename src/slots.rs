@@ -0,0 +1,44 @@
+use crate::preset::Preset;
+use crate::storage;
+use crate::SequencerModel;
+
+//constants
+const SLOTS_FILE: &str = "slots.json";
+pub const SLOT_COUNT: usize = 8;
+
+pub enum SlotAction {
+    Store(usize),
+    Recall(usize),
+}
+
+// Whole configurations kept at hand for arranging live, saved as soon as one is stored
+pub struct PatternSlots {
+    slots: Vec<Option<Preset>>,
+}
+
+impl PatternSlots {
+    pub fn load() -> PatternSlots {
+        let mut slots: Vec<Option<Preset>> = storage::load_json(SLOTS_FILE).unwrap_or_default();
+        slots.resize(SLOT_COUNT, None);
+        PatternSlots { slots }
+    }
+
+    pub fn is_filled(&self, index: usize) -> bool {
+        self.slots[index].is_some()
+    }
+
+    pub fn store(&mut self, index: usize, model: &SequencerModel) {
+        self.slots[index] = Some(Preset::capture(model));
+        if let Err(err) = storage::save_json(SLOTS_FILE, &self.slots) {
+            eprintln!("Could not save the slots: {}", err);
+        }
+    }
+
+    // Sets the model from the slot, returns what could not be applied
+    pub fn recall(&self, index: usize, model: &mut SequencerModel) -> Vec<String> {
+        match &self.slots[index] {
+            Some(preset) => preset.apply(model),
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
+
+use crate::sink::SinkEvent;
+use crate::synth::SoundSource;
+
+pub fn load_sound_font(path: &Path) -> Result<Arc<SoundFont>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let sound_font = SoundFont::new(&mut BufReader::new(file)).map_err(|err| err.to_string())?;
+    Ok(Arc::new(sound_font))
+}
+
+// General MIDI playback of the events through a SoundFont; program changes pick the instrument
+pub struct SoundFontPlayer {
+    synthesizer: Synthesizer,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl SoundFontPlayer {
+    pub fn new(sound_font: &Arc<SoundFont>, sample_rate: u32) -> Result<SoundFontPlayer, String> {
+        let settings = SynthesizerSettings::new(sample_rate as i32);
+        let synthesizer = Synthesizer::new(sound_font, &settings).map_err(|err| err.to_string())?;
+        Ok(SoundFontPlayer {
+            synthesizer,
+            left: Vec::new(),
+            right: Vec::new(),
+        })
+    }
+}
+
+impl SoundSource for SoundFontPlayer {
+    fn handle_event(&mut self, event: SinkEvent) {
+        let bytes = event.to_bytes();
+        let data = |index: usize| bytes.get(index).copied().unwrap_or(0) as i32;
+        self.synthesizer.process_midi_message(
+            (bytes[0] & 0x0F) as i32,
+            (bytes[0] & 0xF0) as i32,
+            data(1),
+            data(2),
+        );
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        // the synthesizer overwrites its buffers, sources add to theirs
+        self.left.resize(left.len(), 0.0);
+        self.right.resize(right.len(), 0.0);
+        self.synthesizer.render(&mut self.left, &mut self.right);
+        for (out, sample) in left.iter_mut().zip(&self.left) {
+            *out += sample;
+        }
+        for (out, sample) in right.iter_mut().zip(&self.right) {
+            *out += sample;
+        }
+    }
+}
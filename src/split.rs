@@ -0,0 +1,25 @@
+//constants
+// the notes below the split point play here
+pub const SPLIT_CHANNEL: u8 = 2;
+
+// Keyboard split of the main voice: from the point up it plays as usual, below it on its own
+// channel and instrument, so one generated line gives both the bass and the lead
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyboardSplit {
+    pub enabled: bool,
+    // MIDI note, the lowest one left to the main instrument
+    pub point: u8,
+    // of the notes below the point
+    pub instrument: u8,
+}
+
+impl KeyboardSplit {
+    // Channel and instrument a note of the main voice goes to
+    pub fn route(&self, note: u8, channel: u8, instrument: u8) -> (u8, u8) {
+        if self.enabled && note < self.point {
+            (SPLIT_CHANNEL, self.instrument)
+        } else {
+            (channel, instrument)
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use crate::notes::PITCH_CLASS_COUNT;
+
+//constants
+// bars kept for the statistics window
+pub const MAX_STATISTICS_BARS: usize = 64;
+
+#[derive(Clone, Copy, Default)]
+struct BarStatistics {
+    pitch_classes: [u32; PITCH_CLASS_COUNT],
+    notes: u32,
+    interval_sum: u32,
+    intervals: u32,
+}
+
+// Notes played by the sequencer thread, gathered per bar
+#[derive(Default)]
+pub struct NoteStatistics {
+    // oldest first, the last one is the bar being played
+    bars: VecDeque<BarStatistics>,
+    last_note: Option<u8>,
+}
+
+pub struct StatisticsSummary {
+    pub pitch_classes: [u32; PITCH_CLASS_COUNT],
+    // in semitones, between consecutive notes
+    pub average_interval: Option<f32>,
+    // oldest first
+    pub notes_per_bar: Vec<u32>,
+}
+
+impl NoteStatistics {
+    pub fn start_bar(&mut self) {
+        if self.bars.len() == MAX_STATISTICS_BARS {
+            self.bars.pop_front();
+        }
+        self.bars.push_back(BarStatistics::default());
+    }
+
+    pub fn add_note(&mut self, note: u8) {
+        if self.bars.is_empty() {
+            self.start_bar();
+        }
+        let bar = self.bars.back_mut().unwrap();
+        bar.pitch_classes[note as usize % PITCH_CLASS_COUNT] += 1;
+        bar.notes += 1;
+        if let Some(last_note) = self.last_note {
+            bar.interval_sum += last_note.abs_diff(note) as u32;
+            bar.intervals += 1;
+        }
+        self.last_note = Some(note);
+    }
+
+    // A stop breaks the line, the interval to the next note is not counted
+    pub fn stop(&mut self) {
+        self.last_note = None;
+    }
+
+    // Over the last bars, the current one included
+    pub fn summary(&self, bars: usize) -> StatisticsSummary {
+        let recent: Vec<&BarStatistics> = self
+            .bars
+            .iter()
+            .skip(self.bars.len().saturating_sub(bars))
+            .collect();
+        let mut pitch_classes = [0; PITCH_CLASS_COUNT];
+        let (mut interval_sum, mut intervals) = (0, 0);
+        for bar in &recent {
+            for (total, count) in pitch_classes.iter_mut().zip(bar.pitch_classes) {
+                *total += count;
+            }
+            interval_sum += bar.interval_sum;
+            intervals += bar.intervals;
+        }
+        StatisticsSummary {
+            pitch_classes,
+            average_interval: (intervals > 0).then(|| interval_sum as f32 / intervals as f32),
+            notes_per_bar: recent.iter().map(|bar| bar.notes).collect(),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::{env, fs, io, path::PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+//constants
+const CONFIG_DIR_NAME: &str = "sound-generator";
+
+// %APPDATA%/sound-generator on Windows, ~/.config/sound-generator elsewhere
+pub fn config_dir() -> PathBuf {
+    let base = match env::var_os("APPDATA") {
+        Some(app_data) => PathBuf::from(app_data),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+    base.join(CONFIG_DIR_NAME)
+}
+
+pub fn load_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let contents = fs::read_to_string(config_dir().join(file_name)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn save_json<T: Serialize>(file_name: &str, value: &T) -> io::Result<()> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    let contents = serde_json::to_string_pretty(value)?;
+    fs::write(dir.join(file_name), contents)
+}
@@ -0,0 +1,63 @@
+use rand::prelude::*;
+use std::{fmt::Display, str::FromStr};
+
+// When the sustain pedal (CC64) is pressed, checked at the start of every bar
+#[derive(Clone, Copy, PartialEq)]
+pub enum SustainMode {
+    Off,
+    Bar,
+    Phrase,
+    Random,
+}
+
+impl Display for SustainMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SustainMode::Off => write!(f, "Off"),
+            SustainMode::Bar => write!(f, "Bar"),
+            SustainMode::Phrase => write!(f, "Phrase"),
+            SustainMode::Random => write!(f, "Random"),
+        }
+    }
+}
+
+impl FromStr for SustainMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Off" => Ok(SustainMode::Off),
+            "Bar" => Ok(SustainMode::Bar),
+            "Phrase" => Ok(SustainMode::Phrase),
+            "Random" => Ok(SustainMode::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SustainAutomation {
+    pub mode: SustainMode,
+    pub phrase_bars: u32,
+    pub probability: f64,
+}
+
+impl SustainAutomation {
+    // Pedal state for the bar starting now:
+    // Some(true) presses it (lifting it first to clear the previous wash), Some(false) lifts it,
+    // None keeps it as it is
+    pub fn pedal_at_bar<R: Rng>(&self, bar: u64, rng: &mut R) -> Option<bool> {
+        match self.mode {
+            SustainMode::Off => Some(false),
+            SustainMode::Bar => Some(true),
+            SustainMode::Phrase => {
+                if bar % self.phrase_bars.max(1) as u64 == 0 {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            SustainMode::Random => Some(rng.gen_bool(self.probability.clamp(0.0, 1.0))),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::drums::DrumVoiceSettings;
+use crate::sink::SinkEvent;
+
+//constants
+const SUSTAIN_CONTROLLER: u8 = 64;
+const ALL_NOTES_OFF_CONTROLLER: u8 = 123;
+// keeps the filter from self-oscillating
+const MAX_RESONANCE: f32 = 0.95;
+const MAX_VOICES: usize = 32;
+// keeps a full chord of voices below clipping
+const OUTPUT_GAIN: f32 = 0.2;
+const CLICK_FREQUENCY: f32 = 1000.0;
+const CLICK_SECONDS: f32 = 0.02;
+const CLICK_GAIN: f32 = 0.5;
+
+// Anything that turns sequencer events into audio
+pub trait SoundSource: Send {
+    fn handle_event(&mut self, event: SinkEvent);
+    // adds the next block of samples to both buffers, which have the same length
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]);
+    // applied in place, sources take the ones they use
+    fn update(&mut self, _parameter: SoundParameter) {}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SoundParameter {
+    Synth(SynthSettings),
+    Drums([DrumVoiceSettings; 3]),
+}
+
+pub fn note_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+pub const WAVEFORMS: &[(Waveform, &str)] = &[
+    (Waveform::Sine, "Sine"),
+    (Waveform::Triangle, "Triangle"),
+    (Waveform::Saw, "Saw"),
+    (Waveform::Square, "Square"),
+];
+
+impl Waveform {
+    pub fn name(&self) -> &'static str {
+        WAVEFORMS.iter().find(|(w, _)| w == self).unwrap().1
+    }
+
+    // phase in [0, 1)
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (TAU * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * phase - 1.0,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+// The editable voice: an oscillator phase-modulated by a sine operator (2-op FM),
+// through a resonant low-pass filter and an ADSR envelope
+#[derive(Clone, Copy, PartialEq)]
+pub struct SynthSettings {
+    pub waveform: Waveform,
+    // modulator frequency relative to the note
+    pub fm_ratio: f32,
+    // 0 leaves the oscillator unmodulated
+    pub fm_index: f32,
+    pub cutoff: f32, // Hz
+    pub resonance: f32,
+    pub attack: f32, // seconds
+    pub decay: f32,  // seconds
+    pub sustain: f32,
+    pub release: f32, // seconds
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+struct Voice {
+    channel: u8,
+    note: u8,
+    phase: f32,
+    modulator_phase: f32,
+    increment: f32,
+    amplitude: f32,
+    stage: Stage,
+    level: f32,
+    // released but kept by the sustain pedal
+    sustained: bool,
+    // state variable filter
+    low: f32,
+    band: f32,
+}
+
+impl Voice {
+    fn is_held(&self) -> bool {
+        self.stage != Stage::Release
+    }
+
+    fn step_envelope(&mut self, settings: &SynthSettings, sample_rate: f32) {
+        let rate = |seconds: f32| 1.0 / (seconds * sample_rate).max(1.0);
+        match self.stage {
+            Stage::Attack => {
+                self.level += rate(settings.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= rate(settings.decay) * (1.0 - settings.sustain);
+                if self.level <= settings.sustain {
+                    self.level = settings.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = settings.sustain,
+            Stage::Release => {
+                if !self.sustained {
+                    self.level = (self.level - rate(settings.release)).max(0.0);
+                }
+            }
+        }
+    }
+}
+
+pub struct Synth {
+    sample_rate: f32,
+    settings: SynthSettings,
+    voices: Vec<Voice>,
+    sustain: [bool; 16],
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, settings: SynthSettings) -> Synth {
+        Synth {
+            sample_rate: sample_rate as f32,
+            settings,
+            voices: Vec::new(),
+            sustain: [false; 16],
+        }
+    }
+
+    fn release(&mut self, channel: u8, note: Option<u8>) {
+        let sustain = self.sustain[channel as usize];
+        for voice in self.voices.iter_mut() {
+            if voice.channel == channel && voice.is_held() && note.map_or(true, |n| n == voice.note)
+            {
+                voice.stage = Stage::Release;
+                voice.sustained = sustain;
+            }
+        }
+    }
+}
+
+impl SoundSource for Synth {
+    fn handle_event(&mut self, event: SinkEvent) {
+        match event {
+            SinkEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => {
+                if self.voices.len() >= MAX_VOICES {
+                    self.voices.remove(0);
+                }
+                self.voices.push(Voice {
+                    channel,
+                    note,
+                    phase: 0.0,
+                    modulator_phase: 0.0,
+                    increment: note_frequency(note) / self.sample_rate,
+                    amplitude: velocity as f32 / 127.0,
+                    stage: Stage::Attack,
+                    level: 0.0,
+                    sustained: false,
+                    low: 0.0,
+                    band: 0.0,
+                });
+            }
+            SinkEvent::NoteOff { channel, note, .. } => self.release(channel, Some(note)),
+            SinkEvent::ControlChange {
+                channel,
+                controller: SUSTAIN_CONTROLLER,
+                value,
+            } => {
+                self.sustain[channel as usize] = value >= 64;
+                if value < 64 {
+                    for voice in self.voices.iter_mut() {
+                        if voice.channel == channel {
+                            voice.sustained = false;
+                        }
+                    }
+                }
+            }
+            SinkEvent::ControlChange {
+                channel,
+                controller: ALL_NOTES_OFF_CONTROLLER,
+                ..
+            } => self.release(channel, None),
+            _ => (),
+        }
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let settings = self.settings;
+        // the Chamberlin filter is only stable well below the Nyquist frequency
+        let cutoff = settings.cutoff.min(self.sample_rate / 6.0);
+        let f = 2.0 * (PI * cutoff / self.sample_rate).sin();
+        let damping = 2.0 * (1.0 - settings.resonance.clamp(0.0, MAX_RESONANCE));
+        for voice in self.voices.iter_mut() {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                voice.step_envelope(&settings, self.sample_rate);
+                let modulation = settings.fm_index * (TAU * voice.modulator_phase).sin() / TAU;
+                let oscillator = settings
+                    .waveform
+                    .sample((voice.phase + modulation).rem_euclid(1.0));
+                voice.low += f * voice.band;
+                let high = oscillator - voice.low - damping * voice.band;
+                voice.band += f * high;
+                let sample = voice.low * voice.amplitude * voice.level * OUTPUT_GAIN;
+                *l += sample;
+                *r += sample;
+                voice.phase = (voice.phase + voice.increment) % 1.0;
+                voice.modulator_phase =
+                    (voice.modulator_phase + voice.increment * settings.fm_ratio) % 1.0;
+            }
+        }
+        self.voices
+            .retain(|voice| voice.is_held() || voice.sustained || voice.level > 0.0);
+    }
+
+    fn update(&mut self, parameter: SoundParameter) {
+        if let SoundParameter::Synth(settings) = parameter {
+            self.settings = settings;
+        }
+    }
+}
+
+// A short decaying sine on every beat, for a click stem
+pub fn render_click(samples: &mut [f32], sample_rate: u32, bpm: f32) {
+    let beat_length = (60.0 / bpm * sample_rate as f32) as usize;
+    let click_length = (CLICK_SECONDS * sample_rate as f32) as usize;
+    for (index, sample) in samples.iter_mut().enumerate() {
+        let position = index % beat_length.max(1);
+        if position < click_length {
+            let t = position as f32 / sample_rate as f32;
+            let decay = 1.0 - position as f32 / click_length as f32;
+            *sample += (TAU * CLICK_FREQUENCY * t).sin() * decay * CLICK_GAIN;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+//constants
+// onsets older than this are forgotten
+const ONSET_WINDOW: Duration = Duration::from_secs(8);
+// notes closer than this are one onset, as in a chord
+const MIN_ONSET_GAP: Duration = Duration::from_millis(60);
+// longest interval counted, between onsets not next to each other too
+const MAX_INTERVAL_SECONDS: f32 = 2.0;
+const MIN_ONSETS: usize = 4;
+// seconds the tempo takes to get most of the way to the estimate
+const FOLLOW_TIME_CONSTANT: f32 = 2.0;
+// closer than this the tempo lands on the estimate
+const FOLLOW_SNAP_BPM: f32 = 0.05;
+
+// Estimates the tempo of a player from when their notes start, without any clock from them
+pub struct TempoEstimator {
+    onsets: VecDeque<Instant>,
+    min_bpm: f32,
+    max_bpm: f32,
+}
+
+impl TempoEstimator {
+    pub fn new(min_bpm: f32, max_bpm: f32) -> TempoEstimator {
+        TempoEstimator {
+            onsets: VecDeque::new(),
+            min_bpm,
+            max_bpm,
+        }
+    }
+
+    pub fn add_onset(&mut self, at: Instant) {
+        let too_close = self
+            .onsets
+            .back()
+            .is_some_and(|last| at.saturating_duration_since(*last) < MIN_ONSET_GAP);
+        if !too_close {
+            self.onsets.push_back(at);
+        }
+        self.forget_before(at);
+    }
+
+    pub fn forget_before(&mut self, now: Instant) {
+        while let Some(first) = self.onsets.front() {
+            if now.saturating_duration_since(*first) <= ONSET_WINDOW {
+                break;
+            }
+            self.onsets.pop_front();
+        }
+    }
+
+    pub fn onset_count(&self) -> usize {
+        self.onsets.len()
+    }
+
+    // Most likely tempo of the onsets kept. Every interval between two of them votes in a
+    // histogram of whole BPM, folded by octaves into the one around the reference tempo,
+    // so a player holding the tempo and playing eighths is not heard at double speed.
+    pub fn estimate(&self, reference_bpm: f32) -> Option<f32> {
+        if self.onsets.len() < MIN_ONSETS {
+            return None;
+        }
+        let low = (reference_bpm / 2.0_f32.sqrt()).max(self.min_bpm);
+        let high = (reference_bpm * 2.0_f32.sqrt()).min(self.max_bpm);
+        let bins = (self.max_bpm - self.min_bpm) as usize + 1;
+        let mut histogram = vec![0.0_f32; bins];
+        let mut votes = Vec::new();
+        for (i, first) in self.onsets.iter().enumerate() {
+            for (distance, second) in self.onsets.iter().skip(i + 1).enumerate() {
+                let interval = second.duration_since(*first).as_secs_f32();
+                if interval > MAX_INTERVAL_SECONDS {
+                    break;
+                }
+                let mut bpm = 60.0 / interval;
+                while bpm >= high && bpm / 2.0 >= low {
+                    bpm /= 2.0;
+                }
+                while bpm < low && bpm * 2.0 <= high {
+                    bpm *= 2.0;
+                }
+                if bpm < self.min_bpm || bpm > self.max_bpm {
+                    continue;
+                }
+                // onsets next to each other say the most about the beat
+                let weight = 1.0 / (distance + 1) as f32;
+                let bin = (bpm - self.min_bpm).round() as usize;
+                // spread over the neighbouring bins against the timing of a human player
+                histogram[bin] += weight;
+                if bin > 0 {
+                    histogram[bin - 1] += weight / 2.0;
+                }
+                if bin + 1 < bins {
+                    histogram[bin + 1] += weight / 2.0;
+                }
+                votes.push((bin, bpm, weight));
+            }
+        }
+        let peak = (0..bins).max_by(|a, b| histogram[*a].total_cmp(&histogram[*b]))?;
+        if histogram[peak] == 0.0 {
+            return None;
+        }
+        // the votes around the peak, finer than whole BPM
+        let (sum, weights) = votes
+            .iter()
+            .filter(|(bin, _, _)| bin.abs_diff(peak) <= 1)
+            .fold((0.0, 0.0), |(sum, weights), (_, bpm, weight)| {
+                (sum + bpm * weight, weights + weight)
+            });
+        Some(sum / weights)
+    }
+}
+
+// Follow mode: the tempo slews toward the one estimated from the MIDI input and the taps
+pub struct TempoFollow {
+    pub enabled: bool,
+    estimator: TempoEstimator,
+    pub estimate: Option<f32>,
+}
+
+impl TempoFollow {
+    pub fn new(min_bpm: f32, max_bpm: f32) -> TempoFollow {
+        TempoFollow {
+            enabled: false,
+            estimator: TempoEstimator::new(min_bpm, max_bpm),
+            estimate: None,
+        }
+    }
+
+    pub fn add_onsets(&mut self, onsets: &[Instant]) {
+        for onset in onsets {
+            self.estimator.add_onset(*onset);
+        }
+    }
+
+    pub fn tap(&mut self) {
+        self.estimator.add_onset(Instant::now());
+    }
+
+    pub fn onset_count(&self) -> usize {
+        self.estimator.onset_count()
+    }
+
+    // The tempo a frame of the given seconds later, None when it stays where it is
+    pub fn update(&mut self, bpm: f32, seconds: f32) -> Option<f32> {
+        self.estimator.forget_before(Instant::now());
+        self.estimate = self.estimator.estimate(bpm);
+        let target = self.estimate.filter(|_| self.enabled)?;
+        if (target - bpm).abs() < FOLLOW_SNAP_BPM {
+            return (target != bpm).then_some(target);
+        }
+        Some(bpm + (target - bpm) * (1.0 - (-seconds / FOLLOW_TIME_CONSTANT).exp()))
+    }
+}
@@ -0,0 +1,120 @@
+use pitch_calc::*;
+use std::{f32::consts::PI, fmt::Display, str::FromStr};
+
+use crate::pitch::{fit_to_range, ScaleGrid};
+
+//constants
+// position in the phrase where the tension peaks before resolving
+const TENSION_PEAK: f32 = 0.8;
+const TENSION_PITCH_DEGREES: f32 = 7.0;
+const TENSION_VELOCITY_RANGE: f32 = 24.0;
+const TENSION_MAX_THINNING: f64 = 0.75;
+
+// Shape of the rise towards the peak
+#[derive(Clone, Copy, PartialEq)]
+pub enum TensionShape {
+    Off,
+    Linear,
+    Arch,
+    Exponential,
+}
+
+impl Display for TensionShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TensionShape::Off => write!(f, "Off"),
+            TensionShape::Linear => write!(f, "Linear"),
+            TensionShape::Arch => write!(f, "Arch"),
+            TensionShape::Exponential => write!(f, "Exponential"),
+        }
+    }
+}
+
+impl FromStr for TensionShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Off" => Ok(TensionShape::Off),
+            "Linear" => Ok(TensionShape::Linear),
+            "Arch" => Ok(TensionShape::Arch),
+            "Exponential" => Ok(TensionShape::Exponential),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct TensionSettings {
+    pub shape: TensionShape,
+    pub phrase_bars: u32,
+    // 0 leaves the notes untouched, 1 applies the full modulation
+    pub depth: f32,
+}
+
+// Modulates note density, pitch height and velocity along a tension arc over each N-bar phrase
+pub struct TensionModulator {
+    settings: TensionSettings,
+    grid: ScaleGrid,
+    min: i32,
+    max: i32,
+}
+
+impl TensionModulator {
+    pub fn new(
+        settings: TensionSettings,
+        scale: Vec<Letter>,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> TensionModulator {
+        TensionModulator {
+            settings,
+            grid: ScaleGrid::new(scale),
+            min: min.step().round() as i32,
+            max: max.step().round() as i32,
+        }
+    }
+
+    pub fn phrase_bars(&self) -> u32 {
+        self.settings.phrase_bars.max(1)
+    }
+
+    // Tension in [0, 1] at a position in [0, 1) of the phrase, already scaled by the depth
+    pub fn level(&self, position: f32) -> f32 {
+        let tension = if position < TENSION_PEAK {
+            let rise = position / TENSION_PEAK;
+            match self.settings.shape {
+                TensionShape::Off => 0.0,
+                TensionShape::Linear => rise,
+                TensionShape::Arch => (rise * PI / 2.0).sin(),
+                TensionShape::Exponential => rise.powi(3),
+            }
+        } else if self.settings.shape == TensionShape::Off {
+            0.0
+        } else {
+            // resolve
+            1.0 - (position - TENSION_PEAK) / (1.0 - TENSION_PEAK)
+        };
+        tension.clamp(0.0, 1.0) * self.settings.depth
+    }
+
+    // Raise the note by scale degrees as tension builds
+    pub fn raise_pitch(&self, note: LetterOctave, level: f32) -> LetterOctave {
+        let degrees = (level * TENSION_PITCH_DEGREES).round() as i32;
+        if degrees == 0 {
+            return note;
+        }
+        let degree = self.grid.to_degree(note.step().round() as i32) + degrees;
+        Step(fit_to_range(self.grid.to_step(degree), self.min, self.max) as f32).to_letter_octave()
+    }
+
+    // Softer than usual at rest, louder at the peak
+    pub fn velocity_offset(&self, level: f32) -> i32 {
+        ((level - self.settings.depth / 2.0) * 2.0 * TENSION_VELOCITY_RANGE) as i32
+    }
+
+    // Probability of dropping a note, thinning out the phrase at low tension
+    pub fn thinning_probability(&self, level: f32) -> f64 {
+        ((self.settings.depth - level) as f64 * TENSION_MAX_THINNING).clamp(0.0, 1.0)
+    }
+}
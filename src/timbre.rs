@@ -0,0 +1,53 @@
+use rand::prelude::*;
+
+//constants
+// bank select, the GM programs are in the first bank
+pub const BANK_SELECT_MSB: u8 = 0;
+pub const BANK_SELECT_LSB: u8 = 32;
+pub const GM_BANK: u8 = 0;
+
+// The instrument of the main voice walking through a set of GM programs, one step every few bars
+#[derive(Clone, PartialEq)]
+pub struct TimbreDrift {
+    pub enabled: bool,
+    pub every_bars: u32,
+    // in increasing order, the walk goes to a neighbour
+    pub programs: Vec<u8>,
+}
+
+impl TimbreDrift {
+    // The program after the one playing at the bar, None when it isn't time to change.
+    // A program outside the set starts the walk at the closest one.
+    pub fn next_program(&self, bar: u64, playing: u8, rng: &mut impl Rng) -> Option<u8> {
+        if !self.enabled || bar == 0 || bar % self.every_bars.max(1) as u64 != 0 {
+            return None;
+        }
+        let Some(index) = self.programs.iter().position(|p| *p == playing) else {
+            return self
+                .programs
+                .iter()
+                .min_by_key(|p| p.abs_diff(playing))
+                .copied();
+        };
+        if self.programs.len() < 2 {
+            return None;
+        }
+        // one step up or down, round the set
+        let count = self.programs.len();
+        let next = if rng.gen_bool(0.5) {
+            (index + 1) % count
+        } else {
+            (index + count - 1) % count
+        };
+        Some(self.programs[next])
+    }
+
+    pub fn toggle(&mut self, program: u8) {
+        match self.programs.binary_search(&program) {
+            Ok(index) => {
+                self.programs.remove(index);
+            }
+            Err(index) => self.programs.insert(index, program),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use core::time::Duration;
+
+// Where the sequencer is, as shown in the transport bar
+#[derive(Clone, Copy, Default)]
+pub struct TransportPosition {
+    // ticks since bar 1, wrapping at the loop end
+    pub tick: u64,
+    pub ticks_per_beat: u64,
+    pub beats_per_bar: u64,
+    // time played since the last return to bar 1, loops included
+    pub elapsed: Duration,
+    pub loop_bars: Option<u32>,
+}
+
+impl TransportPosition {
+    // Beats since bar 1, with the fraction of the current one
+    pub fn beats(&self) -> f64 {
+        self.tick as f64 / self.ticks_per_beat.max(1) as f64
+    }
+
+    // 1-based bar and beat, 0-based tick in the beat
+    pub fn bar_beat_tick(&self) -> (u64, u64, u64) {
+        let ticks_per_beat = self.ticks_per_beat.max(1);
+        let beats_per_bar = self.beats_per_bar.max(1);
+        let beat = self.tick / ticks_per_beat;
+        (
+            beat / beats_per_bar + 1,
+            beat % beats_per_bar + 1,
+            self.tick % ticks_per_beat,
+        )
+    }
+
+    // How far into the loop, from 0 at its start to 1 at its end
+    pub fn loop_progress(&self) -> Option<f32> {
+        self.loop_bars.map(|bars| {
+            let loop_ticks = bars as u64 * self.ticks_per_beat.max(1) * self.beats_per_bar;
+            self.tick as f32 / loop_ticks as f32
+        })
+    }
+}
+
+// The timeline of the sequencer: counts the ticks played, places them in bars and wraps them
+// around the loop. Everything placing notes in the bar reads its position from here.
+pub struct Transport {
+    tick: u64,
+    // time played since the last rewind, the ticks get shorter and longer with the tempo
+    elapsed: Duration,
+    beats_per_bar: u64,
+    loop_bars: Option<u32>,
+}
+
+impl Transport {
+    pub fn new(beats_per_bar: u64) -> Transport {
+        Transport {
+            tick: 0,
+            elapsed: Duration::ZERO,
+            beats_per_bar,
+            loop_bars: None,
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn beats_per_bar(&self) -> u64 {
+        self.beats_per_bar
+    }
+
+    // Follows the rhythm pattern, the position stays where it is
+    pub fn set_beats_per_bar(&mut self, beats_per_bar: u64) {
+        self.beats_per_bar = beats_per_bar.max(1);
+    }
+
+    pub fn bar(&self, ticks_per_beat: u64) -> u64 {
+        self.tick / (ticks_per_beat * self.beats_per_bar)
+    }
+
+    // Moves one tick on, returns true when it wrapped back to the loop start
+    pub fn advance(&mut self, ticks_per_beat: u64, tick_length: Duration) -> bool {
+        self.elapsed += tick_length;
+        self.tick += 1;
+        match self.loop_ticks(ticks_per_beat) {
+            Some(loop_ticks) if self.tick >= loop_ticks => {
+                self.tick = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Back to the start of bar 1
+    pub fn rewind(&mut self) {
+        self.tick = 0;
+        self.elapsed = Duration::ZERO;
+    }
+
+    // Jumps to a tick, folded into the loop
+    pub fn jump_to(&mut self, tick: u64, ticks_per_beat: u64) {
+        self.tick = match self.loop_ticks(ticks_per_beat) {
+            Some(loop_ticks) => tick % loop_ticks,
+            None => tick,
+        };
+    }
+
+    // A position already past a shorter loop goes back to its start, returns true when it did
+    pub fn set_loop_bars(&mut self, loop_bars: Option<u32>, ticks_per_beat: u64) -> bool {
+        self.loop_bars = loop_bars.filter(|bars| *bars > 0);
+        match self.loop_ticks(ticks_per_beat) {
+            Some(loop_ticks) if self.tick >= loop_ticks => {
+                self.tick = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn position(&self, ticks_per_beat: u64) -> TransportPosition {
+        TransportPosition {
+            tick: self.tick,
+            ticks_per_beat,
+            beats_per_bar: self.beats_per_bar,
+            elapsed: self.elapsed,
+            loop_bars: self.loop_bars,
+        }
+    }
+
+    fn loop_ticks(&self, ticks_per_beat: u64) -> Option<u64> {
+        self.loop_bars
+            .map(|bars| bars as u64 * ticks_per_beat * self.beats_per_bar)
+    }
+}
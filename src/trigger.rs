@@ -1,12 +1,19 @@
 use rand::prelude::*;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
-// constants
-const RANDOM_PROBABILITY: f64 = 1.0;
+use crate::chain::ChainParameter;
+use crate::seed::{seeded_rng, SeedStream};
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Trigger {
     Off,
     On,
+    // the pattern step happens but no note is played
+    Rest,
 }
 
 impl Trigger {
@@ -19,25 +26,131 @@ impl Trigger {
     }
 }
 
+// A trigger with the rhythmic context it fired in, for the modules downstream to react to
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TriggerEvent {
+    pub fired: Trigger,
+    // beat of the bar from 0, known once the trigger went through a rhythm divider
+    pub beat_index: Option<u32>,
+    // step of the beat from 0, the first one falling on the beat
+    pub step_index: Option<u32>,
+    // the first step of the bar
+    pub accent: bool,
+    // the draw a random producer compared to its probability, from 0 to 1
+    pub probability_roll: Option<f64>,
+}
+
+impl TriggerEvent {
+    pub const OFF: TriggerEvent = TriggerEvent {
+        fired: Trigger::Off,
+        beat_index: None,
+        step_index: None,
+        accent: false,
+        probability_roll: None,
+    };
+
+    pub fn with_trigger(self, fired: Trigger) -> TriggerEvent {
+        TriggerEvent { fired, ..self }
+    }
+}
+
+impl From<Trigger> for TriggerEvent {
+    fn from(fired: Trigger) -> Self {
+        TriggerEvent {
+            fired,
+            ..TriggerEvent::OFF
+        }
+    }
+}
+
 pub trait TriggerModule: Send + Sync {
-    fn tick(&mut self) -> Trigger;
+    fn tick(&mut self) -> TriggerEvent;
+    fn update(&mut self, _parameter: ChainParameter) {}
 }
 
+// Fires with the trigger probability weighted by the beat of the bar, which the rhythm
+// divider reports, so the downbeats can be busier than the offbeats
 pub struct RandomTriggerProducer<R: Rng> {
     rng: R,
+    probability: f64,
+    // one weight per beat of the bar, none for the same probability on every beat
+    beat_weights: Vec<f32>,
+    beat: u32,
 }
 
 impl RandomTriggerProducer<SmallRng> {
-    pub fn new() -> RandomTriggerProducer<SmallRng> {
+    pub fn new(probability: f64, beat_weights: Vec<f32>) -> RandomTriggerProducer<SmallRng> {
         RandomTriggerProducer {
             rng: SmallRng::from_entropy(),
+            probability,
+            beat_weights,
+            beat: 0,
         }
     }
 }
 
-impl<R: Rng + Send + Sync> TriggerModule for RandomTriggerProducer<R> {
-    fn tick(&mut self) -> Trigger {
-        Trigger::from_bool(self.rng.gen_bool(RANDOM_PROBABILITY))
+impl<R: Rng + Send + Sync> RandomTriggerProducer<R> {
+    fn beat_probability(&self) -> f64 {
+        let weight = match self.beat_weights.len() {
+            0 => 1.0,
+            beats => self.beat_weights[self.beat as usize % beats] as f64,
+        };
+        (self.probability * weight).clamp(0.0, 1.0)
+    }
+}
+
+impl<R: Rng + SeedableRng + Send + Sync> TriggerModule for RandomTriggerProducer<R> {
+    fn tick(&mut self) -> TriggerEvent {
+        let roll = self.rng.gen::<f64>();
+        TriggerEvent {
+            probability_roll: Some(roll),
+            ..Trigger::from_bool(roll < self.beat_probability()).into()
+        }
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::TriggerProbability(probability) => self.probability = probability,
+            ChainParameter::Beat(beat) => self.beat = beat,
+            ChainParameter::Seed(seed) => self.rng = seeded_rng(seed, SeedStream::RandomTrigger),
+            _ => (),
+        }
+    }
+}
+
+pub struct RestGate<R: Rng> {
+    input: Box<dyn TriggerModule>,
+    rng: R,
+    probability: f64,
+}
+
+impl RestGate<SmallRng> {
+    pub fn new(input: Box<dyn TriggerModule>, probability: f64) -> RestGate<SmallRng> {
+        RestGate {
+            input,
+            rng: SmallRng::from_entropy(),
+            probability,
+        }
+    }
+}
+
+impl<R: Rng + SeedableRng + Send + Sync> TriggerModule for RestGate<R> {
+    fn tick(&mut self) -> TriggerEvent {
+        let event = self.input.tick();
+        if event.fired == Trigger::On && self.rng.gen_bool(self.probability) {
+            event.with_trigger(Trigger::Rest)
+        } else {
+            event
+        }
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::RestProbability(probability) => self.probability = probability,
+            ChainParameter::Seed(seed) => self.rng = seeded_rng(seed, SeedStream::RestGate),
+            _ => (),
+        }
+        self.input.update(parameter);
     }
 }
 
@@ -58,79 +171,470 @@ impl ClockDivider {
 }
 
 impl TriggerModule for ClockDivider {
-    fn tick(&mut self) -> Trigger {
-        let trigger = if (self.counter == 0 || self.counter == self.factor) {
+    fn tick(&mut self) -> TriggerEvent {
+        let event = if (self.counter == 0 || self.counter == self.factor) {
             self.counter = 0;
             self.input.tick()
         } else {
-            Trigger::Off
+            TriggerEvent::OFF
         };
         self.counter += 1;
-        trigger
+        event
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        self.input.update(parameter);
     }
 }
 
+// Fires on the active sixteenths of a one-bar pattern of steps.
+// Steps are placed from the position in the bar, so they stay on the grid when a beat
+// doesn't split evenly into four.
+pub struct StepTrigger {
+    steps: u16,
+    beat_length: u32,
+    // tick in the bar
+    position: u32,
+    last_step: Option<u32>,
+}
+
+impl StepTrigger {
+    pub fn new(steps: u16, beat_length: u32) -> StepTrigger {
+        StepTrigger {
+            steps,
+            beat_length: beat_length.max(1),
+            position: 0,
+            last_step: None,
+        }
+    }
+}
+
+impl TriggerModule for StepTrigger {
+    fn tick(&mut self) -> TriggerEvent {
+        let step = self.position * 4 / self.beat_length;
+        let event = if self.last_step != Some(step) && self.steps & 1 << step != 0 {
+            TriggerEvent {
+                fired: Trigger::On,
+                beat_index: Some(step / 4),
+                step_index: Some(step % 4),
+                accent: step == 0,
+                probability_roll: None,
+            }
+        } else {
+            TriggerEvent::OFF
+        };
+        self.last_step = Some(step);
+        self.position = (self.position + 1) % (self.beat_length * 4);
+        event
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        if let ChainParameter::BeatLength(beat_length) = parameter {
+            let beat_length = beat_length.max(1);
+            self.position = self.position * beat_length / self.beat_length;
+            self.beat_length = beat_length;
+        }
+    }
+}
+
+// Delays every second trigger by a share of the distance between triggers, for a shuffled feel
+pub struct SwingModule {
+    input: Box<dyn TriggerModule>,
+    // 0 for straight, 0.5 for half way to the next trigger
+    amount: f32,
+    ticks_since_trigger: u32,
+    // the next trigger is the one delayed
+    offbeat: bool,
+    // delayed trigger and the ticks left before it goes out
+    pending: Option<(TriggerEvent, u32)>,
+}
+
+impl SwingModule {
+    pub fn new(input: Box<dyn TriggerModule>, amount: f32) -> SwingModule {
+        SwingModule {
+            input,
+            amount,
+            ticks_since_trigger: 0,
+            offbeat: false,
+            pending: None,
+        }
+    }
+}
+
+impl TriggerModule for SwingModule {
+    fn tick(&mut self) -> TriggerEvent {
+        let event = self.input.tick();
+        self.ticks_since_trigger += 1;
+        let mut output = TriggerEvent::OFF;
+        if let Some((delayed, ticks_left)) = self.pending {
+            if ticks_left <= 1 {
+                output = delayed;
+                self.pending = None;
+            } else {
+                self.pending = Some((delayed, ticks_left - 1));
+            }
+        }
+        if event.fired != Trigger::Off {
+            let delay = (self.ticks_since_trigger as f32 * self.amount).round() as u32;
+            self.ticks_since_trigger = 0;
+            if self.offbeat && delay > 0 {
+                self.pending = Some((event, delay));
+            } else {
+                output = event;
+            }
+            self.offbeat = !self.offbeat;
+        }
+        output
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        self.input.update(parameter);
+    }
+}
+
+// Repeats each trigger, the repeats spread evenly over the distance between triggers
+pub struct RatchetModule {
+    input: Box<dyn TriggerModule>,
+    // notes per trigger, the trigger included
+    count: u32,
+    ticks_since_trigger: u32,
+    spacing: u32,
+    repeats_left: u32,
+    countdown: u32,
+    // the trigger repeated, its beat and step kept for the repeats
+    repeated: TriggerEvent,
+}
+
+impl RatchetModule {
+    pub fn new(input: Box<dyn TriggerModule>, count: u32) -> RatchetModule {
+        RatchetModule {
+            input,
+            count: count.max(1),
+            ticks_since_trigger: 0,
+            spacing: 1,
+            repeats_left: 0,
+            countdown: 0,
+            repeated: TriggerEvent::OFF,
+        }
+    }
+}
+
+impl TriggerModule for RatchetModule {
+    fn tick(&mut self) -> TriggerEvent {
+        let event = self.input.tick();
+        self.ticks_since_trigger += 1;
+        if event.fired != Trigger::Off {
+            self.spacing = (self.ticks_since_trigger / self.count).max(1);
+            self.ticks_since_trigger = 0;
+            // a rest is not repeated
+            self.repeats_left = if event.fired == Trigger::On {
+                self.count - 1
+            } else {
+                0
+            };
+            self.countdown = self.spacing;
+            self.repeated = TriggerEvent {
+                accent: false,
+                probability_roll: None,
+                ..event
+            };
+            return event;
+        }
+        if self.repeats_left > 0 {
+            self.countdown -= 1;
+            if self.countdown == 0 {
+                self.repeats_left -= 1;
+                self.countdown = self.spacing;
+                return self.repeated;
+            }
+        }
+        TriggerEvent::OFF
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        self.input.update(parameter);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogicOperation {
+    And,
+    Or,
+    Xor,
+}
+
+impl Display for LogicOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            LogicOperation::And => write!(f, "And"),
+            LogicOperation::Or => write!(f, "Or"),
+            LogicOperation::Xor => write!(f, "Xor"),
+        }
+    }
+}
+
+impl FromStr for LogicOperation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "And" => Ok(LogicOperation::And),
+            "Or" => Ok(LogicOperation::Or),
+            "Xor" => Ok(LogicOperation::Xor),
+            _ => Err(()),
+        }
+    }
+}
+
+// Combines the triggers of its input with a second pattern of sixteenths.
+// A trigger of the pattern alone plays a note, the input keeps its rests.
+pub struct LogicModule {
+    input: Box<dyn TriggerModule>,
+    pattern: StepTrigger,
+    operation: LogicOperation,
+}
+
+impl LogicModule {
+    pub fn new(
+        input: Box<dyn TriggerModule>,
+        pattern: StepTrigger,
+        operation: LogicOperation,
+    ) -> LogicModule {
+        LogicModule {
+            input,
+            pattern,
+            operation,
+        }
+    }
+}
+
+impl TriggerModule for LogicModule {
+    fn tick(&mut self) -> TriggerEvent {
+        let event = self.input.tick();
+        let pattern = self.pattern.tick();
+        let input = event.fired != Trigger::Off;
+        match (self.operation, input, pattern.fired == Trigger::On) {
+            (LogicOperation::And, _, true) => event,
+            (LogicOperation::Or, true, _) => event,
+            (LogicOperation::Or, false, true) => pattern,
+            (LogicOperation::Xor, true, false) => event,
+            (LogicOperation::Xor, false, true) => pattern,
+            _ => TriggerEvent::OFF,
+        }
+    }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        self.pattern.update(parameter);
+        self.input.update(parameter);
+    }
+}
+
+// The steps of the current bar as the rhythm divider plays them, for the step lights
+#[derive(Clone, Default)]
+pub struct StepLights {
+    // notes of each beat of the bar, rotated as played
+    pub notes_per_beat: Vec<u32>,
+    // one entry per step passed in the bar, true where a note played
+    pub fired: Vec<bool>,
+}
+
+// Spreads the notes of each beat evenly over its ticks, the way Bresenham draws a line:
+// a note falls on every tick the running count of notes times ticks wraps past the beat length,
+// so k notes land exactly k times per beat whether or not they divide it
 pub struct RhythmDivider {
+    // ticks per beat
     factor: u32,
+    // tick in the current beat
     counter: u32,
-    notes_per_beat: [u32; 4],
+    // one entry per beat of the bar
+    notes_per_beat: Vec<u32>,
+    // beats the bar starts late by
+    offset: u32,
     current_beat_index: u32,
-    current_beat_note: u32,
+    lights: Option<Arc<Mutex<StepLights>>>,
 
     input: Box<dyn TriggerModule>,
 }
 impl RhythmDivider {
+    // The notes per beat must not be empty
     pub fn new(
         input: Box<dyn TriggerModule>,
         factor: u32,
-        notes_per_beat: [u32; 4],
+        notes_per_beat: Vec<u32>,
     ) -> RhythmDivider {
         RhythmDivider {
-            factor: factor,
+            factor: factor.max(1),
             counter: 0,
             notes_per_beat: notes_per_beat,
+            offset: 0,
             current_beat_index: 0,
-            current_beat_note: 0,
+            lights: None,
             input: input,
         }
     }
+
+    // Reports the steps it plays to the step lights
+    pub fn with_lights(mut self, lights: Arc<Mutex<StepLights>>) -> RhythmDivider {
+        self.lights = Some(lights);
+        self
+    }
+
+    // Notes of a beat of the bar, rotated by the offset. More notes than ticks play one
+    // on every tick.
+    fn notes_at(&self, beat: u32) -> u32 {
+        let beats = self.notes_per_beat.len() as u32;
+        self.notes_per_beat[((beat + self.offset) % beats) as usize].min(self.factor)
+    }
+
+    fn start_lights_bar(&self) {
+        let Some(lights) = self.lights.as_ref() else {
+            return;
+        };
+        let mut lights = lights.lock().unwrap();
+        lights.notes_per_beat.clear();
+        lights
+            .notes_per_beat
+            .extend((0..self.notes_per_beat.len() as u32).map(|beat| self.notes_at(beat)));
+        let steps = lights.notes_per_beat.iter().sum::<u32>() as usize;
+        lights.fired.clear();
+        lights.fired.reserve(steps);
+    }
+
+    fn light_step(&self, fired: bool) {
+        if let Some(lights) = self.lights.as_ref() {
+            lights.lock().unwrap().fired.push(fired);
+        }
+    }
 }
 
 impl TriggerModule for RhythmDivider {
-    fn tick(&mut self) -> Trigger {
-        if self.current_beat_note == self.notes_per_beat[self.current_beat_index as usize]
-            && self.counter == self.factor
-        {
-            if self.current_beat_index == 3 {
-                self.current_beat_index = 0;
-            } else {
-                self.current_beat_index += 1;
+    fn tick(&mut self) -> TriggerEvent {
+        if self.counter == 0 {
+            if self.current_beat_index == 0 {
+                self.start_lights_bar();
             }
-            self.current_beat_note = 0;
+            self.input
+                .update(ChainParameter::Beat(self.current_beat_index));
         }
-
-        let trigger = if couter_calculation(
-            self.counter.clone(),
-            self.factor.clone(),
-            self.notes_per_beat[self.current_beat_index as usize].clone(),
-        ) {
-            self.counter = 0;
-            self.current_beat_note += 1;
-            self.input.tick()
+        let notes = self.notes_at(self.current_beat_index);
+        let event = if note_on_tick(self.counter, self.factor, notes) {
+            let event = self.input.tick();
+            self.light_step(event.fired == Trigger::On);
+            // note k falls on the first tick at or after k * ticks / notes, the ticks before the
+            // next note don't reach (k + 1) * ticks / notes
+            let step = self.counter * notes / self.factor;
+            TriggerEvent {
+                beat_index: Some(self.current_beat_index),
+                step_index: Some(step),
+                accent: self.current_beat_index == 0 && step == 0,
+                ..event
+            }
         } else {
-            Trigger::Off
+            TriggerEvent {
+                beat_index: Some(self.current_beat_index),
+                ..TriggerEvent::OFF
+            }
         };
         self.counter += 1;
-        trigger
+        if self.counter >= self.factor {
+            self.counter = 0;
+            self.current_beat_index =
+                (self.current_beat_index + 1) % self.notes_per_beat.len() as u32;
+        }
+        event
     }
+
+    fn update(&mut self, parameter: ChainParameter) {
+        match parameter {
+            ChainParameter::BeatLength(factor) => {
+                // the same place in the beat
+                let factor = factor.max(1);
+                self.counter = self.counter * factor / self.factor;
+                self.factor = factor;
+            }
+            ChainParameter::Rotation(beats) => self.offset = beats,
+            _ => (),
+        }
+        self.input.update(parameter);
+    }
+}
+
+// Note k of the beat falls on the first tick at or after k * ticks / notes
+fn note_on_tick(tick: u32, ticks: u32, notes: u32) -> bool {
+    // a beat without notes stays silent
+    notes > 0 && tick * notes % ticks < notes
+}
+
+// chain
+// The modules wrapped around the trigger producer, the first one closest to it
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerStage {
+    BeatDivision,
+    ClockDivider,
+    Swing,
+    Ratchet,
+    RestGate,
+    Logic,
 }
 
-fn couter_calculation(counter: u32, factor: u32, notes_per_beat: u32) -> bool {
-    if counter == 0 && counter == factor {
-        return true;
+pub const TRIGGER_STAGES: &[TriggerStage] = &[
+    TriggerStage::BeatDivision,
+    TriggerStage::ClockDivider,
+    TriggerStage::Swing,
+    TriggerStage::Ratchet,
+    TriggerStage::RestGate,
+    TriggerStage::Logic,
+];
+
+impl Display for TriggerStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TriggerStage::BeatDivision => write!(f, "Beat division"),
+            TriggerStage::ClockDivider => write!(f, "Clock divider"),
+            TriggerStage::Swing => write!(f, "Swing"),
+            TriggerStage::Ratchet => write!(f, "Ratchet"),
+            TriggerStage::RestGate => write!(f, "Rests"),
+            TriggerStage::Logic => write!(f, "Logic"),
+        }
     }
-    if counter % ((factor + notes_per_beat - 1) / notes_per_beat) == 0 {
-        return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fires on every tick it is asked for
+    struct AlwaysOn;
+
+    impl TriggerModule for AlwaysOn {
+        fn tick(&mut self) -> TriggerEvent {
+            TriggerEvent::from(Trigger::On)
+        }
+    }
+
+    // Every beat length up to 480 ticks, with every number of notes it can hold
+    #[test]
+    fn rhythm_divider_fires_the_notes_of_the_beat() {
+        for factor in 1..=480 {
+            for notes in 0..=factor {
+                let mut divider = RhythmDivider::new(Box::new(AlwaysOn), factor, vec![notes]);
+                let mut fired = Vec::new();
+                for tick in 0..factor {
+                    let event = divider.tick();
+                    assert_eq!(event.beat_index, Some(0));
+                    if event.fired == Trigger::On {
+                        fired.push((tick, event.step_index));
+                    }
+                }
+                let context = format!("{} notes in {} ticks", notes, factor);
+                assert_eq!(fired.len(), notes as usize, "{}", context);
+                if notes > 0 {
+                    assert_eq!(fired[0].0, 0, "{}", context);
+                }
+                let steps: Vec<Option<u32>> = fired.iter().map(|(_, step)| *step).collect();
+                let expected: Vec<Option<u32>> = (0..notes).map(Some).collect();
+                assert_eq!(steps, expected, "{}", context);
+            }
+        }
     }
-    return false;
 }
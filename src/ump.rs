@@ -0,0 +1,165 @@
+use std::fmt::Display;
+
+use crate::sink::SinkEvent;
+
+//constants
+// MIDI 2.0 channel voice messages, two 32 bit words each
+const MIDI2_CHANNEL_VOICE: u32 = 0x4;
+const GROUP: u32 = 0;
+const NOTE_OFF_STATUS: u32 = 0x8;
+const NOTE_ON_STATUS: u32 = 0x9;
+const POLY_PRESSURE_STATUS: u32 = 0xA;
+const CONTROL_CHANGE_STATUS: u32 = 0xB;
+const PROGRAM_CHANGE_STATUS: u32 = 0xC;
+const CHANNEL_PRESSURE_STATUS: u32 = 0xD;
+// midir opens MIDI 1.0 byte streams only. Built with the midi2 feature the packets are written
+// to them as they are, for outputs known to take UMP.
+const BACKEND_SUPPORTS_UMP: bool = cfg!(feature = "midi2");
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MidiProtocol {
+    Midi1,
+    // Universal MIDI Packets, with 16 bit velocities and per note pressure
+    Midi2,
+}
+
+pub const MIDI_PROTOCOLS: [MidiProtocol; 2] = [MidiProtocol::Midi1, MidiProtocol::Midi2];
+
+impl Display for MidiProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MidiProtocol::Midi1 => write!(f, "MIDI 1.0"),
+            MidiProtocol::Midi2 => write!(f, "MIDI 2.0 (experimental)"),
+        }
+    }
+}
+
+impl MidiProtocol {
+    // Whether the MIDI backend can send it, the protocol isn't offered otherwise
+    pub fn is_available(&self) -> bool {
+        match *self {
+            MidiProtocol::Midi1 => true,
+            MidiProtocol::Midi2 => BACKEND_SUPPORTS_UMP,
+        }
+    }
+
+    // The protocol the output ports are opened with, MIDI 1.0 where MIDI 2.0 isn't available
+    pub fn negotiate(&self) -> MidiProtocol {
+        if self.is_available() {
+            *self
+        } else {
+            MidiProtocol::Midi1
+        }
+    }
+}
+
+// Min-center-max scaling of the MIDI 2.0 specification: 0, the center and the top of the
+// smaller range land on 0, the center and the top of the larger one
+fn upscale(value: u32, source_bits: u32, target_bits: u32) -> u32 {
+    let scale_bits = target_bits - source_bits;
+    let mut scaled = value << scale_bits;
+    if value <= 1 << (source_bits - 1) {
+        return scaled;
+    }
+    let repeat_bits = source_bits - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    if scale_bits > repeat_bits {
+        repeat <<= scale_bits - repeat_bits;
+    } else {
+        repeat >>= repeat_bits - scale_bits;
+    }
+    while repeat != 0 {
+        scaled |= repeat;
+        repeat >>= repeat_bits;
+    }
+    scaled
+}
+
+fn first_word(status: u32, channel: u8, index: u8, attribute: u8) -> u32 {
+    MIDI2_CHANNEL_VOICE << 28
+        | GROUP << 24
+        | status << 20
+        | (channel as u32 & 0xF) << 16
+        | (index as u32) << 8
+        | attribute as u32
+}
+
+// The MIDI 2.0 message carrying the event, the 7 bit values scaled up to full resolution
+pub fn ump_words(event: &SinkEvent) -> [u32; 2] {
+    match *event {
+        SinkEvent::NoteOn {
+            channel,
+            note,
+            velocity,
+        } => [
+            first_word(NOTE_ON_STATUS, channel, note, 0),
+            upscale(velocity as u32, 7, 16) << 16,
+        ],
+        SinkEvent::NoteOff {
+            channel,
+            note,
+            velocity,
+        } => [
+            first_word(NOTE_OFF_STATUS, channel, note, 0),
+            upscale(velocity as u32, 7, 16) << 16,
+        ],
+        SinkEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        } => [
+            first_word(CONTROL_CHANGE_STATUS, channel, controller, 0),
+            upscale(value as u32, 7, 32),
+        ],
+        // no bank, only the program
+        SinkEvent::ProgramChange { channel, program } => [
+            first_word(PROGRAM_CHANGE_STATUS, channel, 0, 0),
+            (program as u32) << 24,
+        ],
+        SinkEvent::ChannelPressure { channel, pressure } => [
+            first_word(CHANNEL_PRESSURE_STATUS, channel, 0, 0),
+            upscale(pressure as u32, 7, 32),
+        ],
+    }
+}
+
+// Pressure of a single note, where MIDI 1.0 has the pressure of the whole channel
+pub fn note_pressure_words(channel: u8, note: u8, pressure: u8) -> [u32; 2] {
+    [
+        first_word(POLY_PRESSURE_STATUS, channel, note, 0),
+        upscale(pressure as u32, 7, 32),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bottom, the center and the top of the range stay where they are
+    #[test]
+    fn upscale_keeps_min_center_max() {
+        assert_eq!(upscale(0, 7, 16), 0);
+        assert_eq!(upscale(64, 7, 16), 0x8000);
+        assert_eq!(upscale(127, 7, 16), 0xFFFF);
+        assert_eq!(upscale(0, 7, 32), 0);
+        assert_eq!(upscale(64, 7, 32), 0x8000_0000);
+        assert_eq!(upscale(127, 7, 32), 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn upscale_keeps_the_order() {
+        for value in 1..128 {
+            assert!(upscale(value, 7, 16) > upscale(value - 1, 7, 16));
+        }
+    }
+
+    #[test]
+    fn note_on_words() {
+        let event = SinkEvent::NoteOn {
+            channel: 2,
+            note: 60,
+            velocity: 127,
+        };
+        assert_eq!(ump_words(&event), [0x4092_3C00, 0xFFFF_0000]);
+    }
+}
@@ -0,0 +1,185 @@
+use rand::prelude::*;
+
+use crate::bass::{BassPattern, BassSettings, BASS_PATTERNS};
+use crate::drums::{DrumSettings, DrumVoiceSettings};
+
+//constants
+pub const VARIATION_COUNT: usize = 4;
+pub const VARIATION_NAMES: [&str; VARIATION_COUNT] = ["A", "B", "C", "D"];
+pub const MAX_VARIATION_NOTES_PER_BEAT: u32 = 4;
+// chance of each drum step flipping when a variation is generated
+const DRUM_STEP_FLIP_PROBABILITY: f64 = 0.12;
+// beats of the melody rhythm changed when a variation is generated
+const MELODY_BEATS_CHANGED: usize = 2;
+
+// Four versions of what a track plays, switched at the bar lines or played in turn
+#[derive(Clone, PartialEq)]
+pub struct TrackVariations<T> {
+    // off, the track plays its own settings
+    pub enabled: bool,
+    pub variations: [T; VARIATION_COUNT],
+    // played while not cycling, and the one edited
+    pub selected: usize,
+    // variations played in turn, each for the bars below, empty to stay on the selected one
+    pub cycle: Vec<usize>,
+    pub bars: u32,
+}
+
+impl<T: Clone> TrackVariations<T> {
+    pub fn new(variation: T) -> TrackVariations<T> {
+        TrackVariations {
+            enabled: false,
+            variations: std::array::from_fn(|_| variation.clone()),
+            selected: 0,
+            cycle: Vec::new(),
+            bars: 1,
+        }
+    }
+
+    // The variation playing in a bar, None when the track plays its own settings
+    pub fn index_at(&self, bar: u64) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        if self.cycle.is_empty() {
+            return Some(self.selected);
+        }
+        let step = bar / self.bars.max(1) as u64 % self.cycle.len() as u64;
+        Some(self.cycle[step as usize])
+    }
+
+    // Plays the variation from now on, the cycle stopped
+    pub fn select(&mut self, index: usize) {
+        self.enabled = true;
+        self.selected = index;
+        self.cycle.clear();
+    }
+
+    fn get(&self, index: Option<usize>) -> Option<&T> {
+        index.map(|index| &self.variations[index])
+    }
+}
+
+// Parses a cycle like "AABA", other characters are skipped
+pub fn parse_cycle(text: &str) -> Vec<usize> {
+    text.chars()
+        .filter_map(|c| {
+            VARIATION_NAMES
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(&c.to_string()))
+        })
+        .collect()
+}
+
+pub fn cycle_text(cycle: &[usize]) -> String {
+    cycle.iter().map(|index| VARIATION_NAMES[*index]).collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct BassVariation {
+    pub pattern: BassPattern,
+    pub octaves_below: u32,
+}
+
+// Variation index each track plays in the current bar
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct ActiveVariations {
+    pub melody: Option<usize>,
+    pub drums: Option<usize>,
+    pub bass: Option<usize>,
+}
+
+// The pattern memory: the variations of the melody rhythm, the drum steps and the bass line
+#[derive(Clone, PartialEq)]
+pub struct PatternVariations {
+    // notes on each beat, as many beats as the bar has
+    pub melody: TrackVariations<Vec<u32>>,
+    pub drums: TrackVariations<[DrumVoiceSettings; 3]>,
+    pub bass: TrackVariations<BassVariation>,
+}
+
+impl PatternVariations {
+    pub fn new(notes_per_beat: &[u32], drums: &DrumSettings, bass: &BassSettings) -> Self {
+        PatternVariations {
+            melody: TrackVariations::new(notes_per_beat.to_vec()),
+            drums: TrackVariations::new(drums.voices),
+            bass: TrackVariations::new(BassVariation {
+                pattern: bass.pattern,
+                octaves_below: bass.octaves_below,
+            }),
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.melody.select(index);
+        self.drums.select(index);
+        self.bass.select(index);
+    }
+
+    pub fn active_at(&self, bar: u64) -> ActiveVariations {
+        ActiveVariations {
+            melody: self.melody.index_at(bar),
+            drums: self.drums.index_at(bar),
+            bass: self.bass.index_at(bar),
+        }
+    }
+
+    // The notes per beat to play, a variation made for a bar of another length is skipped
+    pub fn melody(&self, active: &ActiveVariations, notes_per_beat: &[u32]) -> Vec<u32> {
+        match self.melody.get(active.melody) {
+            Some(variation) if variation.len() == notes_per_beat.len() => variation.clone(),
+            _ => notes_per_beat.to_vec(),
+        }
+    }
+
+    pub fn drums(&self, active: &ActiveVariations, drums: DrumSettings) -> DrumSettings {
+        match self.drums.get(active.drums) {
+            Some(voices) => DrumSettings {
+                voices: *voices,
+                ..drums
+            },
+            None => drums,
+        }
+    }
+
+    pub fn bass(&self, active: &ActiveVariations, bass: BassSettings) -> BassSettings {
+        match self.bass.get(active.bass) {
+            Some(variation) => BassSettings {
+                pattern: variation.pattern,
+                octaves_below: variation.octaves_below,
+                ..bass
+            },
+            None => bass,
+        }
+    }
+}
+
+// A few beats get another number of notes
+pub fn vary_melody(notes_per_beat: &[u32], rng: &mut impl Rng) -> Vec<u32> {
+    let mut varied = notes_per_beat.to_vec();
+    for _ in 0..MELODY_BEATS_CHANGED.min(varied.len()) {
+        let beat = rng.gen_range(0..varied.len());
+        varied[beat] = rng.gen_range(0..=MAX_VARIATION_NOTES_PER_BEAT);
+    }
+    varied
+}
+
+// Steps flip here and there, the kick on the downbeat stays
+pub fn vary_drums(voices: &[DrumVoiceSettings; 3], rng: &mut impl Rng) -> [DrumVoiceSettings; 3] {
+    let mut varied = *voices;
+    for (index, voice) in varied.iter_mut().enumerate() {
+        for step in 0..16 {
+            if (index, step) != (0, 0) && rng.gen_bool(DRUM_STEP_FLIP_PROBABILITY) {
+                voice.steps ^= 1 << step;
+            }
+        }
+    }
+    varied
+}
+
+pub fn vary_bass(bass: &BassVariation, rng: &mut impl Rng) -> BassVariation {
+    BassVariation {
+        pattern: *BASS_PATTERNS.choose(rng).unwrap(),
+        ..*bass
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+//constants
+const MAX_VELOCITY: f32 = 127.0;
+const SOFT_EXPONENT: f32 = 0.5;
+const HARD_EXPONENT: f32 = 2.0;
+
+// How an instrument answers the velocities, both in [0, 1]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    Linear,
+    // loud early, for synths that sound thin at low velocities
+    Soft,
+    // quiet until played hard, for synths that jump to loud
+    Hard,
+    // straight lines between (velocity, response) points, in increasing velocity
+    Custom(Vec<[f32; 2]>),
+}
+
+pub const CURVE_NAMES: [&str; 4] = ["Linear", "Soft", "Hard", "Custom"];
+
+impl VelocityCurve {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => CURVE_NAMES[0],
+            VelocityCurve::Soft => CURVE_NAMES[1],
+            VelocityCurve::Hard => CURVE_NAMES[2],
+            VelocityCurve::Custom(_) => CURVE_NAMES[3],
+        }
+    }
+
+    // The curve of a name, a custom one starting out straight
+    pub fn from_name(name: &str) -> VelocityCurve {
+        match name {
+            "Soft" => VelocityCurve::Soft,
+            "Hard" => VelocityCurve::Hard,
+            "Custom" => VelocityCurve::Custom(vec![[0.0, 0.0], [0.5, 0.5], [1.0, 1.0]]),
+            _ => VelocityCurve::Linear,
+        }
+    }
+
+    pub fn response(&self, velocity: f32) -> f32 {
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.powf(SOFT_EXPONENT),
+            VelocityCurve::Hard => velocity.powf(HARD_EXPONENT),
+            VelocityCurve::Custom(points) => {
+                let Some(first) = points.first() else {
+                    return velocity;
+                };
+                if velocity <= first[0] {
+                    return first[1];
+                }
+                for pair in points.windows(2) {
+                    let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+                    if velocity <= x1 {
+                        let t = if x1 > x0 {
+                            (velocity - x0) / (x1 - x0)
+                        } else {
+                            1.0
+                        };
+                        return y0 + t * (y1 - y0);
+                    }
+                }
+                points.last().unwrap()[1]
+            }
+        }
+    }
+}
+
+// The curves set for GM programs, the others play linear
+#[derive(Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VelocityCurves(pub BTreeMap<u8, VelocityCurve>);
+
+impl VelocityCurves {
+    // A note keeps sounding, whatever the curve
+    pub fn apply(&self, instrument: u8, velocity: u8) -> u8 {
+        match self.0.get(&instrument) {
+            Some(curve) => (curve.response(velocity as f32 / MAX_VELOCITY) * MAX_VELOCITY)
+                .round()
+                .clamp(1.0, MAX_VELOCITY) as u8,
+            None => velocity,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{collections::VecDeque, f32::consts::TAU};
+
+use nannou::prelude::*;
+
+use crate::drums::DRUM_CHANNEL;
+use crate::notes::PITCH_CLASS_COUNT;
+use crate::sink::SinkEvent;
+use crate::transport::TransportPosition;
+
+//constants
+// seconds a note stays on screen
+const FLASH_LIFETIME: f32 = 1.5;
+const MAX_FLASHES: usize = 128;
+// notes outside are drawn at the edges
+const LOWEST_NOTE: f32 = 24.0;
+const HIGHEST_NOTE: f32 = 108.0;
+const MAX_NOTE_RADIUS: f32 = 40.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VisualStyle {
+    Off,
+    Pulses,
+    Bars,
+    Orbit,
+}
+
+pub const VISUAL_STYLES: &[(VisualStyle, &str)] = &[
+    (VisualStyle::Off, "Off"),
+    (VisualStyle::Pulses, "Pulses"),
+    (VisualStyle::Bars, "Bars"),
+    (VisualStyle::Orbit, "Orbit"),
+];
+
+struct Flash {
+    note: u8,
+    velocity: u8,
+    drum: bool,
+    born: f32,
+}
+
+impl Flash {
+    // 1 when played, 0 once its lifetime is over
+    fn life(&self, now: f32) -> f32 {
+        (1.0 - (now - self.born) / FLASH_LIFETIME).max(0.0)
+    }
+
+    // by pitch class, so the same note always has the same color
+    fn hue(&self) -> f32 {
+        (self.note as usize % PITCH_CLASS_COUNT) as f32 / PITCH_CLASS_COUNT as f32
+    }
+
+    // 0 for the lowest note shown, 1 for the highest
+    fn height(&self) -> f32 {
+        ((self.note as f32 - LOWEST_NOTE) / (HIGHEST_NOTE - LOWEST_NOTE)).clamp(0.0, 1.0)
+    }
+
+    fn loudness(&self) -> f32 {
+        self.velocity as f32 / 127.0
+    }
+}
+
+// Drawn behind the UI from the note ons sent to the MIDI output, pulsing with the beat
+pub struct Visuals {
+    pub style: VisualStyle,
+    flashes: VecDeque<Flash>,
+    // seconds since the start of the app
+    now: f32,
+    beat: f64,
+    beats_per_bar: u64,
+}
+
+impl Visuals {
+    pub fn new() -> Visuals {
+        Visuals {
+            style: VisualStyle::Pulses,
+            flashes: VecDeque::new(),
+            now: 0.0,
+            beat: 0.0,
+            beats_per_bar: 4,
+        }
+    }
+
+    pub fn update(&mut self, note_ons: Vec<SinkEvent>, now: f32, transport: &TransportPosition) {
+        self.now = now;
+        self.beat = transport.beats();
+        self.beats_per_bar = transport.beats_per_bar.max(1);
+        for event in note_ons {
+            if let SinkEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } = event
+            {
+                if self.flashes.len() >= MAX_FLASHES {
+                    self.flashes.pop_front();
+                }
+                self.flashes.push_back(Flash {
+                    note,
+                    velocity,
+                    drum: channel == DRUM_CHANNEL,
+                    born: now,
+                });
+            }
+        }
+        self.flashes.retain(|flash| flash.life(now) > 0.0);
+    }
+
+    pub fn draw(&self, draw: &Draw, rect: Rect) {
+        // 1 on the beat, fading to 0 until the next one
+        let beat_pulse = 1.0 - self.beat.fract() as f32;
+        match self.style {
+            VisualStyle::Off => (),
+            VisualStyle::Pulses => self.draw_pulses(draw, rect, beat_pulse),
+            VisualStyle::Bars => self.draw_bars(draw, rect, beat_pulse),
+            VisualStyle::Orbit => self.draw_orbit(draw, rect, beat_pulse),
+        }
+    }
+
+    // Circles placed by pitch class across and by pitch up, growing as they fade
+    fn draw_pulses(&self, draw: &Draw, rect: Rect, beat_pulse: f32) {
+        let size = rect.w().min(rect.h());
+        draw.ellipse()
+            .x_y(rect.x(), rect.y())
+            .radius(size * 0.1 * (1.0 + beat_pulse * 0.3))
+            .no_fill()
+            .stroke(rgba(1.0, 1.0, 1.0, 0.2 + beat_pulse * 0.3))
+            .stroke_weight(2.0);
+        for flash in &self.flashes {
+            let life = flash.life(self.now);
+            if flash.drum {
+                draw.ellipse()
+                    .x_y(rect.x(), rect.y())
+                    .radius(size * 0.5 * (1.0 - life * 0.5))
+                    .no_fill()
+                    .stroke(rgba(1.0, 1.0, 1.0, life * 0.4))
+                    .stroke_weight(4.0 * life);
+                continue;
+            }
+            let pitch_class = (flash.note as usize % PITCH_CLASS_COUNT) as f32;
+            let x = rect.left() + (pitch_class + 0.5) / PITCH_CLASS_COUNT as f32 * rect.w();
+            let y = rect.bottom() + flash.height() * rect.h();
+            draw.ellipse()
+                .x_y(x, y)
+                .radius(MAX_NOTE_RADIUS * flash.loudness() * (1.0 + (1.0 - life) * 2.0))
+                .color(hsla(flash.hue(), 0.8, 0.5, life * 0.8));
+        }
+    }
+
+    // A bar per note at its pitch, as tall as it is loud, shrinking as it fades
+    fn draw_bars(&self, draw: &Draw, rect: Rect, beat_pulse: f32) {
+        let bar_width = rect.w() / (HIGHEST_NOTE - LOWEST_NOTE);
+        draw.rect()
+            .x_y(rect.x(), rect.bottom() + 2.0)
+            .w_h(rect.w() * beat_pulse, 4.0)
+            .color(rgba(1.0, 1.0, 1.0, 0.5));
+        for flash in &self.flashes {
+            let life = flash.life(self.now);
+            if flash.drum {
+                draw.rect()
+                    .x_y(rect.x(), rect.y())
+                    .w_h(rect.w(), rect.h())
+                    .color(rgba(1.0, 1.0, 1.0, life * 0.05));
+                continue;
+            }
+            let height = rect.h() * flash.loudness() * life;
+            draw.rect()
+                .x_y(
+                    rect.left() + flash.height() * rect.w(),
+                    rect.bottom() + height / 2.0,
+                )
+                .w_h(bar_width * 2.0, height)
+                .color(hsla(flash.hue(), 0.8, 0.3 + flash.height() * 0.4, life));
+        }
+    }
+
+    // Notes around a circle by pitch class and outwards by pitch, a hand turning once a bar
+    fn draw_orbit(&self, draw: &Draw, rect: Rect, beat_pulse: f32) {
+        let radius = rect.w().min(rect.h()) * 0.45;
+        let bar_position =
+            (self.beat % self.beats_per_bar as f64) as f32 / self.beats_per_bar as f32;
+        let angle = TAU * (0.25 - bar_position);
+        draw.ellipse()
+            .x_y(
+                rect.x() + angle.cos() * radius,
+                rect.y() + angle.sin() * radius,
+            )
+            .radius(6.0 + beat_pulse * 6.0)
+            .color(rgba(1.0, 1.0, 1.0, 0.6));
+        for flash in &self.flashes {
+            let life = flash.life(self.now);
+            if flash.drum {
+                draw.ellipse()
+                    .x_y(rect.x(), rect.y())
+                    .radius(radius * 0.2 * (2.0 - life))
+                    .color(rgba(1.0, 1.0, 1.0, life * 0.2));
+                continue;
+            }
+            let angle = TAU * (0.25 - flash.hue());
+            let distance = radius * (0.2 + flash.height() * 0.8);
+            draw.ellipse()
+                .x_y(
+                    rect.x() + angle.cos() * distance,
+                    rect.y() + angle.sin() * distance,
+                )
+                .radius(MAX_NOTE_RADIUS * 0.5 * flash.loudness() * (0.5 + life))
+                .color(hsla(flash.hue(), 0.8, 0.5, life));
+        }
+    }
+}
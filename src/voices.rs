@@ -0,0 +1,33 @@
+use std::fmt::Display;
+
+// Which sounding note makes room when every voice is taken
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StealPolicy {
+    Oldest,
+    Quietest,
+    // the new note is dropped instead
+    None,
+}
+
+pub const STEAL_POLICIES: [StealPolicy; 3] = [
+    StealPolicy::Oldest,
+    StealPolicy::Quietest,
+    StealPolicy::None,
+];
+
+impl Display for StealPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            StealPolicy::Oldest => write!(f, "Oldest"),
+            StealPolicy::Quietest => write!(f, "Quietest"),
+            StealPolicy::None => write!(f, "None"),
+        }
+    }
+}
+
+// Pitched notes sounding at once on the MIDI output and the internal synth, the drums aside
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VoiceSettings {
+    pub max_voices: usize,
+    pub steal: StealPolicy,
+}